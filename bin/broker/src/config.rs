@@ -0,0 +1,696 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+
+/// Settings come from the TOML file given with `--config`, then environment
+/// variables, then command line flags, each overriding the one before.
+#[derive(Debug, Parser)]
+#[command(name = "mini-kafka", about = "mini-kafka broker")]
+pub struct Args {
+    /// TOML config file
+    #[arg(long, env = "MINI_KAFKA_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address of the native protocol listener
+    #[arg(long, env = "MINI_KAFKA_LISTEN")]
+    pub listen: Option<String>,
+    /// Address of the Kafka protocol listener
+    #[arg(long, env = "MINI_KAFKA_KAFKA_LISTEN")]
+    pub kafka_listen: Option<String>,
+    /// Address of the TLS listener, used when a certificate and key are set
+    #[arg(long, env = "MINI_KAFKA_TLS_LISTEN")]
+    pub tls_listen: Option<String>,
+    /// Address of the HTTP endpoint serving Prometheus metrics at /metrics
+    #[arg(long, env = "MINI_KAFKA_METRICS_LISTEN")]
+    pub metrics_listen: Option<String>,
+    #[arg(long, env = "MINI_KAFKA_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    #[arg(long, env = "MINI_KAFKA_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    /// Require client certificates signed by these CAs
+    #[arg(long, env = "MINI_KAFKA_TLS_CLIENT_CA")]
+    pub tls_client_ca: Option<PathBuf>,
+    /// `username:password` file; when set, native listeners require SASL
+    #[arg(long, env = "MINI_KAFKA_SASL_CREDENTIALS")]
+    pub sasl_credentials: Option<PathBuf>,
+    /// User brokers log in to each other as
+    #[arg(long, env = "MINI_KAFKA_SASL_INTER_BROKER_USERNAME")]
+    pub sasl_inter_broker_username: Option<String>,
+    #[arg(long, env = "MINI_KAFKA_SASL_INTER_BROKER_PASSWORD")]
+    pub sasl_inter_broker_password: Option<String>,
+    /// Data directory, repeatable
+    #[arg(long = "data-dir", env = "MINI_KAFKA_DATA_DIRS", value_delimiter = ',')]
+    pub data_dirs: Vec<PathBuf>,
+    #[arg(long, env = "MINI_KAFKA_MAX_FRAME_SIZE")]
+    pub max_frame_size: Option<u64>,
+    /// Open connections allowed over all listeners
+    #[arg(long, env = "MINI_KAFKA_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+    /// Open connections allowed from one IP address
+    #[arg(long, env = "MINI_KAFKA_MAX_CONNECTIONS_PER_IP")]
+    pub max_connections_per_ip: Option<usize>,
+    /// Close connections that start no request for this long
+    #[arg(long, env = "MINI_KAFKA_IDLE_TIMEOUT_MS")]
+    pub idle_timeout_ms: Option<u64>,
+    /// Time allowed to receive a whole request
+    #[arg(long, env = "MINI_KAFKA_REQUEST_TIMEOUT_MS")]
+    pub request_timeout_ms: Option<u64>,
+    #[arg(long, env = "MINI_KAFKA_AUTO_CREATE_TOPICS")]
+    pub auto_create_topics: Option<bool>,
+    /// Default size limit of a partition log
+    #[arg(long, env = "MINI_KAFKA_RETENTION_BYTES")]
+    pub retention_bytes: Option<u64>,
+    #[arg(long, env = "MINI_KAFKA_FLUSH_INTERVAL_MESSAGES")]
+    pub flush_interval_messages: Option<u64>,
+    #[arg(long, env = "MINI_KAFKA_FLUSH_INTERVAL_MS")]
+    pub flush_interval_ms: Option<u64>,
+    /// Default produce quota of each client
+    #[arg(long, env = "MINI_KAFKA_QUOTA_PRODUCE_BYTES_PER_SEC")]
+    pub quota_produce_bytes_per_sec: Option<u64>,
+    /// Default fetch quota of each client
+    #[arg(long, env = "MINI_KAFKA_QUOTA_FETCH_BYTES_PER_SEC")]
+    pub quota_fetch_bytes_per_sec: Option<u64>,
+    /// Default request rate quota of each client
+    #[arg(long, env = "MINI_KAFKA_QUOTA_REQUESTS_PER_SEC")]
+    pub quota_requests_per_sec: Option<u64>,
+    /// How long connections get to finish their requests on shutdown
+    #[arg(long, env = "MINI_KAFKA_SHUTDOWN_TIMEOUT_MS")]
+    pub shutdown_timeout_ms: Option<u64>,
+    /// Log filter, e.g. `info` or `warn,net=debug`
+    #[arg(long, env = "MINI_KAFKA_LOG")]
+    pub log: Option<String>,
+    #[arg(long, env = "MINI_KAFKA_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Log requests taking this long as warnings, with a breakdown of where the
+    /// time went
+    #[arg(long, env = "MINI_KAFKA_SLOW_REQUEST_MS")]
+    pub slow_request_ms: Option<u64>,
+    /// This broker's id in the cluster
+    #[arg(long, env = "MINI_KAFKA_NODE_ID")]
+    pub node_id: Option<u32>,
+    /// Controllers of the cluster as `id@host:port`, this broker included;
+    /// without any the broker runs alone
+    #[arg(long = "voter", env = "MINI_KAFKA_VOTERS", value_delimiter = ',')]
+    pub voters: Vec<String>,
+    /// Replicas of each partition of new topics
+    #[arg(long, env = "MINI_KAFKA_REPLICATION_FACTOR")]
+    pub replication_factor: Option<u16>,
+    /// In-sync replicas `acks=all` produces need
+    #[arg(long, env = "MINI_KAFKA_MIN_INSYNC_REPLICAS")]
+    pub min_insync_replicas: Option<usize>,
+    /// Followers this far behind drop out of the in-sync replicas
+    #[arg(long, env = "MINI_KAFKA_REPLICA_LAG_TIME_MAX_MS")]
+    pub replica_lag_time_max_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data_dirs: Vec<PathBuf>,
+    pub max_frame_size: u64,
+    pub auto_create_topics: bool,
+    pub shutdown_timeout_ms: u64,
+    pub listeners: Listeners,
+    pub connections: ConnectionLimits,
+    pub tls: Tls,
+    pub sasl: Sasl,
+    pub retention: Retention,
+    pub flush: Flush,
+    pub quotas: Quotas,
+    pub log: Log,
+    pub cluster: Cluster,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Listeners {
+    pub native: String,
+    pub kafka: String,
+    pub tls: String,
+    pub metrics: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionLimits {
+    pub max: Option<usize>,
+    pub max_per_ip: Option<usize>,
+    pub idle_timeout_ms: u64,
+    pub request_timeout_ms: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tls {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sasl {
+    pub credentials: Option<PathBuf>,
+    /// The user this broker logs in as on the other brokers' listeners.
+    pub inter_broker_username: Option<String>,
+    pub inter_broker_password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    pub bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flush {
+    pub interval_messages: u64,
+    pub interval_ms: Option<u64>,
+}
+
+/// Quotas of users and client ids, falling back to `default` for each client
+/// matching neither.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    pub default: Quota,
+    pub users: HashMap<String, Quota>,
+    pub clients: HashMap<String, Quota>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    pub produce_bytes_per_sec: Option<u64>,
+    pub fetch_bytes_per_sec: Option<u64>,
+    pub requests_per_sec: Option<u64>,
+}
+
+impl From<Quota> for broker::Quota {
+    fn from(q: Quota) -> Self {
+        Self {
+            produce_bytes_per_sec: q.produce_bytes_per_sec,
+            fetch_bytes_per_sec: q.fetch_bytes_per_sec,
+            requests_per_sec: q.requests_per_sec,
+        }
+    }
+}
+
+/// Brokers listed as `voters` run the controller quorum together, and take
+/// their topics from it.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cluster {
+    pub node_id: u32,
+    pub voters: Vec<String>,
+    /// Defaults to the number of voters, at most 3.
+    pub replication_factor: Option<u16>,
+    pub min_insync_replicas: usize,
+    pub replica_lag_time_max_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    pub level: String,
+    pub format: LogFormat,
+    pub slow_request_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dirs: vec![PathBuf::from("data")],
+            max_frame_size: net::Limits::default().max_frame_size as u64,
+            auto_create_topics: true,
+            shutdown_timeout_ms: 10_000,
+            listeners: Listeners::default(),
+            connections: ConnectionLimits::default(),
+            tls: Tls::default(),
+            sasl: Sasl::default(),
+            retention: Retention::default(),
+            flush: Flush::default(),
+            quotas: Quotas::default(),
+            log: Log::default(),
+            cluster: Cluster::default(),
+        }
+    }
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        let replication = broker::ReplicationConfig::default();
+        Self {
+            node_id: 0,
+            voters: Vec::new(),
+            replication_factor: None,
+            min_insync_replicas: replication.min_insync_replicas,
+            replica_lag_time_max_ms: replication.lag_time_max.as_millis() as u64,
+        }
+    }
+}
+
+impl Default for Listeners {
+    fn default() -> Self {
+        Self {
+            native: "127.0.0.1:9092".to_string(),
+            kafka: "127.0.0.1:9093".to_string(),
+            tls: "127.0.0.1:9094".to_string(),
+            metrics: "127.0.0.1:9095".to_string(),
+        }
+    }
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        let limits = net::Limits::default();
+        Self {
+            max: limits.max_connections,
+            max_per_ip: limits.max_connections_per_ip,
+            idle_timeout_ms: limits.idle_timeout.map_or(0, |t| t.as_millis() as u64),
+            request_timeout_ms: limits.request_timeout.map_or(0, |t| t.as_millis() as u64),
+        }
+    }
+}
+
+impl Default for Flush {
+    fn default() -> Self {
+        Self {
+            interval_messages: 1,
+            interval_ms: None,
+        }
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            slow_request_ms: net::Limits::default()
+                .slow_request
+                .map_or(0, |t| t.as_millis() as u64),
+        }
+    }
+}
+
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+impl Config {
+    /// Reads the config file named by `args`, if any, and applies the overrides,
+    /// then validates the result. All problems found are returned together.
+    pub fn load(args: Args) -> Result<Self, Vec<String>> {
+        let mut config = match &args.config {
+            Some(path) => Self::read(path).map_err(|e| vec![e])?,
+            None => Self::default(),
+        };
+        config.apply(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    fn apply(&mut self, args: Args) {
+        if !args.data_dirs.is_empty() {
+            self.data_dirs = args.data_dirs;
+        }
+        set(&mut self.max_frame_size, args.max_frame_size);
+        set(&mut self.auto_create_topics, args.auto_create_topics);
+        set(&mut self.shutdown_timeout_ms, args.shutdown_timeout_ms);
+        set(&mut self.listeners.native, args.listen);
+        set(&mut self.listeners.kafka, args.kafka_listen);
+        set(&mut self.listeners.tls, args.tls_listen);
+        set(&mut self.listeners.metrics, args.metrics_listen);
+        set_some(&mut self.connections.max, args.max_connections);
+        set_some(
+            &mut self.connections.max_per_ip,
+            args.max_connections_per_ip,
+        );
+        set(&mut self.connections.idle_timeout_ms, args.idle_timeout_ms);
+        set(
+            &mut self.connections.request_timeout_ms,
+            args.request_timeout_ms,
+        );
+        set_some(&mut self.tls.cert, args.tls_cert);
+        set_some(&mut self.tls.key, args.tls_key);
+        set_some(&mut self.tls.client_ca, args.tls_client_ca);
+        set_some(&mut self.sasl.credentials, args.sasl_credentials);
+        set_some(
+            &mut self.sasl.inter_broker_username,
+            args.sasl_inter_broker_username,
+        );
+        set_some(
+            &mut self.sasl.inter_broker_password,
+            args.sasl_inter_broker_password,
+        );
+        set_some(&mut self.retention.bytes, args.retention_bytes);
+        set(
+            &mut self.flush.interval_messages,
+            args.flush_interval_messages,
+        );
+        set_some(&mut self.flush.interval_ms, args.flush_interval_ms);
+        let default = &mut self.quotas.default;
+        set_some(
+            &mut default.produce_bytes_per_sec,
+            args.quota_produce_bytes_per_sec,
+        );
+        set_some(
+            &mut default.fetch_bytes_per_sec,
+            args.quota_fetch_bytes_per_sec,
+        );
+        set_some(&mut default.requests_per_sec, args.quota_requests_per_sec);
+        set(&mut self.log.level, args.log);
+        set(&mut self.log.format, args.log_format);
+        set(&mut self.log.slow_request_ms, args.slow_request_ms);
+        set(&mut self.cluster.node_id, args.node_id);
+        if !args.voters.is_empty() {
+            self.cluster.voters = args.voters;
+        }
+        set_some(
+            &mut self.cluster.replication_factor,
+            args.replication_factor,
+        );
+        set(
+            &mut self.cluster.min_insync_replicas,
+            args.min_insync_replicas,
+        );
+        set(
+            &mut self.cluster.replica_lag_time_max_ms,
+            args.replica_lag_time_max_ms,
+        );
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        let mut addrs = vec![
+            ("listeners.native", &self.listeners.native),
+            ("listeners.kafka", &self.listeners.kafka),
+            ("listeners.metrics", &self.listeners.metrics),
+        ];
+        if self.tls_enabled() {
+            addrs.push(("listeners.tls", &self.listeners.tls));
+        }
+        let mut seen = HashSet::new();
+        for (name, addr) in addrs {
+            match addr.parse::<SocketAddr>() {
+                Ok(a) if !seen.insert(a) => {
+                    errors.push(format!("{name}: {addr} is used by another listener"))
+                }
+                Ok(_) => {}
+                Err(e) => errors.push(format!("{name}: invalid address {addr:?}: {e}")),
+            }
+        }
+
+        if self.data_dirs.is_empty() {
+            errors.push("data_dirs: at least one data directory is required".to_string());
+        }
+        let mut seen = HashSet::new();
+        for dir in &self.data_dirs {
+            if !seen.insert(dir) {
+                errors.push(format!("data_dirs: {} is listed twice", dir.display()));
+            } else if dir.exists() && !dir.is_dir() {
+                errors.push(format!("data_dirs: {} is not a directory", dir.display()));
+            }
+        }
+
+        if self.max_frame_size == 0 || self.max_frame_size > u32::MAX as u64 {
+            errors.push(format!(
+                "max_frame_size: {} is not between 1 and {}",
+                self.max_frame_size,
+                u32::MAX
+            ));
+        }
+        if self.connections.max == Some(0) {
+            errors.push("connections.max: must be positive".to_string());
+        }
+        if self.connections.max_per_ip == Some(0) {
+            errors.push("connections.max_per_ip: must be positive".to_string());
+        }
+        if self.connections.idle_timeout_ms == 0 {
+            errors.push("connections.idle_timeout_ms: must be positive".to_string());
+        }
+        if self.connections.request_timeout_ms == 0 {
+            errors.push("connections.request_timeout_ms: must be positive".to_string());
+        }
+        if self.log.slow_request_ms == 0 {
+            errors.push("log.slow_request_ms: must be positive".to_string());
+        }
+        if self.retention.bytes == Some(0) {
+            errors.push("retention.bytes: must be positive".to_string());
+        }
+        if self.flush.interval_messages == 0 {
+            errors.push("flush.interval_messages: must be positive".to_string());
+        }
+        if self.flush.interval_ms == Some(0) {
+            errors.push("flush.interval_ms: must be positive".to_string());
+        }
+
+        let quotas = std::iter::once(("quotas.default".to_string(), &self.quotas.default))
+            .chain(
+                self.quotas
+                    .users
+                    .iter()
+                    .map(|(user, q)| (format!("quotas.users.{user}"), q)),
+            )
+            .chain(
+                self.quotas
+                    .clients
+                    .iter()
+                    .map(|(client, q)| (format!("quotas.clients.{client}"), q)),
+            );
+        for (name, quota) in quotas {
+            let rates = [
+                ("produce_bytes_per_sec", quota.produce_bytes_per_sec),
+                ("fetch_bytes_per_sec", quota.fetch_bytes_per_sec),
+                ("requests_per_sec", quota.requests_per_sec),
+            ];
+            for (field, rate) in rates {
+                if rate == Some(0) {
+                    errors.push(format!("{name}.{field}: must be positive"));
+                }
+            }
+        }
+
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), None) => errors.push("tls.key: required with tls.cert".to_string()),
+            (None, Some(_)) => errors.push("tls.cert: required with tls.key".to_string()),
+            _ => {}
+        }
+        if self.tls.client_ca.is_some() && !self.tls_enabled() {
+            errors.push("tls.client_ca: requires tls.cert and tls.key".to_string());
+        }
+        match (
+            &self.sasl.inter_broker_username,
+            &self.sasl.inter_broker_password,
+        ) {
+            (Some(_), None) => errors.push(
+                "sasl.inter_broker_password: required with sasl.inter_broker_username".to_string(),
+            ),
+            (None, Some(_)) => errors.push(
+                "sasl.inter_broker_username: required with sasl.inter_broker_password".to_string(),
+            ),
+            _ => {}
+        }
+        let files = [
+            ("tls.cert", &self.tls.cert),
+            ("tls.key", &self.tls.key),
+            ("tls.client_ca", &self.tls.client_ca),
+            ("sasl.credentials", &self.sasl.credentials),
+        ];
+        for (name, path) in files {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                errors.push(format!("{name}: {} is not a file", path.display()));
+            }
+        }
+
+        for directive in self.log.level.split(',') {
+            let level = directive.rsplit_once('=').map_or(directive, |(_, l)| l);
+            if !LOG_LEVELS.contains(&level.trim().to_ascii_lowercase().as_str()) {
+                errors.push(format!(
+                    "log.level: unknown level {level:?}, expected one of {}",
+                    LOG_LEVELS.join(", ")
+                ));
+            }
+        }
+
+        match self.voters() {
+            Ok(voters) => {
+                if !voters.is_empty() && !voters.contains_key(&self.cluster.node_id) {
+                    errors.push(format!(
+                        "cluster.voters: node {} is not one of the voters",
+                        self.cluster.node_id
+                    ));
+                }
+                if let Some(rf) = self.cluster.replication_factor
+                    && (rf == 0 || usize::from(rf) > voters.len().max(1))
+                {
+                    errors.push(format!(
+                        "cluster.replication_factor: {rf} is not between 1 and the number of voters"
+                    ));
+                }
+            }
+            Err(e) => errors.extend(e),
+        }
+        if self.cluster.min_insync_replicas == 0 {
+            errors.push("cluster.min_insync_replicas: must be positive".to_string());
+        }
+        if self.cluster.replica_lag_time_max_ms == 0 {
+            errors.push("cluster.replica_lag_time_max_ms: must be positive".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The controllers by id, parsed from `cluster.voters`.
+    pub fn voters(&self) -> Result<BTreeMap<u32, String>, Vec<String>> {
+        let mut voters = BTreeMap::new();
+        let mut errors = Vec::new();
+        for voter in &self.cluster.voters {
+            let parsed = voter
+                .split_once('@')
+                .and_then(|(id, addr)| Some((id.parse::<u32>().ok()?, addr)));
+            match parsed {
+                Some((id, addr)) if addr.parse::<SocketAddr>().is_ok() => {
+                    if voters.insert(id, addr.to_string()).is_some() {
+                        errors.push(format!("cluster.voters: node {id} is listed twice"));
+                    }
+                }
+                _ => errors.push(format!(
+                    "cluster.voters: invalid voter {voter:?}, expected id@host:port"
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(voters)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Settings of the controller this broker runs, if it is one of the
+    /// voters.
+    pub fn controller_config(&self) -> Option<controller::ControllerConfig> {
+        let voters = self.voters().ok()?;
+        if voters.is_empty() {
+            return None;
+        }
+        let mut config = controller::ControllerConfig::new(
+            self.cluster.node_id,
+            voters,
+            self.data_dirs[0].join("metadata"),
+        );
+        if let Some(rf) = self.cluster.replication_factor {
+            config.replication_factor = rf;
+        }
+        config.login = self.inter_broker_login();
+        Some(config)
+    }
+
+    /// The user to log in to the other brokers as, if one is set.
+    pub fn inter_broker_login(&self) -> Option<broker::Login> {
+        Some(broker::Login {
+            username: self.sasl.inter_broker_username.clone()?,
+            password: self.sasl.inter_broker_password.clone()?,
+        })
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.cert.is_some() && self.tls.key.is_some()
+    }
+
+    pub fn tls_config(&self) -> Option<net::TlsConfig> {
+        Some(net::TlsConfig {
+            cert_path: self.tls.cert.clone()?,
+            key_path: self.tls.key.clone()?,
+            client_ca_path: self.tls.client_ca.clone(),
+        })
+    }
+
+    pub fn broker_config(&self) -> broker::BrokerConfig {
+        broker::BrokerConfig {
+            data_dirs: self.data_dirs.clone(),
+            auto_create_topics: self.auto_create_topics,
+            log: broker::LogConfig {
+                retention_bytes: self.retention.bytes,
+                flush_interval_messages: self.flush.interval_messages,
+                flush_interval: self.flush.interval_ms.map(Duration::from_millis),
+            },
+            quotas: broker::QuotaConfig {
+                default: self.quotas.default.clone().into(),
+                users: quota_map(&self.quotas.users),
+                clients: quota_map(&self.quotas.clients),
+            },
+            broker_id: self.cluster.node_id,
+            peers: self
+                .voters()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(id, _)| *id != self.cluster.node_id)
+                .collect(),
+            inter_broker_login: self.inter_broker_login(),
+            replication: broker::ReplicationConfig {
+                min_insync_replicas: self.cluster.min_insync_replicas,
+                lag_time_max: Duration::from_millis(self.cluster.replica_lag_time_max_ms),
+                ..broker::ReplicationConfig::default()
+            },
+        }
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+
+    pub fn limits(&self) -> net::Limits {
+        net::Limits {
+            max_frame_size: self.max_frame_size as usize,
+            max_connections: self.connections.max,
+            max_connections_per_ip: self.connections.max_per_ip,
+            idle_timeout: Some(Duration::from_millis(self.connections.idle_timeout_ms)),
+            request_timeout: Some(Duration::from_millis(self.connections.request_timeout_ms)),
+            slow_request: Some(Duration::from_millis(self.log.slow_request_ms)),
+        }
+    }
+}
+
+fn quota_map(quotas: &HashMap<String, Quota>) -> HashMap<String, broker::Quota> {
+    quotas
+        .iter()
+        .map(|(name, q)| (name.clone(), q.clone().into()))
+        .collect()
+}
+
+fn set<T>(field: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *field = value;
+    }
+}
+
+fn set_some<T>(field: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *field = value;
+    }
+}
@@ -1,10 +1,123 @@
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
 use broker::Broker;
+use clap::Parser;
+use controller::Controller;
+use tracing_subscriber::EnvFilter;
+
+mod config;
+mod metrics_http;
+
+use config::{Args, Config, LogFormat};
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    std::fs::create_dir_all("data").ok();
-    let broker = Arc::new(Broker::new(PathBuf::from("data")));
-    net::serve("127.0.0.1:9092", broker).await
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("invalid configuration:");
+            for e in errors {
+                eprintln!("  {e}");
+            }
+            std::process::exit(2);
+        }
+    };
+
+    let logs = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(&config.log.level));
+    match config.log.format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().with_span_list(true).init(),
+    }
+
+    for dir in &config.data_dirs {
+        std::fs::create_dir_all(dir)?;
+    }
+    let broker = Arc::new(Broker::with_config(config.broker_config()));
+    if let Some(controller_config) = config.controller_config() {
+        let controller = Controller::open(controller_config).map_err(std::io::Error::other)?;
+        broker
+            .attach_controller(controller.clone())
+            .map_err(std::io::Error::other)?;
+        controller.start();
+    }
+    let conns = net::Connections::new(config.limits());
+    let tls = config.tls_config();
+    let sasl = match &config.sasl.credentials {
+        Some(path) => Some(Arc::new(net::Credentials::load(path)?)),
+        None => None,
+    };
+    let shutdown = net::Shutdown::new();
+
+    let listeners = async {
+        tokio::try_join!(
+            net::serve(
+                &config.listeners.native,
+                broker.clone(),
+                sasl.clone(),
+                conns.clone(),
+                shutdown.clone(),
+            ),
+            net::serve_kafka(
+                &config.listeners.kafka,
+                broker.clone(),
+                sasl.clone(),
+                conns.clone(),
+                shutdown.clone(),
+            ),
+            metrics_http::serve(&config.listeners.metrics),
+            async {
+                match &tls {
+                    Some(tls) => {
+                        net::serve_tls(
+                            &config.listeners.tls,
+                            broker.clone(),
+                            tls,
+                            sasl.clone(),
+                            conns.clone(),
+                            shutdown.clone(),
+                        )
+                        .await
+                    }
+                    None => Ok(()),
+                }
+            },
+        )
+    };
+    tokio::select! {
+        res = listeners => {
+            res?;
+        }
+        res = shutdown_signal() => {
+            res?;
+            tracing::info!("shutting down");
+        }
+    }
+
+    let timeout = config.shutdown_timeout();
+    if !shutdown.drain(timeout).await {
+        tracing::warn!(
+            connections = shutdown.connections(),
+            ?timeout,
+            "connections still open after shutdown timeout, closing them"
+        );
+    }
+    broker.shutdown().await.map_err(std::io::Error::other)?;
+    tracing::info!("shutdown complete");
+    Ok(())
+}
+
+/// Resolves on SIGINT, or SIGTERM on Unix.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
 }
@@ -1,76 +1,612 @@
-use bytes::{Buf, BufMut, BytesMut};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::Write,
+    process::ExitCode,
+    time::Duration,
+};
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let mut sock = TcpStream::connect("127.0.0.1:9092").await?;
+use bytes::Bytes;
+use clap::{Parser, Subcommand, ValueEnum};
+use client::{Admin, Consumer, ConsumerConfig, OffsetReset, Producer, ProducerConfig};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-    // --- Produce request (api_key=1) ---
-    let produce_payload = build_produce("test", 0, vec![("k1", "v1"), ("k2", "v2")]);
-    write_frame(&mut sock, &produce_payload).await?;
-    let resp1 = read_frame(&mut sock).await?;
-    println!("produce resp bytes ={}", hex_preview(&resp1));
+mod output;
 
-    // --- Fetch request (api_key=2) ---
-    let fetch_payload = build_fetch("test", 0, 0, 1024 * 1024);
-    write_frame(&mut sock, &fetch_payload).await?;
-    let resp2 = read_frame(&mut sock).await?;
-    println!("fetch resp bytes len={}", hex_preview(&resp2));
+use output::Output;
 
-    Ok(())
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser, Debug)]
+#[command(name = "mini-kafka-client", about = "mini-kafka command-line client")]
+struct Args {
+    /// Brokers to connect to, tried in order.
+    #[arg(
+        short,
+        long,
+        env = "MINI_KAFKA_BOOTSTRAP",
+        value_delimiter = ',',
+        default_value = "127.0.0.1:9092"
+    )]
+    bootstrap: Vec<String>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Raw)]
+    output: OutputFormat,
+    /// Prints consumed records through a template instead: %t topic,
+    /// %p partition, %o offset, %k key, %K key length, %s value, %S value
+    /// length, %% a percent sign, and \n, \t, \r, \\ escapes.
+    #[arg(short, long, conflicts_with = "output")]
+    format: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Raw,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Produces a record for each line of stdin.
+    Produce {
+        #[arg(short, long)]
+        topic: String,
+        /// Without one, records go to the partition their key hashes to, or
+        /// round robin without keys.
+        #[arg(short, long)]
+        partition: Option<u16>,
+        /// Lines are key<TAB>value. Lines without a tab have no key.
+        #[arg(short, long)]
+        keyed: bool,
+    },
+    /// Prints the records of a topic.
+    Consume {
+        #[arg(short, long)]
+        topic: String,
+        /// Partitions to read, every one by default.
+        #[arg(short, long)]
+        partition: Vec<u16>,
+        /// beginning, end, stored (the group's committed offset), an offset,
+        /// or -N for the last N records. Defaults to stored with a group and
+        /// beginning without. With a group and no partitions, the partitions
+        /// are shared with the group's other members and only beginning or end
+        /// can be given, for partitions without a committed offset.
+        #[arg(short, long, allow_hyphen_values = true, value_parser = parse_offset)]
+        offset: Option<StartOffset>,
+        /// Keeps waiting for new records instead of stopping at the end.
+        #[arg(short = 'F', long)]
+        follow: bool,
+        /// Stops after this many records.
+        #[arg(short, long)]
+        count: Option<usize>,
+        /// Consumer group whose offsets are used and committed.
+        #[arg(short, long)]
+        group: Option<String>,
+    },
+    /// Lists, creates, deletes and describes topics, and moves partitions
+    /// between brokers.
+    Topics {
+        #[command(subcommand)]
+        command: TopicsCommand,
+    },
+    /// Prints the first and next offsets of partitions, and with a group, its
+    /// committed offsets and lag.
+    Offsets {
+        #[arg(short, long)]
+        topic: String,
+        #[arg(short, long)]
+        partition: Vec<u16>,
+        #[arg(short, long)]
+        group: Option<String>,
+    },
+    /// Lists and describes consumer groups.
+    Groups {
+        #[command(subcommand)]
+        command: GroupsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TopicsCommand {
+    List,
+    Create {
+        topic: String,
+        #[arg(short, long, default_value_t = 1)]
+        partitions: u16,
+    },
+    Delete {
+        topic: String,
+    },
+    /// Raises the topic's partition count; it can't be lowered.
+    AddPartitions {
+        topic: String,
+        /// The new partition count.
+        #[arg(short, long)]
+        partitions: u16,
+    },
+    /// Prints the first and next offset of every partition.
+    Describe {
+        topic: String,
+    },
+    /// Moves a partition to other brokers. Must be sent to the controller
+    /// leader; the move completes once the new replicas have caught up.
+    Reassign {
+        topic: String,
+        #[arg(short, long)]
+        partition: u16,
+        /// Broker ids of the new replicas, the preferred leader first.
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        replicas: Vec<u32>,
+        /// Most bytes per second each new replica copies.
+        #[arg(long)]
+        throttle: Option<u64>,
+    },
+    /// Prints the partition moves under way, with how far the new replicas
+    /// have come.
+    Reassignments,
+}
+
+#[derive(Subcommand, Debug)]
+enum GroupsCommand {
+    List,
+    /// Prints the members with their partitions and the committed offsets
+    /// with their lag.
+    Describe {
+        group: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StartOffset {
+    Beginning,
+    End,
+    Stored,
+    At(i64),
+    /// The last N records.
+    FromEnd(i64),
 }
 
-fn build_produce(topic: &str, partition: u16, kvs: Vec<(&str, &str)>) -> bytes::Bytes {
-    let mut out = BytesMut::with_capacity(256);
-    common::write_api_key(&mut out, 1);
-    common::write_topic(&mut out, topic);
-    common::write_partition(&mut out, partition);
-    common::write_record_count(&mut out, kvs.len() as u16);
-    for (k, v) in kvs {
-        common::write_record(&mut out, k, v);
+fn parse_offset(s: &str) -> std::result::Result<StartOffset, String> {
+    match s {
+        "beginning" => Ok(StartOffset::Beginning),
+        "end" => Ok(StartOffset::End),
+        "stored" => Ok(StartOffset::Stored),
+        _ => match s.parse::<i64>() {
+            Ok(n) if n < 0 => Ok(StartOffset::FromEnd(-n)),
+            Ok(n) => Ok(StartOffset::At(n)),
+            Err(_) => Err(format!(
+                "expected beginning, end, stored, an offset or -N, got {s:?}"
+            )),
+        },
     }
-    out.freeze()
 }
 
-fn build_fetch(topic: &str, partition: u16, offset: i64, max_bytes: u32) -> bytes::Bytes {
-    let mut out = BytesMut::with_capacity(64);
-    common::write_api_key(&mut out, 2);
-    common::write_topic(&mut out, topic);
-    common::write_partition(&mut out, partition);
-    common::write_offset(&mut out, offset);
-    common::write_max_bytes(&mut out, max_bytes);
-    out.freeze()
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let output = match (&args.format, args.output) {
+        (Some(template), _) => match output::parse_template(template) {
+            Ok(pieces) => Output::Template(pieces),
+            Err(e) => {
+                eprintln!("invalid format: {e}");
+                return ExitCode::from(2);
+            }
+        },
+        (None, OutputFormat::Raw) => Output::Raw,
+        (None, OutputFormat::Json) => Output::Json,
+    };
+
+    match run(args.bootstrap, output, args.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-async fn write_frame(sock: &mut TcpStream, payload: &bytes::Bytes) -> std::io::Result<()> {
-    let mut frame = BytesMut::with_capacity(4 + payload.len());
-    frame.put_u32(payload.len() as u32);
-    frame.put_slice(payload);
-    sock.write_all(&frame).await
+async fn run(bootstrap: Vec<String>, output: Output, command: Command) -> Result<()> {
+    let admin = Admin::new(bootstrap.clone());
+    let mut out = std::io::stdout().lock();
+    match command {
+        Command::Produce {
+            topic,
+            partition,
+            keyed,
+        } => produce(bootstrap, &topic, partition, keyed).await,
+        Command::Consume {
+            topic,
+            partition,
+            offset,
+            follow,
+            count,
+            group,
+        } => {
+            let opts = ConsumeOptions {
+                topic,
+                partitions: partition,
+                offset,
+                follow,
+                count,
+                group,
+            };
+            consume(bootstrap, &admin, &output, &mut out, opts).await
+        }
+        Command::Topics { command } => match command {
+            TopicsCommand::List => {
+                for (topic, partitions) in admin.topics().await? {
+                    output.row(
+                        &mut out,
+                        &[
+                            ("topic", json!(topic)),
+                            ("partitions", json!(partitions.len())),
+                        ],
+                    )?;
+                }
+                Ok(())
+            }
+            TopicsCommand::Create { topic, partitions } => {
+                Ok(admin.create_topic(&topic, partitions).await?)
+            }
+            TopicsCommand::Delete { topic } => Ok(admin.delete_topic(&topic).await?),
+            TopicsCommand::AddPartitions { topic, partitions } => {
+                Ok(admin.create_partitions(&topic, partitions).await?)
+            }
+            TopicsCommand::Describe { topic } => {
+                offsets(&admin, &output, &mut out, &topic, vec![], None).await
+            }
+            TopicsCommand::Reassign {
+                topic,
+                partition,
+                replicas,
+                throttle,
+            } => Ok(admin
+                .reassign_partition(&topic, partition, replicas, throttle)
+                .await?),
+            TopicsCommand::Reassignments => {
+                for r in admin.reassignments().await? {
+                    let offsets: serde_json::Map<String, Value> = r
+                        .replica_offsets
+                        .iter()
+                        .map(|(id, offset)| (id.to_string(), json!(offset)))
+                        .collect();
+                    output.row(
+                        &mut out,
+                        &[
+                            ("topic", json!(r.topic)),
+                            ("partition", json!(r.partition)),
+                            ("replicas", json!(r.replicas)),
+                            ("adding", json!(r.adding)),
+                            ("removing", json!(r.removing)),
+                            ("log_end_offset", json!(r.log_end_offset)),
+                            ("replica_offsets", Value::Object(offsets)),
+                        ],
+                    )?;
+                }
+                Ok(())
+            }
+        },
+        Command::Offsets {
+            topic,
+            partition,
+            group,
+        } => {
+            offsets(
+                &admin,
+                &output,
+                &mut out,
+                &topic,
+                partition,
+                group.as_deref(),
+            )
+            .await
+        }
+        Command::Groups { command } => match command {
+            GroupsCommand::List => {
+                for g in admin.groups().await? {
+                    output.row(
+                        &mut out,
+                        &[
+                            ("group", json!(g.group_id)),
+                            ("state", json!(g.state)),
+                            ("protocol_type", json!(g.protocol_type)),
+                        ],
+                    )?;
+                }
+                Ok(())
+            }
+            GroupsCommand::Describe { group } => {
+                describe_group(&admin, &output, &mut out, &group).await
+            }
+        },
+    }
 }
 
-async fn read_frame(sock: &mut TcpStream) -> std::io::Result<bytes::Bytes> {
-    let mut len_buf = [0u8; 4];
-    sock.read_exact(&mut len_buf).await?;
-    let mut cur = std::io::Cursor::new(len_buf);
-    let len = cur.get_u32() as usize;
+async fn produce(
+    bootstrap: Vec<String>,
+    topic: &str,
+    partition: Option<u16>,
+    keyed: bool,
+) -> Result<()> {
+    let producer = Producer::new(ProducerConfig {
+        bootstrap,
+        ..ProducerConfig::new("")
+    });
+    let mut lines = BufReader::new(tokio::io::stdin()).split(b'\n');
+    // bounds memory while keeping plenty of records batched
+    let mut pending = VecDeque::new();
+    while let Some(line) = lines.next_segment().await? {
+        let line = Bytes::from(line);
+        let (key, value) = match line.iter().position(|&b| b == b'\t') {
+            Some(tab) if keyed => (line.slice(..tab), line.slice(tab + 1..)),
+            _ => (Bytes::new(), line),
+        };
+        pending.push_back(match partition {
+            Some(p) => producer.send_to(topic, p, key, value),
+            None => producer.send(topic, key, value),
+        });
+        if pending.len() >= 10_000
+            && let Some(delivery) = pending.pop_front()
+        {
+            delivery.await?;
+        }
+    }
+    producer.flush().await;
+    for delivery in pending {
+        delivery.await?;
+    }
+    Ok(())
+}
 
-    let mut payload = vec![0u8; len];
-    sock.read_exact(&mut payload).await?;
-    Ok(payload.into())
+struct ConsumeOptions {
+    topic: String,
+    partitions: Vec<u16>,
+    offset: Option<StartOffset>,
+    follow: bool,
+    count: Option<usize>,
+    group: Option<String>,
 }
 
-fn hex_preview(b: &bytes::Bytes) -> String {
-    let n = b.len().min(64);
-    let mut s = String::new();
-    for byte in &b[..n] {
-        s.push_str(&format!("{:02x} ", byte));
+async fn consume(
+    bootstrap: Vec<String>,
+    admin: &Admin,
+    output: &Output,
+    out: &mut impl Write,
+    opts: ConsumeOptions,
+) -> Result<()> {
+    let subscribe = opts.group.is_some() && opts.partitions.is_empty();
+    let offset = opts.offset.unwrap_or(if opts.group.is_some() {
+        StartOffset::Stored
+    } else {
+        StartOffset::Beginning
+    });
+    let auto_offset_reset = match offset {
+        StartOffset::End => OffsetReset::Latest,
+        _ => OffsetReset::Earliest,
+    };
+    let mut consumer = Consumer::new(ConsumerConfig {
+        bootstrap,
+        group_id: opts.group.clone(),
+        auto_offset_reset,
+        ..ConsumerConfig::new("")
+    });
+
+    if subscribe {
+        if matches!(offset, StartOffset::At(_) | StartOffset::FromEnd(_)) {
+            return Err(
+                "an offset can only be given with --partition when consuming as a group".into(),
+            );
+        }
+        consumer.subscribe(&[&opts.topic]);
+    } else {
+        let partitions = if opts.partitions.is_empty() {
+            admin.partitions(&opts.topic).await?
+        } else {
+            opts.partitions.clone()
+        };
+        if partitions.is_empty() {
+            return Err(format!("topic {} has no partitions", opts.topic).into());
+        }
+        let assigned: Vec<_> = partitions
+            .iter()
+            .map(|&p| (opts.topic.as_str(), p))
+            .collect();
+        consumer.assign(&assigned);
+        for &p in &partitions {
+            match offset {
+                StartOffset::Beginning => consumer.seek_to_beginning(&opts.topic, p)?,
+                StartOffset::End => consumer.seek_to_end(&opts.topic, p)?,
+                StartOffset::Stored => {}
+                StartOffset::At(o) => consumer.seek(&opts.topic, p, o)?,
+                StartOffset::FromEnd(n) => {
+                    let (start, end) = admin.log_offsets(&opts.topic, p).await?;
+                    consumer.seek(&opts.topic, p, (end - n).max(start))?;
+                }
+            }
+        }
     }
 
-    if b.len() > n {
-        s.push_str("...");
+    // where "the end" is for each partition, taken when it is first assigned
+    let mut ends: HashMap<(String, u16), i64> = HashMap::new();
+    let mut consumed = 0;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    'poll: loop {
+        if !opts.follow && caught_up(admin, &consumer, &mut ends).await? {
+            break;
+        }
+        let records = tokio::select! {
+            records = consumer.poll(Duration::from_millis(500)) => records?,
+            _ = &mut ctrl_c => break,
+        };
+        for (i, record) in records.iter().enumerate() {
+            if opts.count.is_some_and(|count| consumed >= count) {
+                // so the group resumes from the first record not printed
+                let mut skipped = BTreeMap::new();
+                for r in &records[i..] {
+                    skipped.entry(r.partition).or_insert(r.offset);
+                }
+                for (p, offset) in skipped {
+                    consumer.seek(&opts.topic, p, offset)?;
+                }
+                break 'poll;
+            }
+            output.record(out, record)?;
+            consumed += 1;
+        }
+        out.flush()?;
+        if opts.count.is_some_and(|count| consumed >= count) {
+            break;
+        }
+    }
+    out.flush()?;
+    consumer.close().await?;
+    Ok(())
+}
+
+/// Whether every assigned partition was read up to where it ended when it
+/// was first assigned.
+async fn caught_up(
+    admin: &Admin,
+    consumer: &Consumer,
+    ends: &mut HashMap<(String, u16), i64>,
+) -> Result<bool> {
+    let assignment = consumer.assignment();
+    if assignment.is_empty() {
+        return Ok(false);
+    }
+    for (topic, partition) in assignment {
+        let Some(position) = consumer.position(&topic, partition) else {
+            return Ok(false);
+        };
+        let end = match ends.get(&(topic.clone(), partition)) {
+            Some(&end) => end,
+            None => {
+                let (_, end) = admin.log_offsets(&topic, partition).await?;
+                ends.insert((topic, partition), end);
+                end
+            }
+        };
+        if position < end {
+            return Ok(false);
+        }
     }
+    Ok(true)
+}
 
-    s
+async fn offsets(
+    admin: &Admin,
+    output: &Output,
+    out: &mut impl Write,
+    topic: &str,
+    partitions: Vec<u16>,
+    group: Option<&str>,
+) -> Result<()> {
+    let partitions = if partitions.is_empty() {
+        admin.partitions(topic).await?
+    } else {
+        partitions
+    };
+    for partition in partitions {
+        let (start, end) = admin.log_offsets(topic, partition).await?;
+        let mut row = vec![
+            ("topic", json!(topic)),
+            ("partition", json!(partition)),
+            ("start_offset", json!(start)),
+            ("end_offset", json!(end)),
+        ];
+        if let Some(group) = group {
+            let committed = admin.committed(group, topic, partition).await?;
+            row.push(("committed", json!(committed)));
+            row.push(("lag", json!(committed.map(|c| (end - c).max(0)))));
+        }
+        output.row(out, &row)?;
+    }
+    Ok(())
+}
+
+async fn describe_group(
+    admin: &Admin,
+    output: &Output,
+    out: &mut impl Write,
+    group: &str,
+) -> Result<()> {
+    let g = admin.describe_group(group).await?;
+    let summary = [
+        ("group", json!(g.group_id)),
+        ("state", json!(g.state)),
+        ("protocol_type", json!(g.protocol_type)),
+        ("protocol", json!(g.protocol_name)),
+        ("generation", json!(g.generation_id)),
+        ("leader", json!(g.leader)),
+    ];
+    let members: Vec<[(&str, Value); 3]> = g
+        .members
+        .iter()
+        .map(|m| {
+            let partitions: Vec<String> = m
+                .partitions
+                .iter()
+                .map(|(t, p)| format!("{t}/{p}"))
+                .collect();
+            [
+                ("type", json!("member")),
+                ("member_id", json!(m.member_id)),
+                ("partitions", json!(partitions.join(","))),
+            ]
+        })
+        .collect();
+    let mut offsets = Vec::with_capacity(g.offsets.len());
+    for (topic, partition, committed) in &g.offsets {
+        // the topic may have been deleted since
+        let end = admin
+            .log_offsets(topic, *partition)
+            .await
+            .ok()
+            .map(|(_, end)| end);
+        offsets.push([
+            ("type", json!("offset")),
+            ("topic", json!(topic)),
+            ("partition", json!(partition)),
+            ("committed", json!(committed)),
+            ("end_offset", json!(end)),
+            ("lag", json!(end.map(|end| (end - committed).max(0)))),
+        ]);
+    }
+
+    if let Output::Json = output {
+        // one object, with members and offsets nested
+        let nested = |rows: Vec<_>| -> Value {
+            rows.into_iter()
+                .map(|row: Vec<(&str, Value)>| {
+                    row.into_iter()
+                        .filter(|(name, _)| *name != "type")
+                        .map(|(name, value)| (name.to_string(), value))
+                        .collect::<serde_json::Map<_, _>>()
+                        .into()
+                })
+                .collect::<Vec<Value>>()
+                .into()
+        };
+        let mut row = summary.to_vec();
+        row.push((
+            "members",
+            nested(members.into_iter().map(Vec::from).collect()),
+        ));
+        row.push((
+            "offsets",
+            nested(offsets.into_iter().map(Vec::from).collect()),
+        ));
+        return Ok(output.row(out, &row)?);
+    }
+
+    output.row(out, &summary)?;
+    for row in members {
+        output.row(out, &row)?;
+    }
+    for row in offsets {
+        output.row(out, &row)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,145 @@
+use std::io::{self, Write};
+
+use client::ConsumerRecord;
+use serde_json::{Value, json};
+
+/// How records and listings are printed.
+#[derive(Debug)]
+pub enum Output {
+    /// Record values, one per line; listings as tab-separated columns.
+    Raw,
+    /// One JSON object per line.
+    Json,
+    /// Records through a template; listings as with `Raw`.
+    Template(Vec<Piece>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Piece {
+    Text(Vec<u8>),
+    Topic,
+    Partition,
+    Offset,
+    Key,
+    KeyLen,
+    Value,
+    ValueLen,
+}
+
+/// Parses a kcat-style template: `%t` topic, `%p` partition, `%o` offset,
+/// `%k` key, `%K` key length, `%s` value, `%S` value length, `%%` a percent
+/// sign, and the escapes `\n`, `\t`, `\r` and `\\`.
+pub fn parse_template(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = Vec::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        let piece = match c {
+            '%' => match chars.next() {
+                Some('t') => Piece::Topic,
+                Some('p') => Piece::Partition,
+                Some('o') => Piece::Offset,
+                Some('k') => Piece::Key,
+                Some('K') => Piece::KeyLen,
+                Some('s') => Piece::Value,
+                Some('S') => Piece::ValueLen,
+                Some('%') => {
+                    text.push(b'%');
+                    continue;
+                }
+                Some(c) => return Err(format!("unknown template token %{c}")),
+                None => return Err("template ends in %".to_string()),
+            },
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('\\') => '\\',
+                    Some(c) => return Err(format!("unknown template escape \\{c}")),
+                    None => return Err("template ends in \\".to_string()),
+                };
+                text.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+            c => {
+                text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(piece);
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+impl Output {
+    pub fn record(&self, out: &mut impl Write, r: &ConsumerRecord) -> io::Result<()> {
+        match self {
+            Output::Raw => {
+                out.write_all(&r.value)?;
+                out.write_all(b"\n")
+            }
+            Output::Json => {
+                let key = if r.key.is_empty() {
+                    Value::Null
+                } else {
+                    Value::from(String::from_utf8_lossy(&r.key))
+                };
+                let line = json!({
+                    "topic": r.topic,
+                    "partition": r.partition,
+                    "offset": r.offset,
+                    "key": key,
+                    "value": String::from_utf8_lossy(&r.value),
+                });
+                writeln!(out, "{line}")
+            }
+            Output::Template(pieces) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(text) => out.write_all(text)?,
+                        Piece::Topic => out.write_all(r.topic.as_bytes())?,
+                        Piece::Partition => write!(out, "{}", r.partition)?,
+                        Piece::Offset => write!(out, "{}", r.offset)?,
+                        Piece::Key => out.write_all(&r.key)?,
+                        Piece::KeyLen => write!(out, "{}", r.key.len())?,
+                        Piece::Value => out.write_all(&r.value)?,
+                        Piece::ValueLen => write!(out, "{}", r.value.len())?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Prints one row of a listing. Missing values are `null` in JSON and `-`
+    /// otherwise.
+    pub fn row(&self, out: &mut impl Write, fields: &[(&str, Value)]) -> io::Result<()> {
+        match self {
+            Output::Json => {
+                let object: serde_json::Map<String, Value> = fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect();
+                writeln!(out, "{}", Value::Object(object))
+            }
+            Output::Raw | Output::Template(_) => {
+                let columns: Vec<String> = fields
+                    .iter()
+                    .map(|(_, value)| match value {
+                        Value::Null => "-".to_string(),
+                        Value::String(s) => s.clone(),
+                        v => v.to_string(),
+                    })
+                    .collect();
+                writeln!(out, "{}", columns.join("\t"))
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeSet,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Parser;
+use storage::{LeaderEpochCache, LogEntry, LogFile, PartitionLog};
+
+/// Prints the records of partition logs and checks that they can be read,
+/// without a broker. A partition is a single `{topic}-{partition}.log` file
+/// with a `{topic}-{partition}.epochs` file of leader epochs next to it. Its
+/// offset index is built in memory whenever it is opened, so there are no
+/// segments or index files, and records have no timestamps or checksums to
+/// print.
+#[derive(Parser, Debug)]
+#[command(name = "mini-kafka-dump-log")]
+struct Args {
+    /// Log files, `{topic}-{partition}.log` in a broker's data dir, or their
+    /// `.epochs` files.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Prints the keys and values of the records as well.
+    #[arg(short = 'd', long)]
+    print_data: bool,
+    /// Only prints whether each log is intact.
+    #[arg(short, long, conflicts_with = "print_data")]
+    quiet: bool,
+    /// Cuts corrupted logs off after their last valid record. Only run this
+    /// while the broker is stopped.
+    #[arg(long)]
+    truncate_after_last_valid: bool,
+    /// Rebuilds the offset index from the readable records and reports where
+    /// it differs from the one a broker builds when it opens the log.
+    #[arg(long)]
+    rebuild_index: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut out = io::stdout().lock();
+    let mut failed = false;
+    for path in &args.files {
+        match dump(&args, path, &mut out) {
+            Ok(intact) => failed |= !intact,
+            Err(e) => {
+                let _ = out.flush();
+                eprintln!("error: {}: {e}", path.display());
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns whether the log is intact, or was made so.
+fn dump(args: &Args, path: &Path, out: &mut impl Write) -> io::Result<bool> {
+    if path.extension().is_some_and(|ext| ext == "epochs") {
+        return dump_epochs(path, out);
+    }
+    let log = LogFile::read(path).map_err(io::Error::other)?;
+    writeln!(out, "Dumping {}", path.display())?;
+    if !args.quiet {
+        for entry in &log.entries {
+            print_entry(out, entry, args.print_data)?;
+        }
+    }
+
+    match (log.entries.first(), log.entries.last()) {
+        (Some(first), Some(last)) => writeln!(
+            out,
+            "{} records, offsets {}..={}, {} bytes",
+            log.entries.len(),
+            first.offset,
+            last.offset,
+            log.valid_len()
+        )?,
+        _ => writeln!(out, "no records")?,
+    }
+    let index_matches = !args.rebuild_index || check_index(&log, path, out)?;
+    let Some(corruption) = log.corruption else {
+        if !index_matches {
+            return Ok(false);
+        }
+        writeln!(out, "intact")?;
+        return Ok(true);
+    };
+    writeln!(
+        out,
+        "corrupted: {corruption}, {} bytes after the last valid record",
+        log.len - log.valid_len()
+    )?;
+    if !args.truncate_after_last_valid {
+        return Ok(false);
+    }
+
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(log.valid_len())?;
+    file.sync_all()?;
+    writeln!(out, "truncated to {} bytes", log.valid_len())?;
+    Ok(true)
+}
+
+fn print_entry(out: &mut impl Write, entry: &LogEntry, print_data: bool) -> io::Result<()> {
+    write!(
+        out,
+        "offset: {} position: {} size: {} key_size: {} value_size: {}",
+        entry.offset,
+        entry.position,
+        entry.size(),
+        entry.key.len(),
+        entry.value.len()
+    )?;
+    if print_data {
+        write!(
+            out,
+            " key: {} value: {}",
+            String::from_utf8_lossy(&entry.key),
+            String::from_utf8_lossy(&entry.value)
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Rebuilds the offset index and compares it with the broker's, returning
+/// whether they agree.
+fn check_index(log: &LogFile, path: &Path, out: &mut impl Write) -> io::Result<bool> {
+    let rebuilt = log.index();
+    let broker = PartitionLog::scan_index(path).map_err(io::Error::other)?;
+    let offsets: BTreeSet<i64> = rebuilt.keys().chain(broker.keys()).copied().collect();
+
+    let mut mismatches = 0;
+    for offset in offsets {
+        match (rebuilt.get(&offset), broker.get(&offset)) {
+            (Some(expected), Some(found)) if expected == found => continue,
+            (Some(expected), Some(found)) => writeln!(
+                out,
+                "index mismatch: offset {offset} is at byte {expected}, the broker reads byte {found}"
+            )?,
+            (Some(expected), None) => writeln!(
+                out,
+                "index mismatch: offset {offset} at byte {expected} is missing from the broker's index"
+            )?,
+            (None, Some(found)) => writeln!(
+                out,
+                "index mismatch: the broker reads offset {offset} from byte {found}, after the last valid record"
+            )?,
+            (None, None) => unreachable!("offsets come from either index"),
+        }
+        mismatches += 1;
+    }
+    writeln!(
+        out,
+        "rebuilt index of {} offsets, {mismatches} mismatches",
+        rebuilt.len()
+    )?;
+    Ok(mismatches == 0)
+}
+
+/// Prints the leader epochs of a partition, returning whether the file could
+/// be read.
+fn dump_epochs(path: &Path, out: &mut impl Write) -> io::Result<bool> {
+    // a missing file opens as no epochs
+    std::fs::metadata(path)?;
+    writeln!(out, "Dumping {}", path.display())?;
+    let epochs = match LeaderEpochCache::open(path) {
+        Ok(epochs) => epochs,
+        Err(storage::StorageError::Corrupted) => {
+            writeln!(out, "corrupted: not a leader epoch file")?;
+            return Ok(false);
+        }
+        Err(e) => return Err(io::Error::other(e)),
+    };
+    for (epoch, start_offset) in epochs.entries() {
+        writeln!(out, "epoch: {epoch} start_offset: {start_offset}")?;
+    }
+    writeln!(out, "{} epochs", epochs.entries().len())?;
+    Ok(true)
+}
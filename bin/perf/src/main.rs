@@ -0,0 +1,476 @@
+use std::{
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use clap::Parser;
+use client::Client;
+use net::{Connections, Shutdown};
+use protocol::{
+    status,
+    types::{
+        ACKS_ALL, ACKS_LEADER, CreateTopicRequest, FetchRequest, LATEST_TIMESTAMP,
+        ListOffsetsRequest, ProduceRequest, Record, Request, Response,
+    },
+};
+use serde_json::json;
+use tokio::{net::TcpListener, task::JoinSet};
+
+mod stats;
+
+use stats::Summary;
+
+/// Produce requests a producer has sent without an answer with acks=0.
+const MAX_UNACKED: usize = 64;
+/// How long a consumer waits for records that were produced but never show up.
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs producers and consumers against a broker and reports their throughput
+/// and latency.
+#[derive(Parser, Debug)]
+#[command(name = "mini-kafka-perf")]
+struct Args {
+    /// Broker to run against. Without one, a broker is started in this
+    /// process on a temporary data dir and reached over a loopback connection.
+    #[arg(short, long, conflicts_with = "in_process")]
+    bootstrap: Option<String>,
+    /// Calls the broker started in this process directly, without a
+    /// connection.
+    #[arg(long)]
+    in_process: bool,
+    /// Data dir of the broker started in this process. A temporary one is
+    /// removed afterwards.
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Defaults to a new topic for every run.
+    #[arg(short, long)]
+    topic: Option<String>,
+    #[arg(short, long, default_value_t = 1)]
+    partitions: u16,
+    #[arg(long, default_value_t = 1)]
+    producers: usize,
+    #[arg(long, default_value_t = 1)]
+    consumers: usize,
+    /// Records produced by all producers together.
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    records: u64,
+    /// Value bytes of each record, at least 8 for the send time.
+    #[arg(short = 's', long, default_value_t = 100)]
+    record_size: usize,
+    /// Records in each produce request.
+    #[arg(long, default_value_t = 100)]
+    batch_size: usize,
+    /// 1 or all wait for each produce request to be answered, by the leader
+    /// or by every in-sync replica, before sending the next one. 0 keeps
+    /// sending, so there is no produce latency; the native protocol still
+    /// answers every request and the run waits for the answers before
+    /// stopping the clock.
+    #[arg(long, default_value = "1", value_parser = parse_acks)]
+    acks: i16,
+    #[arg(long, default_value_t = 1024 * 1024)]
+    fetch_max_bytes: u32,
+    /// Prints the results as one JSON object.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Where requests go: the broker itself, or a connection to one.
+#[derive(Clone)]
+enum Target {
+    InProcess(Arc<Broker>),
+    Tcp(Arc<Client>),
+}
+
+impl Target {
+    async fn request(&self, req: Request) -> Result<Response, String> {
+        match self {
+            Target::InProcess(broker) => Ok(broker.handle(req).await),
+            Target::Tcp(client) => client.request(&req).await.map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Waits a little for records to be appended.
+    async fn wait_for_records(&self) {
+        match self {
+            Target::InProcess(broker) => {
+                let _ =
+                    tokio::time::timeout(Duration::from_millis(10), broker.appended().notified())
+                        .await;
+            }
+            Target::Tcp(_) => tokio::time::sleep(Duration::from_millis(1)).await,
+        }
+    }
+}
+
+/// How to get a `Target` for each producer and consumer, so that over TCP
+/// each of them has a connection of its own.
+enum Targets {
+    InProcess(Arc<Broker>),
+    Tcp(String),
+}
+
+impl Targets {
+    fn get(&self) -> Target {
+        match self {
+            Targets::InProcess(broker) => Target::InProcess(broker.clone()),
+            Targets::Tcp(addr) => {
+                Target::Tcp(Arc::new(Client::new(vec![addr.clone()], MAX_UNACKED)))
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.record_size < 8 {
+        eprintln!("--record-size must be at least 8");
+        return ExitCode::from(2);
+    }
+    if args.partitions == 0 || args.producers == 0 || args.batch_size == 0 {
+        eprintln!("--partitions, --producers and --batch-size must be at least 1");
+        return ExitCode::from(2);
+    }
+
+    // only a broker started here is ours to stop and clean up
+    let (targets, embedded) = match &args.bootstrap {
+        Some(addr) => (Targets::Tcp(addr.clone()), None),
+        None => {
+            let temporary = args.data_dir.is_none();
+            let dir = args.data_dir.clone().unwrap_or_else(|| {
+                std::env::temp_dir().join(format!("mini-kafka-perf-{}", std::process::id()))
+            });
+            let broker = Arc::new(Broker::new(dir.clone()));
+            let targets = if args.in_process {
+                Targets::InProcess(broker.clone())
+            } else {
+                let listener = match TcpListener::bind("127.0.0.1:0").await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        eprintln!("error: {e}");
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let addr = listener.local_addr().unwrap().to_string();
+                tokio::spawn(net::serve_listener(
+                    listener,
+                    broker.clone(),
+                    None,
+                    Connections::default(),
+                    Shutdown::new(),
+                ));
+                Targets::Tcp(addr)
+            };
+            (targets, Some((broker, dir, temporary)))
+        }
+    };
+
+    let result = run(&args, &targets).await;
+
+    if let Some((broker, dir, temporary)) = embedded {
+        if let Err(e) = broker.shutdown().await {
+            eprintln!("error: {e}");
+        }
+        if temporary {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: &Args, targets: &Targets) -> Result<(), String> {
+    let topic = args.topic.clone().unwrap_or_else(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        format!("perf-{nanos}")
+    });
+    let admin = targets.get();
+    let req = Request::CreateTopic(CreateTopicRequest {
+        topic: topic.clone(),
+        partitions: args.partitions,
+    });
+    match admin.request(req).await? {
+        Response::CreateTopic(r) if r.status == status::OK => {}
+        Response::CreateTopic(r) if r.status == status::TOPIC_ALREADY_EXISTS => {}
+        Response::CreateTopic(r) => {
+            return Err(format!(
+                "creating {topic}: {}",
+                status::name(r.status).to_lowercase()
+            ));
+        }
+        other => return Err(format!("unexpected response {other:?}")),
+    }
+
+    let plan = plan(args);
+    let mut expected = vec![0u64; args.partitions as usize];
+    for batches in &plan {
+        for &(partition, n) in batches {
+            expected[partition as usize] += n as u64;
+        }
+    }
+
+    // consumers start where the partitions end now, in case the topic was
+    // there already
+    let mut consumers = JoinSet::new();
+    let start = Instant::now();
+    for c in 0..args.consumers.min(args.partitions as usize) {
+        let mut partitions = Vec::new();
+        for p in (c..args.partitions as usize).step_by(args.consumers) {
+            let offset = end_offset(&admin, &topic, p as u16).await?;
+            partitions.push((p as u16, offset, expected[p]));
+        }
+        consumers.spawn(consume(
+            targets.get(),
+            topic.clone(),
+            partitions,
+            args.fetch_max_bytes,
+            start,
+        ));
+    }
+
+    let mut producers = JoinSet::new();
+    for batches in plan {
+        producers.spawn(produce(
+            targets.get(),
+            topic.clone(),
+            batches,
+            args.record_size,
+            args.acks,
+            start,
+        ));
+    }
+
+    let mut latencies = Vec::new();
+    while let Some(joined) = producers.join_next().await {
+        latencies.extend(joined.map_err(|e| e.to_string())??);
+    }
+    let bytes = args.records * args.record_size as u64;
+    let produced = Summary::new(args.records, bytes, start.elapsed(), latencies);
+
+    let consumed = if consumers.is_empty() {
+        None
+    } else {
+        let mut records = 0;
+        let mut latencies = Vec::new();
+        while let Some(joined) = consumers.join_next().await {
+            let (n, l) = joined.map_err(|e| e.to_string())??;
+            records += n;
+            latencies.extend(l);
+        }
+        let bytes = records * args.record_size as u64;
+        Some(Summary::new(records, bytes, start.elapsed(), latencies))
+    };
+
+    if args.json {
+        let report = json!({
+            "config": {
+                "topic": topic,
+                "target": match targets {
+                    Targets::InProcess(_) => "in-process".to_string(),
+                    Targets::Tcp(addr) => addr.clone(),
+                },
+                "partitions": args.partitions,
+                "producers": args.producers,
+                "consumers": args.consumers,
+                "records": args.records,
+                "record_size": args.record_size,
+                "batch_size": args.batch_size,
+                "acks": args.acks,
+            },
+            "produce": produced.to_json(),
+            "consume": consumed.as_ref().map(Summary::to_json),
+        });
+        println!("{report}");
+    } else {
+        println!("produce  {produced}");
+        if let Some(consumed) = consumed {
+            println!("consume  {consumed} (end to end)");
+        }
+    }
+    Ok(())
+}
+
+fn parse_acks(s: &str) -> Result<i16, String> {
+    match s {
+        "0" => Ok(0),
+        "1" => Ok(ACKS_LEADER),
+        "all" | "-1" => Ok(ACKS_ALL),
+        _ => Err(format!("expected 0, 1 or all, got {s:?}")),
+    }
+}
+
+/// The batches of each producer as (partition, records). Producers share the
+/// records evenly, and each sends its batches round robin over the
+/// partitions, starting at a partition of its own.
+fn plan(args: &Args) -> Vec<Vec<(u16, usize)>> {
+    let producers = args.producers as u64;
+    (0..producers)
+        .map(|k| {
+            let mut left = args.records / producers + u64::from(k < args.records % producers);
+            let mut batches = Vec::new();
+            let mut partition = (k % args.partitions as u64) as u16;
+            while left > 0 {
+                let n = left.min(args.batch_size as u64);
+                batches.push((partition, n as usize));
+                left -= n;
+                partition = (partition + 1) % args.partitions;
+            }
+            batches
+        })
+        .collect()
+}
+
+async fn end_offset(target: &Target, topic: &str, partition: u16) -> Result<i64, String> {
+    let req = Request::ListOffsets(ListOffsetsRequest {
+        topic: topic.to_string(),
+        partition,
+        timestamp: LATEST_TIMESTAMP,
+    });
+    match target.request(req).await? {
+        Response::ListOffsets(r) if r.status == status::OK => Ok(r.offset),
+        // partitions are created by their first record
+        Response::ListOffsets(r) if r.status == status::UNKNOWN_TOPIC_OR_PARTITION => Ok(0),
+        Response::ListOffsets(r) => Err(format!(
+            "listing offsets of {topic}-{partition}: {}",
+            status::name(r.status).to_lowercase()
+        )),
+        other => Err(format!("unexpected response {other:?}")),
+    }
+}
+
+fn check_produced(resp: Result<Response, String>) -> Result<(), String> {
+    match resp? {
+        Response::Produce(r) if r.status == status::OK => Ok(()),
+        Response::Produce(r) => Err(format!(
+            "producing: {}",
+            status::name(r.status).to_lowercase()
+        )),
+        other => Err(format!("unexpected response {other:?}")),
+    }
+}
+
+/// Sends the batches and returns how long each took to be answered, or
+/// nothing with acks=0. Values start with the time they were sent since
+/// `start`, for consumers to measure end-to-end latency.
+async fn produce(
+    target: Target,
+    topic: String,
+    batches: Vec<(u16, usize)>,
+    record_size: usize,
+    acks: i16,
+    start: Instant,
+) -> Result<Vec<Duration>, String> {
+    let padding = vec![b'x'; record_size - 8];
+    let mut latencies = Vec::with_capacity(batches.len());
+    let mut unacked = JoinSet::new();
+    for (partition, n) in batches {
+        let sent = Instant::now();
+        let mut value = BytesMut::with_capacity(record_size);
+        value.put_u64((sent - start).as_nanos() as u64);
+        value.put_slice(&padding);
+        let value = value.freeze();
+        let records = (0..n)
+            .map(|_| Record {
+                key: Bytes::new(),
+                value: value.clone(),
+            })
+            .collect();
+        let req = Request::Produce(ProduceRequest {
+            topic: topic.clone(),
+            partition,
+            acks,
+            records,
+        });
+        if acks == 0 {
+            if unacked.len() >= MAX_UNACKED
+                && let Some(joined) = unacked.join_next().await
+            {
+                check_produced(joined.map_err(|e| e.to_string())?)?;
+            }
+            let target = target.clone();
+            unacked.spawn(async move { target.request(req).await });
+        } else {
+            check_produced(target.request(req).await)?;
+            latencies.push(sent.elapsed());
+        }
+    }
+    while let Some(joined) = unacked.join_next().await {
+        check_produced(joined.map_err(|e| e.to_string())?)?;
+    }
+    Ok(latencies)
+}
+
+/// Fetches (partition, offset, records) until that many records were read
+/// from each partition, returning how many were read and how long after they
+/// were sent.
+async fn consume(
+    target: Target,
+    topic: String,
+    mut partitions: Vec<(u16, i64, u64)>,
+    max_bytes: u32,
+    start: Instant,
+) -> Result<(u64, Vec<Duration>), String> {
+    let mut records = 0;
+    let mut latencies = Vec::new();
+    let mut progress = Instant::now();
+    while partitions.iter().any(|&(_, _, left)| left > 0) {
+        let mut fetched = false;
+        for (partition, offset, left) in partitions.iter_mut().filter(|(.., left)| *left > 0) {
+            let req = Request::Fetch(FetchRequest {
+                topic: topic.clone(),
+                partition: *partition,
+                offset: *offset,
+                max_bytes,
+            });
+            let items = match target.request(req).await? {
+                Response::Fetch(r) if r.status == status::OK => r.items,
+                Response::Fetch(r) if r.status == status::UNKNOWN_TOPIC_OR_PARTITION => continue,
+                Response::Fetch(r) => {
+                    return Err(format!(
+                        "fetching {topic}-{partition}: {}",
+                        status::name(r.status).to_lowercase()
+                    ));
+                }
+                other => return Err(format!("unexpected response {other:?}")),
+            };
+            let now = start.elapsed();
+            for (at, record) in items {
+                let sent = record
+                    .value
+                    .get(..8)
+                    .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+                    .unwrap_or_default();
+                latencies.push(now.saturating_sub(Duration::from_nanos(sent)));
+                *offset = at + 1;
+                *left = left.saturating_sub(1);
+                records += 1;
+                fetched = true;
+            }
+        }
+        if fetched {
+            progress = Instant::now();
+        } else if progress.elapsed() > STALL_TIMEOUT {
+            return Err(format!(
+                "no records for {}s with {} still expected",
+                STALL_TIMEOUT.as_secs(),
+                partitions.iter().map(|&(_, _, left)| left).sum::<u64>()
+            ));
+        } else {
+            target.wait_for_records().await;
+        }
+    }
+    Ok((records, latencies))
+}
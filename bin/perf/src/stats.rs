@@ -0,0 +1,91 @@
+use std::{fmt, time::Duration};
+
+use serde_json::{Value, json};
+
+/// What one side of a run moved, and how long each request or record took.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub records: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Sorted. Empty when nothing was measured, as with acks=0.
+    pub latencies: Vec<Duration>,
+}
+
+impl Summary {
+    pub fn new(records: u64, bytes: u64, elapsed: Duration, mut latencies: Vec<Duration>) -> Self {
+        latencies.sort_unstable();
+        Self {
+            records,
+            bytes,
+            elapsed,
+            latencies,
+        }
+    }
+
+    fn records_per_sec(&self) -> f64 {
+        self.records as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn mb_per_sec(&self) -> f64 {
+        self.bytes as f64 / (1024.0 * 1024.0) / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The latency `q` of the way through, rounding up, so that the p999 of
+    /// fewer than 1000 samples is the slowest one.
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        let n = self.latencies.len();
+        if n == 0 {
+            return None;
+        }
+        let rank = (q * n as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, n) - 1])
+    }
+
+    fn latency_ms(&self) -> [(&'static str, Option<f64>); 4] {
+        let ms = |d: Option<Duration>| d.map(|d| d.as_secs_f64() * 1000.0);
+        [
+            ("p50", ms(self.percentile(0.5))),
+            ("p99", ms(self.percentile(0.99))),
+            ("p999", ms(self.percentile(0.999))),
+            ("max", ms(self.latencies.last().copied())),
+        ]
+    }
+
+    pub fn to_json(&self) -> Value {
+        let latency: serde_json::Map<String, Value> = self
+            .latency_ms()
+            .into_iter()
+            .map(|(name, ms)| (name.to_string(), ms.map_or(Value::Null, Value::from)))
+            .collect();
+        json!({
+            "records": self.records,
+            "bytes": self.bytes,
+            "seconds": self.elapsed.as_secs_f64(),
+            "records_per_sec": self.records_per_sec(),
+            "mb_per_sec": self.mb_per_sec(),
+            "latency_ms": latency,
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records in {:.3}s, {:.0} records/s, {:.2} MB/s",
+            self.records,
+            self.elapsed.as_secs_f64(),
+            self.records_per_sec(),
+            self.mb_per_sec(),
+        )?;
+        if self.latencies.is_empty() {
+            return Ok(());
+        }
+        write!(f, ", latency ms")?;
+        for (name, ms) in self.latency_ms() {
+            write!(f, " {name} {:.2}", ms.unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
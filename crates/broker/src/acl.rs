@@ -0,0 +1,86 @@
+use protocol::types::{AclBinding, AclOperation, AclPermission, PatternType, ResourceType};
+
+pub const ANONYMOUS: &str = "User:ANONYMOUS";
+const WILDCARD_PRINCIPAL: &str = "User:*";
+const WILDCARD_RESOURCE: &str = "*";
+
+/// ACL principal of a connection: `User:<name>` once authenticated,
+/// `User:ANONYMOUS` otherwise.
+pub fn principal_name(user: Option<&str>) -> String {
+    match user {
+        Some(user) => format!("User:{user}"),
+        None => ANONYMOUS.to_string(),
+    }
+}
+
+/// Decides requests against the current bindings. Resources without any
+/// binding are open to everyone; otherwise a matching deny wins over a
+/// matching allow, and no match at all is a denial.
+#[derive(Debug, Default)]
+pub(crate) struct Acls {
+    bindings: Vec<AclBinding>,
+}
+
+impl Acls {
+    pub(crate) fn bindings(&self) -> &[AclBinding] {
+        &self.bindings
+    }
+
+    /// Returns false if an equal binding already exists.
+    pub(crate) fn add(&mut self, acl: AclBinding) -> bool {
+        if self.bindings.contains(&acl) {
+            return false;
+        }
+        self.bindings.push(acl);
+        true
+    }
+
+    pub(crate) fn remove(&mut self, acl: &AclBinding) -> bool {
+        let before = self.bindings.len();
+        self.bindings.retain(|b| b != acl);
+        self.bindings.len() != before
+    }
+
+    pub(crate) fn authorize(
+        &self,
+        principal: &str,
+        operation: AclOperation,
+        resource_type: ResourceType,
+        name: &str,
+    ) -> bool {
+        let mut relevant = self
+            .bindings
+            .iter()
+            .filter(|b| resource_matches(b, resource_type, name))
+            .peekable();
+        if relevant.peek().is_none() {
+            return true;
+        }
+
+        let mut allowed = false;
+        for b in relevant {
+            if b.principal != principal && b.principal != WILDCARD_PRINCIPAL {
+                continue;
+            }
+            match b.permission {
+                AclPermission::Deny if b.operation == operation => return false,
+                AclPermission::Allow if implies(b.operation, operation) => allowed = true,
+                _ => {}
+            }
+        }
+        allowed
+    }
+}
+
+fn resource_matches(b: &AclBinding, resource_type: ResourceType, name: &str) -> bool {
+    b.resource_type == resource_type
+        && match b.pattern_type {
+            PatternType::Literal => b.resource_name == name || b.resource_name == WILDCARD_RESOURCE,
+            PatternType::Prefixed => name.starts_with(&b.resource_name),
+        }
+}
+
+// any operation on a resource implies being allowed to describe it
+fn implies(granted: AclOperation, requested: AclOperation) -> bool {
+    granted == requested || requested == AclOperation::Describe
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        DescribeGroupResponse, GroupListing, GroupProtocol, GroupProtocolMember, HeartbeatRequest,
+        HeartbeatResponse, JoinGroupRequest, JoinGroupResponse, LeaveGroupRequest,
+        LeaveGroupResponse, MemberAssignment, SyncGroupRequest, SyncGroupResponse,
+    },
+};
+use tokio::{
+    sync::{Mutex, Notify},
+    time::Instant,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum GroupState {
+    Empty,
+    PreparingRebalance,
+    AwaitingSync,
+    Stable,
+}
+
+impl GroupState {
+    fn name(&self) -> &'static str {
+        match self {
+            GroupState::Empty => "Empty",
+            GroupState::PreparingRebalance => "PreparingRebalance",
+            GroupState::AwaitingSync => "AwaitingSync",
+            GroupState::Stable => "Stable",
+        }
+    }
+}
+
+struct Member {
+    protocols: Vec<GroupProtocol>,
+    session_timeout: Duration,
+    rebalance_timeout: Duration,
+    last_seen: Instant,
+    // set once the member has (re)joined during the current rebalance
+    joined: bool,
+}
+
+struct Group {
+    state: GroupState,
+    generation_id: i32,
+    protocol_type: String,
+    protocol_name: String,
+    leader: String,
+    members: BTreeMap<String, Member>,
+    assignments: HashMap<String, Bytes>,
+    rebalance_deadline: Instant,
+}
+
+impl Group {
+    fn new() -> Self {
+        Self {
+            state: GroupState::Empty,
+            generation_id: 0,
+            protocol_type: String::new(),
+            protocol_name: String::new(),
+            leader: String::new(),
+            members: BTreeMap::new(),
+            assignments: HashMap::new(),
+            rebalance_deadline: Instant::now(),
+        }
+    }
+
+    fn prepare_rebalance(&mut self, now: Instant) {
+        if self.state == GroupState::PreparingRebalance {
+            return;
+        }
+
+        if self.members.is_empty() {
+            self.state = GroupState::Empty;
+            return;
+        }
+
+        let timeout = self
+            .members
+            .values()
+            .map(|m| m.rebalance_timeout)
+            .max()
+            .unwrap_or_default();
+        self.state = GroupState::PreparingRebalance;
+        self.rebalance_deadline = now + timeout;
+        self.assignments.clear();
+    }
+
+    fn all_joined(&self) -> bool {
+        self.members.values().all(|m| m.joined)
+    }
+
+    /// Drops members that missed the rebalance, bumps the generation and picks the
+    /// leader and protocol for the new generation.
+    fn complete_rebalance(&mut self) {
+        self.members.retain(|_, m| m.joined);
+        if self.members.is_empty() {
+            self.state = GroupState::Empty;
+            self.generation_id += 1;
+            return;
+        }
+
+        if !self.members.contains_key(&self.leader) {
+            self.leader = self.members.keys().next().cloned().unwrap_or_default();
+        }
+
+        // first protocol of the leader that every member supports
+        let leader = &self.members[&self.leader];
+        self.protocol_name = leader
+            .protocols
+            .iter()
+            .map(|p| &p.name)
+            .find(|name| {
+                self.members
+                    .values()
+                    .all(|m| m.protocols.iter().any(|p| &p.name == *name))
+            })
+            .or_else(|| leader.protocols.first().map(|p| &p.name))
+            .cloned()
+            .unwrap_or_default();
+
+        for m in self.members.values_mut() {
+            m.joined = false;
+        }
+        self.generation_id += 1;
+        self.state = GroupState::AwaitingSync;
+    }
+
+    /// Removes members whose session timed out. Members waiting in a join are kept.
+    fn expire(&mut self, now: Instant) {
+        let before = self.members.len();
+        self.members
+            .retain(|_, m| m.joined || now < m.last_seen + m.session_timeout);
+        if self.members.len() != before {
+            self.prepare_rebalance(now);
+        }
+    }
+
+    fn join_response(&self, member_id: &str) -> JoinGroupResponse {
+        if !self.members.contains_key(member_id) {
+            return join_error(status::UNKNOWN_MEMBER_ID, member_id);
+        }
+
+        let members = if member_id == self.leader {
+            self.members
+                .iter()
+                .map(|(id, m)| GroupProtocolMember {
+                    member_id: id.clone(),
+                    metadata: m
+                        .protocols
+                        .iter()
+                        .find(|p| p.name == self.protocol_name)
+                        .map(|p| p.metadata.clone())
+                        .unwrap_or_default(),
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        JoinGroupResponse {
+            status: status::OK,
+            generation_id: self.generation_id,
+            protocol_name: self.protocol_name.clone(),
+            leader: self.leader.clone(),
+            member_id: member_id.to_string(),
+            members,
+        }
+    }
+}
+
+pub(crate) fn join_error(status: u8, member_id: &str) -> JoinGroupResponse {
+    JoinGroupResponse {
+        status,
+        generation_id: -1,
+        protocol_name: String::new(),
+        leader: String::new(),
+        member_id: member_id.to_string(),
+        members: vec![],
+    }
+}
+
+pub(crate) fn describe_error(status: u8, state: &str) -> DescribeGroupResponse {
+    DescribeGroupResponse {
+        status,
+        state: state.to_string(),
+        protocol_type: String::new(),
+        protocol_name: String::new(),
+        generation_id: -1,
+        leader: String::new(),
+        members: vec![],
+        offsets: vec![],
+    }
+}
+
+pub(crate) fn sync_error(status: u8) -> SyncGroupResponse {
+    SyncGroupResponse {
+        status,
+        assignment: Bytes::new(),
+    }
+}
+
+/// Consumer group membership: join/sync rebalances, heartbeats and leaves.
+pub(crate) struct GroupCoordinator {
+    groups: Mutex<HashMap<String, Group>>,
+    changed: Notify,
+    next_member_id: AtomicU64,
+}
+
+impl GroupCoordinator {
+    pub(crate) fn new() -> Self {
+        Self {
+            groups: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+            next_member_id: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) async fn join(&self, r: JoinGroupRequest) -> JoinGroupResponse {
+        if r.group_id.is_empty() {
+            return join_error(status::INVALID_GROUP_ID, &r.member_id);
+        }
+
+        let member_id;
+        let target_generation;
+        {
+            let mut groups = self.groups.lock().await;
+            let now = Instant::now();
+            let g = groups.entry(r.group_id.clone()).or_insert_with(Group::new);
+            g.expire(now);
+
+            if !g.members.is_empty() && g.protocol_type != r.protocol_type {
+                return join_error(status::INCONSISTENT_GROUP_PROTOCOL, &r.member_id);
+            }
+
+            member_id = if r.member_id.is_empty() {
+                let n = self.next_member_id.fetch_add(1, Ordering::Relaxed);
+                format!("{}-member-{n}", r.group_id)
+            } else if g.members.contains_key(&r.member_id) {
+                r.member_id.clone()
+            } else {
+                return join_error(status::UNKNOWN_MEMBER_ID, &r.member_id);
+            };
+
+            g.protocol_type = r.protocol_type;
+            g.members.insert(
+                member_id.clone(),
+                Member {
+                    protocols: r.protocols,
+                    session_timeout: Duration::from_millis(r.session_timeout_ms as u64),
+                    rebalance_timeout: Duration::from_millis(r.rebalance_timeout_ms as u64),
+                    last_seen: now,
+                    joined: true,
+                },
+            );
+
+            g.prepare_rebalance(now);
+            target_generation = g.generation_id + 1;
+            if g.all_joined() {
+                g.complete_rebalance();
+                self.changed.notify_waiters();
+                return g.join_response(&member_id);
+            }
+        }
+
+        loop {
+            let notified = self.changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let deadline = {
+                let mut groups = self.groups.lock().await;
+                let Some(g) = groups.get_mut(&r.group_id) else {
+                    return join_error(status::UNKNOWN_MEMBER_ID, &member_id);
+                };
+
+                if g.generation_id >= target_generation {
+                    return g.join_response(&member_id);
+                }
+
+                if Instant::now() >= g.rebalance_deadline {
+                    g.complete_rebalance();
+                    self.changed.notify_waiters();
+                    return g.join_response(&member_id);
+                }
+
+                g.rebalance_deadline
+            };
+
+            let _ = tokio::time::timeout_at(deadline, notified).await;
+        }
+    }
+
+    pub(crate) async fn sync(&self, r: SyncGroupRequest) -> SyncGroupResponse {
+        let wait_limit;
+        {
+            let mut groups = self.groups.lock().await;
+            let Some(g) = groups.get_mut(&r.group_id) else {
+                return sync_error(status::UNKNOWN_MEMBER_ID);
+            };
+            let Some(member) = g.members.get_mut(&r.member_id) else {
+                return sync_error(status::UNKNOWN_MEMBER_ID);
+            };
+            member.last_seen = Instant::now();
+            wait_limit = member.session_timeout;
+
+            if r.generation_id != g.generation_id {
+                return sync_error(status::ILLEGAL_GENERATION);
+            }
+
+            if g.state == GroupState::PreparingRebalance {
+                return sync_error(status::REBALANCE_IN_PROGRESS);
+            }
+
+            if r.member_id == g.leader && g.state == GroupState::AwaitingSync {
+                g.assignments = r
+                    .assignments
+                    .into_iter()
+                    .map(|a| (a.member_id, a.assignment))
+                    .collect();
+                g.state = GroupState::Stable;
+                self.changed.notify_waiters();
+            }
+        }
+
+        let deadline = Instant::now() + wait_limit;
+        loop {
+            let notified = self.changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let groups = self.groups.lock().await;
+                let Some(g) = groups.get(&r.group_id) else {
+                    return sync_error(status::UNKNOWN_MEMBER_ID);
+                };
+
+                if g.generation_id != r.generation_id || g.state == GroupState::PreparingRebalance {
+                    return sync_error(status::REBALANCE_IN_PROGRESS);
+                }
+
+                if g.state == GroupState::Stable {
+                    return SyncGroupResponse {
+                        status: status::OK,
+                        assignment: g.assignments.get(&r.member_id).cloned().unwrap_or_default(),
+                    };
+                }
+            }
+
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return sync_error(status::REBALANCE_IN_PROGRESS);
+            }
+        }
+    }
+
+    pub(crate) async fn heartbeat(&self, r: HeartbeatRequest) -> HeartbeatResponse {
+        let mut groups = self.groups.lock().await;
+        let Some(g) = groups.get_mut(&r.group_id) else {
+            return HeartbeatResponse {
+                status: status::UNKNOWN_MEMBER_ID,
+            };
+        };
+
+        let now = Instant::now();
+        g.expire(now);
+        let status = match g.members.get_mut(&r.member_id) {
+            None => status::UNKNOWN_MEMBER_ID,
+            Some(_) if r.generation_id != g.generation_id => status::ILLEGAL_GENERATION,
+            Some(_) if g.state == GroupState::PreparingRebalance => status::REBALANCE_IN_PROGRESS,
+            Some(m) => {
+                m.last_seen = now;
+                status::OK
+            }
+        };
+        HeartbeatResponse { status }
+    }
+
+    pub(crate) async fn leave(&self, r: LeaveGroupRequest) -> LeaveGroupResponse {
+        let mut groups = self.groups.lock().await;
+        let Some(g) = groups.get_mut(&r.group_id) else {
+            return LeaveGroupResponse {
+                status: status::UNKNOWN_MEMBER_ID,
+            };
+        };
+
+        if g.members.remove(&r.member_id).is_none() {
+            return LeaveGroupResponse {
+                status: status::UNKNOWN_MEMBER_ID,
+            };
+        }
+
+        let now = Instant::now();
+        if g.state == GroupState::PreparingRebalance {
+            if g.all_joined() {
+                g.complete_rebalance();
+            }
+        } else {
+            g.prepare_rebalance(now);
+        }
+        self.changed.notify_waiters();
+
+        LeaveGroupResponse { status: status::OK }
+    }
+
+    /// Every group the coordinator knows of, members or not.
+    pub(crate) async fn list(&self) -> Vec<GroupListing> {
+        let mut groups = self.groups.lock().await;
+        let now = Instant::now();
+        groups
+            .iter_mut()
+            .map(|(id, g)| {
+                g.expire(now);
+                GroupListing {
+                    group_id: id.clone(),
+                    protocol_type: g.protocol_type.clone(),
+                    state: g.state.name().to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// The group without its committed offsets, or `None` if the coordinator
+    /// doesn't know it.
+    pub(crate) async fn describe(&self, group_id: &str) -> Option<DescribeGroupResponse> {
+        let mut groups = self.groups.lock().await;
+        let g = groups.get_mut(group_id)?;
+        g.expire(Instant::now());
+        Some(DescribeGroupResponse {
+            status: status::OK,
+            state: g.state.name().to_string(),
+            protocol_type: g.protocol_type.clone(),
+            protocol_name: g.protocol_name.clone(),
+            generation_id: g.generation_id,
+            leader: g.leader.clone(),
+            members: g
+                .members
+                .keys()
+                .map(|id| MemberAssignment {
+                    member_id: id.clone(),
+                    assignment: g.assignments.get(id).cloned().unwrap_or_default(),
+                })
+                .collect(),
+            offsets: vec![],
+        })
+    }
+}
@@ -1,76 +1,2208 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use protocol::types::{FetchResponse, ProduceResponse, Request, Response};
-use storage::PartitionLog;
-use tokio::sync::Mutex;
+use bytes::{Bytes, BytesMut};
+use common::{Decode, Encode};
+use controller::{Controller, Registry};
+pub use protocol::connection::Login;
+use protocol::{
+    connection::Peer,
+    status,
+    types::{
+        ACKS_ALL, AclBinding, AclOperation, AlterPartitionReassignmentsResponse,
+        AppendEntriesResponse, CLUSTER_RESOURCE, CommittedOffset, CreateAclsResponse,
+        CreatePartitionsResponse, CreateTopicResponse, DeleteAclsResponse, DeleteTopicResponse,
+        DescribeAclsResponse, DescribeGroupResponse, DescribePartitionResponse, EARLIEST_TIMESTAMP,
+        FetchRequest, FetchResponse, GroupListing, HeartbeatResponse, LATEST_TIMESTAMP,
+        LeaveGroupResponse, ListGroupsResponse, ListOffsetsResponse,
+        ListPartitionReassignmentsResponse, MetadataResponse, OffsetCommitResponse,
+        OffsetFetchResponse, OffsetForLeaderEpochRequest, OffsetForLeaderEpochResponse,
+        ProduceResponse, Record, ReplicaFetchRequest, ReplicaFetchResponse, Request, ResourceType,
+        Response, TopicMetadata, VoteResponse,
+    },
+};
+pub use storage::{FileRegion, LogConfig};
+use storage::{PartitionLog, StorageError};
+use tokio::{
+    sync::{Mutex, MutexGuard, Notify, OwnedMutexGuard, watch},
+    task::AbortHandle,
+};
+
+mod acl;
+mod group;
+mod quota;
+mod replica;
+
+use acl::Acls;
+pub use acl::{ANONYMOUS, principal_name};
+use group::GroupCoordinator;
+use quota::Quotas;
+pub use quota::{Quota, QuotaConfig, Usage};
+use replica::Replica;
+pub use replica::{PartitionState, ReplicationConfig};
+
+/// Internal topic holding committed consumer group offsets.
+pub const OFFSETS_TOPIC: &str = "__consumer_offsets";
+
+// (group, topic, partition) -> offset
+type CommittedOffsets = HashMap<(String, String, u16), i64>;
+
+/// Written to every data dir by `Broker::shutdown`. Logs in a dir without it may
+/// end in a record torn by a crash and are recovered when opened.
+pub const CLEAN_SHUTDOWN_FILE: &str = ".clean_shutdown";
+
+/// Internal topic holding the ACLs, as a history of added and removed bindings.
+pub const ACLS_TOPIC: &str = "__acls";
+
+const ACL_ADDED: u8 = 1;
+const ACL_REMOVED: u8 = 0;
+
+/// Result of `Broker::fetch_region`: the fields of a `FetchResponse`, with the
+/// records left in the log file.
+#[derive(Debug)]
+pub struct FetchRegion {
+    pub status: u8,
+    pub high_watermark: i64,
+    pub log_start_offset: i64,
+    pub region: Option<FileRegion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    /// New partitions go to the dir holding the fewest partitions.
+    pub data_dirs: Vec<PathBuf>,
+    /// Whether produce, fetch and metadata requests may create missing topics.
+    pub auto_create_topics: bool,
+    /// Retention and flush settings of every partition.
+    pub log: LogConfig,
+    pub quotas: QuotaConfig,
+    /// Id of this broker among those it replicates partitions with.
+    pub broker_id: u32,
+    /// Native listener addresses of the other brokers by id, for followers to
+    /// fetch from.
+    pub peers: BTreeMap<u32, String>,
+    /// Logs followers in to their leaders, for listeners that require SASL.
+    pub inter_broker_login: Option<Login>,
+    pub replication: ReplicationConfig,
+}
+
+impl BrokerConfig {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dirs: vec![data_dir],
+            auto_create_topics: true,
+            log: LogConfig::default(),
+            quotas: QuotaConfig::default(),
+            broker_id: 0,
+            peers: BTreeMap::new(),
+            inter_broker_login: None,
+            replication: ReplicationConfig::default(),
+        }
+    }
+}
+
+type SharedLog = Arc<Mutex<PartitionLog>>;
+type LogGuard = OwnedMutexGuard<PartitionLog>;
 
 pub struct Broker {
-    data_dir: PathBuf,
-    partitions: Mutex<HashMap<(String, u16), PartitionLog>>,
+    config: BrokerConfig,
+    // each partition is opened once; requests take turns on its log
+    partitions: Mutex<HashMap<(String, u16), SharedLog>>,
+    groups: GroupCoordinator,
+    // loaded from OFFSETS_TOPIC on first use
+    offsets: Mutex<Option<CommittedOffsets>>,
+    // loaded from ACLS_TOPIC on first use
+    acls: Mutex<Option<Acls>>,
+    quotas: std::sync::Mutex<Quotas>,
+    // partitions with replicas on other brokers; the rest are read up to
+    // their end
+    replicas: std::sync::Mutex<HashMap<(String, u16), Replica>>,
+    // set when this broker is also one of the controllers, whose registry
+    // then decides which partitions it holds
+    controller: std::sync::OnceLock<Arc<Controller>>,
+    metadata_follower: std::sync::Mutex<Option<AbortHandle>>,
+    appended: Notify,
+    // data dirs that were not cleanly shut down
+    unclean_dirs: HashSet<PathBuf>,
+    closed: AtomicBool,
 }
 
 impl Broker {
     pub fn new(data_dir: PathBuf) -> Self {
+        Self::with_config(BrokerConfig::new(data_dir))
+    }
+
+    pub fn with_config(config: BrokerConfig) -> Self {
+        // the marker only vouches for the previous run, so it is consumed here
+        let unclean_dirs = config
+            .data_dirs
+            .iter()
+            .filter(|dir| std::fs::remove_file(dir.join(CLEAN_SHUTDOWN_FILE)).is_err())
+            .cloned()
+            .collect();
+
         Self {
-            data_dir,
+            quotas: std::sync::Mutex::new(Quotas::new(config.quotas.clone())),
+            config,
+            replicas: std::sync::Mutex::new(HashMap::new()),
+            controller: std::sync::OnceLock::new(),
+            metadata_follower: std::sync::Mutex::new(None),
             partitions: Mutex::new(HashMap::new()),
+            groups: GroupCoordinator::new(),
+            offsets: Mutex::new(None),
+            acls: Mutex::new(None),
+            appended: Notify::new(),
+            unclean_dirs,
+            closed: AtomicBool::new(false),
         }
     }
 
-    async fn get_or_open(&self, topic: &str, partition: u16) -> Result<PartitionLog, String> {
-        let mut map = self.partitions.lock().await;
-        if let Some(log) = map.remove(&(topic.to_string(), partition)) {
-            return Ok(log);
+    /// Syncs and closes every partition log and marks the data dirs as cleanly
+    /// shut down, so the next start skips recovery. Requests made afterwards
+    /// fail, so listeners should be drained first.
+    pub async fn shutdown(&self) -> Result<(), String> {
+        if let Some(follower) = self.metadata_follower.lock().unwrap().take() {
+            follower.abort();
+        }
+        if let Some(controller) = self.controller.get() {
+            controller.shutdown();
+        }
+        // stops the followers' fetchers
+        self.replicas.lock().unwrap().clear();
+        let logs: Vec<_> = {
+            let mut map = self.partitions.lock().await;
+            self.closed.store(true, Ordering::SeqCst);
+            map.drain().map(|(_, log)| log).collect()
+        };
+        // waits for the requests still using them
+        for log in logs {
+            log.lock().await.sync().map_err(|e| e.to_string())?;
+        }
+
+        for dir in &self.config.data_dirs {
+            std::fs::File::create(dir.join(CLEAN_SHUTDOWN_FILE))
+                .and_then(|f| f.sync_all())
+                .map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Makes this broker one of the controllers, and the cluster metadata the
+    /// source of its topics: topics are created and deleted through the
+    /// controller, and the broker leads or follows the partitions the
+    /// registry puts on it. The controller's node id must be the broker id.
+    pub fn attach_controller(self: &Arc<Self>, controller: Arc<Controller>) -> Result<(), String> {
+        if controller.node_id() != self.config.broker_id {
+            return Err(format!(
+                "controller {} cannot be attached to broker {}",
+                controller.node_id(),
+                self.config.broker_id
+            ));
+        }
+        let registry = controller.subscribe();
+        self.controller
+            .set(controller)
+            .map_err(|_| "a controller is already attached".to_string())?;
+        let follower = tokio::spawn(self.clone().follow_metadata(registry));
+        *self.metadata_follower.lock().unwrap() = Some(follower.abort_handle());
+        Ok(())
+    }
+
+    async fn follow_metadata(self: Arc<Self>, mut registry: watch::Receiver<Arc<Registry>>) {
+        loop {
+            let current = registry.borrow_and_update().clone();
+            self.apply_registry(&current).await;
+            if registry.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Leads or follows the partitions the registry puts on this broker, and
+    /// removes the topics and partitions it no longer has.
+    async fn apply_registry(self: &Arc<Self>, registry: &Registry) {
+        let id = self.config.broker_id;
+        for (topic, partitions) in &registry.topics {
+            for (partition, p) in (0u16..).zip(partitions) {
+                if !p.replicas.contains(&id) {
+                    continue;
+                }
+                let current = self
+                    .partition_state(topic, partition)
+                    .map(|s| (s.leader, s.leader_epoch, s.replicas));
+                if current.as_ref() == Some(&(p.leader, p.leader_epoch, p.replicas.clone())) {
+                    continue;
+                }
+                let res = if p.leader == id {
+                    self.become_leader(topic, partition, p.leader_epoch, p.replicas.clone())
+                        .await
+                } else {
+                    let throttle = p
+                        .reassignment
+                        .as_ref()
+                        .filter(|r| r.adding.contains(&id) && r.throttle_bytes_per_sec > 0)
+                        .map(|r| r.throttle_bytes_per_sec as u64);
+                    self.follow_partition(
+                        topic,
+                        partition,
+                        p.leader,
+                        p.leader_epoch,
+                        p.replicas.clone(),
+                        throttle,
+                    )
+                    .await
+                };
+                if let Err(e) = res {
+                    tracing::warn!(topic, partition, error = %e, "cannot take up partition");
+                }
+            }
+        }
+
+        let held: BTreeSet<(String, u16)> = self.replicas.lock().unwrap().keys().cloned().collect();
+        let mut deleted = BTreeSet::new();
+        for (topic, partition) in held {
+            let Some(partitions) = registry.topics.get(&topic) else {
+                deleted.insert(topic);
+                continue;
+            };
+            let moved_off = partitions
+                .get(partition as usize)
+                .is_none_or(|p| !p.replicas.contains(&id));
+            if moved_off && let Err(e) = self.remove_partition(&topic, partition).await {
+                tracing::warn!(topic, partition, error = %e, "cannot remove moved partition");
+            }
+        }
+        for topic in deleted {
+            if let Err(e) = self.remove_topic(&topic).await {
+                tracing::warn!(topic, error = %e, "cannot remove deleted topic");
+            }
+        }
+    }
+
+    /// Counts a request by `user` from `client_id` against its quotas and returns
+    /// how long its response should be delayed. Clients are told the same time
+    /// as `throttle_time_ms`, so they can back off.
+    pub fn record_usage(&self, user: Option<&str>, client_id: &str, usage: Usage) -> Duration {
+        self.quotas.lock().unwrap().record(user, client_id, usage)
+    }
+
+    /// Notified after every successful produce, so fetchers can wait for new data.
+    pub fn appended(&self) -> &Notify {
+        &self.appended
+    }
+
+    async fn get_or_open(&self, topic: &str, partition: u16) -> Result<LogGuard, String> {
+        // the name becomes part of a file path
+        if !valid_topic_name(topic) {
+            return Err(format!("invalid topic name {topic:?}"));
         }
+        let log = {
+            let mut map = self.partitions.lock().await;
+            match map.get(&(topic.to_string(), partition)) {
+                Some(log) => log.clone(),
+                None => {
+                    if self.closed.load(Ordering::SeqCst) {
+                        return Err("broker is shut down".to_string());
+                    }
+
+                    let dir = match self.find_partition(topic, partition) {
+                        Some(dir) => dir,
+                        None => self.least_used_dir(),
+                    };
+                    let mut config = self.config.log.clone();
+                    // the offsets and ACLs topics hold state rather than a
+                    // stream of events; trimming them would forget commits
+                    if topic == OFFSETS_TOPIC || topic == ACLS_TOPIC {
+                        config.retention_bytes = None;
+                    }
+                    let log = if self.unclean_dirs.contains(dir) {
+                        PartitionLog::recover(dir, topic, partition, config)
+                    } else {
+                        PartitionLog::open_with(dir, topic, partition, config)
+                    }
+                    .map_err(|e| e.to_string())?;
+                    let log = Arc::new(Mutex::new(log));
+                    map.insert((topic.to_string(), partition), log.clone());
+                    log
+                }
+            }
+        };
+        Ok(log.lock_owned().await)
+    }
 
-        PartitionLog::open(&self.data_dir, topic, partition).map_err(|e| e.to_string())
+    /// Like `get_or_open`, but a missing partition is only created when
+    /// `auto_create_topics` is set, and never with a controller attached: its
+    /// registry decides which partitions exist, and they are opened as this
+    /// broker takes them on. Otherwise, or for an invalid topic name, the
+    /// status to reply with is returned. A topic's partition count is one
+    /// past its last partition, so the partitions before a created one are
+    /// created along with it.
+    async fn open_partition(
+        &self,
+        topic: &str,
+        partition: u16,
+    ) -> Result<Result<LogGuard, u8>, String> {
+        if !valid_topic_name(topic) {
+            return Ok(Err(status::INVALID_TOPIC));
+        }
+        let exists = self
+            .partitions
+            .lock()
+            .await
+            .contains_key(&(topic.to_string(), partition))
+            || self.find_partition(topic, partition).is_some();
+        if !exists {
+            if !self.config.auto_create_topics || self.controller.get().is_some() {
+                return Ok(Err(status::UNKNOWN_TOPIC_OR_PARTITION));
+            }
+            for earlier in 0..partition {
+                let log = self.get_or_open(topic, earlier).await?;
+                self.put_back(topic, earlier, log).await;
+            }
+        }
+        self.get_or_open(topic, partition).await.map(Ok)
+    }
+
+    fn find_partition(&self, topic: &str, partition: u16) -> Option<&PathBuf> {
+        let filename = format!("{topic}-{partition}.log");
+        self.config
+            .data_dirs
+            .iter()
+            .find(|dir| dir.join(&filename).exists())
+    }
+
+    fn least_used_dir(&self) -> &PathBuf {
+        let count = |dir: &PathBuf| {
+            std::fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter(|e| e.file_name().to_string_lossy().ends_with(".log"))
+                        .count()
+                })
+                .unwrap_or(0)
+        };
+        self.config
+            .data_dirs
+            .iter()
+            .min_by_key(|dir| count(dir))
+            .expect("at least one data dir")
+    }
+
+    /// Releases a log taken with `get_or_open`, recording its size.
+    async fn put_back(&self, topic: &str, partition: u16, mut log: LogGuard) {
+        let partition_label = partition.to_string();
+        let labels = [("topic", topic), ("partition", partition_label.as_str())];
+        metrics::gauge(
+            "mini_kafka_log_end_offset",
+            "Offset the next record appended to a partition gets",
+            &labels,
+        )
+        .set(log.next_offset());
+        metrics::gauge(
+            "mini_kafka_log_size_bytes",
+            "Size of a partition log",
+            &labels,
+        )
+        .set(log.size() as i64);
+
+        if self.closed.load(Ordering::SeqCst) {
+            // a request still running after shutdown
+            let _ = log.sync();
+        }
+    }
+
+    /// The topics of the cluster with their partitions: those in the
+    /// controller's registry when one is attached, wherever their replicas
+    /// are, otherwise the ones in this broker's data dirs.
+    fn known_topics(&self) -> BTreeMap<String, BTreeSet<u16>> {
+        let Some(controller) = self.controller.get() else {
+            return self.list_topics();
+        };
+        controller
+            .registry()
+            .topics
+            .iter()
+            .map(|(topic, partitions)| (topic.clone(), (0..partitions.len() as u16).collect()))
+            .collect()
+    }
+
+    /// Topics and partitions are implied by the `{topic}-{partition}.log` files in the data dirs.
+    fn list_topics(&self) -> BTreeMap<String, BTreeSet<u16>> {
+        let mut topics: BTreeMap<String, BTreeSet<u16>> = BTreeMap::new();
+        let entries = self
+            .config
+            .data_dirs
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten();
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(stem) = name.to_str().and_then(|n| n.strip_suffix(".log")) else {
+                continue;
+            };
+            let Some((topic, partition)) = stem.rsplit_once('-') else {
+                continue;
+            };
+            if let Ok(partition) = partition.parse::<u16>() {
+                topics
+                    .entry(topic.to_string())
+                    .or_default()
+                    .insert(partition);
+            }
+        }
+
+        topics
+    }
+
+    async fn create_topic(&self, topic: &str, partitions: u16) -> Result<u8, String> {
+        if !valid_topic_name(topic) || topic == OFFSETS_TOPIC || topic == ACLS_TOPIC {
+            return Ok(status::INVALID_TOPIC);
+        }
+        if partitions == 0 {
+            return Ok(status::INVALID_PARTITIONS);
+        }
+        if let Some(controller) = self.controller.get() {
+            return Ok(controller.create_topic(topic, partitions).await);
+        }
+        if self.list_topics().contains_key(topic) {
+            return Ok(status::TOPIC_ALREADY_EXISTS);
+        }
+        for partition in 0..partitions {
+            let log = self.get_or_open(topic, partition).await?;
+            self.put_back(topic, partition, log).await;
+        }
+        Ok(status::OK)
+    }
+
+    /// Creates `topic` with one partition for a client asking for it and
+    /// returns its partitions, or the status to reply with if it could not be
+    /// created. With a controller, that takes the leading controller
+    /// registering it.
+    async fn auto_create_topic(&self, topic: &str) -> Result<Result<Vec<u16>, u8>, String> {
+        if !valid_topic_name(topic) {
+            return Ok(Err(status::INVALID_TOPIC));
+        }
+        let Some(controller) = self.controller.get() else {
+            let log = self.get_or_open(topic, 0).await?;
+            self.put_back(topic, 0, log).await;
+            return Ok(Ok(vec![0]));
+        };
+        match self.create_topic(topic, 1).await? {
+            // registered already, without a replica here
+            status::OK | status::TOPIC_ALREADY_EXISTS => {}
+            _ => return Ok(Err(status::UNKNOWN_TOPIC_OR_PARTITION)),
+        }
+        let registry = controller.registry();
+        Ok(registry
+            .topics
+            .get(topic)
+            .map(|partitions| (0..partitions.len() as u16).collect())
+            .ok_or(status::UNKNOWN_TOPIC_OR_PARTITION))
+    }
+
+    /// Creates the partitions of `topic` from its current count up to
+    /// `partitions`, which must be more than it has.
+    async fn create_partitions(&self, topic: &str, partitions: u16) -> Result<u8, String> {
+        if topic == OFFSETS_TOPIC || topic == ACLS_TOPIC {
+            return Ok(status::INVALID_TOPIC);
+        }
+        if let Some(controller) = self.controller.get() {
+            return Ok(controller.create_partitions(topic, partitions).await);
+        }
+        let Some(existing) = self.list_topics().remove(topic) else {
+            return Ok(status::UNKNOWN_TOPIC_OR_PARTITION);
+        };
+        // partitions are implied by their files, so the count is one past
+        // the last of them
+        let current = existing.last().map_or(0, |&p| p + 1);
+        if partitions <= current {
+            return Ok(status::INVALID_PARTITIONS);
+        }
+        for partition in current..partitions {
+            let log = self.get_or_open(topic, partition).await?;
+            self.put_back(topic, partition, log).await;
+        }
+        Ok(status::OK)
     }
 
-    async fn put_back(&self, topic: &str, partition: u16, log: PartitionLog) {
+    async fn delete_topic(&self, topic: &str) -> Result<u8, String> {
+        if topic == OFFSETS_TOPIC || topic == ACLS_TOPIC {
+            return Ok(status::INVALID_TOPIC);
+        }
+        if let Some(controller) = self.controller.get() {
+            return Ok(controller.delete_topic(topic).await);
+        }
+        self.remove_topic(topic).await
+    }
+
+    /// Deletes the partitions of `topic` held by this broker.
+    async fn remove_topic(&self, topic: &str) -> Result<u8, String> {
+        let Some(partitions) = self.list_topics().remove(topic) else {
+            return Ok(status::UNKNOWN_TOPIC_OR_PARTITION);
+        };
+
+        self.replicas.lock().unwrap().retain(|(t, _), _| t != topic);
+        let mut map = self.partitions.lock().await;
+        map.retain(|(t, _), _| t != topic);
+        for partition in partitions {
+            self.delete_partition_files(topic, partition)?;
+        }
+        Ok(status::OK)
+    }
+
+    /// Deletes a partition this broker no longer holds a replica of, as after
+    /// the partition moved to other brokers.
+    async fn remove_partition(&self, topic: &str, partition: u16) -> Result<(), String> {
+        let key = (topic.to_string(), partition);
+        self.replicas.lock().unwrap().remove(&key);
         let mut map = self.partitions.lock().await;
-        map.insert((topic.to_string(), partition), log);
+        map.remove(&key);
+        self.delete_partition_files(topic, partition)?;
+        tracing::info!(topic, partition, "removed partition moved to other brokers");
+        Ok(())
+    }
+
+    fn delete_partition_files(&self, topic: &str, partition: u16) -> Result<(), String> {
+        let Some(dir) = self.find_partition(topic, partition) else {
+            return Ok(());
+        };
+        std::fs::remove_file(dir.join(format!("{topic}-{partition}.log")))
+            .map_err(|e| e.to_string())?;
+        let epochs = dir.join(format!("{topic}-{partition}.epochs"));
+        if let Err(e) = std::fs::remove_file(epochs)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            return Err(e.to_string());
+        }
+        Ok(())
+    }
+
+    /// Groups known to the coordinator, and those that only have committed
+    /// offsets left, which are reported as empty.
+    async fn list_groups(&self, principal: &str) -> Result<Vec<GroupListing>, String> {
+        let mut groups: BTreeMap<String, GroupListing> = self
+            .groups
+            .list()
+            .await
+            .into_iter()
+            .map(|g| (g.group_id.clone(), g))
+            .collect();
+        let offsets = self.load_offsets().await?;
+        for (group_id, _, _) in offsets.iter().flat_map(|m| m.keys()) {
+            if !groups.contains_key(group_id) {
+                let listing = GroupListing {
+                    group_id: group_id.clone(),
+                    protocol_type: String::new(),
+                    state: "Empty".to_string(),
+                };
+                groups.insert(group_id.clone(), listing);
+            }
+        }
+        drop(offsets);
+
+        let mut listed = Vec::with_capacity(groups.len());
+        for (group_id, listing) in groups {
+            if self
+                .authorize(
+                    principal,
+                    AclOperation::Describe,
+                    ResourceType::Group,
+                    &group_id,
+                )
+                .await?
+            {
+                listed.push(listing);
+            }
+        }
+        Ok(listed)
+    }
+
+    async fn describe_group(&self, group_id: &str) -> Result<DescribeGroupResponse, String> {
+        let offsets = self.load_offsets().await?;
+        let mut committed: Vec<CommittedOffset> = offsets
+            .iter()
+            .flatten()
+            .filter(|((g, _, _), _)| g == group_id)
+            .map(|((_, topic, partition), &offset)| CommittedOffset {
+                topic: topic.clone(),
+                partition: *partition,
+                offset,
+            })
+            .collect();
+        drop(offsets);
+        committed.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+
+        let mut resp = match self.groups.describe(group_id).await {
+            Some(resp) => resp,
+            None if committed.is_empty() => group::describe_error(status::OK, "Dead"),
+            None => group::describe_error(status::OK, "Empty"),
+        };
+        resp.offsets = committed;
+        Ok(resp)
+    }
+
+    async fn commit_offset(
+        &self,
+        group_id: String,
+        topic: String,
+        partition: u16,
+        offset: i64,
+    ) -> Result<(), String> {
+        let mut offsets = self.load_offsets().await?;
+
+        let mut key = BytesMut::new();
+        common::write_str(&mut key, &group_id).map_err(|e| e.to_string())?;
+        common::write_str(&mut key, &topic).map_err(|e| e.to_string())?;
+        common::write_partition(&mut key, partition);
+        let record = Record {
+            key: key.freeze(),
+            value: Bytes::copy_from_slice(&offset.to_be_bytes()),
+        };
+
+        let mut log = self.get_or_open(OFFSETS_TOPIC, 0).await?;
+        let res = log.append(&[record]);
+        self.put_back(OFFSETS_TOPIC, 0, log).await;
+        res.map_err(|e| e.to_string())?;
+
+        offsets
+            .get_or_insert_with(HashMap::new)
+            .insert((group_id, topic, partition), offset);
+        Ok(())
+    }
+
+    async fn committed_offset(
+        &self,
+        group_id: String,
+        topic: String,
+        partition: u16,
+    ) -> Result<i64, String> {
+        let offsets = self.load_offsets().await?;
+        Ok(offsets
+            .as_ref()
+            .and_then(|m| m.get(&(group_id, topic, partition)).copied())
+            .unwrap_or(-1))
+    }
+
+    async fn load_offsets(&self) -> Result<MutexGuard<'_, Option<CommittedOffsets>>, String> {
+        let mut offsets = self.offsets.lock().await;
+        if offsets.is_some() {
+            return Ok(offsets);
+        }
+
+        let log = self.get_or_open(OFFSETS_TOPIC, 0).await?;
+        let items = log.fetch(0, u32::MAX);
+        self.put_back(OFFSETS_TOPIC, 0, log).await;
+
+        let mut map = HashMap::new();
+        for (_, rec) in items.map_err(|e| e.to_string())? {
+            let mut key = rec.key;
+            let mut value = rec.value;
+            let (Ok(group), Ok(topic), Ok(partition), Ok(offset)) = (
+                common::read_str(&mut key),
+                common::read_topic(&mut key),
+                common::read_partition(&mut key),
+                common::read_offset(&mut value),
+            ) else {
+                return Err(format!("corrupted record in {OFFSETS_TOPIC}"));
+            };
+            map.insert((group, topic, partition), offset);
+        }
+
+        *offsets = Some(map);
+        Ok(offsets)
+    }
+
+    async fn load_acls(&self) -> Result<MutexGuard<'_, Option<Acls>>, String> {
+        let mut acls = self.acls.lock().await;
+        if acls.is_some() {
+            return Ok(acls);
+        }
+
+        let log = self.get_or_open(ACLS_TOPIC, 0).await?;
+        let items = log.fetch(0, u32::MAX);
+        self.put_back(ACLS_TOPIC, 0, log).await;
+
+        let mut loaded = Acls::default();
+        for (_, rec) in items.map_err(|e| e.to_string())? {
+            let mut key = rec.key;
+            let (Ok(acl), Some(&op)) = (AclBinding::decode(&mut key), rec.value.first()) else {
+                return Err(format!("corrupted record in {ACLS_TOPIC}"));
+            };
+            if op == ACL_ADDED {
+                loaded.add(acl);
+            } else {
+                loaded.remove(&acl);
+            }
+        }
+
+        *acls = Some(loaded);
+        Ok(acls)
+    }
+
+    /// Applies `changes` to the ACLs, persisting the ones that had an effect, and
+    /// returns how many did.
+    async fn update_acls(&self, changes: Vec<AclBinding>, added: bool) -> Result<u32, String> {
+        let mut guard = self.load_acls().await?;
+        let acls = guard.get_or_insert_with(Acls::default);
+
+        let mut records = Vec::new();
+        for acl in changes {
+            let mut key = BytesMut::new();
+            acl.encode(&mut key).map_err(|e| e.to_string())?;
+            let changed = if added {
+                acls.add(acl)
+            } else {
+                acls.remove(&acl)
+            };
+            if changed {
+                records.push(Record {
+                    key: key.freeze(),
+                    value: Bytes::copy_from_slice(&[if added { ACL_ADDED } else { ACL_REMOVED }]),
+                });
+            }
+        }
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let mut log = self.get_or_open(ACLS_TOPIC, 0).await?;
+        let res = log.append(&records);
+        self.put_back(ACLS_TOPIC, 0, log).await;
+        if let Err(e) = res {
+            // keep memory in line with what is on disk
+            *guard = None;
+            return Err(e.to_string());
+        }
+        Ok(records.len() as u32)
+    }
+
+    async fn authorize(
+        &self,
+        principal: &str,
+        operation: AclOperation,
+        resource_type: ResourceType,
+        name: &str,
+    ) -> Result<bool, String> {
+        let acls = self.load_acls().await?;
+        Ok(acls
+            .as_ref()
+            .is_none_or(|a| a.authorize(principal, operation, resource_type, name)))
+    }
+
+    /// Returns the response to send instead of running `req` when `principal` may
+    /// not. Metadata is filtered per topic by the handler itself.
+    async fn check_acls(&self, principal: &str, req: &Request) -> Result<Option<Response>, String> {
+        use AclOperation::*;
+        use ResourceType::*;
+
+        let topic_denied = status::TOPIC_AUTHORIZATION_FAILED;
+        let group_denied = status::GROUP_AUTHORIZATION_FAILED;
+        let cluster_denied = status::CLUSTER_AUTHORIZATION_FAILED;
+
+        // (operation, resource type, resource name, status when denied)
+        let checks = match req {
+            Request::Produce(r) => {
+                // internal topics are only written by the broker itself, the
+                // ACLs through the admin requests
+                if r.topic == OFFSETS_TOPIC || r.topic == ACLS_TOPIC {
+                    return Ok(Some(denied(req, topic_denied)));
+                }
+                vec![(Write, Topic, r.topic.as_str(), topic_denied)]
+            }
+            Request::Fetch(r) => {
+                let mut checks = vec![(Read, Topic, r.topic.as_str(), topic_denied)];
+                // reading the ACLs is describing them
+                if r.topic == ACLS_TOPIC {
+                    checks.push((Describe, Cluster, CLUSTER_RESOURCE, topic_denied));
+                }
+                checks
+            }
+            Request::ListOffsets(r) => vec![(Describe, Topic, r.topic.as_str(), topic_denied)],
+            Request::OffsetCommit(r) => vec![
+                (Read, Group, r.group_id.as_str(), group_denied),
+                (Read, Topic, r.topic.as_str(), topic_denied),
+            ],
+            Request::OffsetFetch(r) => vec![
+                (Describe, Group, r.group_id.as_str(), group_denied),
+                (Describe, Topic, r.topic.as_str(), topic_denied),
+            ],
+            Request::JoinGroup(r) => vec![(Read, Group, r.group_id.as_str(), group_denied)],
+            Request::SyncGroup(r) => vec![(Read, Group, r.group_id.as_str(), group_denied)],
+            Request::Heartbeat(r) => vec![(Read, Group, r.group_id.as_str(), group_denied)],
+            Request::LeaveGroup(r) => vec![(Read, Group, r.group_id.as_str(), group_denied)],
+            Request::CreateAcls(_) | Request::DeleteAcls(_) => {
+                vec![(Alter, Cluster, CLUSTER_RESOURCE, cluster_denied)]
+            }
+            Request::DescribeAcls(_) => {
+                vec![(Describe, Cluster, CLUSTER_RESOURCE, cluster_denied)]
+            }
+            Request::ReplicaFetch(_)
+            | Request::OffsetForLeaderEpoch(_)
+            | Request::DescribePartition(_)
+            | Request::Vote(_)
+            | Request::AppendEntries(_) => {
+                vec![(ClusterAction, Cluster, CLUSTER_RESOURCE, cluster_denied)]
+            }
+            Request::AlterPartitionReassignments(_) => {
+                vec![(Alter, Cluster, CLUSTER_RESOURCE, cluster_denied)]
+            }
+            Request::ListPartitionReassignments(_) => {
+                vec![(Describe, Cluster, CLUSTER_RESOURCE, cluster_denied)]
+            }
+            Request::CreateTopic(r) => vec![(Create, Topic, r.topic.as_str(), topic_denied)],
+            Request::CreatePartitions(r) => vec![(Alter, Topic, r.topic.as_str(), topic_denied)],
+            Request::DeleteTopic(r) => vec![(Delete, Topic, r.topic.as_str(), topic_denied)],
+            Request::DescribeGroup(r) => {
+                vec![(Describe, Group, r.group_id.as_str(), group_denied)]
+            }
+            // groups the client may not describe are left out by the handler
+            Request::ListGroups(_) => vec![],
+            Request::Metadata(_) | Request::SaslHandshake(_) | Request::SaslAuthenticate(_) => {
+                vec![]
+            }
+        };
+
+        for (operation, resource_type, name, status) in checks {
+            if !self
+                .authorize(principal, operation, resource_type, name)
+                .await?
+            {
+                return Ok(Some(denied(req, status)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Makes this broker the leader of the partition in `leader_epoch`, with
+    /// `replicas`, which include this broker, copying its records. Consumers
+    /// only get records once every replica has them. Fails for an older epoch
+    /// than the partition is in.
+    pub async fn become_leader(
+        &self,
+        topic: &str,
+        partition: u16,
+        leader_epoch: i32,
+        replicas: Vec<u32>,
+    ) -> Result<(), String> {
+        self.check_leader_epoch(topic, partition, leader_epoch)?;
+        let id = self.config.broker_id;
+        if self.update_replicas(topic, partition, id, leader_epoch, replicas.clone(), None) {
+            self.appended.notify_waiters();
+            return Ok(());
+        }
+        let mut log = self.get_or_open(topic, partition).await?;
+        let res = match log.latest_epoch() {
+            Some(latest) if latest > leader_epoch => Err(format!(
+                "leader epoch {leader_epoch} is older than the log's {latest}"
+            )),
+            // records appended from now on are this epoch's
+            _ => log.assign_epoch(leader_epoch).map_err(|e| e.to_string()),
+        };
+        let (start, end) = (log.start_offset(), log.next_offset());
+        self.put_back(topic, partition, log).await;
+        res?;
+
+        let key = (topic.to_string(), partition);
+        let mut map = self.replicas.lock().unwrap();
+        // a former follower keeps the high watermark it had from the old
+        // leader; otherwise it is rebuilt from the followers' fetches
+        let high_watermark = map.get(&key).map_or(start, |r| r.high_watermark).min(end);
+        let mut replica = Replica::new(
+            self.config.broker_id,
+            leader_epoch,
+            replicas,
+            high_watermark,
+            end,
+        );
+        replica.update(Instant::now(), self.config.replication.lag_time_max);
+        map.insert(key, replica);
+        drop(map);
+        self.appended.notify_waiters();
+        Ok(())
+    }
+
+    /// Makes this broker a follower of the partition, copying the records of
+    /// `leader`, which must be one of the peers, in `leader_epoch`. The log is
+    /// first cut back to where it stops matching the leader's. Fails for an
+    /// older epoch than the partition is in.
+    pub async fn become_follower(
+        self: &Arc<Self>,
+        topic: &str,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+        replicas: Vec<u32>,
+    ) -> Result<(), String> {
+        self.follow_partition(topic, partition, leader, leader_epoch, replicas, None)
+            .await
+    }
+
+    /// `become_follower`, copying at most `throttle` bytes per second.
+    async fn follow_partition(
+        self: &Arc<Self>,
+        topic: &str,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+        replicas: Vec<u32>,
+        throttle: Option<u64>,
+    ) -> Result<(), String> {
+        if !self.config.peers.contains_key(&leader) {
+            return Err(format!("no address for broker {leader}"));
+        }
+        self.check_leader_epoch(topic, partition, leader_epoch)?;
+        if self.update_replicas(
+            topic,
+            partition,
+            leader,
+            leader_epoch,
+            replicas.clone(),
+            throttle,
+        ) {
+            return Ok(());
+        }
+        let log = self.get_or_open(topic, partition).await?;
+        let (start, end) = (log.start_offset(), log.next_offset());
+        self.put_back(topic, partition, log).await;
+
+        let key = (topic.to_string(), partition);
+        let mut map = self.replicas.lock().unwrap();
+        let high_watermark = map.get(&key).map_or(start, |r| r.high_watermark).min(end);
+        let mut replica = Replica::new(leader, leader_epoch, replicas, high_watermark, end);
+        replica.throttle = throttle;
+        let fetcher =
+            tokio::spawn(
+                self.clone()
+                    .follow(topic.to_string(), partition, leader, leader_epoch),
+            );
+        replica.fetcher = Some(fetcher.abort_handle());
+        map.insert(key, replica);
+        Ok(())
+    }
+
+    /// Moves a partition held under `leader` in `leader_epoch` to `replicas`,
+    /// keeping what the broker knows of it, as when replicas are added or
+    /// removed without a change of leader. Returns false if the partition is
+    /// held otherwise.
+    fn update_replicas(
+        &self,
+        topic: &str,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+        replicas: Vec<u32>,
+        throttle: Option<u64>,
+    ) -> bool {
+        let mut map = self.replicas.lock().unwrap();
+        let Some(replica) = map.get_mut(&(topic.to_string(), partition)) else {
+            return false;
+        };
+        if replica.leader != leader || replica.leader_epoch != leader_epoch {
+            return false;
+        }
+        replica.set_replicas(replicas);
+        replica.throttle = throttle;
+        if leader == self.config.broker_id {
+            self.update_replica(topic, partition, replica);
+        }
+        true
+    }
+
+    fn check_leader_epoch(
+        &self,
+        topic: &str,
+        partition: u16,
+        leader_epoch: i32,
+    ) -> Result<(), String> {
+        let map = self.replicas.lock().unwrap();
+        match map.get(&(topic.to_string(), partition)) {
+            Some(r) if r.leader_epoch > leader_epoch => Err(format!(
+                "leader epoch {leader_epoch} is older than the partition's {}",
+                r.leader_epoch
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with `NOT_LEADER_OR_FOLLOWER` unless this broker leads the
+    /// partition and `replica_id` follows it, and with `FENCED_LEADER_EPOCH`
+    /// or `UNKNOWN_LEADER_EPOCH` when the follower knows the leader in an
+    /// older or newer epoch.
+    fn check_follower(
+        &self,
+        topic: &str,
+        partition: u16,
+        replica_id: u32,
+        leader_epoch: i32,
+    ) -> Result<(), u8> {
+        let map = self.replicas.lock().unwrap();
+        match map.get(&(topic.to_string(), partition)) {
+            Some(p) if p.leader == self.config.broker_id && p.replicas.contains(&replica_id) => {
+                match leader_epoch.cmp(&p.leader_epoch) {
+                    std::cmp::Ordering::Less => Err(status::FENCED_LEADER_EPOCH),
+                    std::cmp::Ordering::Greater => Err(status::UNKNOWN_LEADER_EPOCH),
+                    std::cmp::Ordering::Equal => Ok(()),
+                }
+            }
+            _ => Err(status::NOT_LEADER_OR_FOLLOWER),
+        }
+    }
+
+    /// Replication state of the partition, if this broker is the leader or a
+    /// follower of it.
+    pub fn partition_state(&self, topic: &str, partition: u16) -> Option<PartitionState> {
+        let mut map = self.replicas.lock().unwrap();
+        let replica = map.get_mut(&(topic.to_string(), partition))?;
+        if replica.leader == self.config.broker_id {
+            self.update_replica(topic, partition, replica);
+        }
+        Some(replica.state())
+    }
+
+    /// `Replica::update` on the leader, logging changes to the in-sync
+    /// replicas.
+    fn update_replica(&self, topic: &str, partition: u16, replica: &mut Replica) -> bool {
+        let isr = replica.isr.clone();
+        let moved = replica.update(Instant::now(), self.config.replication.lag_time_max);
+        if replica.isr != isr {
+            tracing::info!(topic, partition, isr = ?replica.isr, "in-sync replicas changed");
+        }
+        moved
+    }
+
+    /// Fails with `NOT_LEADER_OR_FOLLOWER` on followers of the partition, and
+    /// for `ACKS_ALL` with `NOT_ENOUGH_REPLICAS` while too few replicas are in
+    /// sync.
+    fn check_produce(&self, topic: &str, partition: u16, acks: i16) -> Result<(), u8> {
+        let mut map = self.replicas.lock().unwrap();
+        let in_sync = match map.get_mut(&(topic.to_string(), partition)) {
+            Some(r) if r.leader != self.config.broker_id => {
+                return Err(status::NOT_LEADER_OR_FOLLOWER);
+            }
+            Some(r) => {
+                self.update_replica(topic, partition, r);
+                r.isr.len()
+            }
+            None => 1,
+        };
+        if acks == ACKS_ALL && in_sync < self.config.replication.min_insync_replicas {
+            return Err(status::NOT_ENOUGH_REPLICAS);
+        }
+        Ok(())
+    }
+
+    /// Waits until the in-sync replicas have the records before `end`, and
+    /// returns the status of the `ACKS_ALL` produce request that appended
+    /// them.
+    async fn await_replication(&self, topic: &str, partition: u16, end: i64) -> u8 {
+        let config = &self.config.replication;
+        let key = (topic.to_string(), partition);
+        let deadline = Instant::now() + config.ack_timeout;
+        loop {
+            let mut appended = std::pin::pin!(self.appended.notified());
+            appended.as_mut().enable();
+            let (high_watermark, in_sync, moved) = {
+                let mut map = self.replicas.lock().unwrap();
+                match map.get_mut(&key) {
+                    None => return status::OK,
+                    Some(r) if r.leader != self.config.broker_id => {
+                        return status::NOT_LEADER_OR_FOLLOWER;
+                    }
+                    Some(r) => {
+                        let moved = self.update_replica(topic, partition, r);
+                        (r.high_watermark, r.isr.len(), moved)
+                    }
+                }
+            };
+            if moved {
+                self.appended.notify_waiters();
+            }
+            // replicas that fell out of sync no longer hold the high watermark
+            // back, but then there may not be enough left
+            if high_watermark >= end {
+                return if in_sync < config.min_insync_replicas {
+                    status::NOT_ENOUGH_REPLICAS_AFTER_APPEND
+                } else {
+                    status::OK
+                };
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return status::REQUEST_TIMED_OUT;
+            }
+            // wakes up now and then to drop followers that fell behind
+            let _ = tokio::time::timeout(remaining.min(config.lag_time_max / 2), appended).await;
+        }
+    }
+
+    /// Where consumers have to stop reading `log`: at the high watermark of a
+    /// replicated partition, and at the end of the log otherwise. Followers
+    /// don't serve consumers.
+    fn readable_end(&self, topic: &str, partition: u16, log: &PartitionLog) -> Result<i64, u8> {
+        let mut map = self.replicas.lock().unwrap();
+        match map.get_mut(&(topic.to_string(), partition)) {
+            None => Ok(log.next_offset()),
+            Some(r) if r.leader == self.config.broker_id => {
+                self.update_replica(topic, partition, r);
+                Ok(r.high_watermark)
+            }
+            Some(_) => Err(status::NOT_LEADER_OR_FOLLOWER),
+        }
+    }
+
+    fn appended_on_leader(&self, topic: &str, partition: u16, log_end_offset: i64) {
+        let mut map = self.replicas.lock().unwrap();
+        if let Some(r) = map.get_mut(&(topic.to_string(), partition))
+            && r.leader == self.config.broker_id
+        {
+            r.log_end_offset = log_end_offset;
+            self.update_replica(topic, partition, r);
+        }
+    }
+
+    /// Answers a follower's fetch, taking its offset as the end of its log.
+    async fn replica_fetch(&self, r: ReplicaFetchRequest) -> Result<ReplicaFetchResponse, String> {
+        let key = (r.topic.clone(), r.partition);
+        let error = |status| ReplicaFetchResponse {
+            status,
+            high_watermark: -1,
+            log_start_offset: -1,
+            epochs: vec![],
+            items: vec![],
+        };
+        let deadline = Instant::now() + Duration::from_millis(r.max_wait_ms.into());
+        loop {
+            if let Err(status) =
+                self.check_follower(&r.topic, r.partition, r.replica_id, r.leader_epoch)
+            {
+                return Ok(error(status));
+            }
+            let mut appended = std::pin::pin!(self.appended.notified());
+            appended.as_mut().enable();
+
+            let log = self.get_or_open(&r.topic, r.partition).await?;
+            let log_start_offset = log.start_offset();
+            let log_end_offset = log.next_offset();
+            let items = if r.offset < log_start_offset || r.offset > log_end_offset {
+                None
+            } else {
+                Some(log.fetch(r.offset, r.max_bytes))
+            };
+            let epochs = log.epochs().to_vec();
+            self.put_back(&r.topic, r.partition, log).await;
+
+            let (high_watermark, advanced) = {
+                let mut map = self.replicas.lock().unwrap();
+                let Some(replica) = map.get_mut(&key) else {
+                    return Ok(error(status::NOT_LEADER_OR_FOLLOWER));
+                };
+                let advanced = items.is_some() && {
+                    replica.log_end_offset = log_end_offset;
+                    replica.fetched(r.replica_id, r.offset, Instant::now());
+                    self.update_replica(&r.topic, r.partition, replica)
+                };
+                (replica.high_watermark, advanced)
+            };
+            if advanced {
+                self.appended.notify_waiters();
+            }
+
+            let Some(items) = items else {
+                return Ok(ReplicaFetchResponse {
+                    status: status::OFFSET_OUT_OF_RANGE,
+                    high_watermark,
+                    log_start_offset,
+                    epochs: vec![],
+                    items: vec![],
+                });
+            };
+            let items = items.map_err(|e| e.to_string())?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Some(&(last, _)) = items.last() {
+                return Ok(ReplicaFetchResponse {
+                    status: status::OK,
+                    high_watermark,
+                    log_start_offset,
+                    epochs: epochs_between(&epochs, r.offset, last),
+                    items,
+                });
+            }
+            if remaining.is_zero() {
+                return Ok(ReplicaFetchResponse {
+                    status: status::OK,
+                    high_watermark,
+                    log_start_offset,
+                    epochs: vec![],
+                    items,
+                });
+            }
+            let _ = tokio::time::timeout(remaining, appended).await;
+        }
+    }
+
+    /// Cuts the log back to where it matches the leader's, then copies the
+    /// records of `leader` until the replica is dropped. Stops once the
+    /// leader is in a later epoch, or the controller's registry has moved the
+    /// partition on; the broker then takes up the new leadership as it learns
+    /// of it.
+    async fn follow(
+        self: Arc<Self>,
+        topic: String,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+    ) {
+        let peer = Peer::new(
+            self.config.peers[&leader].clone(),
+            self.config.inter_broker_login.clone(),
+        );
+        let mut truncated = false;
+        let mut rejected = None;
+        loop {
+            let res = if truncated {
+                self.fetch_from_leader(&peer, &topic, partition, leader_epoch)
+                    .await
+            } else {
+                self.truncate_to_leader(&peer, &topic, partition, leader_epoch)
+                    .await
+                    .map(|()| truncated = true)
+            };
+            match res {
+                Ok(()) => rejected = None,
+                Err(FollowError::Rejected(status)) => {
+                    // warned once for every change, not for every retry
+                    if rejected != Some(status) {
+                        tracing::warn!(
+                            %topic,
+                            partition,
+                            leader,
+                            leader_epoch,
+                            status = status::name(status),
+                            "leader rejected the follower"
+                        );
+                    }
+                    rejected = Some(status);
+                    if status == status::FENCED_LEADER_EPOCH
+                        || self.leadership_moved(&topic, partition, leader, leader_epoch)
+                    {
+                        tracing::warn!(%topic, partition, leader, leader_epoch, "stopped following");
+                        return;
+                    }
+                    tokio::time::sleep(self.config.replication.fetch_backoff).await;
+                }
+                Err(FollowError::Failed(e)) => {
+                    tracing::debug!(%topic, partition, leader, error = %e, "replica fetch failed");
+                    tokio::time::sleep(self.config.replication.fetch_backoff).await;
+                }
+            }
+        }
+    }
+
+    /// Whether the controller's registry has another leader or epoch for the
+    /// partition than `leader` in `leader_epoch`, or no longer puts it on this
+    /// broker. Without a controller, leadership only moves when it is handed
+    /// to the broker.
+    fn leadership_moved(
+        &self,
+        topic: &str,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+    ) -> bool {
+        let Some(controller) = self.controller.get() else {
+            return false;
+        };
+        controller
+            .registry()
+            .partition(topic, partition)
+            .is_none_or(|p| {
+                p.leader != leader
+                    || p.leader_epoch != leader_epoch
+                    || !p.replicas.contains(&self.config.broker_id)
+            })
+    }
+
+    /// Removes the records of the log past the end of its latest epoch in
+    /// the leader's log, which the leader doesn't have, as when this broker
+    /// led the partition before and its last records were never replicated.
+    /// Logs without epochs predate them and are kept.
+    async fn truncate_to_leader(
+        &self,
+        peer: &Peer,
+        topic: &str,
+        partition: u16,
+        leader_epoch: i32,
+    ) -> Result<(), FollowError> {
+        let log = self.get_or_open(topic, partition).await?;
+        let latest = log.latest_epoch();
+        self.put_back(topic, partition, log).await;
+        let Some(latest) = latest else {
+            return Ok(());
+        };
+
+        let req = Request::OffsetForLeaderEpoch(OffsetForLeaderEpochRequest {
+            replica_id: self.config.broker_id,
+            topic: topic.to_string(),
+            partition,
+            current_leader_epoch: leader_epoch,
+            leader_epoch: latest,
+        });
+        let resp = match peer.request(&req).await.map_err(|e| e.to_string())? {
+            Response::OffsetForLeaderEpoch(r) => r,
+            Response::Error { message } => return Err(message.into()),
+            other => return Err(format!("unexpected response {other:?}").into()),
+        };
+        if resp.status != status::OK {
+            return Err(FollowError::Rejected(resp.status));
+        }
+
+        let mut log = self.get_or_open(topic, partition).await?;
+        let end = if resp.leader_epoch < 0 {
+            // the leader has nothing of our epochs; only what every replica
+            // had is sure to match
+            let map = self.replicas.lock().unwrap();
+            map.get(&(topic.to_string(), partition))
+                .map_or(log.start_offset(), |r| r.high_watermark)
+        } else {
+            // the epoch may end earlier here, where an epoch the leader never
+            // had starts
+            let (_, own_end) = log.end_offset_for_epoch(resp.leader_epoch);
+            let own_end = if own_end < 0 {
+                log.epochs()
+                    .first()
+                    .map_or(log.next_offset(), |&(_, start)| start)
+            } else {
+                own_end
+            };
+            resp.end_offset.min(own_end)
+        };
+        let log_end_offset = log.next_offset();
+        let res = log.truncate(end).map_err(|e| e.to_string());
+        self.put_back(topic, partition, log).await;
+        res?;
+        if end < log_end_offset {
+            tracing::info!(
+                topic,
+                partition,
+                from = end,
+                to = log_end_offset,
+                "removed records the leader doesn't have"
+            );
+        }
+        Ok(())
     }
 
+    /// The state of a partition this broker leads, for the controller to see
+    /// how far new replicas have come.
+    fn describe_partition(&self, topic: &str, partition: u16) -> DescribePartitionResponse {
+        let mut map = self.replicas.lock().unwrap();
+        match map.get_mut(&(topic.to_string(), partition)) {
+            Some(r) if r.leader == self.config.broker_id => {
+                self.update_replica(topic, partition, r);
+                DescribePartitionResponse {
+                    status: status::OK,
+                    leader_epoch: r.leader_epoch,
+                    log_end_offset: r.log_end_offset,
+                    high_watermark: r.high_watermark,
+                    isr: r.isr.clone(),
+                    replica_offsets: r.follower_offsets.clone().into_iter().collect(),
+                }
+            }
+            _ => describe_partition_error(status::NOT_LEADER_OR_FOLLOWER),
+        }
+    }
+
+    /// Answers a follower asking where an epoch of its log ends in ours.
+    async fn offset_for_leader_epoch(
+        &self,
+        r: OffsetForLeaderEpochRequest,
+    ) -> Result<OffsetForLeaderEpochResponse, String> {
+        if let Err(status) =
+            self.check_follower(&r.topic, r.partition, r.replica_id, r.current_leader_epoch)
+        {
+            return Ok(OffsetForLeaderEpochResponse {
+                status,
+                leader_epoch: -1,
+                end_offset: -1,
+            });
+        }
+        let log = self.get_or_open(&r.topic, r.partition).await?;
+        let (leader_epoch, end_offset) = log.end_offset_for_epoch(r.leader_epoch);
+        self.put_back(&r.topic, r.partition, log).await;
+        Ok(OffsetForLeaderEpochResponse {
+            status: status::OK,
+            leader_epoch,
+            end_offset,
+        })
+    }
+
+    /// Copies the next records of the leader's log, then waits as long as the
+    /// replica's throttle takes to allow their bytes.
+    async fn fetch_from_leader(
+        &self,
+        peer: &Peer,
+        topic: &str,
+        partition: u16,
+        leader_epoch: i32,
+    ) -> Result<(), FollowError> {
+        let log = self.get_or_open(topic, partition).await?;
+        let offset = log.next_offset();
+        self.put_back(topic, partition, log).await;
+        let key = (topic.to_string(), partition);
+        let throttle = self
+            .replicas
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(|r| r.throttle);
+
+        let max_wait = self.config.replication.fetch_max_wait.as_millis();
+        let req = Request::ReplicaFetch(ReplicaFetchRequest {
+            replica_id: self.config.broker_id,
+            topic: topic.to_string(),
+            partition,
+            leader_epoch,
+            offset,
+            max_bytes: self.config.replication.fetch_max_bytes,
+            max_wait_ms: max_wait.try_into().unwrap_or(u32::MAX),
+        });
+        let resp = match peer.request(&req).await.map_err(|e| e.to_string())? {
+            Response::ReplicaFetch(r) => r,
+            Response::Error { message } => return Err(message.into()),
+            other => return Err(format!("unexpected response {other:?}").into()),
+        };
+        if matches!(
+            resp.status,
+            status::FENCED_LEADER_EPOCH
+                | status::UNKNOWN_LEADER_EPOCH
+                | status::NOT_LEADER_OR_FOLLOWER
+        ) {
+            return Err(FollowError::Rejected(resp.status));
+        }
+
+        let bytes: usize = resp.items.iter().map(|(_, r)| r.stored_len()).sum();
+        let mut log = self.get_or_open(topic, partition).await?;
+        let res = append_fetched(&mut log, offset, resp);
+        self.put_back(topic, partition, log).await;
+        let high_watermark = res?;
+
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&key) {
+            replica.high_watermark = replica.high_watermark.max(high_watermark);
+        }
+        if let Some(rate) = throttle
+            && bytes > 0
+        {
+            tokio::time::sleep(Duration::from_secs_f64(bytes as f64 / rate as f64)).await;
+        }
+        Ok(())
+    }
+
+    /// Zero-copy counterpart of `Request::Fetch`, for transports that can send file
+    /// ranges directly. `user` is the authenticated user, as for `handle_as`.
+    pub async fn fetch_region(
+        &self,
+        user: Option<&str>,
+        r: &FetchRequest,
+    ) -> Result<FetchRegion, String> {
+        let req = Request::Fetch(FetchRequest {
+            topic: r.topic.clone(),
+            partition: r.partition,
+            offset: r.offset,
+            max_bytes: r.max_bytes,
+        });
+        if let Some(resp) = self.check_acls(&principal_name(user), &req).await? {
+            let Response::Fetch(resp) = resp else {
+                return Err(format!("unexpected acl response: {resp:?}"));
+            };
+            return Ok(FetchRegion {
+                status: resp.status,
+                high_watermark: resp.high_watermark,
+                log_start_offset: resp.log_start_offset,
+                region: None,
+            });
+        }
+
+        let log = match self.open_partition(&r.topic, r.partition).await? {
+            Ok(log) => log,
+            Err(status) => {
+                return Ok(FetchRegion {
+                    status,
+                    high_watermark: -1,
+                    log_start_offset: -1,
+                    region: None,
+                });
+            }
+        };
+
+        let high_watermark = match self.readable_end(&r.topic, r.partition, &log) {
+            Ok(end) => end,
+            Err(status) => {
+                self.put_back(&r.topic, r.partition, log).await;
+                return Ok(FetchRegion {
+                    status,
+                    high_watermark: -1,
+                    log_start_offset: -1,
+                    region: None,
+                });
+            }
+        };
+        let log_start_offset = log.start_offset();
+        let res = if r.offset < log_start_offset || r.offset > log.next_offset() {
+            Ok(FetchRegion {
+                status: status::OFFSET_OUT_OF_RANGE,
+                high_watermark,
+                log_start_offset,
+                region: None,
+            })
+        } else {
+            log.fetch_region_until(r.offset, high_watermark, r.max_bytes)
+                .map(|region| {
+                    bytes_out(&r.topic).add(region.len);
+                    FetchRegion {
+                        status: status::OK,
+                        high_watermark,
+                        log_start_offset,
+                        region: Some(region),
+                    }
+                })
+                .map_err(|e| format!("fetch error: {e}"))
+        };
+
+        self.put_back(&r.topic, r.partition, log).await;
+
+        res
+    }
+
+    /// Handles `req` on behalf of an unauthenticated client.
     pub async fn handle(&self, req: Request) -> Response {
+        self.handle_as(None, req).await
+    }
+
+    /// Handles `req` on behalf of `user`, the name the client authenticated as,
+    /// after checking it against the ACLs.
+    pub async fn handle_as(&self, user: Option<&str>, req: Request) -> Response {
+        let principal = principal_name(user);
+        match self.check_acls(&principal, &req).await {
+            Ok(None) => {}
+            Ok(Some(resp)) => return resp,
+            Err(e) => {
+                return Response::Error {
+                    message: format!("acl error: {e}"),
+                };
+            }
+        }
+
         match req {
             Request::Produce(r) => {
-                let mut log = match self.get_or_open(&r.topic, r.partition).await {
-                    Ok(x) => x,
+                if !matches!(r.acks, -1..=1) {
+                    return denied(&Request::Produce(r), status::INVALID_REQUIRED_ACKS);
+                }
+                let mut log = match self.open_partition(&r.topic, r.partition).await {
+                    Ok(Ok(x)) => x,
+                    Ok(Err(status)) => {
+                        return Response::Produce(ProduceResponse {
+                            status,
+                            base_offset: -1,
+                            throttle_time_ms: 0,
+                        });
+                    }
                     Err(e) => return Response::Error { message: e },
                 };
 
-                let resp = match log.append(&r.records) {
-                    Ok(base) => Response::Produce(ProduceResponse {
-                        status: 0,
-                        base_offset: base,
-                    }),
+                if let Err(status) = self.check_produce(&r.topic, r.partition, r.acks) {
+                    self.put_back(&r.topic, r.partition, log).await;
+                    return Response::Produce(ProduceResponse {
+                        status,
+                        base_offset: -1,
+                        throttle_time_ms: 0,
+                    });
+                }
+                let mut resp = match log.append(&r.records) {
+                    Ok(base) => {
+                        self.appended_on_leader(&r.topic, r.partition, log.next_offset());
+                        let bytes = r.records.iter().map(Record::stored_len).sum::<usize>();
+                        bytes_in(&r.topic).add(bytes as u64);
+                        Response::Produce(ProduceResponse {
+                            status: 0,
+                            base_offset: base,
+                            throttle_time_ms: 0,
+                        })
+                    }
+                    Err(StorageError::RecordTooLarge { .. }) => {
+                        Response::Produce(ProduceResponse {
+                            status: status::RECORD_TOO_LARGE,
+                            base_offset: -1,
+                            throttle_time_ms: 0,
+                        })
+                    }
                     Err(e) => Response::Error {
                         message: format!("append error: {e}"),
                     },
                 };
 
+                let end = log.next_offset();
                 self.put_back(&r.topic, r.partition, log).await;
+                self.appended.notify_waiters();
 
+                if r.acks == ACKS_ALL
+                    && let Response::Produce(p) = &mut resp
+                    && p.status == status::OK
+                {
+                    p.status = self.await_replication(&r.topic, r.partition, end).await;
+                    if p.status != status::OK {
+                        p.base_offset = -1;
+                    }
+                }
                 resp
             }
 
             Request::Fetch(r) => {
-                let log = match self.get_or_open(&r.topic, r.partition).await {
-                    Ok(x) => x,
+                let log = match self.open_partition(&r.topic, r.partition).await {
+                    Ok(Ok(x)) => x,
+                    Ok(Err(status)) => {
+                        return Response::Fetch(FetchResponse {
+                            status,
+                            high_watermark: -1,
+                            log_start_offset: -1,
+                            throttle_time_ms: 0,
+                            items: vec![],
+                        });
+                    }
                     Err(e) => return Response::Error { message: e },
                 };
 
-                let resp = match log.fetch(r.offset, r.max_bytes) {
-                    Ok(items) => Response::Fetch(FetchResponse { status: 0, items }),
-                    Err(e) => Response::Error {
-                        message: format!("fetch error: {e}"),
-                    },
+                let high_watermark = match self.readable_end(&r.topic, r.partition, &log) {
+                    Ok(end) => end,
+                    Err(status) => {
+                        self.put_back(&r.topic, r.partition, log).await;
+                        return denied(&Request::Fetch(r), status);
+                    }
+                };
+                let log_start_offset = log.start_offset();
+                let resp = if r.offset < log_start_offset || r.offset > log.next_offset() {
+                    Response::Fetch(FetchResponse {
+                        status: status::OFFSET_OUT_OF_RANGE,
+                        high_watermark,
+                        log_start_offset,
+                        throttle_time_ms: 0,
+                        items: vec![],
+                    })
+                } else {
+                    match log.fetch(r.offset, r.max_bytes) {
+                        Ok(mut items) => {
+                            items.retain(|&(offset, _)| offset < high_watermark);
+                            let bytes =
+                                items.iter().map(|(_, rec)| rec.stored_len()).sum::<usize>();
+                            bytes_out(&r.topic).add(bytes as u64);
+                            Response::Fetch(FetchResponse {
+                                status: 0,
+                                high_watermark,
+                                log_start_offset,
+                                throttle_time_ms: 0,
+                                items,
+                            })
+                        }
+                        Err(e) => Response::Error {
+                            message: format!("fetch error: {e}"),
+                        },
+                    }
                 };
 
                 self.put_back(&r.topic, r.partition, log).await;
 
                 resp
             }
+
+            Request::ListOffsets(r) => {
+                let log = match self.open_partition(&r.topic, r.partition).await {
+                    Ok(Ok(x)) => x,
+                    Ok(Err(status)) => {
+                        return Response::ListOffsets(ListOffsetsResponse { status, offset: -1 });
+                    }
+                    Err(e) => return Response::Error { message: e },
+                };
+
+                // records carry no timestamps, so only the two special lookups can be answered
+                let resp = match self.readable_end(&r.topic, r.partition, &log) {
+                    Ok(end) => {
+                        let offset = match r.timestamp {
+                            EARLIEST_TIMESTAMP => log.start_offset(),
+                            LATEST_TIMESTAMP => end,
+                            _ => -1,
+                        };
+                        ListOffsetsResponse { status: 0, offset }
+                    }
+                    Err(status) => ListOffsetsResponse { status, offset: -1 },
+                };
+
+                self.put_back(&r.topic, r.partition, log).await;
+
+                Response::ListOffsets(resp)
+            }
+
+            Request::Metadata(r) => {
+                let allow_auto_create = r.allow_auto_create && self.config.auto_create_topics;
+                let existing = self.known_topics();
+                let topics = if r.topics.is_empty() {
+                    // topics the client may not describe are left out
+                    let mut topics = Vec::with_capacity(existing.len());
+                    for (name, partitions) in existing {
+                        match self
+                            .authorize(
+                                &principal,
+                                AclOperation::Describe,
+                                ResourceType::Topic,
+                                &name,
+                            )
+                            .await
+                        {
+                            Ok(true) => topics.push(TopicMetadata {
+                                status: 0,
+                                name,
+                                partitions: partitions.into_iter().collect(),
+                            }),
+                            Ok(false) => {}
+                            Err(e) => return Response::Error { message: e },
+                        }
+                    }
+                    topics
+                } else {
+                    let mut topics = Vec::with_capacity(r.topics.len());
+                    for name in r.topics {
+                        let operation = if existing.contains_key(&name) || !allow_auto_create {
+                            AclOperation::Describe
+                        } else {
+                            AclOperation::Create
+                        };
+                        match self
+                            .authorize(&principal, operation, ResourceType::Topic, &name)
+                            .await
+                        {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                topics.push(TopicMetadata {
+                                    status: status::TOPIC_AUTHORIZATION_FAILED,
+                                    name,
+                                    partitions: vec![],
+                                });
+                                continue;
+                            }
+                            Err(e) => return Response::Error { message: e },
+                        }
+
+                        let meta = match existing.get(&name) {
+                            Some(partitions) => TopicMetadata {
+                                status: 0,
+                                name,
+                                partitions: partitions.iter().copied().collect(),
+                            },
+                            None if allow_auto_create => {
+                                match self.auto_create_topic(&name).await {
+                                    Ok(Ok(partitions)) => TopicMetadata {
+                                        status: 0,
+                                        name,
+                                        partitions,
+                                    },
+                                    Ok(Err(status)) => TopicMetadata {
+                                        status,
+                                        name,
+                                        partitions: vec![],
+                                    },
+                                    Err(e) => return Response::Error { message: e },
+                                }
+                            }
+                            None => TopicMetadata {
+                                status: status::UNKNOWN_TOPIC_OR_PARTITION,
+                                name,
+                                partitions: vec![],
+                            },
+                        };
+                        topics.push(meta);
+                    }
+                    topics
+                };
+
+                Response::Metadata(MetadataResponse { topics })
+            }
+
+            Request::OffsetCommit(r) => {
+                match self
+                    .commit_offset(r.group_id, r.topic, r.partition, r.offset)
+                    .await
+                {
+                    Ok(()) => Response::OffsetCommit(OffsetCommitResponse { status: 0 }),
+                    Err(e) => Response::Error {
+                        message: format!("offset commit error: {e}"),
+                    },
+                }
+            }
+
+            Request::OffsetFetch(r) => {
+                match self
+                    .committed_offset(r.group_id, r.topic, r.partition)
+                    .await
+                {
+                    Ok(offset) => Response::OffsetFetch(OffsetFetchResponse { status: 0, offset }),
+                    Err(e) => Response::Error {
+                        message: format!("offset fetch error: {e}"),
+                    },
+                }
+            }
+
+            Request::JoinGroup(r) => Response::JoinGroup(self.groups.join(r).await),
+            Request::SyncGroup(r) => Response::SyncGroup(self.groups.sync(r).await),
+            Request::Heartbeat(r) => Response::Heartbeat(self.groups.heartbeat(r).await),
+            Request::LeaveGroup(r) => Response::LeaveGroup(self.groups.leave(r).await),
+
+            Request::CreateAcls(r) => match self.update_acls(r.acls, true).await {
+                Ok(_) => Response::CreateAcls(CreateAclsResponse { status: 0 }),
+                Err(e) => Response::Error {
+                    message: format!("acl error: {e}"),
+                },
+            },
+
+            Request::DeleteAcls(r) => match self.update_acls(r.acls, false).await {
+                Ok(deleted) => Response::DeleteAcls(DeleteAclsResponse { status: 0, deleted }),
+                Err(e) => Response::Error {
+                    message: format!("acl error: {e}"),
+                },
+            },
+
+            Request::DescribeAcls(_) => match self.load_acls().await {
+                Ok(acls) => Response::DescribeAcls(DescribeAclsResponse {
+                    status: 0,
+                    acls: acls
+                        .as_ref()
+                        .map(|a| a.bindings().to_vec())
+                        .unwrap_or_default(),
+                }),
+                Err(e) => Response::Error {
+                    message: format!("acl error: {e}"),
+                },
+            },
+
+            Request::CreateTopic(r) => match self.create_topic(&r.topic, r.partitions).await {
+                Ok(status) => Response::CreateTopic(CreateTopicResponse { status }),
+                Err(e) => Response::Error {
+                    message: format!("create topic error: {e}"),
+                },
+            },
+
+            Request::CreatePartitions(r) => {
+                match self.create_partitions(&r.topic, r.partitions).await {
+                    Ok(status) => Response::CreatePartitions(CreatePartitionsResponse { status }),
+                    Err(e) => Response::Error {
+                        message: format!("create partitions error: {e}"),
+                    },
+                }
+            }
+
+            Request::DeleteTopic(r) => match self.delete_topic(&r.topic).await {
+                Ok(status) => Response::DeleteTopic(DeleteTopicResponse { status }),
+                Err(e) => Response::Error {
+                    message: format!("delete topic error: {e}"),
+                },
+            },
+
+            Request::ListGroups(_) => match self.list_groups(&principal).await {
+                Ok(groups) => Response::ListGroups(ListGroupsResponse { status: 0, groups }),
+                Err(e) => Response::Error {
+                    message: format!("list groups error: {e}"),
+                },
+            },
+
+            Request::DescribeGroup(r) => match self.describe_group(&r.group_id).await {
+                Ok(resp) => Response::DescribeGroup(resp),
+                Err(e) => Response::Error {
+                    message: format!("describe group error: {e}"),
+                },
+            },
+
+            Request::ReplicaFetch(r) => match self.replica_fetch(r).await {
+                Ok(resp) => Response::ReplicaFetch(resp),
+                Err(e) => Response::Error {
+                    message: format!("replica fetch error: {e}"),
+                },
+            },
+
+            Request::OffsetForLeaderEpoch(r) => match self.offset_for_leader_epoch(r).await {
+                Ok(resp) => Response::OffsetForLeaderEpoch(resp),
+                Err(e) => Response::Error {
+                    message: format!("offset for leader epoch error: {e}"),
+                },
+            },
+
+            Request::DescribePartition(r) => {
+                Response::DescribePartition(self.describe_partition(&r.topic, r.partition))
+            }
+
+            Request::AlterPartitionReassignments(r) => {
+                let status = match self.controller.get() {
+                    Some(controller) => {
+                        controller
+                            .reassign_partition(
+                                &r.topic,
+                                r.partition,
+                                r.replicas,
+                                r.throttle_bytes_per_sec,
+                            )
+                            .await
+                    }
+                    None => status::NOT_CONTROLLER,
+                };
+                Response::AlterPartitionReassignments(AlterPartitionReassignmentsResponse {
+                    status,
+                })
+            }
+
+            Request::ListPartitionReassignments(_) => {
+                let listed = match self.controller.get() {
+                    Some(controller) => controller.reassignments(),
+                    None => Err(status::NOT_CONTROLLER),
+                };
+                let (status, reassignments) = match listed {
+                    Ok(reassignments) => (status::OK, reassignments),
+                    Err(status) => (status, vec![]),
+                };
+                Response::ListPartitionReassignments(ListPartitionReassignmentsResponse {
+                    status,
+                    reassignments,
+                })
+            }
+
+            Request::Vote(r) => match self.controller.get() {
+                Some(controller) => match controller.handle_vote(r) {
+                    Ok(resp) => Response::Vote(resp),
+                    Err(e) => Response::Error {
+                        message: format!("vote error: {e}"),
+                    },
+                },
+                None => not_a_controller(),
+            },
+
+            Request::AppendEntries(r) => match self.controller.get() {
+                Some(controller) => match controller.handle_append_entries(r) {
+                    Ok(resp) => Response::AppendEntries(resp),
+                    Err(e) => Response::Error {
+                        message: format!("append entries error: {e}"),
+                    },
+                },
+                None => not_a_controller(),
+            },
+
+            // authentication belongs to the connection and is done by `net`
+            Request::SaslHandshake(_) | Request::SaslAuthenticate(_) => Response::Error {
+                message: "SASL requests are handled by the listener".to_string(),
+            },
+        }
+    }
+}
+
+/// The response to `req` carrying only the error `status`.
+fn denied(req: &Request, status: u8) -> Response {
+    match req {
+        Request::Produce(_) => Response::Produce(ProduceResponse {
+            status,
+            base_offset: -1,
+            throttle_time_ms: 0,
+        }),
+        Request::Fetch(_) => Response::Fetch(FetchResponse {
+            status,
+            high_watermark: -1,
+            log_start_offset: -1,
+            throttle_time_ms: 0,
+            items: vec![],
+        }),
+        Request::ReplicaFetch(_) => Response::ReplicaFetch(ReplicaFetchResponse {
+            status,
+            high_watermark: -1,
+            log_start_offset: -1,
+            epochs: vec![],
+            items: vec![],
+        }),
+        Request::OffsetForLeaderEpoch(_) => {
+            Response::OffsetForLeaderEpoch(OffsetForLeaderEpochResponse {
+                status,
+                leader_epoch: -1,
+                end_offset: -1,
+            })
+        }
+        Request::ListOffsets(_) => {
+            Response::ListOffsets(ListOffsetsResponse { status, offset: -1 })
+        }
+        Request::OffsetCommit(_) => Response::OffsetCommit(OffsetCommitResponse { status }),
+        Request::OffsetFetch(_) => {
+            Response::OffsetFetch(OffsetFetchResponse { status, offset: -1 })
+        }
+        Request::JoinGroup(r) => Response::JoinGroup(group::join_error(status, &r.member_id)),
+        Request::SyncGroup(_) => Response::SyncGroup(group::sync_error(status)),
+        Request::Heartbeat(_) => Response::Heartbeat(HeartbeatResponse { status }),
+        Request::LeaveGroup(_) => Response::LeaveGroup(LeaveGroupResponse { status }),
+        Request::CreateAcls(_) => Response::CreateAcls(CreateAclsResponse { status }),
+        Request::DeleteAcls(_) => Response::DeleteAcls(DeleteAclsResponse { status, deleted: 0 }),
+        Request::DescribeAcls(_) => Response::DescribeAcls(DescribeAclsResponse {
+            status,
+            acls: vec![],
+        }),
+        Request::CreateTopic(_) => Response::CreateTopic(CreateTopicResponse { status }),
+        Request::CreatePartitions(_) => {
+            Response::CreatePartitions(CreatePartitionsResponse { status })
+        }
+        Request::DeleteTopic(_) => Response::DeleteTopic(DeleteTopicResponse { status }),
+        Request::ListGroups(_) => Response::ListGroups(ListGroupsResponse {
+            status,
+            groups: vec![],
+        }),
+        Request::DescribeGroup(_) => Response::DescribeGroup(group::describe_error(status, "")),
+        Request::DescribePartition(_) => {
+            Response::DescribePartition(describe_partition_error(status))
+        }
+        Request::AlterPartitionReassignments(_) => {
+            Response::AlterPartitionReassignments(AlterPartitionReassignmentsResponse { status })
+        }
+        Request::ListPartitionReassignments(_) => {
+            Response::ListPartitionReassignments(ListPartitionReassignmentsResponse {
+                status,
+                reassignments: vec![],
+            })
+        }
+        Request::Vote(_) => Response::Vote(VoteResponse {
+            status,
+            term: -1,
+            vote_granted: false,
+        }),
+        Request::AppendEntries(_) => Response::AppendEntries(AppendEntriesResponse {
+            status,
+            term: -1,
+            success: false,
+            log_end_offset: -1,
+        }),
+        Request::Metadata(_) | Request::SaslHandshake(_) | Request::SaslAuthenticate(_) => {
+            Response::Error {
+                message: format!("not authorized (status {status})"),
+            }
+        }
+    }
+}
+
+fn describe_partition_error(status: u8) -> DescribePartitionResponse {
+    DescribePartitionResponse {
+        status,
+        leader_epoch: -1,
+        log_end_offset: -1,
+        high_watermark: -1,
+        isr: vec![],
+        replica_offsets: vec![],
+    }
+}
+
+fn not_a_controller() -> Response {
+    Response::Error {
+        message: "this broker is not a controller".to_string(),
+    }
+}
+
+/// The epochs of `epochs` that records `from..=to` were appended in.
+fn epochs_between(epochs: &[(i32, i64)], from: i64, to: i64) -> Vec<(i32, i64)> {
+    let first = epochs.partition_point(|&(_, start)| start <= from);
+    epochs[first.saturating_sub(1)..]
+        .iter()
+        .take_while(|&&(_, start)| start <= to)
+        .copied()
+        .collect()
+}
+
+/// Appends the records a follower fetched from `offset`, each in the leader
+/// epoch the leader appended it in, returning the high watermark the leader
+/// sent, as far as the log has come.
+/// Why a follower could not copy from its leader.
+enum FollowError {
+    /// The leader answered with this status, as when it is in another epoch
+    /// or doesn't take this broker as a follower.
+    Rejected(u8),
+    Failed(String),
+}
+
+impl From<String> for FollowError {
+    fn from(e: String) -> Self {
+        FollowError::Failed(e)
+    }
+}
+
+fn append_fetched(
+    log: &mut PartitionLog,
+    offset: i64,
+    resp: ReplicaFetchResponse,
+) -> Result<i64, String> {
+    match resp.status {
+        status::OK => {}
+        // the leader's log starts past ours, as after retention
+        status::OFFSET_OUT_OF_RANGE if offset < resp.log_start_offset => {
+            log.reset(resp.log_start_offset)
+                .map_err(|e| e.to_string())?;
+        }
+        status => return Err(status::name(status).to_string()),
+    }
+    if let Some(&(first, _)) = resp.items.first()
+        && first != log.next_offset()
+    {
+        return Err(format!(
+            "fetched offset {first} while the log ends at {}",
+            log.next_offset()
+        ));
+    }
+    let mut items = resp.items.into_iter().peekable();
+    let mut epochs = resp.epochs.into_iter().peekable();
+    while items.peek().is_some() {
+        // the epoch of the next record, and where the one after starts
+        while let Some(&(epoch, start)) = epochs.peek()
+            && start <= log.next_offset()
+        {
+            log.assign_epoch(epoch).map_err(|e| e.to_string())?;
+            epochs.next();
         }
+        let until = epochs.peek().map_or(i64::MAX, |&(_, start)| start);
+        let records: Vec<Record> = std::iter::from_fn(|| items.next_if(|(o, _)| *o < until))
+            .map(|(_, r)| r)
+            .collect();
+        log.append(&records).map_err(|e| e.to_string())?;
     }
+    Ok(resp.high_watermark.min(log.next_offset()))
+}
+
+/// Names that are safe as file names: ASCII letters, digits, `.`, `_` and
+/// `-`, as in Kafka.
+fn valid_topic_name(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= 249
+        && topic != "."
+        && topic != ".."
+        && topic
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+fn bytes_in(topic: &str) -> std::sync::Arc<metrics::Counter> {
+    metrics::counter(
+        "mini_kafka_topic_bytes_in_total",
+        "Record bytes appended to a topic",
+        &[("topic", topic)],
+    )
+}
+
+fn bytes_out(topic: &str) -> std::sync::Arc<metrics::Counter> {
+    metrics::counter(
+        "mini_kafka_topic_bytes_out_total",
+        "Record bytes fetched from a topic",
+        &[("topic", topic)],
+    )
 }
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Rate limits of one client. `None` leaves a rate unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub produce_bytes_per_sec: Option<u64>,
+    pub fetch_bytes_per_sec: Option<u64>,
+    pub requests_per_sec: Option<u64>,
+}
+
+/// Quotas keyed by authenticated user, then by client id. A client matching
+/// neither gets its own allowance of `default`.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaConfig {
+    pub default: Quota,
+    pub users: HashMap<String, Quota>,
+    pub clients: HashMap<String, Quota>,
+}
+
+/// What one request counts against its client's quotas, besides the request
+/// itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub produce_bytes: u64,
+    pub fetch_bytes: u64,
+}
+
+// a client may run this far ahead of its rates before being throttled
+const BURST: Duration = Duration::from_secs(1);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum QuotaKey {
+    User(String),
+    Client(String),
+    Default(Option<String>, String),
+}
+
+/// Usage of one quota, as the time at which the client will be back within its
+/// rate if it sends nothing more.
+#[derive(Debug, Default)]
+struct Rates {
+    produce: Option<Instant>,
+    fetch: Option<Instant>,
+    requests: Option<Instant>,
+}
+
+pub(crate) struct Quotas {
+    config: QuotaConfig,
+    rates: HashMap<QuotaKey, Rates>,
+    last_prune: Instant,
+}
+
+impl Quotas {
+    pub(crate) fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            rates: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Counts a request against the quota of `user` or `client_id` and returns
+    /// how long its response should be held back.
+    pub(crate) fn record(&mut self, user: Option<&str>, client_id: &str, usage: Usage) -> Duration {
+        let (key, quota) = self.resolve(user, client_id);
+        if quota == Quota::default() {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        self.prune(now);
+
+        let rates = self.rates.entry(key).or_default();
+        [
+            (
+                &mut rates.produce,
+                usage.produce_bytes,
+                quota.produce_bytes_per_sec,
+            ),
+            (
+                &mut rates.fetch,
+                usage.fetch_bytes,
+                quota.fetch_bytes_per_sec,
+            ),
+            (&mut rates.requests, 1, quota.requests_per_sec),
+        ]
+        .into_iter()
+        .filter(|&(_, amount, _)| amount > 0)
+        .filter_map(|(until, amount, rate)| Some(charge(until, now, amount, rate?)))
+        .max()
+        .unwrap_or_default()
+    }
+
+    fn resolve(&self, user: Option<&str>, client_id: &str) -> (QuotaKey, Quota) {
+        if let Some(user) = user
+            && let Some(quota) = self.config.users.get(user)
+        {
+            return (QuotaKey::User(user.to_string()), quota.clone());
+        }
+        if let Some(quota) = self.config.clients.get(client_id) {
+            return (QuotaKey::Client(client_id.to_string()), quota.clone());
+        }
+        let key = QuotaKey::Default(user.map(str::to_string), client_id.to_string());
+        (key, self.config.default.clone())
+    }
+
+    // forgets clients that have been back within their rates for a while
+    fn prune(&mut self, now: Instant) {
+        if now.duration_since(self.last_prune) < PRUNE_INTERVAL {
+            return;
+        }
+        self.last_prune = now;
+        self.rates.retain(|_, r| {
+            [r.produce, r.fetch, r.requests]
+                .into_iter()
+                .flatten()
+                .any(|until| until > now)
+        });
+    }
+}
+
+/// Adds `amount` at `rate` per second to the usage ending at `until` and returns
+/// how far that runs ahead of the rate past the burst allowance.
+fn charge(until: &mut Option<Instant>, now: Instant, amount: u64, rate: u64) -> Duration {
+    let start = until.map_or(now, |t| t.max(now));
+    let end = start + Duration::from_secs_f64(amount as f64 / rate.max(1) as f64);
+    *until = Some(end);
+    end.saturating_duration_since(now + BURST)
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use tokio::task::AbortHandle;
+
+/// How followers fetch from their leaders.
+#[derive(Debug, Clone)]
+pub struct ReplicationConfig {
+    /// Most record bytes a follower asks for at once.
+    pub fetch_max_bytes: u32,
+    /// How long the leader holds a fetch that finds no new records.
+    pub fetch_max_wait: Duration,
+    /// How long a follower waits after a failed fetch.
+    pub fetch_backoff: Duration,
+    /// Followers whose log hasn't reached the end of the leader's for this
+    /// long drop out of the in-sync replicas.
+    pub lag_time_max: Duration,
+    /// In-sync replicas, the leader included, that `ACKS_ALL` produce
+    /// requests need.
+    pub min_insync_replicas: usize,
+    /// How long an `ACKS_ALL` produce request waits for the in-sync replicas.
+    pub ack_timeout: Duration,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            fetch_max_bytes: 1024 * 1024,
+            fetch_max_wait: Duration::from_millis(500),
+            fetch_backoff: Duration::from_millis(100),
+            lag_time_max: Duration::from_secs(10),
+            min_insync_replicas: 1,
+            ack_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What a broker knows about a partition it holds a replica of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionState {
+    pub leader: u32,
+    /// Goes up with every change of leader.
+    pub leader_epoch: i32,
+    pub replicas: Vec<u32>,
+    /// The replicas whose logs keep up with the leader's, the leader
+    /// included. Only known to the leader.
+    pub isr: Vec<u32>,
+    /// Records below it are on every in-sync replica, and only those are
+    /// given to consumers.
+    pub high_watermark: i64,
+    /// Where each follower's log ended at its last fetch. Only known to the
+    /// leader.
+    pub follower_offsets: BTreeMap<u32, i64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Replica {
+    pub(crate) leader: u32,
+    pub(crate) leader_epoch: i32,
+    pub(crate) replicas: Vec<u32>,
+    pub(crate) isr: Vec<u32>,
+    pub(crate) high_watermark: i64,
+    /// End of the leader's log, on the leader.
+    pub(crate) log_end_offset: i64,
+    pub(crate) follower_offsets: BTreeMap<u32, i64>,
+    /// When each follower's log last reached the end of the leader's.
+    caught_up_at: BTreeMap<u32, Instant>,
+    /// The task fetching from the leader, on followers. Aborted when the
+    /// replica is dropped.
+    pub(crate) fetcher: Option<AbortHandle>,
+    /// Most bytes per second a follower copies, while the partition moves
+    /// onto it.
+    pub(crate) throttle: Option<u64>,
+}
+
+impl Replica {
+    /// A replica led by `leader` in `leader_epoch` whose log ends at
+    /// `log_end_offset`. Every replica starts out in sync, and followers have
+    /// `lag_time_max` from now to show that they are.
+    pub(crate) fn new(
+        leader: u32,
+        leader_epoch: i32,
+        replicas: Vec<u32>,
+        high_watermark: i64,
+        log_end_offset: i64,
+    ) -> Self {
+        let now = Instant::now();
+        let mut isr = replicas.clone();
+        isr.sort_unstable();
+        Self {
+            leader,
+            leader_epoch,
+            isr,
+            caught_up_at: replicas.iter().map(|&id| (id, now)).collect(),
+            replicas,
+            high_watermark,
+            log_end_offset,
+            follower_offsets: BTreeMap::new(),
+            fetcher: None,
+            throttle: None,
+        }
+    }
+
+    /// Moves the partition to `replicas` under the same leader. Replicas new
+    /// to it join the in-sync replicas once they have caught up.
+    pub(crate) fn set_replicas(&mut self, replicas: Vec<u32>) {
+        self.isr.retain(|id| replicas.contains(id));
+        self.follower_offsets.retain(|id, _| replicas.contains(id));
+        self.caught_up_at.retain(|id, _| replicas.contains(id));
+        self.replicas = replicas;
+    }
+
+    /// Notes that `follower` fetched from `offset`, where its log ends.
+    pub(crate) fn fetched(&mut self, follower: u32, offset: i64, now: Instant) {
+        self.follower_offsets.insert(follower, offset);
+        if offset >= self.log_end_offset {
+            self.caught_up_at.insert(follower, now);
+        }
+    }
+
+    /// Drops followers from the in-sync replicas that haven't caught up
+    /// within `lag_time_max`, takes back those that reached the high
+    /// watermark, and moves the high watermark up to where every in-sync
+    /// replica's log has reached. Returns whether the high watermark moved.
+    pub(crate) fn update(&mut self, now: Instant, lag_time_max: Duration) -> bool {
+        for &id in self.replicas.iter().filter(|&&id| id != self.leader) {
+            let lagging = self
+                .caught_up_at
+                .get(&id)
+                .is_none_or(|&at| now.saturating_duration_since(at) > lag_time_max);
+            let in_sync = self.isr.contains(&id);
+            if in_sync && lagging {
+                self.isr.retain(|&r| r != id);
+            } else if !in_sync
+                && self
+                    .follower_offsets
+                    .get(&id)
+                    .is_some_and(|&o| o >= self.high_watermark)
+            {
+                self.isr.push(id);
+                self.isr.sort_unstable();
+                // a follower that came back has another `lag_time_max` to
+                // catch up completely
+                self.caught_up_at.insert(id, now);
+            }
+        }
+
+        let reached = self
+            .isr
+            .iter()
+            .filter(|&&id| id != self.leader)
+            .try_fold(self.log_end_offset, |reached, id| {
+                self.follower_offsets.get(id).map(|&o| reached.min(o))
+            });
+        match reached {
+            Some(reached) if reached > self.high_watermark => {
+                self.high_watermark = reached;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn state(&self) -> PartitionState {
+        PartitionState {
+            leader: self.leader,
+            leader_epoch: self.leader_epoch,
+            replicas: self.replicas.clone(),
+            isr: self.isr.clone(),
+            high_watermark: self.high_watermark,
+            follower_offsets: self.follower_offsets.clone(),
+        }
+    }
+}
+
+impl Drop for Replica {
+    fn drop(&mut self) {
+        if let Some(fetcher) = &self.fetcher {
+            fetcher.abort();
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::path::PathBuf;
+
+use broker::{ACLS_TOPIC, Broker, OFFSETS_TOPIC};
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        AclBinding, AclOperation, AclPermission, CLUSTER_RESOURCE, CreateAclsRequest,
+        DeleteAclsRequest, DescribeAclsRequest, FetchRequest, HeartbeatRequest, MetadataRequest,
+        PatternType, ProduceRequest, Record, Request, ResourceType, Response,
+    },
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn acl(
+    principal: &str,
+    operation: AclOperation,
+    resource_type: ResourceType,
+    resource_name: &str,
+    pattern_type: PatternType,
+    permission: AclPermission,
+) -> AclBinding {
+    AclBinding {
+        principal: principal.to_string(),
+        resource_type,
+        resource_name: resource_name.to_string(),
+        pattern_type,
+        operation,
+        permission,
+    }
+}
+
+fn allow_topic(principal: &str, operation: AclOperation, topic: &str) -> AclBinding {
+    acl(
+        principal,
+        operation,
+        ResourceType::Topic,
+        topic,
+        PatternType::Literal,
+        AclPermission::Allow,
+    )
+}
+
+async fn create_acls(broker: &Broker, user: Option<&str>, acls: Vec<AclBinding>) -> u8 {
+    let req = Request::CreateAcls(CreateAclsRequest { acls });
+    match broker.handle_as(user, req).await {
+        Response::CreateAcls(r) => r.status,
+        other => panic!("expected CreateAcls response, got {other:?}"),
+    }
+}
+
+async fn produce(broker: &Broker, user: Option<&str>, topic: &str) -> u8 {
+    let req = Request::Produce(ProduceRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+    match broker.handle_as(user, req).await {
+        Response::Produce(r) => r.status,
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+}
+
+async fn fetch(broker: &Broker, user: Option<&str>, topic: &str) -> u8 {
+    let req = Request::Fetch(FetchRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        offset: 0,
+        max_bytes: 1024,
+    });
+    match broker.handle_as(user, req).await {
+        Response::Fetch(r) => r.status,
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn topics_without_acls_stay_open() {
+    let broker = Broker::new(temp_data_dir("acl-open"));
+
+    assert_eq!(
+        create_acls(
+            &broker,
+            None,
+            vec![allow_topic("User:alice", AclOperation::Write, "orders")]
+        )
+        .await,
+        status::OK
+    );
+
+    assert_eq!(produce(&broker, Some("bob"), "logs").await, status::OK);
+    assert_eq!(
+        produce(&broker, Some("bob"), "orders").await,
+        status::TOPIC_AUTHORIZATION_FAILED
+    );
+    assert_eq!(produce(&broker, Some("alice"), "orders").await, status::OK);
+    // writing does not grant reading
+    assert_eq!(
+        fetch(&broker, Some("alice"), "orders").await,
+        status::TOPIC_AUTHORIZATION_FAILED
+    );
+}
+
+#[tokio::test]
+async fn deny_wins_over_prefixed_allow() {
+    let broker = Broker::new(temp_data_dir("acl-deny"));
+
+    let acls = vec![
+        acl(
+            "User:*",
+            AclOperation::Read,
+            ResourceType::Topic,
+            "app-",
+            PatternType::Prefixed,
+            AclPermission::Allow,
+        ),
+        acl(
+            "User:mallory",
+            AclOperation::Read,
+            ResourceType::Topic,
+            "app-secrets",
+            PatternType::Literal,
+            AclPermission::Deny,
+        ),
+    ];
+    assert_eq!(create_acls(&broker, None, acls).await, status::OK);
+
+    assert_eq!(
+        fetch(&broker, Some("alice"), "app-events").await,
+        status::OK
+    );
+    assert_eq!(fetch(&broker, None, "app-secrets").await, status::OK);
+    assert_eq!(
+        fetch(&broker, Some("mallory"), "app-secrets").await,
+        status::TOPIC_AUTHORIZATION_FAILED
+    );
+    assert_eq!(
+        fetch(&broker, Some("mallory"), "app-events").await,
+        status::OK
+    );
+}
+
+#[tokio::test]
+async fn metadata_hides_topics_that_cannot_be_described() {
+    let broker = Broker::new(temp_data_dir("acl-metadata"));
+    assert_eq!(produce(&broker, None, "public").await, status::OK);
+    assert_eq!(produce(&broker, None, "private").await, status::OK);
+
+    let acls = vec![allow_topic("User:alice", AclOperation::Read, "private")];
+    assert_eq!(create_acls(&broker, None, acls).await, status::OK);
+
+    let metadata = |user, topics: &[&str]| {
+        let req = Request::Metadata(MetadataRequest {
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            allow_auto_create: false,
+        });
+        let broker = &broker;
+        async move {
+            match broker.handle_as(user, req).await {
+                Response::Metadata(r) => r
+                    .topics
+                    .into_iter()
+                    .map(|t| (t.name, t.status))
+                    .collect::<Vec<_>>(),
+                other => panic!("expected Metadata response, got {other:?}"),
+            }
+        }
+    };
+
+    let names: Vec<String> = metadata(Some("bob"), &[])
+        .await
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert!(names.contains(&"public".to_string()));
+    assert!(!names.contains(&"private".to_string()));
+
+    // reading implies describing
+    assert_eq!(
+        metadata(Some("alice"), &["private"]).await,
+        vec![("private".to_string(), status::OK)]
+    );
+    assert_eq!(
+        metadata(Some("bob"), &["private"]).await,
+        vec![("private".to_string(), status::TOPIC_AUTHORIZATION_FAILED)]
+    );
+}
+
+#[tokio::test]
+async fn group_and_cluster_denials() {
+    let broker = Broker::new(temp_data_dir("acl-cluster"));
+
+    let acls = vec![
+        acl(
+            "User:admin",
+            AclOperation::Alter,
+            ResourceType::Cluster,
+            CLUSTER_RESOURCE,
+            PatternType::Literal,
+            AclPermission::Allow,
+        ),
+        acl(
+            "User:alice",
+            AclOperation::Read,
+            ResourceType::Group,
+            "g",
+            PatternType::Literal,
+            AclPermission::Allow,
+        ),
+    ];
+    assert_eq!(create_acls(&broker, None, acls).await, status::OK);
+
+    let grant = vec![allow_topic("User:bob", AclOperation::Read, "t")];
+    assert_eq!(
+        create_acls(&broker, Some("bob"), grant.clone()).await,
+        status::CLUSTER_AUTHORIZATION_FAILED
+    );
+    assert_eq!(create_acls(&broker, Some("admin"), grant).await, status::OK);
+
+    let heartbeat = Request::Heartbeat(HeartbeatRequest {
+        group_id: "g".to_string(),
+        generation_id: 1,
+        member_id: "m".to_string(),
+    });
+    assert!(matches!(
+        broker.handle_as(Some("bob"), heartbeat).await,
+        Response::Heartbeat(r) if r.status == status::GROUP_AUTHORIZATION_FAILED
+    ));
+}
+
+#[tokio::test]
+async fn acls_survive_restart() {
+    let dir = temp_data_dir("acl-restart");
+    let kept = allow_topic("User:alice", AclOperation::Write, "t");
+    let removed = allow_topic("User:bob", AclOperation::Write, "t");
+
+    {
+        let broker = Broker::new(dir.clone());
+        let acls = vec![kept.clone(), removed.clone()];
+        assert_eq!(create_acls(&broker, None, acls).await, status::OK);
+
+        let req = Request::DeleteAcls(DeleteAclsRequest {
+            acls: vec![removed.clone(), removed],
+        });
+        assert!(matches!(
+            broker.handle(req).await,
+            Response::DeleteAcls(r) if r.status == status::OK && r.deleted == 1
+        ));
+    }
+
+    let broker = Broker::new(dir);
+    let req = Request::DescribeAcls(DescribeAclsRequest {});
+    match broker.handle(req).await {
+        Response::DescribeAcls(r) => assert_eq!(r.acls, vec![kept]),
+        other => panic!("expected DescribeAcls response, got {other:?}"),
+    }
+    assert_eq!(
+        produce(&broker, Some("bob"), "t").await,
+        status::TOPIC_AUTHORIZATION_FAILED
+    );
+    assert_eq!(produce(&broker, Some("alice"), "t").await, status::OK);
+}
+
+#[tokio::test]
+async fn internal_topics_are_protected() {
+    let broker = Broker::new(temp_data_dir("acl-internal"));
+
+    for topic in [OFFSETS_TOPIC, ACLS_TOPIC] {
+        assert_eq!(
+            produce(&broker, None, topic).await,
+            status::TOPIC_AUTHORIZATION_FAILED
+        );
+    }
+
+    let acls = vec![
+        allow_topic("User:*", AclOperation::Read, ACLS_TOPIC),
+        acl(
+            "User:admin",
+            AclOperation::Describe,
+            ResourceType::Cluster,
+            CLUSTER_RESOURCE,
+            PatternType::Literal,
+            AclPermission::Allow,
+        ),
+    ];
+    assert_eq!(create_acls(&broker, None, acls).await, status::OK);
+
+    assert_eq!(
+        fetch(&broker, Some("bob"), ACLS_TOPIC).await,
+        status::TOPIC_AUTHORIZATION_FAILED
+    );
+    assert_eq!(fetch(&broker, Some("admin"), ACLS_TOPIC).await, status::OK);
+}
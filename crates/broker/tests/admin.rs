@@ -0,0 +1,279 @@
+use std::path::PathBuf;
+
+use broker::{Broker, BrokerConfig, OFFSETS_TOPIC};
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        CreatePartitionsRequest, CreateTopicRequest, DeleteTopicRequest, DescribeGroupRequest,
+        GroupProtocol, JoinGroupRequest, ListGroupsRequest, MemberAssignment, MetadataRequest,
+        OffsetCommitRequest, ProduceRequest, Record, Request, Response, SyncGroupRequest,
+    },
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn create_topic(broker: &Broker, topic: &str, partitions: u16) -> u8 {
+    let req = Request::CreateTopic(CreateTopicRequest {
+        topic: topic.to_string(),
+        partitions,
+    });
+    match broker.handle(req).await {
+        Response::CreateTopic(r) => r.status,
+        other => panic!("expected CreateTopic response, got {other:?}"),
+    }
+}
+
+async fn create_partitions(broker: &Broker, topic: &str, partitions: u16) -> u8 {
+    let req = Request::CreatePartitions(CreatePartitionsRequest {
+        topic: topic.to_string(),
+        partitions,
+    });
+    match broker.handle(req).await {
+        Response::CreatePartitions(r) => r.status,
+        other => panic!("expected CreatePartitions response, got {other:?}"),
+    }
+}
+
+async fn delete_topic(broker: &Broker, topic: &str) -> u8 {
+    let req = Request::DeleteTopic(DeleteTopicRequest {
+        topic: topic.to_string(),
+    });
+    match broker.handle(req).await {
+        Response::DeleteTopic(r) => r.status,
+        other => panic!("expected DeleteTopic response, got {other:?}"),
+    }
+}
+
+async fn partitions(broker: &Broker, topic: &str) -> (u8, Vec<u16>) {
+    let req = Request::Metadata(MetadataRequest {
+        topics: vec![topic.to_string()],
+        allow_auto_create: false,
+    });
+    match broker.handle(req).await {
+        Response::Metadata(mut r) => {
+            let t = r.topics.remove(0);
+            (t.status, t.partitions)
+        }
+        other => panic!("expected Metadata response, got {other:?}"),
+    }
+}
+
+async fn produce(broker: &Broker, topic: &str, partition: u16) -> (u8, i64) {
+    let req = Request::Produce(ProduceRequest {
+        topic: topic.to_string(),
+        partition,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+    match broker.handle(req).await {
+        Response::Produce(r) => (r.status, r.base_offset),
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn topics_are_created_and_deleted() {
+    let broker = Broker::with_config(BrokerConfig {
+        auto_create_topics: false,
+        ..BrokerConfig::new(temp_data_dir("admin-topics"))
+    });
+
+    assert_eq!(create_topic(&broker, "orders", 3).await, status::OK);
+    assert_eq!(
+        partitions(&broker, "orders").await,
+        (status::OK, vec![0, 1, 2])
+    );
+    assert_eq!(
+        create_topic(&broker, "orders", 3).await,
+        status::TOPIC_ALREADY_EXISTS
+    );
+    assert_eq!(
+        create_topic(&broker, "empty", 0).await,
+        status::INVALID_PARTITIONS
+    );
+    for name in ["", "..", "a/b", OFFSETS_TOPIC] {
+        assert_eq!(create_topic(&broker, name, 1).await, status::INVALID_TOPIC);
+    }
+    assert_eq!(produce(&broker, "orders", 2).await, (status::OK, 0));
+
+    assert_eq!(delete_topic(&broker, "orders").await, status::OK);
+    assert_eq!(
+        partitions(&broker, "orders").await.0,
+        status::UNKNOWN_TOPIC_OR_PARTITION
+    );
+    assert_eq!(
+        produce(&broker, "orders", 2).await.0,
+        status::UNKNOWN_TOPIC_OR_PARTITION
+    );
+    assert_eq!(
+        delete_topic(&broker, "orders").await,
+        status::UNKNOWN_TOPIC_OR_PARTITION
+    );
+    assert_eq!(
+        delete_topic(&broker, OFFSETS_TOPIC).await,
+        status::INVALID_TOPIC
+    );
+
+    // a recreated topic starts over
+    assert_eq!(create_topic(&broker, "orders", 1).await, status::OK);
+    assert_eq!(produce(&broker, "orders", 0).await, (status::OK, 0));
+}
+
+#[tokio::test]
+async fn partitions_are_added_to_topics() {
+    let broker = Broker::with_config(BrokerConfig {
+        auto_create_topics: false,
+        ..BrokerConfig::new(temp_data_dir("admin-partitions"))
+    });
+    assert_eq!(create_topic(&broker, "orders", 2).await, status::OK);
+    assert_eq!(produce(&broker, "orders", 1).await, (status::OK, 0));
+
+    assert_eq!(create_partitions(&broker, "orders", 4).await, status::OK);
+    assert_eq!(
+        partitions(&broker, "orders").await,
+        (status::OK, vec![0, 1, 2, 3])
+    );
+    assert_eq!(produce(&broker, "orders", 3).await, (status::OK, 0));
+    // existing partitions keep their records
+    assert_eq!(produce(&broker, "orders", 1).await, (status::OK, 1));
+
+    // topics never shrink
+    for count in [1, 4] {
+        assert_eq!(
+            create_partitions(&broker, "orders", count).await,
+            status::INVALID_PARTITIONS
+        );
+    }
+    assert_eq!(
+        create_partitions(&broker, "missing", 2).await,
+        status::UNKNOWN_TOPIC_OR_PARTITION
+    );
+    assert_eq!(
+        create_partitions(&broker, OFFSETS_TOPIC, 100).await,
+        status::INVALID_TOPIC
+    );
+}
+
+#[tokio::test]
+async fn produces_do_not_skip_partitions() {
+    let broker = Broker::new(temp_data_dir("admin-partition-holes"));
+    assert_eq!(create_topic(&broker, "orders", 2).await, status::OK);
+
+    // writing past the last partition creates the ones in between
+    assert_eq!(produce(&broker, "orders", 3).await, (status::OK, 0));
+    assert_eq!(
+        partitions(&broker, "orders").await,
+        (status::OK, vec![0, 1, 2, 3])
+    );
+    assert_eq!(produce(&broker, "events", 2).await, (status::OK, 0));
+    assert_eq!(
+        partitions(&broker, "events").await,
+        (status::OK, vec![0, 1, 2])
+    );
+
+    assert_eq!(create_partitions(&broker, "orders", 5).await, status::OK);
+    assert_eq!(
+        partitions(&broker, "orders").await,
+        (status::OK, vec![0, 1, 2, 3, 4])
+    );
+}
+
+#[tokio::test]
+async fn groups_are_listed_and_described() {
+    let broker = Broker::new(temp_data_dir("admin-groups"));
+
+    let req = Request::JoinGroup(JoinGroupRequest {
+        group_id: "active".to_string(),
+        member_id: String::new(),
+        session_timeout_ms: 10_000,
+        rebalance_timeout_ms: 5_000,
+        protocol_type: "consumer".to_string(),
+        protocols: vec![GroupProtocol {
+            name: "range".to_string(),
+            metadata: Bytes::new(),
+        }],
+    });
+    let joined = match broker.handle(req).await {
+        Response::JoinGroup(r) => r,
+        other => panic!("expected JoinGroup response, got {other:?}"),
+    };
+    let req = Request::SyncGroup(SyncGroupRequest {
+        group_id: "active".to_string(),
+        generation_id: joined.generation_id,
+        member_id: joined.member_id.clone(),
+        assignments: vec![MemberAssignment {
+            member_id: joined.member_id.clone(),
+            assignment: Bytes::from_static(b"mine"),
+        }],
+    });
+    assert!(!broker.handle(req).await.is_error());
+    for (group, partition, offset) in [("active", 1, 5), ("active", 0, 3), ("idle", 0, 7)] {
+        let req = Request::OffsetCommit(OffsetCommitRequest {
+            group_id: group.to_string(),
+            topic: "t".to_string(),
+            partition,
+            offset,
+        });
+        assert!(!broker.handle(req).await.is_error());
+    }
+
+    let groups = match broker
+        .handle(Request::ListGroups(ListGroupsRequest {}))
+        .await
+    {
+        Response::ListGroups(r) => r.groups,
+        other => panic!("expected ListGroups response, got {other:?}"),
+    };
+    let listed: Vec<_> = groups
+        .iter()
+        .map(|g| {
+            (
+                g.group_id.as_str(),
+                g.state.as_str(),
+                g.protocol_type.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        listed,
+        [("active", "Stable", "consumer"), ("idle", "Empty", "")]
+    );
+
+    let describe = |group: &str| {
+        Request::DescribeGroup(DescribeGroupRequest {
+            group_id: group.to_string(),
+        })
+    };
+    let active = match broker.handle(describe("active")).await {
+        Response::DescribeGroup(r) => r,
+        other => panic!("expected DescribeGroup response, got {other:?}"),
+    };
+    assert_eq!(active.state, "Stable");
+    assert_eq!(active.protocol_name, "range");
+    assert_eq!(active.leader, joined.member_id);
+    assert_eq!(active.members.len(), 1);
+    assert_eq!(&active.members[0].assignment[..], b"mine");
+    let offsets: Vec<_> = active
+        .offsets
+        .iter()
+        .map(|o| (o.topic.as_str(), o.partition, o.offset))
+        .collect();
+    assert_eq!(offsets, [("t", 0, 3), ("t", 1, 5)]);
+
+    match broker.handle(describe("missing")).await {
+        Response::DescribeGroup(r) => {
+            assert_eq!(r.status, status::OK);
+            assert_eq!(r.state, "Dead");
+            assert!(r.members.is_empty() && r.offsets.is_empty());
+        }
+        other => panic!("expected DescribeGroup response, got {other:?}"),
+    }
+}
@@ -0,0 +1,585 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use broker::{Broker, BrokerConfig, Login, ReplicationConfig};
+use bytes::Bytes;
+use controller::{Controller, ControllerConfig};
+use net::{Connections, Credentials, Shutdown};
+use protocol::{
+    status,
+    types::{
+        ACKS_LEADER, AlterPartitionReassignmentsRequest, CreatePartitionsRequest,
+        CreateTopicRequest, DeleteTopicRequest, ListPartitionReassignmentsRequest, MetadataRequest,
+        PartitionReassignment, ProduceRequest, Record, Request, Response,
+    },
+};
+use storage::LogFile;
+use tokio::net::TcpListener;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+struct Node {
+    broker: Arc<Broker>,
+    controller: Arc<Controller>,
+    shutdown: Shutdown,
+    dir: PathBuf,
+}
+
+impl Node {
+    /// Closes the node's listener and connections and stops its controller.
+    async fn stop(&self) {
+        self.shutdown.trigger();
+        self.broker.shutdown().await.unwrap();
+    }
+}
+
+/// Brokers 0..n that are also the controllers of the cluster, serving the
+/// native protocol on localhost ports.
+async fn start_cluster(name: &str, n: u32) -> Vec<Node> {
+    start_cluster_with(name, n, None).await
+}
+
+/// Like `start_cluster`, with listeners requiring SASL from the users in
+/// `sasl` and the nodes logging in to each other as its `Login`.
+async fn start_cluster_with(name: &str, n: u32, sasl: Option<(Credentials, Login)>) -> Vec<Node> {
+    let (credentials, login) = sasl.map(|(c, l)| (Arc::new(c), l)).unzip();
+    let mut listeners = Vec::new();
+    for _ in 0..n {
+        listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+    }
+    let addrs: BTreeMap<u32, String> = listeners
+        .iter()
+        .enumerate()
+        .map(|(id, l)| (id as u32, l.local_addr().unwrap().to_string()))
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (id, listener) in (0..n).zip(listeners) {
+        let dir = temp_data_dir(&format!("{name}-{id}"));
+        let mut peers = addrs.clone();
+        peers.remove(&id);
+        let broker = Arc::new(Broker::with_config(BrokerConfig {
+            broker_id: id,
+            peers,
+            inter_broker_login: login.clone(),
+            replication: ReplicationConfig {
+                fetch_max_wait: Duration::from_millis(50),
+                fetch_backoff: Duration::from_millis(10),
+                ..ReplicationConfig::default()
+            },
+            ..BrokerConfig::new(dir.clone())
+        }));
+        let controller = Controller::open(ControllerConfig {
+            tick: Duration::from_millis(10),
+            session_ticks: 30,
+            login: login.clone(),
+            ..ControllerConfig::new(id, addrs.clone(), dir.join("metadata"))
+        })
+        .unwrap();
+        broker.attach_controller(controller.clone()).unwrap();
+        controller.start();
+        let shutdown = Shutdown::new();
+        tokio::spawn(net::serve_listener(
+            listener,
+            broker.clone(),
+            credentials.clone(),
+            Connections::default(),
+            shutdown.clone(),
+        ));
+        nodes.push(Node {
+            broker,
+            controller,
+            shutdown,
+            dir,
+        });
+    }
+    nodes
+}
+
+async fn eventually(what: &str, mut check: impl FnMut() -> bool) {
+    for _ in 0..500 {
+        if check() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out waiting for {what}");
+}
+
+/// Waits for a controller that leads the others and returns its index.
+async fn controller_leader(nodes: &[Node]) -> usize {
+    let mut leader = None;
+    eventually("a controller leader", || {
+        leader = nodes.iter().position(|n| n.controller.is_leader());
+        let Some(l) = leader else {
+            return false;
+        };
+        let id = nodes[l].controller.node_id();
+        nodes
+            .iter()
+            .filter(|n| n.controller.leader() == Some(id))
+            .count()
+            * 2
+            > nodes.len()
+    })
+    .await;
+    leader.unwrap()
+}
+
+async fn create_topic(broker: &Broker, topic: &str, partitions: u16) -> u8 {
+    let req = Request::CreateTopic(CreateTopicRequest {
+        topic: topic.to_string(),
+        partitions,
+    });
+    match broker.handle(req).await {
+        Response::CreateTopic(r) => r.status,
+        other => panic!("expected CreateTopic response, got {other:?}"),
+    }
+}
+
+/// Creates `topic` through the controller leader, retrying while the leader
+/// has yet to commit the entry starting its term.
+async fn create_topic_on_leader(nodes: &[Node], topic: &str, partitions: u16) {
+    for _ in 0..100 {
+        let leader = controller_leader(nodes).await;
+        match create_topic(&nodes[leader].broker, topic, partitions).await {
+            status::OK => return,
+            status::NOT_CONTROLLER => tokio::time::sleep(Duration::from_millis(20)).await,
+            other => panic!("cannot create {topic}: status {other}"),
+        }
+    }
+    panic!("timed out creating {topic}");
+}
+
+async fn reassign(broker: &Broker, topic: &str, partition: u16, replicas: &[u32]) -> u8 {
+    let req = Request::AlterPartitionReassignments(AlterPartitionReassignmentsRequest {
+        topic: topic.to_string(),
+        partition,
+        replicas: replicas.to_vec(),
+        throttle_bytes_per_sec: 1000,
+    });
+    match broker.handle(req).await {
+        Response::AlterPartitionReassignments(r) => r.status,
+        other => panic!("expected AlterPartitionReassignments response, got {other:?}"),
+    }
+}
+
+/// Moves a partition through the controller leader, retrying as
+/// `create_topic_on_leader` does.
+async fn reassign_on_leader(nodes: &[Node], topic: &str, partition: u16, replicas: &[u32]) {
+    for _ in 0..100 {
+        let leader = controller_leader(nodes).await;
+        match reassign(&nodes[leader].broker, topic, partition, replicas).await {
+            status::OK => return,
+            status::NOT_CONTROLLER => tokio::time::sleep(Duration::from_millis(20)).await,
+            other => panic!("cannot reassign {topic}-{partition}: status {other}"),
+        }
+    }
+    panic!("timed out reassigning {topic}-{partition}");
+}
+
+async fn reassignments(broker: &Broker) -> Vec<PartitionReassignment> {
+    let req = Request::ListPartitionReassignments(ListPartitionReassignmentsRequest {});
+    match broker.handle(req).await {
+        Response::ListPartitionReassignments(r) => {
+            assert_eq!(r.status, status::OK);
+            r.reassignments
+        }
+        other => panic!("expected ListPartitionReassignments response, got {other:?}"),
+    }
+}
+
+async fn produce(broker: &Broker, topic: &str, partition: u16, values: &[&str]) -> u8 {
+    let req = Request::Produce(ProduceRequest {
+        topic: topic.to_string(),
+        partition,
+        acks: ACKS_LEADER,
+        records: values
+            .iter()
+            .map(|v| Record {
+                key: Bytes::new(),
+                value: Bytes::copy_from_slice(v.as_bytes()),
+            })
+            .collect(),
+    });
+    match broker.handle(req).await {
+        Response::Produce(r) => r.status,
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+}
+
+/// The status and partitions of `topic` in a Metadata response that may
+/// create it.
+async fn auto_create(broker: &Broker, topic: &str) -> (u8, Vec<u16>) {
+    let req = Request::Metadata(MetadataRequest {
+        topics: vec![topic.to_string()],
+        allow_auto_create: true,
+    });
+    match broker.handle(req).await {
+        Response::Metadata(mut r) => {
+            let t = r.topics.remove(0);
+            (t.status, t.partitions)
+        }
+        other => panic!("expected Metadata response, got {other:?}"),
+    }
+}
+
+/// The topics a Metadata request for every topic lists, with their
+/// partitions.
+async fn list_topics(broker: &Broker) -> Vec<(String, Vec<u16>)> {
+    let req = Request::Metadata(MetadataRequest {
+        topics: vec![],
+        allow_auto_create: false,
+    });
+    match broker.handle(req).await {
+        Response::Metadata(r) => r
+            .topics
+            .into_iter()
+            .map(|t| (t.name, t.partitions))
+            .collect(),
+        other => panic!("expected Metadata response, got {other:?}"),
+    }
+}
+
+fn log_values(path: &std::path::Path) -> Vec<Bytes> {
+    match LogFile::read(path) {
+        Ok(log) => log.entries.iter().map(|e| e.value.clone()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn topics_are_created_through_the_controller_leader() {
+    let nodes = start_cluster("controller-create", 3).await;
+    let leader = controller_leader(&nodes).await;
+    let follower = (leader + 1) % nodes.len();
+    assert_eq!(
+        create_topic(&nodes[follower].broker, "t", 3).await,
+        status::NOT_CONTROLLER
+    );
+    create_topic_on_leader(&nodes, "t", 3).await;
+    assert_eq!(
+        create_topic(&nodes[leader].broker, "t", 3).await,
+        status::TOPIC_ALREADY_EXISTS
+    );
+
+    // every broker takes the part the registry gives it
+    for node in &nodes {
+        eventually("brokers to take up the registry", || {
+            let registry = node.controller.registry();
+            let Some(partitions) = registry.topics.get("t") else {
+                return false;
+            };
+            (0u16..).zip(partitions).all(|(partition, p)| {
+                node.broker
+                    .partition_state("t", partition)
+                    .is_some_and(|s| s.leader == p.leader && s.replicas == p.replicas)
+            })
+        })
+        .await;
+    }
+
+    let registry = nodes[leader].controller.registry();
+    let p = registry.partition("t", 1).unwrap();
+    assert_eq!(p.replicas.len(), 3);
+    let partition_leader = &nodes[p.leader as usize];
+    assert_eq!(
+        produce(&partition_leader.broker, "t", 1, &["a", "b"]).await,
+        0
+    );
+    for node in &nodes {
+        eventually("the records to be replicated", || {
+            log_values(&node.dir.join("t-1.log")) == ["a", "b"]
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn brokers_log_in_to_each_other_when_listeners_require_sasl() {
+    let credentials = Credentials::parse("broker:broker-secret\n").unwrap();
+    let login = Login {
+        username: "broker".to_string(),
+        password: "broker-secret".to_string(),
+    };
+    let nodes = start_cluster_with("controller-sasl", 3, Some((credentials, login))).await;
+    create_topic_on_leader(&nodes, "t", 1).await;
+
+    let registry = nodes[controller_leader(&nodes).await].controller.registry();
+    let p = registry.partition("t", 0).unwrap();
+    let partition_leader = &nodes[p.leader as usize];
+    eventually("the partition leader to take up the registry", || {
+        partition_leader.broker.partition_state("t", 0).is_some()
+    })
+    .await;
+    assert_eq!(
+        produce(&partition_leader.broker, "t", 0, &["a", "b"]).await,
+        0
+    );
+    for node in &nodes {
+        eventually("the records to be replicated", || {
+            log_values(&node.dir.join("t-0.log")) == ["a", "b"]
+        })
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn metadata_lists_the_registry_on_every_broker() {
+    let nodes = start_cluster("controller-metadata", 4).await;
+    create_topic_on_leader(&nodes, "t", 2).await;
+    let registry = nodes[controller_leader(&nodes).await].controller.registry();
+    // three replicas of each partition, so some broker lacks one of them
+    let (outsider, partition) = (0..nodes.len() as u32)
+        .flat_map(|id| [(id, 0u16), (id, 1)])
+        .find(|&(id, partition)| {
+            !registry
+                .partition("t", partition)
+                .unwrap()
+                .replicas
+                .contains(&id)
+        })
+        .unwrap();
+
+    let node = &nodes[outsider as usize];
+    eventually("the broker to learn of the topic", || {
+        node.controller.registry().topics.contains_key("t")
+    })
+    .await;
+    assert!(!node.dir.join(format!("t-{partition}.log")).exists());
+    assert_eq!(
+        list_topics(&node.broker).await,
+        [("t".to_string(), vec![0, 1])]
+    );
+}
+
+#[tokio::test]
+async fn deleted_topics_are_removed_from_every_broker() {
+    let nodes = start_cluster("controller-delete", 3).await;
+    create_topic_on_leader(&nodes, "t", 2).await;
+    for node in &nodes {
+        eventually("the partitions to be created", || {
+            node.dir.join("t-0.log").exists() && node.dir.join("t-1.log").exists()
+        })
+        .await;
+    }
+
+    let leader = controller_leader(&nodes).await;
+    let req = Request::DeleteTopic(DeleteTopicRequest {
+        topic: "t".to_string(),
+    });
+    match nodes[leader].broker.handle(req).await {
+        Response::DeleteTopic(r) => assert_eq!(r.status, status::OK),
+        other => panic!("expected DeleteTopic response, got {other:?}"),
+    }
+    for node in &nodes {
+        eventually("the partitions to be removed", || {
+            !node.dir.join("t-0.log").exists() && !node.dir.join("t-1.log").exists()
+        })
+        .await;
+        assert!(node.broker.partition_state("t", 0).is_none());
+    }
+}
+
+#[tokio::test]
+async fn partitions_move_off_a_stopped_broker() {
+    let nodes = start_cluster("controller-failover", 3).await;
+    create_topic_on_leader(&nodes, "t", 3).await;
+    let registry = nodes[controller_leader(&nodes).await].controller.registry();
+    let led_by_0: Vec<u16> = (0u16..)
+        .zip(&registry.topics["t"])
+        .filter(|(_, p)| p.leader == 0)
+        .map(|(partition, _)| partition)
+        .collect();
+    assert!(!led_by_0.is_empty());
+
+    nodes[0].stop().await;
+    let rest = &nodes[1..];
+    for node in rest {
+        eventually("leadership to move off the stopped broker", || {
+            let registry = node.controller.registry();
+            led_by_0.iter().all(|&partition| {
+                registry.partition("t", partition).is_some_and(|p| {
+                    p.leader != 0
+                        && p.leader_epoch > 0
+                        && node
+                            .broker
+                            .partition_state("t", partition)
+                            .is_some_and(|s| s.leader == p.leader)
+                })
+            })
+        })
+        .await;
+    }
+    let registry = rest[0].controller.registry();
+    let p = registry.partition("t", led_by_0[0]).unwrap();
+    let new_leader = &nodes[p.leader as usize];
+    assert_eq!(
+        produce(&new_leader.broker, "t", led_by_0[0], &["a"]).await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn partitions_move_to_other_brokers() {
+    let nodes = start_cluster("controller-reassign", 4).await;
+    create_topic_on_leader(&nodes, "t", 1).await;
+    let registry = nodes[controller_leader(&nodes).await].controller.registry();
+    assert_eq!(registry.partition("t", 0).unwrap().replicas, [0, 1, 2]);
+    eventually("the leader to take up the partition", || {
+        nodes[0]
+            .broker
+            .partition_state("t", 0)
+            .is_some_and(|s| s.leader == 0)
+    })
+    .await;
+    let values: Vec<String> = (0..20).map(|i| format!("record-{i:02}")).collect();
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    assert_eq!(produce(&nodes[0].broker, "t", 0, &values).await, 0);
+
+    // 20 records of 23 bytes take the new replica half a second at 1000
+    // bytes per second
+    let started = Instant::now();
+    reassign_on_leader(&nodes, "t", 0, &[3, 1]).await;
+    let leader = controller_leader(&nodes).await;
+    let listed = reassignments(&nodes[leader].broker).await;
+    let [r] = &listed[..] else {
+        panic!("expected one reassignment, got {listed:?}");
+    };
+    assert_eq!((r.topic.as_str(), r.partition), ("t", 0));
+    assert_eq!(r.replicas, [3, 1, 0, 2]);
+    assert_eq!((&r.adding[..], &r.removing[..]), (&[3][..], &[0, 2][..]));
+    assert_eq!(r.throttle_bytes_per_sec, 1000);
+    assert_eq!(
+        reassign(&nodes[leader].broker, "t", 0, &[2]).await,
+        status::REASSIGNMENT_IN_PROGRESS
+    );
+
+    for node in &nodes {
+        eventually("the move to complete", || {
+            node.controller
+                .registry()
+                .partition("t", 0)
+                .is_some_and(|p| p.reassignment.is_none() && p.replicas == [3, 1] && p.leader == 3)
+        })
+        .await;
+    }
+    assert!(started.elapsed() >= Duration::from_millis(400));
+    for old in [&nodes[0], &nodes[2]] {
+        eventually("the old replicas to be removed", || {
+            old.broker.partition_state("t", 0).is_none()
+        })
+        .await;
+        assert!(!old.dir.join("t-0.log").exists());
+    }
+    assert_eq!(log_values(&nodes[3].dir.join("t-0.log")), values);
+    assert!(reassignments(&nodes[leader].broker).await.is_empty());
+
+    eventually("the new leader to take over", || {
+        nodes[3]
+            .broker
+            .partition_state("t", 0)
+            .is_some_and(|s| s.leader == 3 && s.leader_epoch == 1)
+    })
+    .await;
+    assert_eq!(produce(&nodes[3].broker, "t", 0, &["last"]).await, 0);
+    eventually("the remaining replica to follow the new leader", || {
+        log_values(&nodes[1].dir.join("t-0.log")).len() == 21
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn partitions_are_added_through_the_controller_leader() {
+    let nodes = start_cluster("controller-partitions", 3).await;
+    create_topic_on_leader(&nodes, "t", 2).await;
+    let leader = controller_leader(&nodes).await;
+    let create_partitions = |partitions| {
+        let req = Request::CreatePartitions(CreatePartitionsRequest {
+            topic: "t".to_string(),
+            partitions,
+        });
+        nodes[leader].broker.handle(req)
+    };
+    match create_partitions(4).await {
+        Response::CreatePartitions(r) => assert_eq!(r.status, status::OK),
+        other => panic!("expected CreatePartitions response, got {other:?}"),
+    }
+    match create_partitions(3).await {
+        Response::CreatePartitions(r) => assert_eq!(r.status, status::INVALID_PARTITIONS),
+        other => panic!("expected CreatePartitions response, got {other:?}"),
+    }
+
+    for node in &nodes {
+        eventually("the new partitions to be created", || {
+            node.controller
+                .registry()
+                .topics
+                .get("t")
+                .is_some_and(|partitions| partitions.len() == 4)
+                && node.dir.join("t-3.log").exists()
+        })
+        .await;
+    }
+    let registry = nodes[leader].controller.registry();
+    let p = registry.partition("t", 3).unwrap();
+    let partition_leader = &nodes[p.leader as usize];
+    eventually("the new partition to be led", || {
+        partition_leader
+            .broker
+            .partition_state("t", 3)
+            .is_some_and(|s| s.leader == p.leader)
+    })
+    .await;
+    assert_eq!(produce(&partition_leader.broker, "t", 3, &["a"]).await, 0);
+}
+
+#[tokio::test]
+async fn topics_are_auto_created_through_the_controller() {
+    let nodes = start_cluster("controller-auto-create", 3).await;
+    let leader = controller_leader(&nodes).await;
+
+    // produces don't create partitions the registry doesn't have
+    assert_eq!(
+        produce(&nodes[leader].broker, "auto", 0, &["a"]).await,
+        status::UNKNOWN_TOPIC_OR_PARTITION
+    );
+    for node in &nodes {
+        assert!(!node.dir.join("auto-0.log").exists());
+    }
+    let follower = (leader + 1) % nodes.len();
+    assert_eq!(
+        auto_create(&nodes[follower].broker, "auto").await,
+        (status::UNKNOWN_TOPIC_OR_PARTITION, vec![])
+    );
+
+    let mut created = false;
+    for _ in 0..100 {
+        let leader = controller_leader(&nodes).await;
+        if auto_create(&nodes[leader].broker, "auto").await == (status::OK, vec![0]) {
+            created = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(created, "timed out auto-creating the topic");
+
+    let registry = nodes[leader].controller.registry();
+    let p = registry.partition("auto", 0).unwrap();
+    let partition_leader = &nodes[p.leader as usize].broker;
+    eventually("the partition leader to take it up", || {
+        partition_leader
+            .partition_state("auto", 0)
+            .is_some_and(|s| s.leader == p.leader)
+    })
+    .await;
+    assert_eq!(produce(partition_leader, "auto", 0, &["a"]).await, 0);
+}
@@ -0,0 +1,173 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::{Broker, BrokerConfig, LogConfig};
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        GroupProtocol, HeartbeatRequest, JoinGroupRequest, JoinGroupResponse, LeaveGroupRequest,
+        MemberAssignment, OffsetCommitRequest, OffsetFetchRequest, Request, Response,
+        SyncGroupRequest,
+    },
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn join(broker: &Broker, member_id: &str) -> JoinGroupResponse {
+    let req = Request::JoinGroup(JoinGroupRequest {
+        group_id: "g".to_string(),
+        member_id: member_id.to_string(),
+        session_timeout_ms: 10_000,
+        rebalance_timeout_ms: 5_000,
+        protocol_type: "consumer".to_string(),
+        protocols: vec![GroupProtocol {
+            name: "range".to_string(),
+            metadata: Bytes::from_static(b"meta"),
+        }],
+    });
+    match broker.handle(req).await {
+        Response::JoinGroup(r) => r,
+        other => panic!("expected JoinGroup response, got {other:?}"),
+    }
+}
+
+async fn heartbeat(broker: &Broker, member_id: &str, generation_id: i32) -> u8 {
+    let req = Request::Heartbeat(HeartbeatRequest {
+        group_id: "g".to_string(),
+        generation_id,
+        member_id: member_id.to_string(),
+    });
+    match broker.handle(req).await {
+        Response::Heartbeat(r) => r.status,
+        other => panic!("expected Heartbeat response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn second_member_triggers_rebalance() {
+    let broker = Arc::new(Broker::new(temp_data_dir("group-rebalance")));
+
+    let first = join(&broker, "").await;
+    assert_eq!(first.status, status::OK);
+    assert_eq!(first.generation_id, 1);
+    assert_eq!(first.leader, first.member_id);
+
+    // a new member forces the existing one to rejoin before anyone gets a generation
+    let b = broker.clone();
+    let second = tokio::spawn(async move { join(&b, "").await });
+    tokio::task::yield_now().await;
+    while heartbeat(&broker, &first.member_id, 1).await != status::REBALANCE_IN_PROGRESS {
+        tokio::task::yield_now().await;
+    }
+
+    let rejoined = join(&broker, &first.member_id).await;
+    let second = second.await.unwrap();
+    assert_eq!(rejoined.generation_id, 2);
+    assert_eq!(second.generation_id, 2);
+    assert_eq!(rejoined.leader, first.member_id);
+    assert_eq!(rejoined.members.len(), 2);
+    assert!(second.members.is_empty());
+
+    let req = Request::SyncGroup(SyncGroupRequest {
+        group_id: "g".to_string(),
+        generation_id: 2,
+        member_id: first.member_id.clone(),
+        assignments: vec![MemberAssignment {
+            member_id: second.member_id.clone(),
+            assignment: Bytes::from_static(b"p0"),
+        }],
+    });
+    assert!(matches!(broker.handle(req).await, Response::SyncGroup(r) if r.status == status::OK));
+
+    let req = Request::SyncGroup(SyncGroupRequest {
+        group_id: "g".to_string(),
+        generation_id: 2,
+        member_id: second.member_id.clone(),
+        assignments: vec![],
+    });
+    match broker.handle(req).await {
+        Response::SyncGroup(r) => assert_eq!(&r.assignment[..], b"p0"),
+        other => panic!("expected SyncGroup response, got {other:?}"),
+    }
+
+    assert_eq!(heartbeat(&broker, &second.member_id, 2).await, status::OK);
+
+    let req = Request::LeaveGroup(LeaveGroupRequest {
+        group_id: "g".to_string(),
+        member_id: first.member_id.clone(),
+    });
+    broker.handle(req).await;
+    assert_eq!(
+        heartbeat(&broker, &second.member_id, 2).await,
+        status::REBALANCE_IN_PROGRESS
+    );
+}
+
+#[tokio::test]
+async fn committed_offsets_survive_restart() {
+    let dir = temp_data_dir("group-offsets");
+
+    let broker = Broker::new(dir.clone());
+    let req = Request::OffsetCommit(OffsetCommitRequest {
+        group_id: "g".to_string(),
+        topic: "t".to_string(),
+        partition: 3,
+        offset: 42,
+    });
+    assert!(
+        matches!(broker.handle(req).await, Response::OffsetCommit(r) if r.status == status::OK)
+    );
+    drop(broker);
+
+    let broker = Broker::new(dir);
+    let req = Request::OffsetFetch(OffsetFetchRequest {
+        group_id: "g".to_string(),
+        topic: "t".to_string(),
+        partition: 3,
+    });
+    match broker.handle(req).await {
+        Response::OffsetFetch(r) => assert_eq!(r.offset, 42),
+        other => panic!("expected OffsetFetch response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn committed_offsets_are_not_trimmed_by_retention() {
+    let dir = temp_data_dir("group-offsets-retention");
+    let config = BrokerConfig {
+        log: LogConfig {
+            retention_bytes: Some(64),
+            ..LogConfig::default()
+        },
+        ..BrokerConfig::new(dir)
+    };
+
+    let broker = Broker::with_config(config.clone());
+    for partition in 0..10 {
+        let req = Request::OffsetCommit(OffsetCommitRequest {
+            group_id: "g".to_string(),
+            topic: "t".to_string(),
+            partition,
+            offset: 42,
+        });
+        assert!(
+            matches!(broker.handle(req).await, Response::OffsetCommit(r) if r.status == status::OK)
+        );
+    }
+    drop(broker);
+
+    let broker = Broker::with_config(config);
+    let req = Request::OffsetFetch(OffsetFetchRequest {
+        group_id: "g".to_string(),
+        topic: "t".to_string(),
+        partition: 0,
+    });
+    match broker.handle(req).await {
+        Response::OffsetFetch(r) => assert_eq!(r.offset, 42),
+        other => panic!("expected OffsetFetch response, got {other:?}"),
+    }
+}
@@ -0,0 +1,115 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::Broker;
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{FetchRequest, ProduceRequest, Record, Request, Response},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn produce(broker: &Broker, topic: &str, value: String) -> i64 {
+    let req = Request::Produce(ProduceRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from(value),
+        }],
+    });
+    match broker.handle(req).await {
+        Response::Produce(r) => {
+            assert_eq!(r.status, status::OK);
+            r.base_offset
+        }
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+}
+
+async fn fetch(broker: &Broker, topic: &str, offset: i64) -> Vec<(i64, Record)> {
+    let req = Request::Fetch(FetchRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        offset,
+        max_bytes: 1 << 20,
+    });
+    match broker.handle(req).await {
+        Response::Fetch(r) => {
+            assert_eq!(r.status, status::OK);
+            r.items
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_produces_get_unique_contiguous_offsets() {
+    let dir = temp_data_dir("concurrent-produce");
+    let broker = Arc::new(Broker::new(dir.clone()));
+
+    let mut tasks = Vec::new();
+    for producer in 0..8 {
+        let broker = broker.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut offsets = Vec::new();
+            for i in 0..25 {
+                offsets.push(produce(&broker, "events", format!("{producer}-{i}")).await);
+            }
+            offsets
+        }));
+    }
+    for _ in 0..4 {
+        let broker = broker.clone();
+        tasks.push(tokio::spawn(async move {
+            for _ in 0..25 {
+                fetch(&broker, "events", 0).await;
+            }
+            vec![]
+        }));
+    }
+
+    let mut offsets = Vec::new();
+    for task in tasks {
+        offsets.extend(task.await.unwrap());
+    }
+    offsets.sort();
+    assert_eq!(offsets, (0..200).collect::<Vec<i64>>());
+
+    let items = fetch(&broker, "events", 0).await;
+    let fetched: Vec<i64> = items.iter().map(|(offset, _)| *offset).collect();
+    assert_eq!(fetched, (0..200).collect::<Vec<i64>>());
+
+    broker.shutdown().await.unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn topic_names_cannot_escape_the_data_dir() {
+    let root = temp_data_dir("produce-invalid-topic");
+    let dir = root.join("data");
+    let broker = Broker::new(dir.clone());
+
+    let req = Request::Produce(ProduceRequest {
+        topic: "../x".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+    match broker.handle(req).await {
+        Response::Produce(r) => assert_eq!(r.status, status::INVALID_TOPIC),
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+    assert!(!root.join("x-0.log").exists());
+
+    broker.shutdown().await.unwrap();
+    let _ = std::fs::remove_dir_all(&root);
+}
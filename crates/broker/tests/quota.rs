@@ -0,0 +1,98 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use broker::{Broker, BrokerConfig, Quota, QuotaConfig, Usage};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn produce(bytes: u64) -> Usage {
+    Usage {
+        produce_bytes: bytes,
+        ..Usage::default()
+    }
+}
+
+fn broker_with(name: &str, quotas: QuotaConfig) -> Broker {
+    Broker::with_config(BrokerConfig {
+        quotas,
+        ..BrokerConfig::new(temp_data_dir(name))
+    })
+}
+
+#[test]
+fn no_quota_means_no_throttle() {
+    let broker = Broker::new(temp_data_dir("quota-none"));
+    for _ in 0..100 {
+        assert_eq!(
+            broker.record_usage(None, "c", produce(1 << 30)),
+            Duration::ZERO
+        );
+    }
+}
+
+#[test]
+fn byte_rate_throttles_past_the_burst() {
+    let broker = broker_with(
+        "quota-bytes",
+        QuotaConfig {
+            default: Quota {
+                produce_bytes_per_sec: Some(1000),
+                ..Quota::default()
+            },
+            ..QuotaConfig::default()
+        },
+    );
+
+    // the first second of traffic is free
+    assert_eq!(broker.record_usage(None, "c", produce(500)), Duration::ZERO);
+    let throttle = broker.record_usage(None, "c", produce(2500));
+    assert!(
+        throttle > Duration::from_millis(1900) && throttle <= Duration::from_secs(2),
+        "{throttle:?}"
+    );
+    // fetches are counted separately
+    let fetch = Usage {
+        fetch_bytes: 1 << 20,
+        ..Usage::default()
+    };
+    assert_eq!(broker.record_usage(None, "c", fetch), Duration::ZERO);
+    // each client gets its own default allowance
+    assert_eq!(broker.record_usage(None, "d", produce(500)), Duration::ZERO);
+}
+
+#[test]
+fn user_quota_wins_over_client_quota() {
+    let limited = Quota {
+        requests_per_sec: Some(1),
+        ..Quota::default()
+    };
+    let broker = broker_with(
+        "quota-precedence",
+        QuotaConfig {
+            default: limited.clone(),
+            users: HashMap::from([(
+                "alice".to_string(),
+                Quota {
+                    requests_per_sec: Some(1_000_000),
+                    ..Quota::default()
+                },
+            )]),
+            clients: HashMap::from([("shared".to_string(), limited)]),
+        },
+    );
+
+    for _ in 0..100 {
+        assert_eq!(
+            broker.record_usage(Some("alice"), "shared", Usage::default()),
+            Duration::ZERO
+        );
+    }
+
+    // without a user quota the client id's applies, shared by everyone using it
+    broker.record_usage(Some("bob"), "shared", Usage::default());
+    broker.record_usage(None, "shared", Usage::default());
+    assert!(broker.record_usage(Some("bob"), "shared", Usage::default()) > Duration::ZERO);
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use broker::{Broker, BrokerConfig, ReplicationConfig};
+use bytes::Bytes;
+use net::{Connections, Shutdown};
+use protocol::{
+    status,
+    types::{
+        ACKS_ALL, ACKS_LEADER, FetchRequest, LATEST_TIMESTAMP, ListOffsetsRequest,
+        OffsetForLeaderEpochRequest, ProduceRequest, Record, ReplicaFetchRequest, Request,
+        Response,
+    },
+};
+use storage::LogFile;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+struct Node {
+    broker: Arc<Broker>,
+    dir: PathBuf,
+}
+
+/// Brokers 0..n serving the native protocol on localhost ports, each knowing
+/// the others as peers.
+async fn start_cluster(name: &str, n: u32) -> Vec<Node> {
+    start_cluster_with(name, n, ReplicationConfig::default()).await
+}
+
+/// Like `start_cluster`, with followers fetching more often than `config`
+/// says.
+async fn start_cluster_with(name: &str, n: u32, config: ReplicationConfig) -> Vec<Node> {
+    let mut listeners = Vec::new();
+    for _ in 0..n {
+        listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+    }
+    let addrs: BTreeMap<u32, String> = listeners
+        .iter()
+        .enumerate()
+        .map(|(id, l)| (id as u32, l.local_addr().unwrap().to_string()))
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (id, listener) in (0..n).zip(listeners) {
+        let dir = temp_data_dir(&format!("{name}-{id}"));
+        let mut peers = addrs.clone();
+        peers.remove(&id);
+        let broker = Arc::new(Broker::with_config(BrokerConfig {
+            broker_id: id,
+            peers,
+            replication: ReplicationConfig {
+                fetch_max_wait: Duration::from_millis(50),
+                fetch_backoff: Duration::from_millis(10),
+                ..config.clone()
+            },
+            ..BrokerConfig::new(dir.clone())
+        }));
+        tokio::spawn(net::serve_listener(
+            listener,
+            broker.clone(),
+            None,
+            Connections::default(),
+            Shutdown::new(),
+        ));
+        nodes.push(Node { broker, dir });
+    }
+    nodes
+}
+
+async fn eventually(what: &str, mut check: impl FnMut() -> bool) {
+    for _ in 0..500 {
+        if check() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out waiting for {what}");
+}
+
+async fn produce(broker: &Broker, topic: &str, values: &[&'static str]) -> u8 {
+    produce_with_acks(broker, topic, ACKS_LEADER, values).await
+}
+
+async fn produce_with_acks(broker: &Broker, topic: &str, acks: i16, values: &[&'static str]) -> u8 {
+    let req = Request::Produce(ProduceRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        acks,
+        records: values
+            .iter()
+            .map(|v| Record {
+                key: Bytes::new(),
+                value: Bytes::from_static(v.as_bytes()),
+            })
+            .collect(),
+    });
+    match broker.handle(req).await {
+        Response::Produce(r) => r.status,
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+}
+
+/// Status, high watermark and offsets of a consumer fetch from the start.
+async fn fetch(broker: &Broker, topic: &str) -> (u8, i64, Vec<i64>) {
+    let req = Request::Fetch(FetchRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        offset: 0,
+        max_bytes: u32::MAX,
+    });
+    match broker.handle(req).await {
+        Response::Fetch(r) => (
+            r.status,
+            r.high_watermark,
+            r.items.iter().map(|(offset, _)| *offset).collect(),
+        ),
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
+
+async fn latest_offset(broker: &Broker, topic: &str) -> (u8, i64) {
+    let req = Request::ListOffsets(ListOffsetsRequest {
+        topic: topic.to_string(),
+        partition: 0,
+        timestamp: LATEST_TIMESTAMP,
+    });
+    match broker.handle(req).await {
+        Response::ListOffsets(r) => (r.status, r.offset),
+        other => panic!("expected ListOffsets response, got {other:?}"),
+    }
+}
+
+fn high_watermark(broker: &Broker, topic: &str) -> i64 {
+    broker.partition_state(topic, 0).unwrap().high_watermark
+}
+
+fn isr(broker: &Broker, topic: &str) -> Vec<u32> {
+    broker.partition_state(topic, 0).unwrap().isr
+}
+
+/// Followers drop out of the in-sync replicas quickly, and `ACKS_ALL` needs
+/// both replicas.
+fn strict_config() -> ReplicationConfig {
+    ReplicationConfig {
+        lag_time_max: Duration::from_millis(300),
+        min_insync_replicas: 2,
+        ack_timeout: Duration::from_secs(5),
+        ..ReplicationConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn records_are_replicated_to_followers() {
+    let nodes = start_cluster("replication-copy", 3).await;
+    nodes[0]
+        .broker
+        .become_leader("t", 0, 0, vec![0, 1, 2])
+        .await
+        .unwrap();
+    for node in &nodes[1..] {
+        node.broker
+            .become_follower("t", 0, 0, 0, vec![0, 1, 2])
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(produce(&nodes[0].broker, "t", &["a", "b", "c"]).await, 0);
+    eventually("the high watermark to reach the end", || {
+        high_watermark(&nodes[0].broker, "t") == 3
+    })
+    .await;
+    let state = nodes[0].broker.partition_state("t", 0).unwrap();
+    assert_eq!(state.leader, 0);
+    assert_eq!(state.follower_offsets, BTreeMap::from([(1, 3), (2, 3)]));
+    assert_eq!(fetch(&nodes[0].broker, "t").await, (0, 3, vec![0, 1, 2]));
+
+    for node in &nodes[1..] {
+        eventually("followers to learn the high watermark", || {
+            high_watermark(&node.broker, "t") == 3
+        })
+        .await;
+        let log = LogFile::read(&node.dir.join("t-0.log")).unwrap();
+        let values: Vec<_> = log.entries.iter().map(|e| e.value.clone()).collect();
+        assert_eq!(values, ["a", "b", "c"]);
+    }
+}
+
+#[tokio::test]
+async fn consumers_only_see_records_every_replica_has() {
+    let nodes = start_cluster("replication-watermark", 2).await;
+    nodes[0]
+        .broker
+        .become_leader("t", 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+
+    assert_eq!(produce(&nodes[0].broker, "t", &["a", "b"]).await, 0);
+    assert_eq!(fetch(&nodes[0].broker, "t").await, (0, 0, vec![]));
+    assert_eq!(latest_offset(&nodes[0].broker, "t").await, (0, 0));
+
+    nodes[1]
+        .broker
+        .become_follower("t", 0, 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+    eventually("the follower to catch up", || {
+        high_watermark(&nodes[0].broker, "t") == 2
+    })
+    .await;
+    assert_eq!(fetch(&nodes[0].broker, "t").await, (0, 2, vec![0, 1]));
+    assert_eq!(latest_offset(&nodes[0].broker, "t").await, (0, 2));
+}
+
+#[tokio::test]
+async fn followers_refuse_clients() {
+    let nodes = start_cluster("replication-follower", 2).await;
+    nodes[0]
+        .broker
+        .become_leader("t", 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+    nodes[1]
+        .broker
+        .become_follower("t", 0, 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+
+    let follower = &nodes[1].broker;
+    assert_eq!(
+        produce(follower, "t", &["a"]).await,
+        status::NOT_LEADER_OR_FOLLOWER
+    );
+    assert_eq!(fetch(follower, "t").await.0, status::NOT_LEADER_OR_FOLLOWER);
+    assert_eq!(
+        latest_offset(follower, "t").await.0,
+        status::NOT_LEADER_OR_FOLLOWER
+    );
+}
+
+#[tokio::test]
+async fn a_former_leader_drops_the_records_the_new_leader_never_got() {
+    let nodes = start_cluster("replication-diverge", 2).await;
+    let (old, new) = (&nodes[0].broker, &nodes[1].broker);
+    old.become_leader("t", 0, 0, vec![0, 1]).await.unwrap();
+    new.become_follower("t", 0, 0, 0, vec![0, 1]).await.unwrap();
+    assert_eq!(produce(old, "t", &["a", "b"]).await, 0);
+    eventually("the follower to catch up", || high_watermark(old, "t") == 2).await;
+
+    // the follower takes over while the old leader still takes records
+    new.become_leader("t", 0, 1, vec![0, 1]).await.unwrap();
+    assert_eq!(produce(old, "t", &["lost-1", "lost-2"]).await, 0);
+    assert_eq!(produce(new, "t", &["c"]).await, 0);
+
+    old.become_follower("t", 0, 1, 1, vec![0, 1]).await.unwrap();
+    eventually("the old leader to follow the new one", || {
+        high_watermark(new, "t") == 3
+    })
+    .await;
+    let log = LogFile::read(&nodes[0].dir.join("t-0.log")).unwrap();
+    let values: Vec<_> = log.entries.iter().map(|e| e.value.clone()).collect();
+    assert_eq!(values, ["a", "b", "c"]);
+    for node in &nodes {
+        let epochs = std::fs::read_to_string(node.dir.join("t-0.epochs")).unwrap();
+        assert_eq!(epochs, "0\n2\n0 0\n1 2\n");
+    }
+
+    // an older epoch than the partition's is refused
+    assert!(old.become_leader("t", 0, 0, vec![0, 1]).await.is_err());
+}
+
+#[tokio::test]
+async fn followers_must_know_the_leader_epoch() {
+    let nodes = start_cluster("replication-fencing", 2).await;
+    let leader = &nodes[0].broker;
+    leader.become_leader("t", 0, 3, vec![0, 1]).await.unwrap();
+    assert_eq!(produce(leader, "t", &["a", "b"]).await, 0);
+
+    for (epoch, expected) in [
+        (2, status::FENCED_LEADER_EPOCH),
+        (4, status::UNKNOWN_LEADER_EPOCH),
+        (3, status::OK),
+    ] {
+        let req = Request::ReplicaFetch(ReplicaFetchRequest {
+            replica_id: 1,
+            topic: "t".to_string(),
+            partition: 0,
+            leader_epoch: epoch,
+            offset: 1,
+            max_bytes: u32::MAX,
+            max_wait_ms: 0,
+        });
+        match leader.handle(req).await {
+            Response::ReplicaFetch(r) => {
+                assert_eq!(r.status, expected);
+                if r.status == status::OK {
+                    assert_eq!(r.epochs, [(3, 0)]);
+                    assert_eq!(r.items.len(), 1);
+                }
+            }
+            other => panic!("expected ReplicaFetch response, got {other:?}"),
+        }
+    }
+
+    for (asked, expected) in [(1, (-1, -1)), (3, (3, 2)), (5, (3, 2))] {
+        let req = Request::OffsetForLeaderEpoch(OffsetForLeaderEpochRequest {
+            replica_id: 1,
+            topic: "t".to_string(),
+            partition: 0,
+            current_leader_epoch: 3,
+            leader_epoch: asked,
+        });
+        match leader.handle(req).await {
+            Response::OffsetForLeaderEpoch(r) => {
+                assert_eq!(r.status, status::OK);
+                assert_eq!((r.leader_epoch, r.end_offset), expected);
+            }
+            other => panic!("expected OffsetForLeaderEpoch response, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn acks_all_waits_for_the_in_sync_replicas() {
+    let nodes = start_cluster_with("isr-acks-all", 2, strict_config()).await;
+    nodes[0]
+        .broker
+        .become_leader("t", 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+    nodes[1]
+        .broker
+        .become_follower("t", 0, 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        produce_with_acks(&nodes[0].broker, "t", ACKS_ALL, &["a", "b"]).await,
+        status::OK
+    );
+    assert_eq!(high_watermark(&nodes[0].broker, "t"), 2);
+    assert_eq!(isr(&nodes[0].broker, "t"), [0, 1]);
+}
+
+#[tokio::test]
+async fn lagging_followers_leave_and_rejoin_the_in_sync_replicas() {
+    let nodes = start_cluster_with("isr-rejoin", 2, strict_config()).await;
+    let leader = &nodes[0].broker;
+    leader.become_leader("t", 0, 0, vec![0, 1]).await.unwrap();
+    assert_eq!(isr(leader, "t"), [0, 1]);
+
+    // the follower never fetches
+    assert_eq!(produce(leader, "t", &["a"]).await, status::OK);
+    eventually("the follower to drop out", || isr(leader, "t") == [0]).await;
+    assert_eq!(high_watermark(leader, "t"), 1);
+    assert_eq!(
+        produce_with_acks(leader, "t", ACKS_ALL, &["b"]).await,
+        status::NOT_ENOUGH_REPLICAS
+    );
+    assert_eq!(fetch(leader, "t").await, (0, 1, vec![0]));
+
+    nodes[1]
+        .broker
+        .become_follower("t", 0, 0, 0, vec![0, 1])
+        .await
+        .unwrap();
+    eventually("the follower to rejoin", || isr(leader, "t") == [0, 1]).await;
+    assert_eq!(
+        produce_with_acks(leader, "t", ACKS_ALL, &["c"]).await,
+        status::OK
+    );
+    assert_eq!(fetch(leader, "t").await, (0, 2, vec![0, 1]));
+}
+
+#[tokio::test]
+async fn acks_all_fails_when_followers_fall_behind_after_the_append() {
+    let nodes = start_cluster_with("isr-after-append", 2, strict_config()).await;
+    let leader = &nodes[0].broker;
+    leader.become_leader("t", 0, 0, vec![0, 1]).await.unwrap();
+
+    // the follower still counts as in sync, but never fetches the records
+    assert_eq!(
+        produce_with_acks(leader, "t", ACKS_ALL, &["a"]).await,
+        status::NOT_ENOUGH_REPLICAS_AFTER_APPEND
+    );
+    assert_eq!(isr(leader, "t"), [0]);
+    assert_eq!(high_watermark(leader, "t"), 1);
+}
+
+#[tokio::test]
+async fn invalid_acks_are_rejected() {
+    let nodes = start_cluster("isr-invalid-acks", 1).await;
+    assert_eq!(
+        produce_with_acks(&nodes[0].broker, "t", 2, &["a"]).await,
+        status::INVALID_REQUIRED_ACKS
+    );
+}
+
+/// Relays connections to `target`, counting the bytes sent towards it.
+async fn counting_proxy(target: String) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let sent = Arc::new(AtomicUsize::new(0));
+    let counter = sent.clone();
+    tokio::spawn(async move {
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            let mut outbound = TcpStream::connect(&target).await.unwrap();
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let (mut in_read, mut in_write) = inbound.split();
+                let (mut out_read, mut out_write) = outbound.split();
+                let upstream = async {
+                    let mut buf = [0; 4096];
+                    loop {
+                        let n = in_read.read(&mut buf).await.unwrap_or(0);
+                        if n == 0 || out_write.write_all(&buf[..n]).await.is_err() {
+                            break;
+                        }
+                        counter.fetch_add(n, Ordering::SeqCst);
+                    }
+                };
+                let downstream = tokio::io::copy(&mut out_read, &mut in_write);
+                let _ = tokio::join!(upstream, downstream);
+            });
+        }
+    });
+    (addr, sent)
+}
+
+#[tokio::test]
+async fn fenced_followers_stop_fetching() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let leader_addr = listener.local_addr().unwrap().to_string();
+    let leader_dir = temp_data_dir("replication-fenced-0");
+    let leader = Arc::new(Broker::new(leader_dir.clone()));
+    tokio::spawn(net::serve_listener(
+        listener,
+        leader.clone(),
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    leader.become_leader("t", 0, 3, vec![0, 1]).await.unwrap();
+
+    let (proxy_addr, sent) = counting_proxy(leader_addr).await;
+    let follower_dir = temp_data_dir("replication-fenced-1");
+    let follower = Arc::new(Broker::with_config(BrokerConfig {
+        broker_id: 1,
+        peers: BTreeMap::from([(0, proxy_addr)]),
+        replication: ReplicationConfig {
+            fetch_backoff: Duration::from_millis(10),
+            ..ReplicationConfig::default()
+        },
+        ..BrokerConfig::new(follower_dir.clone())
+    }));
+    // the follower missed the leader's last two elections
+    follower
+        .become_follower("t", 0, 0, 1, vec![0, 1])
+        .await
+        .unwrap();
+
+    eventually("the follower to reach the leader", || {
+        sent.load(Ordering::SeqCst) > 0
+    })
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let before = sent.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(sent.load(Ordering::SeqCst), before);
+
+    let _ = std::fs::remove_dir_all(&leader_dir);
+    let _ = std::fs::remove_dir_all(&follower_dir);
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use protocol::{
+    status,
+    types::{
+        AlterPartitionReassignmentsRequest, CreatePartitionsRequest, CreateTopicRequest,
+        DeleteTopicRequest, DescribeGroupRequest, EARLIEST_TIMESTAMP, GroupListing,
+        LATEST_TIMESTAMP, ListGroupsRequest, ListOffsetsRequest, ListPartitionReassignmentsRequest,
+        MetadataRequest, OffsetFetchRequest, PartitionReassignment, Request, Response,
+    },
+};
+
+use crate::{
+    Client, Error, Result,
+    group::{PROTOCOL_TYPE, decode_assignment},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupMember {
+    pub member_id: String,
+    /// Only known for groups of this crate's consumers.
+    pub partitions: Vec<(String, u16)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDescription {
+    pub group_id: String,
+    pub state: String,
+    pub protocol_type: String,
+    pub protocol_name: String,
+    pub generation_id: i32,
+    pub leader: String,
+    pub members: Vec<GroupMember>,
+    /// (topic, partition, offset) of every committed offset.
+    pub offsets: Vec<(String, u16, i64)>,
+}
+
+/// Topic and group administration, and offset lookups. Nothing is created
+/// implicitly, unlike through a `Producer` or `Consumer`.
+#[derive(Debug)]
+pub struct Admin {
+    client: Client,
+}
+
+impl Admin {
+    pub fn new(bootstrap: Vec<String>) -> Self {
+        Self {
+            client: Client::new(bootstrap, 1),
+        }
+    }
+
+    /// Every topic the client may describe, with its partitions.
+    pub async fn topics(&self) -> Result<BTreeMap<String, Vec<u16>>> {
+        self.metadata(vec![]).await
+    }
+
+    pub async fn partitions(&self, topic: &str) -> Result<Vec<u16>> {
+        let mut topics = self.metadata(vec![topic.to_string()]).await?;
+        Ok(topics.remove(topic).unwrap_or_default())
+    }
+
+    async fn metadata(&self, topics: Vec<String>) -> Result<BTreeMap<String, Vec<u16>>> {
+        let req = Request::Metadata(MetadataRequest {
+            topics,
+            allow_auto_create: false,
+        });
+        match self.client.request(&req).await? {
+            Response::Metadata(r) => r
+                .topics
+                .into_iter()
+                .map(|t| match t.status {
+                    status::OK => Ok((t.name, t.partitions)),
+                    s => Err(Error::Status(s)),
+                })
+                .collect(),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("metadata")),
+        }
+    }
+
+    pub async fn create_topic(&self, topic: &str, partitions: u16) -> Result<()> {
+        let req = Request::CreateTopic(CreateTopicRequest {
+            topic: topic.to_string(),
+            partitions,
+        });
+        match self.client.request(&req).await? {
+            Response::CreateTopic(r) if r.status == status::OK => Ok(()),
+            Response::CreateTopic(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("create topic")),
+        }
+    }
+
+    /// Raises the partition count of `topic` to `partitions`. Topics never
+    /// lose partitions, so asking for as many as it has or fewer fails.
+    pub async fn create_partitions(&self, topic: &str, partitions: u16) -> Result<()> {
+        let req = Request::CreatePartitions(CreatePartitionsRequest {
+            topic: topic.to_string(),
+            partitions,
+        });
+        match self.client.request(&req).await? {
+            Response::CreatePartitions(r) if r.status == status::OK => Ok(()),
+            Response::CreatePartitions(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("create partitions")),
+        }
+    }
+
+    pub async fn delete_topic(&self, topic: &str) -> Result<()> {
+        let req = Request::DeleteTopic(DeleteTopicRequest {
+            topic: topic.to_string(),
+        });
+        match self.client.request(&req).await? {
+            Response::DeleteTopic(r) if r.status == status::OK => Ok(()),
+            Response::DeleteTopic(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("delete topic")),
+        }
+    }
+
+    /// Moves the partition to `replicas`, the first of which is its preferred
+    /// leader. The move completes in the background once the new replicas
+    /// have caught up, each copying at most `throttle_bytes_per_sec` if
+    /// given. Must be sent to the controller leader.
+    pub async fn reassign_partition(
+        &self,
+        topic: &str,
+        partition: u16,
+        replicas: Vec<u32>,
+        throttle_bytes_per_sec: Option<u64>,
+    ) -> Result<()> {
+        let req = Request::AlterPartitionReassignments(AlterPartitionReassignmentsRequest {
+            topic: topic.to_string(),
+            partition,
+            replicas,
+            throttle_bytes_per_sec: throttle_bytes_per_sec
+                .map_or(0, |t| t.try_into().unwrap_or(i64::MAX)),
+        });
+        match self.client.request(&req).await? {
+            Response::AlterPartitionReassignments(r) if r.status == status::OK => Ok(()),
+            Response::AlterPartitionReassignments(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("alter partition reassignments")),
+        }
+    }
+
+    /// The partition moves under way, as known to the controller leader.
+    pub async fn reassignments(&self) -> Result<Vec<PartitionReassignment>> {
+        let req = Request::ListPartitionReassignments(ListPartitionReassignmentsRequest {});
+        match self.client.request(&req).await? {
+            Response::ListPartitionReassignments(r) if r.status == status::OK => {
+                Ok(r.reassignments)
+            }
+            Response::ListPartitionReassignments(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("list partition reassignments")),
+        }
+    }
+
+    /// The offset of the first record still in the partition and the offset
+    /// the next record appended gets.
+    pub async fn log_offsets(&self, topic: &str, partition: u16) -> Result<(i64, i64)> {
+        let earliest = self
+            .list_offset(topic, partition, EARLIEST_TIMESTAMP)
+            .await?;
+        let latest = self.list_offset(topic, partition, LATEST_TIMESTAMP).await?;
+        Ok((earliest, latest))
+    }
+
+    async fn list_offset(&self, topic: &str, partition: u16, timestamp: i64) -> Result<i64> {
+        let req = Request::ListOffsets(ListOffsetsRequest {
+            topic: topic.to_string(),
+            partition,
+            timestamp,
+        });
+        match self.client.request(&req).await? {
+            Response::ListOffsets(r) if r.status == status::OK => Ok(r.offset),
+            Response::ListOffsets(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("list offsets")),
+        }
+    }
+
+    pub async fn committed(
+        &self,
+        group_id: &str,
+        topic: &str,
+        partition: u16,
+    ) -> Result<Option<i64>> {
+        let req = Request::OffsetFetch(OffsetFetchRequest {
+            group_id: group_id.to_string(),
+            topic: topic.to_string(),
+            partition,
+        });
+        match self.client.request(&req).await? {
+            Response::OffsetFetch(r) if r.status == status::OK => {
+                Ok(Some(r.offset).filter(|&o| o >= 0))
+            }
+            Response::OffsetFetch(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("offset fetch")),
+        }
+    }
+
+    /// Groups the client may describe.
+    pub async fn groups(&self) -> Result<Vec<GroupListing>> {
+        match self
+            .client
+            .request(&Request::ListGroups(ListGroupsRequest {}))
+            .await?
+        {
+            Response::ListGroups(r) if r.status == status::OK => Ok(r.groups),
+            Response::ListGroups(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("list groups")),
+        }
+    }
+
+    pub async fn describe_group(&self, group_id: &str) -> Result<GroupDescription> {
+        let req = Request::DescribeGroup(DescribeGroupRequest {
+            group_id: group_id.to_string(),
+        });
+        let r = match self.client.request(&req).await? {
+            Response::DescribeGroup(r) if r.status == status::OK => r,
+            Response::DescribeGroup(r) => return Err(Error::Status(r.status)),
+            Response::Error { message } => return Err(Error::Broker(message)),
+            _ => return Err(Error::UnexpectedResponse("describe group")),
+        };
+        let members = r
+            .members
+            .into_iter()
+            .map(|m| {
+                let partitions = if r.protocol_type == PROTOCOL_TYPE {
+                    decode_assignment(&m.assignment)?
+                } else {
+                    vec![]
+                };
+                Ok(GroupMember {
+                    member_id: m.member_id,
+                    partitions,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(GroupDescription {
+            group_id: group_id.to_string(),
+            state: r.state,
+            protocol_type: r.protocol_type,
+            protocol_name: r.protocol_name,
+            generation_id: r.generation_id,
+            leader: r.leader,
+            members,
+            offsets: r
+                .offsets
+                .into_iter()
+                .map(|o| (o.topic, o.partition, o.offset))
+                .collect(),
+        })
+    }
+}
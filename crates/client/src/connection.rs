@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use protocol::{
+    connection::Connection,
+    types::{MetadataRequest, Request, Response},
+};
+use tokio::{sync::Semaphore, time::Instant};
+
+use crate::{Error, Result};
+
+/// A connection to whichever bootstrap broker answers first, opened on first
+/// use and again after it breaks. At most `max_in_flight` requests are sent
+/// without an answer, and after a response reports a quota delay no request is
+/// sent until it has passed.
+#[derive(Debug)]
+pub struct Client {
+    bootstrap: Vec<String>,
+    conn: tokio::sync::Mutex<Option<Arc<Connection>>>,
+    in_flight: Semaphore,
+    throttled_until: Mutex<Option<Instant>>,
+}
+
+impl Client {
+    pub fn new(bootstrap: Vec<String>, max_in_flight: usize) -> Self {
+        Self {
+            bootstrap,
+            conn: tokio::sync::Mutex::new(None),
+            in_flight: Semaphore::new(max_in_flight.max(1)),
+            throttled_until: Mutex::new(None),
+        }
+    }
+
+    pub async fn request(&self, req: &Request) -> Result<Response> {
+        let _permit = self.in_flight.acquire().await.map_err(|_| Error::Closed)?;
+        let throttled_until = *self.throttled_until.lock().unwrap();
+        if let Some(until) = throttled_until {
+            tokio::time::sleep_until(until).await;
+        }
+
+        let resp = self.connection().await?.request(req).await?;
+        let throttle_ms = match &resp {
+            Response::Produce(r) => r.throttle_time_ms,
+            Response::Fetch(r) => r.throttle_time_ms,
+            _ => 0,
+        };
+        if throttle_ms > 0 {
+            let until = Instant::now() + Duration::from_millis(throttle_ms as u64);
+            *self.throttled_until.lock().unwrap() = Some(until);
+        }
+        Ok(resp)
+    }
+
+    /// The partitions of each of `topics`, creating missing topics if the broker
+    /// allows it.
+    pub async fn partitions(&self, topics: &[&str]) -> Result<HashMap<String, Vec<u16>>> {
+        let req = Request::Metadata(MetadataRequest {
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            allow_auto_create: true,
+        });
+        match self.request(&req).await? {
+            Response::Metadata(r) => r
+                .topics
+                .into_iter()
+                .map(|t| match t.status {
+                    protocol::status::OK => Ok((t.name, t.partitions)),
+                    s => Err(Error::Status(s)),
+                })
+                .collect(),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("metadata")),
+        }
+    }
+
+    async fn connection(&self) -> Result<Arc<Connection>> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = &*conn
+            && !c.is_closed()
+        {
+            return Ok(c.clone());
+        }
+        let mut err = Error::NoBootstrap;
+        for addr in &self.bootstrap {
+            match Connection::connect(addr).await {
+                Ok(c) => {
+                    let c = Arc::new(c);
+                    *conn = Some(c.clone());
+                    return Ok(c);
+                }
+                Err(e) => {
+                    tracing::debug!(addr, error = %e, "bootstrap broker unreachable");
+                    err = e.into();
+                }
+            }
+        }
+        Err(err)
+    }
+}
+
+/// Delay before retry number `attempt`, counting from 0: `base`, doubling
+/// each time up to `max`.
+pub(crate) fn backoff(base: Duration, max: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.min(16)).min(max)
+}
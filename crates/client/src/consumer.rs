@@ -0,0 +1,574 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        EARLIEST_TIMESTAMP, FetchRequest, FetchResponse, LATEST_TIMESTAMP, ListOffsetsRequest,
+        OffsetCommitRequest, OffsetFetchRequest, Record, Request, Response,
+    },
+};
+use tokio::{task::JoinSet, time::Instant};
+
+use crate::{
+    Client, Error, Result,
+    group::{GroupConfig, Membership},
+};
+
+/// Where to start in a partition with no committed offset, or whose position
+/// is no longer in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetReset {
+    Earliest,
+    Latest,
+    /// `poll` fails instead.
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    /// Brokers to connect to, tried in order.
+    pub bootstrap: Vec<String>,
+    /// Needed to commit offsets and to share the partitions of subscribed
+    /// topics with other consumers.
+    pub group_id: Option<String>,
+    /// Positions are committed this often from `poll`, and before a rebalance
+    /// and on `close`. With `None`, only `commit` commits them.
+    pub auto_commit_interval: Option<Duration>,
+    pub auto_offset_reset: OffsetReset,
+    /// Most records returned by one `poll`.
+    pub max_poll_records: usize,
+    /// Most record bytes fetched from a partition at once.
+    pub fetch_max_bytes: u32,
+    /// Wait before fetching again from a partition that had nothing new.
+    pub fetch_backoff: Duration,
+    /// Group members are dropped if they send no heartbeat for this long.
+    /// Heartbeats are sent from `poll`.
+    pub session_timeout: Duration,
+    /// Time members get to rejoin when the group rebalances.
+    pub rebalance_timeout: Duration,
+    pub heartbeat_interval: Duration,
+}
+
+impl ConsumerConfig {
+    pub fn new(bootstrap: impl Into<String>) -> Self {
+        Self {
+            bootstrap: vec![bootstrap.into()],
+            group_id: None,
+            auto_commit_interval: Some(Duration::from_secs(5)),
+            auto_offset_reset: OffsetReset::Latest,
+            max_poll_records: 500,
+            fetch_max_bytes: 1024 * 1024,
+            fetch_backoff: Duration::from_millis(100),
+            session_timeout: Duration::from_secs(10),
+            rebalance_timeout: Duration::from_secs(30),
+            heartbeat_interval: Duration::from_secs(3),
+        }
+    }
+
+    fn group(&self) -> GroupConfig {
+        GroupConfig {
+            session_timeout: self.session_timeout,
+            rebalance_timeout: self.rebalance_timeout,
+            heartbeat_interval: self.heartbeat_interval,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerRecord {
+    pub topic: String,
+    pub partition: u16,
+    pub offset: i64,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+/// Where the next record of a partition is read from. All but `At` are
+/// looked up on the next `poll`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// The committed offset, or `auto_offset_reset` without one.
+    Committed,
+    Earliest,
+    Latest,
+    At(i64),
+}
+
+#[derive(Debug)]
+struct PartitionState {
+    position: Position,
+    paused: bool,
+    buffer: VecDeque<(i64, Record)>,
+    fetching: bool,
+    // the last fetch had nothing new
+    idle: bool,
+    committed: Option<i64>,
+}
+
+impl PartitionState {
+    fn new() -> Self {
+        Self {
+            position: Position::Committed,
+            paused: false,
+            buffer: VecDeque::new(),
+            fetching: false,
+            idle: false,
+            committed: None,
+        }
+    }
+
+    fn seek(&mut self, position: Position) {
+        self.position = position;
+        self.buffer.clear();
+        self.idle = false;
+    }
+}
+
+type TopicPartition = (String, u16);
+
+struct Fetched {
+    tp: TopicPartition,
+    offset: i64,
+    result: Result<FetchResponse>,
+}
+
+/// Reads records from assigned partitions, or from the partitions of subscribed
+/// topics, shared with the other members of its group. Fetches are sent in the
+/// background, so the next records of a partition are on their way while the
+/// last ones are processed.
+pub struct Consumer {
+    config: ConsumerConfig,
+    client: Arc<Client>,
+    subscription: Vec<String>,
+    membership: Option<Membership>,
+    // set when the subscription changed or the group is rebalancing
+    rejoin: bool,
+    // left on the next poll after the subscription was replaced
+    leaving: Option<Membership>,
+    partitions: BTreeMap<TopicPartition, PartitionState>,
+    fetches: JoinSet<Fetched>,
+    last_commit: Instant,
+}
+
+impl Consumer {
+    pub fn new(config: ConsumerConfig) -> Self {
+        // one request at a time would hold every fetch behind a slow one
+        let client = Arc::new(Client::new(config.bootstrap.clone(), 16));
+        Self {
+            config,
+            client,
+            subscription: Vec::new(),
+            membership: None,
+            rejoin: false,
+            leaving: None,
+            partitions: BTreeMap::new(),
+            fetches: JoinSet::new(),
+            last_commit: Instant::now(),
+        }
+    }
+
+    /// Reads from every partition of `topics`, or with a `group_id`, from the
+    /// ones the group assigns to this consumer. Replaces any earlier
+    /// subscription or assignment.
+    pub fn subscribe(&mut self, topics: &[&str]) {
+        self.subscription = topics.iter().map(|t| t.to_string()).collect();
+        if self.membership.is_none() {
+            self.membership = self.config.group_id.clone().map(Membership::new);
+        }
+        self.rejoin = true;
+    }
+
+    /// Reads from exactly these partitions. Replaces any earlier subscription
+    /// or assignment.
+    pub fn assign(&mut self, partitions: &[(&str, u16)]) {
+        self.subscription.clear();
+        self.rejoin = false;
+        if let Some(membership) = self.membership.take() {
+            self.leaving = Some(membership);
+        }
+        self.set_assignment(
+            partitions
+                .iter()
+                .map(|&(t, p)| (t.to_string(), p))
+                .collect(),
+        );
+    }
+
+    pub fn assignment(&self) -> Vec<(String, u16)> {
+        self.partitions.keys().cloned().collect()
+    }
+
+    /// The offset of the next record `poll` returns from the partition, once
+    /// known.
+    pub fn position(&self, topic: &str, partition: u16) -> Option<i64> {
+        match self
+            .partitions
+            .get(&(topic.to_string(), partition))?
+            .position
+        {
+            Position::At(offset) => Some(offset),
+            _ => None,
+        }
+    }
+
+    pub fn seek(&mut self, topic: &str, partition: u16, offset: i64) -> Result<()> {
+        self.state(topic, partition)?.seek(Position::At(offset));
+        Ok(())
+    }
+
+    pub fn seek_to_beginning(&mut self, topic: &str, partition: u16) -> Result<()> {
+        self.state(topic, partition)?.seek(Position::Earliest);
+        Ok(())
+    }
+
+    pub fn seek_to_end(&mut self, topic: &str, partition: u16) -> Result<()> {
+        self.state(topic, partition)?.seek(Position::Latest);
+        Ok(())
+    }
+
+    /// Stops returning records of the partition until it is resumed.
+    pub fn pause(&mut self, topic: &str, partition: u16) -> Result<()> {
+        self.state(topic, partition)?.paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&mut self, topic: &str, partition: u16) -> Result<()> {
+        self.state(topic, partition)?.paused = false;
+        Ok(())
+    }
+
+    fn state(&mut self, topic: &str, partition: u16) -> Result<&mut PartitionState> {
+        self.partitions
+            .get_mut(&(topic.to_string(), partition))
+            .ok_or_else(|| Error::NotAssigned {
+                topic: topic.to_string(),
+                partition,
+            })
+    }
+
+    /// Returns the next records, waiting up to `timeout` for some to arrive.
+    /// Also sends group heartbeats and auto-commits, so it has to be called
+    /// more often than the session timeout.
+    pub async fn poll(&mut self, timeout: Duration) -> Result<Vec<ConsumerRecord>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.maintain().await?;
+            let records = self.take_buffered();
+            self.send_fetches();
+            if !records.is_empty() {
+                return Ok(records);
+            }
+
+            // wake up for heartbeats even when nothing arrives
+            let wake = deadline.min(Instant::now() + self.config.heartbeat_interval);
+            if self.fetches.is_empty() {
+                tokio::time::sleep_until(wake).await;
+            } else if let Ok(Some(fetched)) =
+                tokio::time::timeout_at(wake, self.fetches.join_next()).await
+            {
+                let fetched = fetched.expect("fetch tasks don't panic");
+                self.handle_fetched(fetched)?;
+            }
+            if Instant::now() >= deadline {
+                return Ok(vec![]);
+            }
+        }
+    }
+
+    /// Commits the position of every partition, so the group resumes from
+    /// there.
+    pub async fn commit(&mut self) -> Result<()> {
+        let Some(group_id) = self.config.group_id.clone() else {
+            return Err(Error::NoGroupId);
+        };
+        for ((topic, partition), state) in &mut self.partitions {
+            let Position::At(offset) = state.position else {
+                continue;
+            };
+            if state.committed == Some(offset) {
+                continue;
+            }
+            let req = Request::OffsetCommit(OffsetCommitRequest {
+                group_id: group_id.clone(),
+                topic: topic.clone(),
+                partition: *partition,
+                offset,
+            });
+            match self.client.request(&req).await? {
+                Response::OffsetCommit(r) if r.status == status::OK => {
+                    state.committed = Some(offset);
+                }
+                Response::OffsetCommit(r) => return Err(Error::Status(r.status)),
+                Response::Error { message } => return Err(Error::Broker(message)),
+                _ => return Err(Error::UnexpectedResponse("offset commit")),
+            }
+        }
+        self.last_commit = Instant::now();
+        Ok(())
+    }
+
+    /// Commits if auto-commit is on and leaves the group.
+    pub async fn close(mut self) -> Result<()> {
+        if self.config.auto_commit_interval.is_some() && self.config.group_id.is_some() {
+            self.commit().await?;
+        }
+        if let Some(mut membership) = self.leaving.take() {
+            membership.leave(&self.client).await?;
+        }
+        if let Some(mut membership) = self.membership.take() {
+            membership.leave(&self.client).await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the group membership and assignment up to date, auto-commits and
+    /// looks up the positions of partitions that need one.
+    async fn maintain(&mut self) -> Result<()> {
+        if let Some(mut membership) = self.leaving.take() {
+            membership.leave(&self.client).await?;
+        }
+
+        let group = self.config.group();
+        if let Some(membership) = &mut self.membership
+            && !self.rejoin
+        {
+            self.rejoin = membership.heartbeat(&self.client, &group).await?;
+        }
+        if self.rejoin {
+            self.auto_commit(true).await?;
+            let assigned = match &mut self.membership {
+                Some(membership) => {
+                    membership
+                        .join(&self.client, &group, &self.subscription)
+                        .await?
+                }
+                None => {
+                    let topics: Vec<&str> = self.subscription.iter().map(String::as_str).collect();
+                    let partitions = self.client.partitions(&topics).await?;
+                    partitions
+                        .into_iter()
+                        .flat_map(|(t, ps)| ps.into_iter().map(move |p| (t.clone(), p)))
+                        .collect()
+                }
+            };
+            self.set_assignment(assigned);
+            self.rejoin = false;
+        }
+        self.auto_commit(false).await?;
+
+        let pending: Vec<_> = self
+            .partitions
+            .iter()
+            .filter(|(_, s)| !matches!(s.position, Position::At(_)))
+            .map(|(tp, s)| (tp.clone(), s.position))
+            .collect();
+        for ((topic, partition), position) in pending {
+            let offset = match self.look_up(&topic, partition, position).await {
+                Ok(offset) => offset,
+                // the partition may not exist yet; looked up again on the next poll
+                Err(e) if e.is_retriable() => {
+                    tracing::debug!(topic, partition, error = %e, "offset lookup failed");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(state) = self.partitions.get_mut(&(topic, partition)) {
+                state.position = Position::At(offset);
+            }
+        }
+        Ok(())
+    }
+
+    async fn auto_commit(&mut self, now: bool) -> Result<()> {
+        let Some(interval) = self.config.auto_commit_interval else {
+            return Ok(());
+        };
+        if self.config.group_id.is_some() && (now || self.last_commit.elapsed() >= interval) {
+            self.commit().await?;
+        }
+        Ok(())
+    }
+
+    fn set_assignment(&mut self, assigned: Vec<TopicPartition>) {
+        let mut partitions = BTreeMap::new();
+        for tp in assigned {
+            let state = self
+                .partitions
+                .remove(&tp)
+                .unwrap_or_else(PartitionState::new);
+            partitions.insert(tp, state);
+        }
+        self.partitions = partitions;
+    }
+
+    async fn look_up(&self, topic: &str, partition: u16, position: Position) -> Result<i64> {
+        let timestamp = match position {
+            Position::At(offset) => return Ok(offset),
+            Position::Earliest => EARLIEST_TIMESTAMP,
+            Position::Latest => LATEST_TIMESTAMP,
+            Position::Committed => {
+                if let Some(offset) = self.committed(topic, partition).await? {
+                    return Ok(offset);
+                }
+                match self.config.auto_offset_reset {
+                    OffsetReset::Earliest => EARLIEST_TIMESTAMP,
+                    OffsetReset::Latest => LATEST_TIMESTAMP,
+                    OffsetReset::Fail => {
+                        return Err(Error::NoOffset {
+                            topic: topic.to_string(),
+                            partition,
+                        });
+                    }
+                }
+            }
+        };
+        let req = Request::ListOffsets(ListOffsetsRequest {
+            topic: topic.to_string(),
+            partition,
+            timestamp,
+        });
+        match self.client.request(&req).await? {
+            Response::ListOffsets(r) if r.status == status::OK => Ok(r.offset),
+            Response::ListOffsets(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("list offsets")),
+        }
+    }
+
+    async fn committed(&self, topic: &str, partition: u16) -> Result<Option<i64>> {
+        let Some(group_id) = &self.config.group_id else {
+            return Ok(None);
+        };
+        let req = Request::OffsetFetch(OffsetFetchRequest {
+            group_id: group_id.clone(),
+            topic: topic.to_string(),
+            partition,
+        });
+        match self.client.request(&req).await? {
+            Response::OffsetFetch(r) if r.status == status::OK => {
+                Ok(Some(r.offset).filter(|&o| o >= 0))
+            }
+            Response::OffsetFetch(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("offset fetch")),
+        }
+    }
+
+    /// Takes up to `max_poll_records` buffered records of partitions that are
+    /// not paused, moving their positions past them.
+    fn take_buffered(&mut self) -> Vec<ConsumerRecord> {
+        let mut records = Vec::new();
+        for ((topic, partition), state) in &mut self.partitions {
+            if state.paused {
+                continue;
+            }
+            while records.len() < self.config.max_poll_records
+                && let Some((offset, record)) = state.buffer.pop_front()
+            {
+                state.position = Position::At(offset + 1);
+                records.push(ConsumerRecord {
+                    topic: topic.clone(),
+                    partition: *partition,
+                    offset,
+                    key: record.key,
+                    value: record.value,
+                });
+            }
+        }
+        records
+    }
+
+    /// Starts a fetch for every partition that is not paused and has nothing
+    /// buffered or on its way.
+    fn send_fetches(&mut self) {
+        for ((topic, partition), state) in &mut self.partitions {
+            let Position::At(offset) = state.position else {
+                continue;
+            };
+            if state.paused || state.fetching || !state.buffer.is_empty() {
+                continue;
+            }
+            state.fetching = true;
+            let delay = if state.idle {
+                self.config.fetch_backoff
+            } else {
+                Duration::ZERO
+            };
+            let client = self.client.clone();
+            let tp = (topic.clone(), *partition);
+            let req = Request::Fetch(FetchRequest {
+                topic: topic.clone(),
+                partition: *partition,
+                offset,
+                max_bytes: self.config.fetch_max_bytes,
+            });
+            self.fetches.spawn(async move {
+                tokio::time::sleep(delay).await;
+                let result = match client.request(&req).await {
+                    Ok(Response::Fetch(r)) => Ok(r),
+                    Ok(Response::Error { message }) => Err(Error::Broker(message)),
+                    Ok(_) => Err(Error::UnexpectedResponse("fetch")),
+                    Err(e) => Err(e),
+                };
+                Fetched { tp, offset, result }
+            });
+        }
+    }
+
+    fn handle_fetched(&mut self, fetched: Fetched) -> Result<()> {
+        let Fetched { tp, offset, result } = fetched;
+        let reset = self.config.auto_offset_reset;
+        let Some(state) = self.partitions.get_mut(&tp) else {
+            return Ok(());
+        };
+        state.fetching = false;
+        // seeked while the fetch was out
+        if state.position != Position::At(offset) {
+            return Ok(());
+        }
+
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) if e.is_retriable() => {
+                tracing::debug!(topic = tp.0, partition = tp.1, error = %e, "fetch failed");
+                state.idle = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        match resp.status {
+            status::OK => {
+                state.idle = resp.items.is_empty();
+                // a fetch may start before the requested offset
+                state
+                    .buffer
+                    .extend(resp.items.into_iter().filter(|(o, _)| *o >= offset));
+                Ok(())
+            }
+            status::OFFSET_OUT_OF_RANGE => {
+                match reset {
+                    OffsetReset::Earliest => state.seek(Position::Earliest),
+                    OffsetReset::Latest => state.seek(Position::Latest),
+                    OffsetReset::Fail => {
+                        return Err(Error::OffsetOutOfRange {
+                            topic: tp.0,
+                            partition: tp.1,
+                            offset,
+                        });
+                    }
+                }
+                Ok(())
+            }
+            status::UNKNOWN_TOPIC_OR_PARTITION => {
+                state.idle = true;
+                Ok(())
+            }
+            s => Err(Error::Status(s)),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use protocol::{
+    error::{ConnectionError, ProtoError},
+    status,
+};
+use thiserror::Error;
+
+/// Errors are cloned to every record of a failed batch, so the sources are
+/// shared.
+#[derive(Debug, Clone, Error)]
+pub enum Error {
+    #[error("io: {0}")]
+    Io(Arc<std::io::Error>),
+    #[error("protocol: {0}")]
+    Protocol(Arc<ProtoError>),
+    #[error("broker returned {name} (status {status})", status = .0, name = status::name(*.0))]
+    Status(u8),
+    #[error("broker error: {0}")]
+    Broker(String),
+    #[error("unexpected response to a {0} request")]
+    UnexpectedResponse(&'static str),
+    #[error("no bootstrap addresses")]
+    NoBootstrap,
+    #[error("client closed")]
+    Closed,
+    #[error("offset {offset} is out of range for {topic}/{partition}")]
+    OffsetOutOfRange {
+        topic: String,
+        partition: u16,
+        offset: i64,
+    },
+    #[error("no committed offset for {topic}/{partition}")]
+    NoOffset { topic: String, partition: u16 },
+    #[error("committing offsets needs a group id")]
+    NoGroupId,
+    #[error("{topic}/{partition} is not assigned")]
+    NotAssigned { topic: String, partition: u16 },
+}
+
+impl Error {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Error::Io(_) => true,
+            Error::Status(s) => matches!(
+                *s,
+                status::UNKNOWN_TOPIC_OR_PARTITION
+                    | status::NOT_LEADER_OR_FOLLOWER
+                    | status::REQUEST_TIMED_OUT
+                    | status::NOT_ENOUGH_REPLICAS
+                    | status::NOT_ENOUGH_REPLICAS_AFTER_APPEND
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(Arc::new(e))
+    }
+}
+
+impl From<ProtoError> for Error {
+    fn from(e: ProtoError) -> Self {
+        Error::Protocol(Arc::new(e))
+    }
+}
+
+impl From<ConnectionError> for Error {
+    fn from(e: ConnectionError) -> Self {
+        match e {
+            ConnectionError::Io(e) => Error::Io(e),
+            ConnectionError::Protocol(e) => Error::Protocol(e),
+            ConnectionError::Authentication(message) => Error::Broker(message),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use common::{Decode, Encode};
+use protocol::{
+    error::ProtoError,
+    status,
+    types::{
+        GroupProtocol, GroupProtocolMember, HeartbeatRequest, JoinGroupRequest, LeaveGroupRequest,
+        MemberAssignment, Request, Response, SyncGroupRequest,
+    },
+};
+use tokio::time::Instant;
+
+use crate::{Client, Error, Result};
+
+/// Group protocol type of consumers of this crate. Kafka clients use their own
+/// metadata format, so they can't share a group with these.
+pub(crate) const PROTOCOL_TYPE: &str = "mini-kafka-consumer";
+const RANGE_ASSIGNOR: &str = "range";
+
+/// Metadata a member joins with.
+#[derive(Debug, Encode, Decode)]
+struct Subscription {
+    topics: Vec<String>,
+}
+
+/// What the leader hands each member through SyncGroup.
+#[derive(Debug, Default, Encode, Decode)]
+struct Assignment {
+    partitions: Vec<(String, Vec<u16>)>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GroupConfig {
+    pub(crate) session_timeout: Duration,
+    pub(crate) rebalance_timeout: Duration,
+    pub(crate) heartbeat_interval: Duration,
+}
+
+/// Membership of one consumer in a group.
+#[derive(Debug)]
+pub(crate) struct Membership {
+    group_id: String,
+    member_id: String,
+    generation_id: i32,
+    last_heartbeat: Instant,
+}
+
+impl Membership {
+    pub(crate) fn new(group_id: String) -> Self {
+        Self {
+            group_id,
+            member_id: String::new(),
+            generation_id: -1,
+            last_heartbeat: Instant::now(),
+        }
+    }
+
+    /// Joins the group, or rejoins it after a rebalance started, and returns
+    /// the partitions assigned to this member.
+    pub(crate) async fn join(
+        &mut self,
+        client: &Client,
+        config: &GroupConfig,
+        topics: &[String],
+    ) -> Result<Vec<(String, u16)>> {
+        let mut metadata = BytesMut::new();
+        Subscription {
+            topics: topics.to_vec(),
+        }
+        .encode(&mut metadata)
+        .map_err(ProtoError::from)?;
+        let metadata = metadata.freeze();
+
+        loop {
+            let req = Request::JoinGroup(JoinGroupRequest {
+                group_id: self.group_id.clone(),
+                member_id: self.member_id.clone(),
+                session_timeout_ms: config.session_timeout.as_millis() as u32,
+                rebalance_timeout_ms: config.rebalance_timeout.as_millis() as u32,
+                protocol_type: PROTOCOL_TYPE.to_string(),
+                protocols: vec![GroupProtocol {
+                    name: RANGE_ASSIGNOR.to_string(),
+                    metadata: metadata.clone(),
+                }],
+            });
+            let joined = match client.request(&req).await? {
+                Response::JoinGroup(r) => r,
+                Response::Error { message } => return Err(Error::Broker(message)),
+                _ => return Err(Error::UnexpectedResponse("join group")),
+            };
+            match joined.status {
+                status::OK => {}
+                status::UNKNOWN_MEMBER_ID => {
+                    self.member_id.clear();
+                    continue;
+                }
+                s => return Err(Error::Status(s)),
+            }
+            self.member_id = joined.member_id;
+            self.generation_id = joined.generation_id;
+
+            let assignments = if joined.leader == self.member_id {
+                assign(client, &joined.members).await?
+            } else {
+                vec![]
+            };
+            let req = Request::SyncGroup(SyncGroupRequest {
+                group_id: self.group_id.clone(),
+                generation_id: self.generation_id,
+                member_id: self.member_id.clone(),
+                assignments,
+            });
+            let synced = match client.request(&req).await? {
+                Response::SyncGroup(r) => r,
+                Response::Error { message } => return Err(Error::Broker(message)),
+                _ => return Err(Error::UnexpectedResponse("sync group")),
+            };
+            match synced.status {
+                status::OK => {}
+                status::REBALANCE_IN_PROGRESS | status::ILLEGAL_GENERATION => continue,
+                status::UNKNOWN_MEMBER_ID => {
+                    self.member_id.clear();
+                    continue;
+                }
+                s => return Err(Error::Status(s)),
+            }
+            self.last_heartbeat = Instant::now();
+
+            return decode_assignment(&synced.assignment);
+        }
+    }
+
+    /// Sends a heartbeat if one is due. Returns whether the member has to
+    /// rejoin the group.
+    pub(crate) async fn heartbeat(
+        &mut self,
+        client: &Client,
+        config: &GroupConfig,
+    ) -> Result<bool> {
+        if self.last_heartbeat.elapsed() < config.heartbeat_interval {
+            return Ok(false);
+        }
+        let req = Request::Heartbeat(HeartbeatRequest {
+            group_id: self.group_id.clone(),
+            generation_id: self.generation_id,
+            member_id: self.member_id.clone(),
+        });
+        let status = match client.request(&req).await? {
+            Response::Heartbeat(r) => r.status,
+            Response::Error { message } => return Err(Error::Broker(message)),
+            _ => return Err(Error::UnexpectedResponse("heartbeat")),
+        };
+        self.last_heartbeat = Instant::now();
+        match status {
+            status::OK => Ok(false),
+            status::REBALANCE_IN_PROGRESS | status::ILLEGAL_GENERATION => Ok(true),
+            status::UNKNOWN_MEMBER_ID => {
+                self.member_id.clear();
+                Ok(true)
+            }
+            s => Err(Error::Status(s)),
+        }
+    }
+
+    pub(crate) async fn leave(&mut self, client: &Client) -> Result<()> {
+        if self.member_id.is_empty() {
+            return Ok(());
+        }
+        let req = Request::LeaveGroup(LeaveGroupRequest {
+            group_id: self.group_id.clone(),
+            member_id: std::mem::take(&mut self.member_id),
+        });
+        match client.request(&req).await? {
+            Response::LeaveGroup(r) if r.status == status::OK => Ok(()),
+            Response::LeaveGroup(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("leave group")),
+        }
+    }
+}
+
+/// The partitions in an assignment handed out by the leader.
+pub(crate) fn decode_assignment(mut bytes: &[u8]) -> Result<Vec<(String, u16)>> {
+    let assignment = if bytes.is_empty() {
+        Assignment::default()
+    } else {
+        Assignment::decode(&mut bytes).map_err(ProtoError::from)?
+    };
+    Ok(assignment
+        .partitions
+        .into_iter()
+        .flat_map(|(topic, partitions)| partitions.into_iter().map(move |p| (topic.clone(), p)))
+        .collect())
+}
+
+/// Assigns the partitions of every subscribed topic, run by the group leader.
+async fn assign(client: &Client, members: &[GroupProtocolMember]) -> Result<Vec<MemberAssignment>> {
+    let mut subscriptions = BTreeMap::new();
+    for m in members {
+        let sub = Subscription::decode(&mut m.metadata.clone()).map_err(ProtoError::from)?;
+        subscriptions.insert(m.member_id.as_str(), sub.topics);
+    }
+    let topics: BTreeSet<&str> = subscriptions
+        .values()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    let topics: Vec<&str> = topics.into_iter().collect();
+    let partitions = client.partitions(&topics).await?;
+
+    let mut assignments: BTreeMap<&str, Assignment> = subscriptions
+        .keys()
+        .map(|&member| (member, Assignment::default()))
+        .collect();
+    for (topic, partitions) in &partitions {
+        let subscribers: Vec<&str> = subscriptions
+            .iter()
+            .filter(|(_, topics)| topics.contains(topic))
+            .map(|(&member, _)| member)
+            .collect();
+        for (member, range) in subscribers
+            .iter()
+            .zip(ranges(partitions, subscribers.len()))
+        {
+            if !range.is_empty() {
+                let assignment = assignments.get_mut(member).expect("every member is listed");
+                assignment.partitions.push((topic.clone(), range.to_vec()));
+            }
+        }
+    }
+
+    assignments
+        .into_iter()
+        .map(|(member, assignment)| {
+            let mut buf = BytesMut::new();
+            assignment.encode(&mut buf).map_err(ProtoError::from)?;
+            Ok(MemberAssignment {
+                member_id: member.to_string(),
+                assignment: Bytes::from(buf),
+            })
+        })
+        .collect()
+}
+
+/// Splits `partitions` into `n` consecutive ranges, the first ones one longer
+/// when they don't divide evenly.
+fn ranges(partitions: &[u16], n: usize) -> Vec<&[u16]> {
+    if n == 0 {
+        return vec![];
+    }
+    let (per, extra) = (partitions.len() / n, partitions.len() % n);
+    let mut rest = partitions;
+    (0..n)
+        .map(|i| {
+            let (range, tail) = rest.split_at(per + (i < extra) as usize);
+            rest = tail;
+            range
+        })
+        .collect()
+}
@@ -0,0 +1,26 @@
+//! Async clients for the native protocol.
+//!
+//! A `Client` holds one pipelined connection to a broker, reconnecting through
+//! the bootstrap addresses when it breaks. A `Producer` batches records sent
+//! through it per partition. A `Consumer` reads assigned partitions, or shares
+//! the partitions of subscribed topics with the other members of its group.
+//! `Admin` manages topics and describes groups.
+
+mod admin;
+mod connection;
+mod consumer;
+mod error;
+mod group;
+mod partitioner;
+mod producer;
+
+pub use admin::{Admin, GroupDescription, GroupMember};
+pub use connection::Client;
+pub use consumer::{Consumer, ConsumerConfig, ConsumerRecord, OffsetReset};
+pub use error::Error;
+pub use partitioner::{DefaultPartitioner, Partitioner, murmur2};
+pub use producer::{Delivery, Producer, ProducerConfig};
+pub use protocol::connection::Connection;
+pub use protocol::types::{ACKS_ALL, ACKS_LEADER, GroupListing, PartitionReassignment};
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Picks the partition of records sent without one.
+pub trait Partitioner: std::fmt::Debug + Send + Sync {
+    /// Returns one of `partitions`, the partitions of `topic`, which is never
+    /// empty. `key` is empty for records without a key.
+    fn partition(&self, topic: &str, key: &[u8], partitions: &[u16]) -> u16;
+}
+
+/// Hashes keys with murmur2 like Kafka's default partitioner, so a key lands on
+/// the same partition as it would from a Kafka client. Records without a key
+/// go round robin.
+#[derive(Debug, Default)]
+pub struct DefaultPartitioner {
+    next: AtomicUsize,
+}
+
+impl Partitioner for DefaultPartitioner {
+    fn partition(&self, _topic: &str, key: &[u8], partitions: &[u16]) -> u16 {
+        let i = if key.is_empty() {
+            self.next.fetch_add(1, Ordering::Relaxed)
+        } else {
+            (murmur2(key) & 0x7fff_ffff) as usize
+        };
+        partitions[i % partitions.len()]
+    }
+}
+
+/// The 32-bit murmur2 hash, with the seed Kafka uses.
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, &b) in rest.iter().enumerate() {
+            h ^= (b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
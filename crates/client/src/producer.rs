@@ -0,0 +1,335 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{ACKS_ALL, ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+    time::Instant,
+};
+
+use crate::{Client, DefaultPartitioner, Error, Partitioner, Result, connection::backoff};
+
+#[derive(Debug, Clone)]
+pub struct ProducerConfig {
+    /// Brokers to connect to, tried in order.
+    pub bootstrap: Vec<String>,
+    /// How long a record may wait for others to join its batch.
+    pub linger: Duration,
+    /// A batch is sent as soon as its records take this many bytes.
+    pub batch_size: usize,
+    /// `ACKS_ALL` has a batch acknowledged once every in-sync replica has it,
+    /// `ACKS_LEADER` once the leader has.
+    pub acks: i16,
+    /// Produce requests sent without an answer yet. With more than one, a batch
+    /// that is retried may land after one sent later for the same partition.
+    pub max_in_flight: usize,
+    /// Times a batch is sent again after a connection error or a retriable
+    /// broker error, such as a missing partition or too few in-sync replicas. A batch whose response was lost may be written twice.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after up to
+    /// `retry_backoff_max`.
+    pub retry_backoff: Duration,
+    pub retry_backoff_max: Duration,
+    /// How long the partitions of a topic are cached.
+    pub metadata_max_age: Duration,
+    pub partitioner: Arc<dyn Partitioner>,
+}
+
+impl ProducerConfig {
+    pub fn new(bootstrap: impl Into<String>) -> Self {
+        Self {
+            bootstrap: vec![bootstrap.into()],
+            linger: Duration::from_millis(5),
+            batch_size: 16 * 1024,
+            acks: ACKS_ALL,
+            max_in_flight: 5,
+            retries: 5,
+            retry_backoff: Duration::from_millis(100),
+            retry_backoff_max: Duration::from_secs(1),
+            metadata_max_age: Duration::from_secs(300),
+            partitioner: Arc::new(DefaultPartitioner::default()),
+        }
+    }
+}
+
+/// Sends records in batches, one per partition, from a background task.
+/// Dropping the producer sends what is still batched.
+#[derive(Debug)]
+pub struct Producer {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+enum Command {
+    Send(PendingRecord),
+    Flush(oneshot::Sender<()>),
+}
+
+struct PendingRecord {
+    topic: String,
+    partition: Option<u16>,
+    record: Record,
+    delivered: oneshot::Sender<Result<i64>>,
+}
+
+/// Resolves to the offset a sent record was written at.
+#[derive(Debug)]
+pub struct Delivery(oneshot::Receiver<Result<i64>>);
+
+impl Future for Delivery {
+    type Output = Result<i64>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|res| res.unwrap_or(Err(Error::Closed)))
+    }
+}
+
+impl Producer {
+    /// Must be called within a Tokio runtime. Connects on the first send.
+    pub fn new(config: ProducerConfig) -> Self {
+        let (commands, rx) = mpsc::unbounded_channel();
+        let client = Arc::new(Client::new(config.bootstrap.clone(), config.max_in_flight));
+        let accumulator = Accumulator {
+            config,
+            client,
+            partitions: HashMap::new(),
+            batches: HashMap::new(),
+            in_flight: JoinSet::new(),
+        };
+        tokio::spawn(accumulator.run(rx));
+        Self { commands }
+    }
+
+    /// Queues a record for the partition the partitioner picks from its key.
+    /// The record is sent whether or not the returned future is awaited.
+    pub fn send(&self, topic: &str, key: impl Into<Bytes>, value: impl Into<Bytes>) -> Delivery {
+        self.enqueue(topic, None, key.into(), value.into())
+    }
+
+    /// Like `send`, to the given partition.
+    pub fn send_to(
+        &self,
+        topic: &str,
+        partition: u16,
+        key: impl Into<Bytes>,
+        value: impl Into<Bytes>,
+    ) -> Delivery {
+        self.enqueue(topic, Some(partition), key.into(), value.into())
+    }
+
+    fn enqueue(&self, topic: &str, partition: Option<u16>, key: Bytes, value: Bytes) -> Delivery {
+        let (delivered, delivery) = oneshot::channel();
+        let record = PendingRecord {
+            topic: topic.to_string(),
+            partition,
+            record: Record { key, value },
+            delivered,
+        };
+        // if the task is gone, the dropped sender fails the delivery
+        let _ = self.commands.send(Command::Send(record));
+        Delivery(delivery)
+    }
+
+    /// Sends every batched record without waiting for its linger time, and
+    /// returns once all records sent so far have been answered.
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.commands.send(Command::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+struct Batch {
+    records: Vec<Record>,
+    delivered: Vec<oneshot::Sender<Result<i64>>>,
+    bytes: usize,
+    deadline: Instant,
+}
+
+struct Accumulator {
+    config: ProducerConfig,
+    client: Arc<Client>,
+    partitions: HashMap<String, (Vec<u16>, Instant)>,
+    batches: HashMap<(String, u16), Batch>,
+    in_flight: JoinSet<()>,
+}
+
+impl Accumulator {
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command>) {
+        loop {
+            let deadline = self.batches.values().map(|b| b.deadline).min();
+            tokio::select! {
+                cmd = commands.recv() => match cmd {
+                    Some(Command::Send(record)) => self.append(record).await,
+                    Some(Command::Flush(done)) => {
+                        self.flush().await;
+                        let _ = done.send(());
+                    }
+                    None => {
+                        self.flush().await;
+                        return;
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() =>
+                {
+                    let now = Instant::now();
+                    let expired: Vec<_> = self
+                        .batches
+                        .iter()
+                        .filter(|(_, b)| b.deadline <= now)
+                        .map(|(key, _)| key.clone())
+                        .collect();
+                    for key in expired {
+                        self.send_batch(key);
+                    }
+                }
+                Some(_) = self.in_flight.join_next(), if !self.in_flight.is_empty() => {}
+            }
+        }
+    }
+
+    async fn append(&mut self, pending: PendingRecord) {
+        let partition = match pending.partition {
+            Some(p) => p,
+            None => match self.partitions_of(&pending.topic).await {
+                Ok(partitions) => self.config.partitioner.partition(
+                    &pending.topic,
+                    &pending.record.key,
+                    &partitions,
+                ),
+                Err(e) => {
+                    let _ = pending.delivered.send(Err(e));
+                    return;
+                }
+            },
+        };
+
+        let key = (pending.topic, partition);
+        let batch = self.batches.entry(key.clone()).or_insert_with(|| Batch {
+            records: Vec::new(),
+            delivered: Vec::new(),
+            bytes: 0,
+            deadline: Instant::now() + self.config.linger,
+        });
+        batch.bytes += pending.record.stored_len();
+        batch.records.push(pending.record);
+        batch.delivered.push(pending.delivered);
+        if batch.bytes >= self.config.batch_size {
+            self.send_batch(key);
+        }
+    }
+
+    async fn partitions_of(&mut self, topic: &str) -> Result<Vec<u16>> {
+        if let Some((partitions, fetched)) = self.partitions.get(topic)
+            && fetched.elapsed() < self.config.metadata_max_age
+        {
+            return Ok(partitions.clone());
+        }
+        let client = &self.client;
+        let partitions = retrying(&self.config, "metadata", || async {
+            client
+                .partitions(&[topic])
+                .await?
+                .remove(topic)
+                .filter(|p| !p.is_empty())
+                .ok_or(Error::Status(status::UNKNOWN_TOPIC_OR_PARTITION))
+        })
+        .await?;
+        self.partitions
+            .insert(topic.to_string(), (partitions.clone(), Instant::now()));
+        Ok(partitions)
+    }
+
+    fn send_batch(&mut self, key: (String, u16)) {
+        let Some(batch) = self.batches.remove(&key) else {
+            return;
+        };
+        let client = self.client.clone();
+        let config = self.config.clone();
+        self.in_flight.spawn(async move {
+            let (topic, partition) = key;
+            match produce(&client, &config, topic, partition, batch.records).await {
+                Ok(base_offset) => {
+                    for (i, delivered) in batch.delivered.into_iter().enumerate() {
+                        let _ = delivered.send(Ok(base_offset + i as i64));
+                    }
+                }
+                Err(e) => {
+                    for delivered in batch.delivered {
+                        let _ = delivered.send(Err(e.clone()));
+                    }
+                }
+            }
+        });
+    }
+
+    async fn flush(&mut self) {
+        let keys: Vec<_> = self.batches.keys().cloned().collect();
+        for key in keys {
+            self.send_batch(key);
+        }
+        while self.in_flight.join_next().await.is_some() {}
+    }
+}
+
+/// Sends one batch and returns its base offset.
+async fn produce(
+    client: &Client,
+    config: &ProducerConfig,
+    topic: String,
+    partition: u16,
+    records: Vec<Record>,
+) -> Result<i64> {
+    let req = Request::Produce(ProduceRequest {
+        topic,
+        partition,
+        acks: config.acks,
+        records,
+    });
+    retrying(config, "produce", || async {
+        match client.request(&req).await? {
+            Response::Produce(r) if r.status == status::OK => Ok(r.base_offset),
+            Response::Produce(r) => Err(Error::Status(r.status)),
+            Response::Error { message } => Err(Error::Broker(message)),
+            _ => Err(Error::UnexpectedResponse("produce")),
+        }
+    })
+    .await
+}
+
+/// Runs `f` until it succeeds, fails with an error that is not retriable, or
+/// has been retried `config.retries` times.
+async fn retrying<T, F, Fut>(config: &ProducerConfig, what: &str, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        let err = match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+        if attempt >= config.retries || !err.is_retriable() {
+            return Err(err);
+        }
+        let delay = backoff(config.retry_backoff, config.retry_backoff_max, attempt);
+        tracing::debug!(error = %err, attempt, ?delay, "retrying {what}");
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
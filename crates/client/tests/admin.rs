@@ -0,0 +1,85 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use broker::Broker;
+use client::{Admin, Consumer, ConsumerConfig, Error, OffsetReset};
+use net::{Connections, Shutdown};
+use protocol::status;
+use tokio::net::TcpListener;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start_broker(name: &str) -> String {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    addr
+}
+
+#[tokio::test]
+async fn topics_are_managed() {
+    let addr = start_broker("admin-client-topics").await;
+    let admin = Admin::new(vec![addr]);
+
+    admin.create_topic("managed", 2).await.unwrap();
+    assert_eq!(admin.partitions("managed").await.unwrap(), [0, 1]);
+    assert_eq!(admin.topics().await.unwrap()["managed"], [0, 1]);
+    assert_eq!(admin.log_offsets("managed", 1).await.unwrap(), (0, 0));
+    assert!(matches!(
+        admin.create_topic("managed", 2).await,
+        Err(Error::Status(status::TOPIC_ALREADY_EXISTS))
+    ));
+
+    admin.delete_topic("managed").await.unwrap();
+    assert!(!admin.topics().await.unwrap().contains_key("managed"));
+}
+
+#[tokio::test]
+async fn consumer_groups_are_described() {
+    let addr = start_broker("admin-client-groups").await;
+    let admin = Admin::new(vec![addr.clone()]);
+    admin.create_topic("described", 2).await.unwrap();
+
+    let mut consumer = Consumer::new(ConsumerConfig {
+        group_id: Some("describing".to_string()),
+        auto_offset_reset: OffsetReset::Earliest,
+        ..ConsumerConfig::new(addr)
+    });
+    consumer.subscribe(&["described"]);
+    consumer.poll(Duration::from_millis(50)).await.unwrap();
+    consumer.commit().await.unwrap();
+
+    let groups = admin.groups().await.unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].group_id, "describing");
+
+    let group = admin.describe_group("describing").await.unwrap();
+    assert_eq!(group.state, "Stable");
+    assert_eq!(group.members.len(), 1);
+    assert_eq!(
+        group.members[0].partitions,
+        [("described".to_string(), 0), ("described".to_string(), 1)]
+    );
+    assert_eq!(
+        group.offsets,
+        [
+            ("described".to_string(), 0, 0),
+            ("described".to_string(), 1, 0)
+        ]
+    );
+    assert_eq!(
+        admin.committed("describing", "described", 1).await.unwrap(),
+        Some(0)
+    );
+    consumer.close().await.unwrap();
+}
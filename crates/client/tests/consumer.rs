@@ -0,0 +1,260 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use broker::Broker;
+use client::{
+    Consumer, ConsumerConfig, ConsumerRecord, Error, OffsetReset, Producer, ProducerConfig,
+};
+use net::{Connections, Shutdown};
+use tokio::net::TcpListener;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start_broker(name: &str) -> (Arc<Broker>, String) {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker.clone(),
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    (broker, addr)
+}
+
+async fn produce(addr: &str, topic: &str, partition: u16, values: &[&str]) {
+    let producer = Producer::new(ProducerConfig::new(addr));
+    let deliveries: Vec<_> = values
+        .iter()
+        .map(|v| producer.send_to(topic, partition, "", v.to_string()))
+        .collect();
+    for delivery in deliveries {
+        delivery.await.unwrap();
+    }
+}
+
+fn config(addr: &str) -> ConsumerConfig {
+    ConsumerConfig {
+        auto_offset_reset: OffsetReset::Earliest,
+        fetch_backoff: Duration::from_millis(10),
+        heartbeat_interval: Duration::from_millis(50),
+        ..ConsumerConfig::new(addr)
+    }
+}
+
+/// Polls until `n` records arrived, or fails after a few seconds.
+async fn poll_n(consumer: &mut Consumer, n: usize) -> Vec<ConsumerRecord> {
+    let mut records = Vec::new();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while records.len() < n {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "got {} of {n} records",
+            records.len()
+        );
+        records.extend(consumer.poll(Duration::from_millis(100)).await.unwrap());
+    }
+    records
+}
+
+fn values(records: &[ConsumerRecord]) -> Vec<String> {
+    records
+        .iter()
+        .map(|r| String::from_utf8(r.value.to_vec()).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn assigned_partition_is_read_and_seeked() {
+    let (_broker, addr) = start_broker("consumer-assign").await;
+    produce(&addr, "assigned", 0, &["a", "b", "c"]).await;
+
+    let mut consumer = Consumer::new(config(&addr));
+    consumer.assign(&[("assigned", 0)]);
+    let records = poll_n(&mut consumer, 3).await;
+    assert_eq!(values(&records), ["a", "b", "c"]);
+    assert_eq!(
+        records.iter().map(|r| r.offset).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    assert_eq!(consumer.position("assigned", 0), Some(3));
+
+    consumer.seek("assigned", 0, 1).unwrap();
+    assert_eq!(values(&poll_n(&mut consumer, 2).await), ["b", "c"]);
+
+    consumer.seek_to_beginning("assigned", 0).unwrap();
+    assert_eq!(values(&poll_n(&mut consumer, 3).await), ["a", "b", "c"]);
+
+    // records produced while polling arrive too
+    consumer.seek_to_end("assigned", 0).unwrap();
+    assert!(
+        consumer
+            .poll(Duration::from_millis(50))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    produce(&addr, "assigned", 0, &["d"]).await;
+    assert_eq!(values(&poll_n(&mut consumer, 1).await), ["d"]);
+
+    assert!(matches!(
+        consumer.seek("assigned", 1, 0),
+        Err(Error::NotAssigned { .. })
+    ));
+}
+
+#[tokio::test]
+async fn committed_offsets_are_resumed_from() {
+    let (_broker, addr) = start_broker("consumer-commit").await;
+    produce(&addr, "committed", 0, &["a", "b", "c", "d"]).await;
+    let config = ConsumerConfig {
+        group_id: Some("resuming".to_string()),
+        auto_commit_interval: None,
+        ..config(&addr)
+    };
+
+    let mut consumer = Consumer::new(config.clone());
+    consumer.assign(&[("committed", 0)]);
+    assert_eq!(
+        values(&poll_n(&mut consumer, 4).await),
+        ["a", "b", "c", "d"]
+    );
+    consumer.seek("committed", 0, 2).unwrap();
+    consumer.commit().await.unwrap();
+    consumer.close().await.unwrap();
+
+    let mut consumer = Consumer::new(config);
+    consumer.assign(&[("committed", 0)]);
+    assert_eq!(values(&poll_n(&mut consumer, 2).await), ["c", "d"]);
+
+    let mut groupless = Consumer::new(ConsumerConfig::new(&addr));
+    groupless.assign(&[("committed", 0)]);
+    assert!(matches!(groupless.commit().await, Err(Error::NoGroupId)));
+}
+
+#[tokio::test]
+async fn auto_commit_commits_on_close() {
+    let (_broker, addr) = start_broker("consumer-auto-commit").await;
+    produce(&addr, "auto", 0, &["a", "b"]).await;
+    let config = ConsumerConfig {
+        group_id: Some("auto".to_string()),
+        auto_commit_interval: Some(Duration::from_secs(60)),
+        ..config(&addr)
+    };
+
+    let mut consumer = Consumer::new(config.clone());
+    consumer.assign(&[("auto", 0)]);
+    poll_n(&mut consumer, 2).await;
+    consumer.close().await.unwrap();
+
+    produce(&addr, "auto", 0, &["c"]).await;
+    let mut consumer = Consumer::new(config);
+    consumer.assign(&[("auto", 0)]);
+    assert_eq!(values(&poll_n(&mut consumer, 1).await), ["c"]);
+}
+
+#[tokio::test]
+async fn out_of_range_positions_are_reset() {
+    let (_broker, addr) = start_broker("consumer-reset").await;
+    produce(&addr, "reset", 0, &["a", "b"]).await;
+
+    let mut consumer = Consumer::new(config(&addr));
+    consumer.assign(&[("reset", 0)]);
+    consumer.seek("reset", 0, 10).unwrap();
+    assert_eq!(values(&poll_n(&mut consumer, 2).await), ["a", "b"]);
+
+    let mut consumer = Consumer::new(ConsumerConfig {
+        auto_offset_reset: OffsetReset::Fail,
+        ..config(&addr)
+    });
+    consumer.assign(&[("reset", 0)]);
+    // no group, so no committed offset to start from
+    assert!(matches!(
+        consumer.poll(Duration::from_millis(100)).await,
+        Err(Error::NoOffset { .. })
+    ));
+    consumer.seek("reset", 0, 10).unwrap();
+    let err = loop {
+        match consumer.poll(Duration::from_millis(100)).await {
+            Ok(records) => assert!(records.is_empty()),
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(err, Error::OffsetOutOfRange { offset: 10, .. }));
+}
+
+#[tokio::test]
+async fn paused_partitions_return_nothing_until_resumed() {
+    let (_broker, addr) = start_broker("consumer-pause").await;
+    produce(&addr, "paused", 0, &["a"]).await;
+    produce(&addr, "paused", 1, &["b"]).await;
+
+    let mut consumer = Consumer::new(config(&addr));
+    consumer.assign(&[("paused", 0), ("paused", 1)]);
+    consumer.pause("paused", 0).unwrap();
+    let records = poll_n(&mut consumer, 1).await;
+    assert_eq!(values(&records), ["b"]);
+    assert!(
+        consumer
+            .poll(Duration::from_millis(100))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    consumer.resume("paused", 0).unwrap();
+    let records = poll_n(&mut consumer, 1).await;
+    assert_eq!(
+        (records[0].partition, values(&records)),
+        (0, vec!["a".to_string()])
+    );
+}
+
+#[tokio::test]
+async fn group_members_split_subscribed_partitions() {
+    let (_broker, addr) = start_broker("consumer-group").await;
+    for p in 0..2 {
+        produce(&addr, "shared", p, &["x"]).await;
+    }
+    let config = ConsumerConfig {
+        group_id: Some("splitting".to_string()),
+        rebalance_timeout: Duration::from_secs(2),
+        ..config(&addr)
+    };
+
+    let mut first = Consumer::new(config.clone());
+    first.subscribe(&["shared"]);
+    assert_eq!(poll_n(&mut first, 2).await.len(), 2);
+    assert_eq!(first.assignment().len(), 2);
+
+    // the first member has to keep polling to rejoin while the second joins
+    let mut second = Consumer::new(config);
+    second.subscribe(&["shared"]);
+    let polling = tokio::spawn(async move {
+        while first.assignment().len() != 1 {
+            first.poll(Duration::from_millis(50)).await.unwrap();
+        }
+        first
+    });
+    second.poll(Duration::from_millis(50)).await.unwrap();
+    let first = tokio::time::timeout(Duration::from_secs(5), polling)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut assigned = first.assignment();
+    assigned.extend(second.assignment());
+    assigned.sort();
+    assert_eq!(
+        assigned,
+        [("shared".to_string(), 0), ("shared".to_string(), 1)]
+    );
+
+    first.close().await.unwrap();
+    second.close().await.unwrap();
+}
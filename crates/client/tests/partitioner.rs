@@ -0,0 +1,33 @@
+use client::{DefaultPartitioner, Partitioner, murmur2};
+
+#[test]
+fn murmur2_matches_kafka() {
+    // from Kafka's own tests of `Utils.murmur2`
+    let cases: &[(&str, i32)] = &[
+        ("21", -973932308),
+        ("foobar", -790332482),
+        ("a-little-bit-long-string", -985981536),
+        ("a-little-bit-longer-string", -1486304829),
+        ("lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8", -58897971),
+        ("abc", 479470107),
+    ];
+    for &(input, hash) in cases {
+        assert_eq!(murmur2(input.as_bytes()), hash, "{input}");
+    }
+}
+
+#[test]
+fn keys_stick_to_a_partition_and_the_rest_go_round_robin() {
+    let partitioner = DefaultPartitioner::default();
+    let partitions = [0, 1, 2];
+
+    let first = partitioner.partition("t", b"user-1", &partitions);
+    for _ in 0..10 {
+        assert_eq!(partitioner.partition("t", b"user-1", &partitions), first);
+    }
+
+    let picked: Vec<u16> = (0..6)
+        .map(|_| partitioner.partition("t", b"", &partitions))
+        .collect();
+    assert_eq!(picked, [0, 1, 2, 0, 1, 2]);
+}
@@ -0,0 +1,161 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use broker::Broker;
+use client::{Partitioner, Producer, ProducerConfig, murmur2};
+use net::{Connections, Shutdown};
+use protocol::types::{LATEST_TIMESTAMP, ListOffsetsRequest, Request, Response};
+use tokio::net::TcpListener;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start_broker(name: &str) -> (Arc<Broker>, String) {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker.clone(),
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    (broker, addr)
+}
+
+async fn log_end_offset(broker: &Broker, topic: &str, partition: u16) -> i64 {
+    let req = Request::ListOffsets(ListOffsetsRequest {
+        topic: topic.to_string(),
+        partition,
+        timestamp: LATEST_TIMESTAMP,
+    });
+    match broker.handle(req).await {
+        Response::ListOffsets(r) => r.offset,
+        other => panic!("expected ListOffsets response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn records_wait_for_linger_or_flush() {
+    let (broker, addr) = start_broker("producer-linger").await;
+    let producer = Producer::new(ProducerConfig {
+        linger: Duration::from_secs(60),
+        ..ProducerConfig::new(addr)
+    });
+
+    let deliveries: Vec<_> = (0..3)
+        .map(|i| producer.send_to("lingering", 0, "", format!("v{i}")))
+        .collect();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(log_end_offset(&broker, "lingering", 0).await, 0);
+
+    producer.flush().await;
+    for (i, delivery) in deliveries.into_iter().enumerate() {
+        assert_eq!(delivery.await.unwrap(), i as i64);
+    }
+    assert_eq!(log_end_offset(&broker, "lingering", 0).await, 3);
+}
+
+#[tokio::test]
+async fn full_batches_go_out_without_lingering() {
+    let (_broker, addr) = start_broker("producer-batch-size").await;
+    let producer = Producer::new(ProducerConfig {
+        linger: Duration::from_secs(60),
+        batch_size: 1,
+        ..ProducerConfig::new(addr)
+    });
+
+    for i in 0..5 {
+        let delivery = producer.send_to("eager", 0, "", "v");
+        let offset = tokio::time::timeout(Duration::from_secs(5), delivery)
+            .await
+            .expect("a full batch is sent right away")
+            .unwrap();
+        assert_eq!(offset, i);
+    }
+}
+
+#[derive(Debug)]
+struct LastPartition;
+
+impl Partitioner for LastPartition {
+    fn partition(&self, _topic: &str, _key: &[u8], partitions: &[u16]) -> u16 {
+        *partitions.last().unwrap()
+    }
+}
+
+#[tokio::test]
+async fn keys_are_partitioned_by_hash_or_a_custom_partitioner() {
+    let (broker, addr) = start_broker("producer-partitioner").await;
+    let producer = Producer::new(ProducerConfig {
+        linger: Duration::ZERO,
+        ..ProducerConfig::new(addr.clone())
+    });
+    // topics get their partitions as they are written to
+    for p in 0..3 {
+        producer.send_to("keyed", p, "", "v").await.unwrap();
+    }
+
+    let hashed = ((murmur2(b"user-7") & 0x7fff_ffff) % 3) as u16;
+    producer.send("keyed", "user-7", "v").await.unwrap();
+    producer.send("keyed", "user-7", "v").await.unwrap();
+    assert_eq!(log_end_offset(&broker, "keyed", hashed).await, 3);
+
+    let custom = Producer::new(ProducerConfig {
+        linger: Duration::ZERO,
+        partitioner: Arc::new(LastPartition),
+        ..ProducerConfig::new(addr)
+    });
+    let end = log_end_offset(&broker, "keyed", 2).await;
+    assert_eq!(custom.send("keyed", "user-7", "v").await.unwrap(), end);
+}
+
+#[tokio::test]
+async fn sends_are_retried_until_the_broker_is_reachable() {
+    // reserve an address nobody listens on yet
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let producer = Producer::new(ProducerConfig {
+        linger: Duration::ZERO,
+        retries: 10,
+        retry_backoff: Duration::from_millis(50),
+        retry_backoff_max: Duration::from_millis(200),
+        ..ProducerConfig::new(addr.to_string())
+    });
+    let delivery = producer.send("late", "k", "v");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let broker = Arc::new(Broker::new(temp_data_dir("producer-retry")));
+    let listener = TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+
+    assert_eq!(delivery.await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn retries_give_up() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let producer = Producer::new(ProducerConfig {
+        retries: 2,
+        retry_backoff: Duration::from_millis(10),
+        ..ProducerConfig::new(addr.to_string())
+    });
+    let err = producer.send_to("nowhere", 0, "", "v").await.unwrap_err();
+    assert!(matches!(err, client::Error::Io(_)), "{err:?}");
+}
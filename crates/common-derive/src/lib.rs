@@ -0,0 +1,200 @@
+//! `#[derive(Encode, Decode)]` for the traits in `common::codec`.
+//!
+//! Structs encode their fields in declaration order. Enums encode a `u8` tag
+//! followed by the variant's fields; every variant needs `#[wire(tag = <expr>)]`.
+//! A field marked `#[wire(default = <expr>)]` decodes as `<expr>` when the
+//! buffer ends before it, so fields added at the end of a message can still be
+//! read from peers that don't send them yet.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Expr, Field, Fields, Variant, parse_macro_input, spanned::Spanned};
+
+#[proc_macro_derive(Encode, attributes(wire))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_encode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(wire))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_encode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(s) => {
+            let (pattern, bindings) = destructure(&s.fields);
+            quote! {
+                let #name #pattern = self;
+                #(::common::Encode::encode(#bindings, buf)?;)*
+            }
+        }
+        Data::Enum(e) => {
+            let mut arms = Vec::with_capacity(e.variants.len());
+            for v in &e.variants {
+                let tag = variant_tag(v)?;
+                let ident = &v.ident;
+                let (pattern, bindings) = destructure(&v.fields);
+                arms.push(quote! {
+                    #name::#ident #pattern => {
+                        ::common::Encode::encode(&((#tag) as u8), buf)?;
+                        #(::common::Encode::encode(#bindings, buf)?;)*
+                    }
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(input.span(), "unions cannot derive Encode"));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::common::Encode for #name #ty_generics #where_clause {
+            fn encode(
+                &self,
+                buf: &mut ::common::__private::BytesMut,
+            ) -> ::core::result::Result<(), ::common::IoError> {
+                #body
+                Ok(())
+            }
+        }
+    })
+}
+
+fn expand_decode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(s) => {
+            let ctor = construct(quote!(#name), &s.fields)?;
+            quote! { Ok(#ctor) }
+        }
+        Data::Enum(e) => {
+            let mut arms = Vec::with_capacity(e.variants.len());
+            for v in &e.variants {
+                let tag = variant_tag(v)?;
+                let ident = &v.ident;
+                let ctor = construct(quote!(#name::#ident), &v.fields)?;
+                arms.push(quote! {
+                    t if t == ((#tag) as u8) => Ok(#ctor),
+                });
+            }
+            quote! {
+                let tag = <u8 as ::common::Decode>::decode(buf)?;
+                match tag {
+                    #(#arms)*
+                    t => Err(::common::IoError::UnknownTag(t)),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(input.span(), "unions cannot derive Decode"));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::common::Decode for #name #ty_generics #where_clause {
+            fn decode(
+                buf: &mut dyn ::common::__private::Buf,
+            ) -> ::core::result::Result<Self, ::common::IoError> {
+                #body
+            }
+        }
+    })
+}
+
+/// Pattern binding every field by reference, plus the bound names in order.
+fn destructure(fields: &Fields) -> (TokenStream2, Vec<syn::Ident>) {
+    match fields {
+        Fields::Named(named) => {
+            let names: Vec<_> = named.named.iter().filter_map(|f| f.ident.clone()).collect();
+            (quote!({ #(#names),* }), names)
+        }
+        Fields::Unnamed(unnamed) => {
+            let names: Vec<_> = (0..unnamed.unnamed.len())
+                .map(|i| format_ident!("f{i}"))
+                .collect();
+            (quote!(( #(#names),* )), names)
+        }
+        Fields::Unit => (quote!(), vec![]),
+    }
+}
+
+/// Constructor expression decoding every field in declaration order.
+fn construct(path: TokenStream2, fields: &Fields) -> syn::Result<TokenStream2> {
+    Ok(match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            let values = named
+                .named
+                .iter()
+                .map(decode_field)
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote!(#path { #(#names: #values,)* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let values = unnamed
+                .unnamed
+                .iter()
+                .map(decode_field)
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote!(#path( #(#values),* ))
+        }
+        Fields::Unit => path,
+    })
+}
+
+fn decode_field(f: &Field) -> syn::Result<TokenStream2> {
+    let mut default = None;
+    for attr in f.attrs.iter().filter(|a| a.path().is_ident("wire")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                default = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `default = <expr>`"))
+            }
+        })?;
+    }
+    Ok(match default {
+        Some(default) => quote! {
+            if ::common::__private::Buf::has_remaining(buf) {
+                ::common::Decode::decode(buf)?
+            } else {
+                #default
+            }
+        },
+        None => quote!(::common::Decode::decode(buf)?),
+    })
+}
+
+fn variant_tag(v: &Variant) -> syn::Result<Expr> {
+    let mut tag = None;
+    for attr in v.attrs.iter().filter(|a| a.path().is_ident("wire")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag = <expr>`"))
+            }
+        })?;
+    }
+    tag.ok_or_else(|| syn::Error::new(v.span(), "missing #[wire(tag = ...)] on variant"))
+}
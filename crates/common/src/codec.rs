@@ -0,0 +1,165 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{error::IoError, read::*, write::*};
+
+/// Wire encoding: big-endian integers, `u16`-prefixed strings, `u32`-prefixed
+/// bytes and `u16`-counted arrays.
+pub trait Encode {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError>;
+}
+
+pub trait Decode: Sized {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError>;
+}
+
+impl Encode for u8 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_u8(*self);
+        Ok(())
+    }
+}
+
+impl Decode for u8 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_u8(buf)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_u8(*self as u8);
+        Ok(())
+    }
+}
+
+impl Decode for bool {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        Ok(read_u8(buf)? != 0)
+    }
+}
+
+impl Encode for u16 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_u16(*self);
+        Ok(())
+    }
+}
+
+impl Decode for u16 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_u16(buf)
+    }
+}
+
+impl Encode for i16 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_i16(*self);
+        Ok(())
+    }
+}
+
+impl Decode for i16 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_i16(buf)
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_u32(*self);
+        Ok(())
+    }
+}
+
+impl Decode for u32 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_u32(buf)
+    }
+}
+
+impl Encode for i32 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_i32(*self);
+        Ok(())
+    }
+}
+
+impl Decode for i32 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_i32(buf)
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        buf.put_i64(*self);
+        Ok(())
+    }
+}
+
+impl Decode for i64 {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_i64(buf)
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        write_str(buf, self)
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        read_str(buf)
+    }
+}
+
+impl Encode for Bytes {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        write_bytes(buf, self)
+    }
+}
+
+impl Decode for Bytes {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        let len = read_u32(buf)? as usize;
+        ensure_remaining(buf, len)?;
+        Ok(buf.copy_to_bytes(len))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        let count = u16::try_from(self.len()).map_err(|_| IoError::ArrayTooLong)?;
+        buf.put_u16(count);
+        for item in self {
+            item.encode(buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        let count = read_u16(buf)? as usize;
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(T::decode(buf)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        self.0.encode(buf)?;
+        self.1.encode(buf)
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        Ok((A::decode(buf)?, B::decode(buf)?))
+    }
+}
@@ -8,4 +8,16 @@ pub enum IoError {
     InvalidApiKey(u8),
     #[error("string too long")]
     StringTooLong,
+    #[error("bytes too long")]
+    BytesTooLong,
+    #[error("array too long")]
+    ArrayTooLong,
+    #[error("unknown tag: {0}")]
+    UnknownTag(u8),
+    #[error("varint overflow")]
+    VarintOverflow,
+    #[error("invalid utf-8")]
+    InvalidUtf8,
+    #[error("unexpected null")]
+    UnexpectedNull,
 }
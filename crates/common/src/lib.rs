@@ -1,7 +1,15 @@
+pub mod codec;
 pub mod error;
 pub mod read;
 pub mod write;
 
+pub use codec::{Decode, Encode};
+pub use common_derive::{Decode, Encode};
 pub use error::IoError;
 pub use read::*;
 pub use write::*;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use bytes::{Buf, BytesMut};
+}
@@ -62,11 +62,21 @@ pub fn read_u16(buf: &mut dyn Buf) -> Result<u16, IoError> {
     Ok(buf.get_u16())
 }
 
+pub fn read_i16(buf: &mut dyn Buf) -> Result<i16, IoError> {
+    ensure_remaining(buf, 2)?;
+    Ok(buf.get_i16())
+}
+
 pub fn read_u32(buf: &mut dyn Buf) -> Result<u32, IoError> {
     ensure_remaining(buf, 4)?;
     Ok(buf.get_u32())
 }
 
+pub fn read_i32(buf: &mut dyn Buf) -> Result<i32, IoError> {
+    ensure_remaining(buf, 4)?;
+    Ok(buf.get_i32())
+}
+
 pub fn read_i64(buf: &mut dyn Buf) -> Result<i64, IoError> {
     ensure_remaining(buf, 8)?;
     Ok(buf.get_i64())
@@ -77,7 +87,7 @@ pub fn read_str(buf: &mut dyn Buf) -> Result<String, IoError> {
     ensure_remaining(buf, len)?;
     let mut v = vec![0u8; len];
     buf.copy_to_slice(&mut v);
-    Ok(String::from_utf8_lossy(&v).to_string())
+    String::from_utf8(v).map_err(|_| IoError::InvalidUtf8)
 }
 
 pub fn read_bytes(buf: &mut dyn Buf) -> Result<Vec<u8>, IoError> {
@@ -87,3 +97,105 @@ pub fn read_bytes(buf: &mut dyn Buf) -> Result<Vec<u8>, IoError> {
     buf.copy_to_slice(&mut v);
     Ok(v)
 }
+
+// ---------- variable-length ----------
+pub fn read_unsigned_varint(buf: &mut dyn Buf) -> Result<u32, IoError> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let b = read_u8(buf)?;
+        // the fifth byte only has room for the top four bits
+        if i == 4 && b > 0x0f {
+            return Err(IoError::VarintOverflow);
+        }
+        value |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(IoError::VarintOverflow)
+}
+
+pub fn read_unsigned_varlong(buf: &mut dyn Buf) -> Result<u64, IoError> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let b = read_u8(buf)?;
+        // the tenth byte only has room for the top bit
+        if i == 9 && b > 0x01 {
+            return Err(IoError::VarintOverflow);
+        }
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(IoError::VarintOverflow)
+}
+
+pub fn read_varint(buf: &mut dyn Buf) -> Result<i32, IoError> {
+    let raw = read_unsigned_varint(buf)?;
+    Ok(((raw >> 1) as i32) ^ -((raw & 1) as i32))
+}
+
+pub fn read_varlong(buf: &mut dyn Buf) -> Result<i64, IoError> {
+    let raw = read_unsigned_varlong(buf)?;
+    Ok(((raw >> 1) as i64) ^ -((raw & 1) as i64))
+}
+
+// ---------- compact (length + 1 as unsigned varint, 0 = null) ----------
+pub fn read_compact_len(buf: &mut dyn Buf) -> Result<Option<usize>, IoError> {
+    match read_unsigned_varint(buf)? {
+        0 => Ok(None),
+        n => Ok(Some(n as usize - 1)),
+    }
+}
+
+pub fn read_compact_str(buf: &mut dyn Buf) -> Result<String, IoError> {
+    read_compact_nullable_str(buf)?.ok_or(IoError::UnexpectedNull)
+}
+
+pub fn read_compact_nullable_str(buf: &mut dyn Buf) -> Result<Option<String>, IoError> {
+    let Some(len) = read_compact_len(buf)? else {
+        return Ok(None);
+    };
+    ensure_remaining(buf, len)?;
+    let mut v = vec![0u8; len];
+    buf.copy_to_slice(&mut v);
+    String::from_utf8(v)
+        .map(Some)
+        .map_err(|_| IoError::InvalidUtf8)
+}
+
+pub fn read_compact_bytes(buf: &mut dyn Buf) -> Result<Bytes, IoError> {
+    read_compact_nullable_bytes(buf)?.ok_or(IoError::UnexpectedNull)
+}
+
+pub fn read_compact_nullable_bytes(buf: &mut dyn Buf) -> Result<Option<Bytes>, IoError> {
+    let Some(len) = read_compact_len(buf)? else {
+        return Ok(None);
+    };
+    ensure_remaining(buf, len)?;
+    Ok(Some(buf.copy_to_bytes(len)))
+}
+
+pub fn read_compact_array<T>(
+    buf: &mut dyn Buf,
+    f: impl FnMut(&mut dyn Buf) -> Result<T, IoError>,
+) -> Result<Vec<T>, IoError> {
+    read_compact_nullable_array(buf, f)?.ok_or(IoError::UnexpectedNull)
+}
+
+pub fn read_compact_nullable_array<T>(
+    buf: &mut dyn Buf,
+    mut f: impl FnMut(&mut dyn Buf) -> Result<T, IoError>,
+) -> Result<Option<Vec<T>>, IoError> {
+    let Some(len) = read_compact_len(buf)? else {
+        return Ok(None);
+    };
+    // every element takes at least one byte, which bounds the allocation
+    ensure_remaining(buf, len)?;
+    let mut items = Vec::with_capacity(len);
+    for _ in 0..len {
+        items.push(f(buf)?);
+    }
+    Ok(Some(items))
+}
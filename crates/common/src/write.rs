@@ -3,12 +3,22 @@ use bytes::{BufMut, Bytes, BytesMut};
 use crate::error::IoError;
 
 pub fn write_str(out: &mut BytesMut, s: &str) -> Result<(), IoError> {
-    let bytes = s.as_bytes();
-    if bytes.len() > u16::MAX as usize {
-        return Err(IoError::StringTooLong);
-    }
-    out.put_u16(bytes.len() as u16);
-    out.put_slice(bytes);
+    write_len_u16(out, s.len(), IoError::StringTooLong)?;
+    out.put_slice(s.as_bytes());
+    Ok(())
+}
+
+/// Length prefixes are checked rather than cast, so oversized fields fail
+/// instead of silently corrupting the stream.
+fn write_len_u16(buf: &mut BytesMut, len: usize, err: IoError) -> Result<(), IoError> {
+    let len = u16::try_from(len).map_err(|_| err)?;
+    buf.put_u16(len);
+    Ok(())
+}
+
+fn write_len_u32(buf: &mut BytesMut, len: usize, err: IoError) -> Result<(), IoError> {
+    let len = u32::try_from(len).map_err(|_| err)?;
+    buf.put_u32(len);
     Ok(())
 }
 
@@ -16,39 +26,41 @@ pub fn write_api_key(buf: &mut BytesMut, key: u8) {
     buf.put_u8(key);
 }
 
-pub fn write_topic(buf: &mut BytesMut, topic: &str) {
-    buf.put_u16(topic.len() as u16);
-    buf.put_slice(topic.as_bytes());
+pub fn write_topic(buf: &mut BytesMut, topic: &str) -> Result<(), IoError> {
+    write_str(buf, topic)
 }
 
 pub fn write_partition(buf: &mut BytesMut, partition: u16) {
     buf.put_u16(partition);
 }
 
-pub fn write_record_count(buf: &mut BytesMut, count: u16) {
-    buf.put_u16(count);
+pub fn write_record_count(buf: &mut BytesMut, count: usize) -> Result<(), IoError> {
+    write_len_u16(buf, count, IoError::ArrayTooLong)
 }
 
-pub fn write_record(buf: &mut BytesMut, key: &str, value: &str) {
-    write_key(buf, key);
-    write_value(buf, value);
+pub fn write_record(buf: &mut BytesMut, key: &str, value: &str) -> Result<(), IoError> {
+    write_key(buf, key)?;
+    write_value(buf, value)
 }
 
-pub fn write_record_bytes(buf: &mut BytesMut, key: &Bytes, value: &Bytes) {
-    buf.put_u16(key.len() as u16);
+pub fn write_record_bytes(buf: &mut BytesMut, key: &Bytes, value: &Bytes) -> Result<(), IoError> {
+    write_len_u16(buf, key.len(), IoError::BytesTooLong)?;
     buf.put_slice(key);
-    buf.put_u32(value.len() as u32);
+    write_len_u32(buf, value.len(), IoError::BytesTooLong)?;
     buf.put_slice(value);
+    Ok(())
 }
 
-pub fn write_key(buf: &mut BytesMut, key: &str) {
-    buf.put_u16(key.len() as u16);
+pub fn write_key(buf: &mut BytesMut, key: &str) -> Result<(), IoError> {
+    write_len_u16(buf, key.len(), IoError::BytesTooLong)?;
     buf.put_slice(key.as_bytes());
+    Ok(())
 }
 
-pub fn write_value(buf: &mut BytesMut, value: &str) {
-    buf.put_u32(value.len() as u32);
+pub fn write_value(buf: &mut BytesMut, value: &str) -> Result<(), IoError> {
+    write_len_u32(buf, value.len(), IoError::BytesTooLong)?;
     buf.put_slice(value.as_bytes());
+    Ok(())
 }
 
 pub fn write_offset(buf: &mut BytesMut, offset: i64) {
@@ -62,3 +74,96 @@ pub fn write_max_bytes(buf: &mut BytesMut, max_bytes: u32) {
 pub fn write_status(buf: &mut BytesMut, status: u8) {
     buf.put_u8(status);
 }
+
+pub fn write_bytes(buf: &mut BytesMut, bytes: &[u8]) -> Result<(), IoError> {
+    write_len_u32(buf, bytes.len(), IoError::BytesTooLong)?;
+    buf.put_slice(bytes);
+    Ok(())
+}
+
+// ---------- variable-length ----------
+pub fn write_unsigned_varint(buf: &mut BytesMut, mut v: u32) {
+    while v >= 0x80 {
+        buf.put_u8((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    buf.put_u8(v as u8);
+}
+
+pub fn write_unsigned_varlong(buf: &mut BytesMut, mut v: u64) {
+    while v >= 0x80 {
+        buf.put_u8((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    buf.put_u8(v as u8);
+}
+
+pub fn write_varint(buf: &mut BytesMut, v: i32) {
+    write_unsigned_varint(buf, ((v << 1) ^ (v >> 31)) as u32);
+}
+
+pub fn write_varlong(buf: &mut BytesMut, v: i64) {
+    write_unsigned_varlong(buf, ((v << 1) ^ (v >> 63)) as u64);
+}
+
+// ---------- compact (length + 1 as unsigned varint, 0 = null) ----------
+pub fn write_compact_len(buf: &mut BytesMut, len: Option<usize>) -> Result<(), IoError> {
+    match len {
+        None => write_unsigned_varint(buf, 0),
+        Some(len) => {
+            let len = u32::try_from(len)
+                .ok()
+                .and_then(|n| n.checked_add(1))
+                .ok_or(IoError::ArrayTooLong)?;
+            write_unsigned_varint(buf, len);
+        }
+    }
+    Ok(())
+}
+
+pub fn write_compact_str(buf: &mut BytesMut, s: &str) -> Result<(), IoError> {
+    write_compact_nullable_str(buf, Some(s))
+}
+
+pub fn write_compact_nullable_str(buf: &mut BytesMut, s: Option<&str>) -> Result<(), IoError> {
+    write_compact_len(buf, s.map(str::len)).map_err(|_| IoError::StringTooLong)?;
+    if let Some(s) = s {
+        buf.put_slice(s.as_bytes());
+    }
+    Ok(())
+}
+
+pub fn write_compact_bytes(buf: &mut BytesMut, bytes: &[u8]) -> Result<(), IoError> {
+    write_compact_nullable_bytes(buf, Some(bytes))
+}
+
+pub fn write_compact_nullable_bytes(
+    buf: &mut BytesMut,
+    bytes: Option<&[u8]>,
+) -> Result<(), IoError> {
+    write_compact_len(buf, bytes.map(<[u8]>::len)).map_err(|_| IoError::BytesTooLong)?;
+    if let Some(bytes) = bytes {
+        buf.put_slice(bytes);
+    }
+    Ok(())
+}
+
+pub fn write_compact_array<T>(
+    buf: &mut BytesMut,
+    items: &[T],
+    f: impl FnMut(&mut BytesMut, &T) -> Result<(), IoError>,
+) -> Result<(), IoError> {
+    write_compact_nullable_array(buf, Some(items), f)
+}
+
+pub fn write_compact_nullable_array<T>(
+    buf: &mut BytesMut,
+    items: Option<&[T]>,
+    mut f: impl FnMut(&mut BytesMut, &T) -> Result<(), IoError>,
+) -> Result<(), IoError> {
+    write_compact_len(buf, items.map(<[T]>::len))?;
+    for item in items.unwrap_or_default() {
+        f(buf, item)?;
+    }
+    Ok(())
+}
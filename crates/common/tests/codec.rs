@@ -0,0 +1,117 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use common::{Decode, Encode, IoError};
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Header {
+    id: u16,
+    name: String,
+    payload: Bytes,
+    tags: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Pair(i32, i64);
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Versioned {
+    id: u16,
+    #[wire(default = 7)]
+    added: u32,
+}
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+enum Message {
+    #[wire(tag = 1)]
+    Header(Header),
+    #[wire(tag = 2)]
+    Pair(Pair),
+    #[wire(tag = 9)]
+    Empty,
+}
+
+fn round_trip<T: Encode + Decode>(value: &T) -> T {
+    let mut out = BytesMut::new();
+    value.encode(&mut out).unwrap();
+    let mut buf = out.freeze();
+    let decoded = T::decode(&mut buf).unwrap();
+    assert_eq!(buf.remaining(), 0);
+    decoded
+}
+
+#[test]
+fn round_trip_primitives() {
+    assert_eq!(round_trip(&7u8), 7);
+    assert!(round_trip(&true));
+    assert_eq!(round_trip(&513u16), 513);
+    assert_eq!(round_trip(&u32::MAX), u32::MAX);
+    assert_eq!(round_trip(&-5i32), -5);
+    assert_eq!(round_trip(&-1i16), -1);
+    assert_eq!(round_trip(&i64::MIN), i64::MIN);
+    assert_eq!(round_trip(&"topic".to_string()), "topic");
+    assert_eq!(
+        round_trip(&Bytes::from_static(b"val")),
+        Bytes::from_static(b"val")
+    );
+    assert_eq!(round_trip(&vec![1u16, 2, 3]), vec![1, 2, 3]);
+    assert_eq!(round_trip(&(4i64, 5u8)), (4, 5));
+}
+
+#[test]
+fn derived_struct_matches_field_order() {
+    let h = Header {
+        id: 1,
+        name: "ab".to_string(),
+        payload: Bytes::from_static(b"x"),
+        tags: vec![9],
+    };
+
+    let mut out = BytesMut::new();
+    h.encode(&mut out).unwrap();
+    assert_eq!(
+        &out[..],
+        &[0, 1, 0, 2, b'a', b'b', 0, 0, 0, 1, b'x', 0, 1, 0, 0, 0, 9]
+    );
+    assert_eq!(round_trip(&h), h);
+}
+
+#[test]
+fn derived_enum_writes_tag_first() {
+    let m = Message::Pair(Pair(1, 2));
+    let mut out = BytesMut::new();
+    m.encode(&mut out).unwrap();
+    assert_eq!(out[0], 2);
+    assert_eq!(round_trip(&m), m);
+    assert_eq!(round_trip(&Message::Empty), Message::Empty);
+}
+
+#[test]
+fn missing_trailing_field_takes_its_default() {
+    let v = Versioned { id: 1, added: 2 };
+    assert_eq!(round_trip(&v), v);
+
+    let mut buf = Bytes::from_static(&[0, 1]);
+    assert_eq!(
+        Versioned::decode(&mut buf).unwrap(),
+        Versioned { id: 1, added: 7 }
+    );
+}
+
+#[test]
+fn unknown_enum_tag_is_rejected() {
+    let mut buf = Bytes::from_static(&[3]);
+    match Message::decode(&mut buf).unwrap_err() {
+        IoError::UnknownTag(3) => {}
+        e => panic!("expected IoError::UnknownTag, got {e:?}"),
+    }
+}
+
+#[test]
+fn array_longer_than_u16_is_rejected() {
+    let items = vec![0u8; u16::MAX as usize + 1];
+    let mut out = BytesMut::new();
+    match items.encode(&mut out).unwrap_err() {
+        IoError::ArrayTooLong => {}
+        e => panic!("expected IoError::ArrayTooLong, got {e:?}"),
+    }
+}
@@ -6,11 +6,11 @@ use common::{IoError, read_api_key, read_partition, read_record, read_record_cou
 fn round_trip_basic_fields() {
     let mut out = BytesMut::with_capacity(128);
     write_api_key(&mut out, 1);
-    write_topic(&mut out, "topic");
+    write_topic(&mut out, "topic").unwrap();
     write_partition(&mut out, 3);
-    write_record_count(&mut out, 2);
+    write_record_count(&mut out, 2).unwrap();
 
-    let mut buf = Bytes::from(out.freeze());
+    let mut buf = out.freeze();
     assert_eq!(read_api_key(&mut buf).unwrap(), 1);
     assert_eq!(read_topic(&mut buf).unwrap(), "topic");
     assert_eq!(read_partition(&mut buf).unwrap(), 3);
@@ -23,9 +23,9 @@ fn round_trip_record_bytes() {
     let mut out = BytesMut::with_capacity(64);
     let key = Bytes::from_static(b"k");
     let value = Bytes::from_static(b"val");
-    write_record_bytes(&mut out, &key, &value);
+    write_record_bytes(&mut out, &key, &value).unwrap();
 
-    let mut buf = Bytes::from(out.freeze());
+    let mut buf = out.freeze();
     let (read_key, read_value) = read_record(&mut buf).unwrap();
     assert_eq!(read_key, key);
     assert_eq!(read_value, value);
@@ -41,3 +41,30 @@ fn read_str_returns_eof_on_short_buffer() {
         _ => panic!("expected IoError::Eof"),
     }
 }
+
+#[test]
+fn oversized_fields_are_rejected() {
+    let mut out = BytesMut::new();
+    let big_key = Bytes::from(vec![0u8; u16::MAX as usize + 1]);
+    match write_record_bytes(&mut out, &big_key, &Bytes::new()).unwrap_err() {
+        IoError::BytesTooLong => {}
+        _ => panic!("expected IoError::BytesTooLong"),
+    }
+    match write_record_count(&mut out, 70_000).unwrap_err() {
+        IoError::ArrayTooLong => {}
+        _ => panic!("expected IoError::ArrayTooLong"),
+    }
+    match write_topic(&mut out, &"t".repeat(u16::MAX as usize + 1)).unwrap_err() {
+        IoError::StringTooLong => {}
+        _ => panic!("expected IoError::StringTooLong"),
+    }
+}
+
+#[test]
+fn read_str_rejects_invalid_utf8() {
+    let mut buf = Bytes::from_static(&[0x00, 0x02, 0xc3, 0x28]);
+    match read_str(&mut buf).unwrap_err() {
+        IoError::InvalidUtf8 => {}
+        _ => panic!("expected IoError::InvalidUtf8"),
+    }
+}
@@ -0,0 +1,136 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use proptest::prelude::*;
+
+use common::{
+    IoError, read_compact_array, read_compact_bytes, read_compact_nullable_bytes,
+    read_compact_nullable_str, read_compact_str, read_u16, read_unsigned_varint,
+    read_unsigned_varlong, read_varint, read_varlong, write_compact_array, write_compact_bytes,
+    write_compact_nullable_bytes, write_compact_nullable_str, write_compact_str,
+    write_unsigned_varint, write_unsigned_varlong, write_varint, write_varlong,
+};
+
+proptest! {
+    #[test]
+    fn unsigned_varint_round_trip(v: u32) {
+        let mut out = BytesMut::new();
+        write_unsigned_varint(&mut out, v);
+        prop_assert!(out.len() <= 5);
+        let mut buf = out.freeze();
+        prop_assert_eq!(read_unsigned_varint(&mut buf).unwrap(), v);
+        prop_assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn unsigned_varlong_round_trip(v: u64) {
+        let mut out = BytesMut::new();
+        write_unsigned_varlong(&mut out, v);
+        prop_assert!(out.len() <= 10);
+        let mut buf = out.freeze();
+        prop_assert_eq!(read_unsigned_varlong(&mut buf).unwrap(), v);
+        prop_assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn varint_round_trip(v: i32) {
+        let mut out = BytesMut::new();
+        write_varint(&mut out, v);
+        let mut buf = out.freeze();
+        prop_assert_eq!(read_varint(&mut buf).unwrap(), v);
+        prop_assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn varlong_round_trip(v: i64) {
+        let mut out = BytesMut::new();
+        write_varlong(&mut out, v);
+        let mut buf = out.freeze();
+        prop_assert_eq!(read_varlong(&mut buf).unwrap(), v);
+        prop_assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn compact_fields_round_trip(
+        s in ".{0,64}",
+        ns in proptest::option::of(".{0,64}"),
+        b in proptest::collection::vec(any::<u8>(), 0..300),
+        nb in proptest::option::of(proptest::collection::vec(any::<u8>(), 0..300)),
+        items in proptest::collection::vec(any::<u16>(), 0..200),
+    ) {
+        let mut out = BytesMut::new();
+        write_compact_str(&mut out, &s).unwrap();
+        write_compact_nullable_str(&mut out, ns.as_deref()).unwrap();
+        write_compact_bytes(&mut out, &b).unwrap();
+        write_compact_nullable_bytes(&mut out, nb.as_deref()).unwrap();
+        write_compact_array(&mut out, &items, |out, v| {
+            out.put_u16(*v);
+            Ok(())
+        })
+        .unwrap();
+
+        let mut buf = out.freeze();
+        prop_assert_eq!(read_compact_str(&mut buf).unwrap(), s);
+        prop_assert_eq!(read_compact_nullable_str(&mut buf).unwrap(), ns);
+        prop_assert_eq!(&read_compact_bytes(&mut buf).unwrap()[..], &b[..]);
+        prop_assert_eq!(
+            read_compact_nullable_bytes(&mut buf).unwrap().map(|b| b.to_vec()),
+            nb
+        );
+        prop_assert_eq!(read_compact_array(&mut buf, read_u16).unwrap(), items);
+        prop_assert_eq!(buf.remaining(), 0);
+    }
+}
+
+#[test]
+fn zigzag_matches_kafka_encoding() {
+    let mut out = BytesMut::new();
+    for v in [0, -1, 1, -2, 63, -64, 64] {
+        write_varint(&mut out, v);
+    }
+    assert_eq!(&out[..], &[0x00, 0x01, 0x02, 0x03, 0x7e, 0x7f, 0x80, 0x01]);
+}
+
+#[test]
+fn compact_null_is_zero() {
+    let mut out = BytesMut::new();
+    write_compact_nullable_str(&mut out, None).unwrap();
+    write_compact_str(&mut out, "").unwrap();
+    assert_eq!(&out[..], &[0x00, 0x01]);
+
+    let mut buf = Bytes::from_static(&[0x00]);
+    assert!(matches!(
+        read_compact_str(&mut buf),
+        Err(IoError::UnexpectedNull)
+    ));
+}
+
+#[test]
+fn overlong_varints_are_rejected() {
+    // six continuation bytes never terminate a u32
+    let mut buf = Bytes::from_static(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    assert!(matches!(
+        read_unsigned_varint(&mut buf),
+        Err(IoError::VarintOverflow)
+    ));
+
+    // fifth byte carrying bits above u32::MAX
+    let mut buf = Bytes::from_static(&[0xff, 0xff, 0xff, 0xff, 0x1f]);
+    assert!(matches!(
+        read_unsigned_varint(&mut buf),
+        Err(IoError::VarintOverflow)
+    ));
+
+    let mut buf = Bytes::from_static(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]);
+    assert!(matches!(
+        read_unsigned_varlong(&mut buf),
+        Err(IoError::VarintOverflow)
+    ));
+
+    let mut buf = Bytes::from_static(&[0x80, 0x80]);
+    assert!(matches!(read_varint(&mut buf), Err(IoError::Eof)));
+}
+
+#[test]
+fn compact_length_beyond_buffer_is_eof() {
+    let mut buf = Bytes::from_static(&[0x05, b'a']);
+    assert!(matches!(read_compact_bytes(&mut buf), Err(IoError::Eof)));
+}
@@ -0,0 +1,556 @@
+//! The controller quorum keeping the cluster metadata.
+//!
+//! Controllers replicate the metadata log between them with Raft. `RaftNode`
+//! holds the protocol itself, without I/O besides its log, so that quorums
+//! can be run deterministically; a `Controller` drives one over the native
+//! protocol. Committed records of the log are applied to a `Registry` of the
+//! topics and where their partitions live, which brokers follow.
+//!
+//! Partitions move between brokers in two steps: the new replicas are added
+//! and copy the partition from its leader, and once the leader has them all
+//! in sync the old ones are dropped. The controller leader asks partition
+//! leaders how far their moves have come.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use protocol::{
+    connection::{Login, Peer},
+    status,
+    types::{
+        AppendEntriesRequest, AppendEntriesResponse, DescribePartitionRequest,
+        PartitionReassignment, Request, Response, VoteRequest, VoteResponse,
+    },
+};
+use tokio::{sync::watch, task::AbortHandle};
+
+mod metadata;
+mod raft;
+
+pub use metadata::{
+    MetadataRecord, PartitionRegistration, Reassignment, Registry, assign_replicas,
+};
+pub use raft::{METADATA_TOPIC, Message, RaftConfig, RaftError, RaftNode, Role};
+
+#[derive(Debug, Clone)]
+pub struct ControllerConfig {
+    pub node_id: u32,
+    /// Native listener addresses of the controllers by id, this one
+    /// included. Every controller is also a broker, which new partitions are
+    /// spread over.
+    pub voters: BTreeMap<u32, String>,
+    /// Holds the metadata log and the quorum state.
+    pub dir: PathBuf,
+    /// How often the node's clock ticks.
+    pub tick: Duration,
+    pub raft: RaftConfig,
+    /// Replicas of each partition of new topics.
+    pub replication_factor: u16,
+    /// Leaders that have not answered the controller for this many ticks
+    /// lose their partitions to other replicas.
+    pub session_ticks: u32,
+    /// How long a change waits to be committed.
+    pub commit_timeout: Duration,
+    /// How often, in ticks, the leader asks the leaders of moving partitions
+    /// how far the move has come.
+    pub reassignment_poll_ticks: u32,
+    /// Logs in to the other controllers, for listeners that require SASL.
+    pub login: Option<Login>,
+}
+
+impl ControllerConfig {
+    pub fn new(node_id: u32, voters: BTreeMap<u32, String>, dir: PathBuf) -> Self {
+        let replication_factor = voters.len().clamp(1, 3) as u16;
+        Self {
+            node_id,
+            voters,
+            dir,
+            tick: Duration::from_millis(50),
+            raft: RaftConfig::default(),
+            replication_factor,
+            session_ticks: 40,
+            commit_timeout: Duration::from_secs(10),
+            reassignment_poll_ticks: 10,
+            login: None,
+        }
+    }
+}
+
+pub struct Controller {
+    config: ControllerConfig,
+    node: Mutex<RaftNode>,
+    registry: watch::Sender<Arc<Registry>>,
+    // every controller's broker, this one's included
+    brokers: BTreeMap<u32, Peer>,
+    // held from checking a change against the registry until it is
+    // committed, so that the check still holds
+    changing: Arc<tokio::sync::Mutex<()>>,
+    ticker: Mutex<Option<AbortHandle>>,
+    // what partition leaders last said about the moves under way
+    progress: Mutex<BTreeMap<(String, u16), Progress>>,
+    polling: AtomicBool,
+}
+
+/// Where a moving partition's leader had its log end, and where those of the
+/// followers did.
+struct Progress {
+    log_end_offset: i64,
+    replica_offsets: BTreeMap<u32, i64>,
+}
+
+impl Controller {
+    /// Opens the metadata log. Nothing happens until `start`.
+    pub fn open(config: ControllerConfig) -> Result<Arc<Self>, RaftError> {
+        let node = RaftNode::open(
+            config.node_id,
+            config.voters.keys().copied(),
+            &config.dir,
+            config.raft.clone(),
+        )?;
+        let brokers = config
+            .voters
+            .iter()
+            .map(|(id, addr)| (*id, Peer::new(addr.clone(), config.login.clone())))
+            .collect();
+        Ok(Arc::new(Self {
+            config,
+            node: Mutex::new(node),
+            registry: watch::Sender::new(Arc::default()),
+            brokers,
+            changing: Arc::new(tokio::sync::Mutex::new(())),
+            ticker: Mutex::new(None),
+            progress: Mutex::new(BTreeMap::new()),
+            polling: AtomicBool::new(false),
+        }))
+    }
+
+    /// Starts the node's clock.
+    pub fn start(self: &Arc<Self>) {
+        let this = self.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(this.config.tick);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let poll_ticks = u64::from(this.config.reassignment_poll_ticks.max(1));
+            for ticks in 1u64.. {
+                interval.tick().await;
+                if let Err(e) = this.step(RaftNode::tick) {
+                    tracing::warn!(error = %e, "metadata log tick failed");
+                }
+                this.move_leaders();
+                if ticks % poll_ticks == 0 {
+                    this.check_reassignments();
+                }
+            }
+        });
+        *self.ticker.lock().unwrap() = Some(task.abort_handle());
+    }
+
+    /// Stops the node's clock. Requests from the other controllers are still
+    /// answered until the listeners are closed.
+    pub fn shutdown(&self) {
+        if let Some(ticker) = self.ticker.lock().unwrap().take() {
+            ticker.abort();
+        }
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.config.node_id
+    }
+
+    /// The controller leading the quorum, if known.
+    pub fn leader(&self) -> Option<u32> {
+        self.node.lock().unwrap().leader()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.node.lock().unwrap().role() == Role::Leader
+    }
+
+    /// The registry as of the last committed record this node applied.
+    pub fn registry(&self) -> Arc<Registry> {
+        self.registry.borrow().clone()
+    }
+
+    /// Notified with every change of the registry.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Registry>> {
+        self.registry.subscribe()
+    }
+
+    pub fn handle_vote(self: &Arc<Self>, req: VoteRequest) -> Result<VoteResponse, RaftError> {
+        self.step(|node| node.handle_vote(req))
+    }
+
+    pub fn handle_append_entries(
+        self: &Arc<Self>,
+        req: AppendEntriesRequest,
+    ) -> Result<AppendEntriesResponse, RaftError> {
+        self.step(|node| node.handle_append(req))
+    }
+
+    /// Registers `topic`, spreading its partitions over the brokers. Returns
+    /// `NOT_CONTROLLER` on the other controllers.
+    pub async fn create_topic(self: &Arc<Self>, topic: &str, partitions: u16) -> u8 {
+        let _changing = self.changing.lock().await;
+        if !self.node.lock().unwrap().is_leader_ready() {
+            return status::NOT_CONTROLLER;
+        }
+        if self.registry().topics.contains_key(topic) {
+            return status::TOPIC_ALREADY_EXISTS;
+        }
+        let brokers: Vec<u32> = self.config.voters.keys().copied().collect();
+        let replication_factor = self.config.replication_factor;
+        if replication_factor == 0 || usize::from(replication_factor) > brokers.len() {
+            return status::INVALID_REPLICATION_FACTOR;
+        }
+        self.commit(MetadataRecord::TopicCreated {
+            topic: topic.to_string(),
+            replicas: assign_replicas(&brokers, partitions, replication_factor),
+        })
+        .await
+    }
+
+    /// Raises the partition count of `topic` to `partitions`, spreading the
+    /// new ones as if the topic had been created with them. Returns
+    /// `NOT_CONTROLLER` on the other controllers.
+    pub async fn create_partitions(self: &Arc<Self>, topic: &str, partitions: u16) -> u8 {
+        let _changing = self.changing.lock().await;
+        if !self.node.lock().unwrap().is_leader_ready() {
+            return status::NOT_CONTROLLER;
+        }
+        let Some(current) = self.registry().topics.get(topic).map(Vec::len) else {
+            return status::UNKNOWN_TOPIC_OR_PARTITION;
+        };
+        if usize::from(partitions) <= current {
+            return status::INVALID_PARTITIONS;
+        }
+        let brokers: Vec<u32> = self.config.voters.keys().copied().collect();
+        let replication_factor = self.config.replication_factor;
+        if replication_factor == 0 || usize::from(replication_factor) > brokers.len() {
+            return status::INVALID_REPLICATION_FACTOR;
+        }
+        let mut replicas = assign_replicas(&brokers, partitions, replication_factor);
+        self.commit(MetadataRecord::PartitionsCreated {
+            topic: topic.to_string(),
+            first_partition: current as u16,
+            replicas: replicas.split_off(current),
+        })
+        .await
+    }
+
+    /// Removes `topic` from the registry, after which brokers delete its
+    /// partitions. Returns `NOT_CONTROLLER` on the other controllers.
+    pub async fn delete_topic(self: &Arc<Self>, topic: &str) -> u8 {
+        let _changing = self.changing.lock().await;
+        if !self.node.lock().unwrap().is_leader_ready() {
+            return status::NOT_CONTROLLER;
+        }
+        if !self.registry().topics.contains_key(topic) {
+            return status::UNKNOWN_TOPIC_OR_PARTITION;
+        }
+        self.commit(MetadataRecord::TopicDeleted {
+            topic: topic.to_string(),
+        })
+        .await
+    }
+
+    /// Starts moving a partition to `replicas`, which completes once the new
+    /// ones are in sync. Returns `NOT_CONTROLLER` on the other controllers.
+    pub async fn reassign_partition(
+        self: &Arc<Self>,
+        topic: &str,
+        partition: u16,
+        replicas: Vec<u32>,
+        throttle_bytes_per_sec: i64,
+    ) -> u8 {
+        let _changing = self.changing.lock().await;
+        if !self.node.lock().unwrap().is_leader_ready() {
+            return status::NOT_CONTROLLER;
+        }
+        let registry = self.registry();
+        let Some(p) = registry.partition(topic, partition) else {
+            return status::UNKNOWN_TOPIC_OR_PARTITION;
+        };
+        let distinct = replicas.iter().collect::<BTreeSet<_>>().len() == replicas.len();
+        let known = replicas
+            .iter()
+            .all(|id| self.config.voters.contains_key(id));
+        if replicas.is_empty() || !distinct || !known {
+            return status::INVALID_REPLICA_ASSIGNMENT;
+        }
+        if p.reassignment.is_some() {
+            return status::REASSIGNMENT_IN_PROGRESS;
+        }
+        self.commit(MetadataRecord::ReassignmentStarted {
+            topic: topic.to_string(),
+            partition,
+            replicas,
+            throttle_bytes_per_sec,
+        })
+        .await
+    }
+
+    /// The moves under way, with how far they had come when the partition
+    /// leaders were last asked. Only the leader asks them, so the others
+    /// return `NOT_CONTROLLER`.
+    pub fn reassignments(&self) -> Result<Vec<PartitionReassignment>, u8> {
+        if !self.is_leader() {
+            return Err(status::NOT_CONTROLLER);
+        }
+        let registry = self.registry();
+        let progress = self.progress.lock().unwrap();
+        let mut reassignments = Vec::new();
+        for (topic, partitions) in &registry.topics {
+            for (partition, p) in (0u16..).zip(partitions) {
+                let Some(r) = &p.reassignment else {
+                    continue;
+                };
+                let progress = progress.get(&(topic.clone(), partition));
+                let replica_offsets = r
+                    .adding
+                    .iter()
+                    .map(|id| {
+                        let offset = progress.and_then(|p| p.replica_offsets.get(id));
+                        (*id, offset.copied().unwrap_or(-1))
+                    })
+                    .collect();
+                reassignments.push(PartitionReassignment {
+                    topic: topic.clone(),
+                    partition,
+                    replicas: p.replicas.clone(),
+                    adding: r.adding.clone(),
+                    removing: r.removing.clone(),
+                    throttle_bytes_per_sec: r.throttle_bytes_per_sec,
+                    log_end_offset: progress.map_or(-1, |p| p.log_end_offset),
+                    replica_offsets,
+                });
+            }
+        }
+        Ok(reassignments)
+    }
+
+    /// Appends `record` to the metadata log and waits until it is applied.
+    async fn commit(self: &Arc<Self>, record: MetadataRecord) -> u8 {
+        let data = match record.to_bytes() {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot encode metadata record");
+                return status::CORRUPT_MESSAGE;
+            }
+        };
+        let mut registry = self.subscribe();
+        let (offset, term) = match self.step(|node| Ok((node.propose(data)?, node.term()))) {
+            Ok(proposed) => proposed,
+            Err(RaftError::NotLeader(_)) => return status::NOT_CONTROLLER,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot append to the metadata log");
+                return status::NOT_CONTROLLER;
+            }
+        };
+        let applied = registry.wait_for(|r| r.applied_offset > offset);
+        if tokio::time::timeout(self.config.commit_timeout, applied)
+            .await
+            .is_err()
+        {
+            return status::REQUEST_TIMED_OUT;
+        }
+        // another leader may have put its own entry there
+        if self.node.lock().unwrap().entry_term(offset) != Some(term) {
+            return status::NOT_CONTROLLER;
+        }
+        status::OK
+    }
+
+    /// On the leader, moves partitions off leaders that stopped answering.
+    fn move_leaders(self: &Arc<Self>) {
+        let live = {
+            let node = self.node.lock().unwrap();
+            if !node.is_leader_ready() {
+                return;
+            }
+            node.live_voters(self.config.session_ticks)
+        };
+        let moves = self.registry().leaders_to_move(&live);
+        if moves.is_empty() {
+            return;
+        }
+        let Ok(changing) = self.changing.clone().try_lock_owned() else {
+            return;
+        };
+        let this = self.clone();
+        tokio::spawn(async move {
+            let _changing = changing;
+            for record in moves {
+                tracing::info!(?record, "moving partition leadership");
+                if this.commit(record).await != status::OK {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// On the leader, asks the leaders of moving partitions how far the move
+    /// has come, one partition after the other.
+    fn check_reassignments(self: &Arc<Self>) {
+        if !self.node.lock().unwrap().is_leader_ready() {
+            return;
+        }
+        let registry = self.registry();
+        let moving: Vec<(String, u16, PartitionRegistration)> = registry
+            .topics
+            .iter()
+            .flat_map(|(topic, partitions)| {
+                (0u16..)
+                    .zip(partitions)
+                    .filter(|(_, p)| p.reassignment.is_some())
+                    .map(|(partition, p)| (topic.clone(), partition, p.clone()))
+            })
+            .collect();
+        self.progress
+            .lock()
+            .unwrap()
+            .retain(|(topic, partition), _| {
+                moving.iter().any(|(t, p, _)| t == topic && p == partition)
+            });
+        if moving.is_empty() || self.polling.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            for (topic, partition, p) in moving {
+                this.check_reassignment(topic, partition, p).await;
+            }
+            this.polling.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Notes how far the move of the partition registered as `p` has come,
+    /// and completes it once the leader has every replica it moves to in
+    /// sync. The leader keeps the partition if it is one of them, otherwise
+    /// the first of them takes over.
+    async fn check_reassignment(
+        self: &Arc<Self>,
+        topic: String,
+        partition: u16,
+        p: PartitionRegistration,
+    ) {
+        let Some(peer) = self.brokers.get(&p.leader) else {
+            return;
+        };
+        let req = Request::DescribePartition(DescribePartitionRequest {
+            topic: topic.clone(),
+            partition,
+        });
+        let timeout = self.config.tick * self.config.session_ticks;
+        let r = match tokio::time::timeout(timeout, peer.request(&req)).await {
+            Ok(Ok(Response::DescribePartition(r))) if r.status == status::OK => r,
+            Ok(Ok(other)) => {
+                tracing::debug!(leader = p.leader, response = ?other, "cannot describe partition");
+                return;
+            }
+            Ok(Err(e)) => {
+                tracing::debug!(leader = p.leader, error = %e, "partition leader request failed");
+                return;
+            }
+            Err(_) => return,
+        };
+        // a leader that has yet to take up the move knows nothing of the new
+        // replicas
+        if r.leader_epoch != p.leader_epoch {
+            return;
+        }
+        self.progress.lock().unwrap().insert(
+            (topic.clone(), partition),
+            Progress {
+                log_end_offset: r.log_end_offset,
+                replica_offsets: r.replica_offsets.into_iter().collect(),
+            },
+        );
+        let target = p.target_replicas();
+        if !target.iter().all(|id| r.isr.contains(id)) {
+            return;
+        }
+        let leader = if target.contains(&p.leader) {
+            p.leader
+        } else {
+            target[0]
+        };
+        let _changing = self.changing.lock().await;
+        if self.registry().partition(&topic, partition) != Some(&p) {
+            return;
+        }
+        let record = MetadataRecord::ReassignmentCompleted {
+            topic,
+            partition,
+            leader,
+            leader_epoch: p.leader_epoch + 1,
+        };
+        tracing::info!(?record, "completing partition reassignment");
+        self.commit(record).await;
+    }
+
+    /// Runs `f` on the node, then applies what it committed and sends what
+    /// it has for the other controllers.
+    fn step<T>(
+        self: &Arc<Self>,
+        f: impl FnOnce(&mut RaftNode) -> Result<T, RaftError>,
+    ) -> Result<T, RaftError> {
+        let (res, messages, committed) = {
+            let mut node = self.node.lock().unwrap();
+            let res = f(&mut node);
+            let committed = node.take_committed();
+            (res, node.take_messages(), committed)
+        };
+        let committed = committed?;
+        if !committed.is_empty() {
+            self.registry.send_modify(|registry| {
+                let registry = Arc::make_mut(registry);
+                for (offset, data) in &committed {
+                    registry.apply(*offset, data);
+                }
+            });
+        }
+        for (to, message) in messages {
+            self.send(to, message);
+        }
+        res
+    }
+
+    fn send(self: &Arc<Self>, to: u32, message: Message) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let Some(peer) = this.brokers.get(&to) else {
+                return;
+            };
+            let req = match message {
+                Message::Vote(r) => Request::Vote(r),
+                Message::AppendEntries(r) => Request::AppendEntries(r),
+            };
+            let timeout = this.config.tick * this.config.raft.election_ticks;
+            let res = match tokio::time::timeout(timeout, peer.request(&req)).await {
+                Ok(Ok(Response::Vote(r))) => this.step(|node| node.handle_vote_response(to, r)),
+                Ok(Ok(Response::AppendEntries(r))) => {
+                    this.step(|node| node.handle_append_response(to, r))
+                }
+                Ok(Ok(other)) => {
+                    tracing::debug!(to, response = ?other, "unexpected response from controller");
+                    return;
+                }
+                Ok(Err(e)) => {
+                    tracing::debug!(to, error = %e, "controller request failed");
+                    return;
+                }
+                Err(_) => return,
+            };
+            if let Err(e) = res {
+                tracing::warn!(error = %e, "metadata log update failed");
+            }
+        });
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::{Bytes, BytesMut};
+use common::{Decode, Encode, IoError};
+
+/// A change to the registry, as stored in the metadata log.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum MetadataRecord {
+    /// A topic whose partition `p` is on the brokers `replicas[p]`, led by
+    /// the first of them.
+    #[wire(tag = 1)]
+    TopicCreated {
+        topic: String,
+        replicas: Vec<Vec<u32>>,
+    },
+    #[wire(tag = 2)]
+    TopicDeleted { topic: String },
+    /// Every change of a partition's leader starts a new leader epoch.
+    #[wire(tag = 3)]
+    LeaderChanged {
+        topic: String,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+    },
+    /// Starts moving a partition to `replicas`. Until the move completes the
+    /// partition is on its old replicas as well, and the new ones copy it
+    /// at most `throttle_bytes_per_sec` each when above 0.
+    #[wire(tag = 4)]
+    ReassignmentStarted {
+        topic: String,
+        partition: u16,
+        replicas: Vec<u32>,
+        throttle_bytes_per_sec: i64,
+    },
+    /// Drops the replicas a partition moved off, leaving it led by `leader`,
+    /// one of those it moved to.
+    #[wire(tag = 5)]
+    ReassignmentCompleted {
+        topic: String,
+        partition: u16,
+        leader: u32,
+        leader_epoch: i32,
+    },
+    /// New partitions of a topic that had `first_partition` of them, the
+    /// next one on `replicas[0]` and so on, as in `TopicCreated`.
+    #[wire(tag = 6)]
+    PartitionsCreated {
+        topic: String,
+        first_partition: u16,
+        replicas: Vec<Vec<u32>>,
+    },
+}
+
+impl MetadataRecord {
+    pub fn to_bytes(&self) -> Result<Bytes, IoError> {
+        let mut buf = BytesMut::new();
+        self.encode(&mut buf)?;
+        Ok(buf.freeze())
+    }
+}
+
+/// Where a partition lives. While it moves, `replicas` are those it moves
+/// to followed by those it moves off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionRegistration {
+    pub replicas: Vec<u32>,
+    pub leader: u32,
+    pub leader_epoch: i32,
+    pub reassignment: Option<Reassignment>,
+}
+
+/// The replicas a partition gains and loses in a move under way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reassignment {
+    pub adding: Vec<u32>,
+    pub removing: Vec<u32>,
+    pub throttle_bytes_per_sec: i64,
+}
+
+impl PartitionRegistration {
+    /// The replicas the partition ends up on.
+    pub fn target_replicas(&self) -> Vec<u32> {
+        match &self.reassignment {
+            Some(r) => self
+                .replicas
+                .iter()
+                .copied()
+                .filter(|id| !r.removing.contains(id))
+                .collect(),
+            None => self.replicas.clone(),
+        }
+    }
+
+    /// Whether `replica` is new to the partition and still copying it.
+    pub fn is_adding(&self, replica: u32) -> bool {
+        self.reassignment
+            .as_ref()
+            .is_some_and(|r| r.adding.contains(&replica))
+    }
+}
+
+/// The topics and partitions of the cluster, built by applying the committed
+/// records of the metadata log in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registry {
+    pub topics: BTreeMap<String, Vec<PartitionRegistration>>,
+    /// Offset of the next entry to apply.
+    pub applied_offset: i64,
+}
+
+impl Registry {
+    /// Applies the entry at `offset`. Entries without data only move
+    /// `applied_offset`, and records that don't fit the registry, such as a
+    /// second creation of a topic, are skipped, so that every node ends up
+    /// with the same registry.
+    pub fn apply(&mut self, offset: i64, data: &[u8]) {
+        self.applied_offset = offset + 1;
+        if data.is_empty() {
+            return;
+        }
+        let record = match MetadataRecord::decode(&mut &data[..]) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!(offset, error = %e, "skipping unreadable metadata record");
+                return;
+            }
+        };
+        match record {
+            MetadataRecord::TopicCreated { topic, replicas } => {
+                if self.topics.contains_key(&topic) || replicas.iter().any(Vec::is_empty) {
+                    return;
+                }
+                let partitions = replicas
+                    .into_iter()
+                    .map(|replicas| PartitionRegistration {
+                        leader: replicas[0],
+                        replicas,
+                        leader_epoch: 0,
+                        reassignment: None,
+                    })
+                    .collect();
+                self.topics.insert(topic, partitions);
+            }
+            MetadataRecord::TopicDeleted { topic } => {
+                self.topics.remove(&topic);
+            }
+            MetadataRecord::LeaderChanged {
+                topic,
+                partition,
+                leader,
+                leader_epoch,
+            } => {
+                if let Some(p) = self.partition_mut(&topic, partition)
+                    && p.replicas.contains(&leader)
+                    && !p.is_adding(leader)
+                    && leader_epoch > p.leader_epoch
+                {
+                    p.leader = leader;
+                    p.leader_epoch = leader_epoch;
+                }
+            }
+            MetadataRecord::ReassignmentStarted {
+                topic,
+                partition,
+                replicas,
+                throttle_bytes_per_sec,
+            } => {
+                let distinct = replicas.iter().collect::<BTreeSet<_>>().len() == replicas.len();
+                let Some(p) = self.partition_mut(&topic, partition) else {
+                    return;
+                };
+                if p.reassignment.is_some() || replicas.is_empty() || !distinct {
+                    return;
+                }
+                let adding: Vec<u32> = replicas
+                    .iter()
+                    .copied()
+                    .filter(|id| !p.replicas.contains(id))
+                    .collect();
+                let removing: Vec<u32> = p
+                    .replicas
+                    .iter()
+                    .copied()
+                    .filter(|id| !replicas.contains(id))
+                    .collect();
+                // the same replicas in another order need no copying
+                p.replicas = replicas;
+                if adding.is_empty() && removing.is_empty() {
+                    return;
+                }
+                p.replicas.extend(&removing);
+                p.reassignment = Some(Reassignment {
+                    adding,
+                    removing,
+                    throttle_bytes_per_sec,
+                });
+            }
+            MetadataRecord::ReassignmentCompleted {
+                topic,
+                partition,
+                leader,
+                leader_epoch,
+            } => {
+                if let Some(p) = self.partition_mut(&topic, partition)
+                    && p.reassignment.is_some()
+                    && p.target_replicas().contains(&leader)
+                    && leader_epoch > p.leader_epoch
+                {
+                    p.replicas = p.target_replicas();
+                    p.reassignment = None;
+                    p.leader = leader;
+                    p.leader_epoch = leader_epoch;
+                }
+            }
+            MetadataRecord::PartitionsCreated {
+                topic,
+                first_partition,
+                replicas,
+            } => {
+                let Some(partitions) = self.topics.get_mut(&topic) else {
+                    return;
+                };
+                // a record for another partition count than the topic's, as
+                // when one is committed twice, would leave gaps or overlaps
+                if partitions.len() != first_partition as usize
+                    || replicas.iter().any(Vec::is_empty)
+                {
+                    return;
+                }
+                partitions.extend(replicas.into_iter().map(|replicas| PartitionRegistration {
+                    leader: replicas[0],
+                    replicas,
+                    leader_epoch: 0,
+                    reassignment: None,
+                }));
+            }
+        }
+    }
+
+    fn partition_mut(&mut self, topic: &str, partition: u16) -> Option<&mut PartitionRegistration> {
+        self.topics.get_mut(topic)?.get_mut(partition as usize)
+    }
+
+    pub fn partition(&self, topic: &str, partition: u16) -> Option<&PartitionRegistration> {
+        self.topics.get(topic)?.get(partition as usize)
+    }
+
+    /// Leader changes moving partitions off leaders outside `live`, to the
+    /// first live replica that is not still copying the partition.
+    /// Partitions without one keep their leader.
+    pub fn leaders_to_move(&self, live: &BTreeSet<u32>) -> Vec<MetadataRecord> {
+        let mut moves = Vec::new();
+        for (topic, partitions) in &self.topics {
+            for (partition, p) in (0u16..).zip(partitions) {
+                if live.contains(&p.leader) {
+                    continue;
+                }
+                if let Some(&leader) = p
+                    .replicas
+                    .iter()
+                    .find(|&&r| live.contains(&r) && !p.is_adding(r))
+                {
+                    moves.push(MetadataRecord::LeaderChanged {
+                        topic: topic.clone(),
+                        partition,
+                        leader,
+                        leader_epoch: p.leader_epoch + 1,
+                    });
+                }
+            }
+        }
+        moves
+    }
+}
+
+/// Spreads `partitions` partitions of `replication_factor` replicas each
+/// over `brokers`, each partition starting one broker further along so that
+/// leadership is spread too.
+pub fn assign_replicas(brokers: &[u32], partitions: u16, replication_factor: u16) -> Vec<Vec<u32>> {
+    (0..partitions as usize)
+        .map(|p| {
+            (0..replication_factor as usize)
+                .map(|r| brokers[(p + r) % brokers.len()])
+                .collect()
+        })
+        .collect()
+}
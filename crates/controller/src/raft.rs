@@ -0,0 +1,574 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{
+        AppendEntriesRequest, AppendEntriesResponse, RaftEntry, Record, VoteRequest, VoteResponse,
+    },
+};
+use storage::{LogConfig, PartitionLog, StorageError};
+
+/// Name the metadata log is stored under, as partition 0 of a topic.
+pub const METADATA_TOPIC: &str = "__cluster_metadata";
+
+/// Holds the current term and vote next to the metadata log.
+const STATE_FILE: &str = "quorum-state";
+
+const NO_VOTE: u32 = u32::MAX;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RaftError {
+    #[error("storage: {0}")]
+    Storage(#[from] StorageError),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("corrupted {0}")]
+    Corrupted(&'static str),
+    /// Only the leader takes proposals; carries the leader if one is known.
+    #[error("not the leader")]
+    NotLeader(Option<u32>),
+}
+
+/// Timing of a `RaftNode`, in ticks of whatever clock drives it.
+#[derive(Debug, Clone)]
+pub struct RaftConfig {
+    /// Ticks without hearing from a leader before a follower stands for
+    /// election. Each node waits between this and twice as long, so that
+    /// they rarely stand at once.
+    pub election_ticks: u32,
+    /// Ticks between the leader's appends to each follower.
+    pub heartbeat_ticks: u32,
+    /// Most entries sent to a follower at once.
+    pub max_append_entries: usize,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            election_ticks: 10,
+            heartbeat_ticks: 2,
+            max_append_entries: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// A request for another node, handed out by `RaftNode::take_messages`. Its
+/// response goes back through `handle_vote_response` or
+/// `handle_append_response`; lost requests and responses are fine.
+#[derive(Debug)]
+pub enum Message {
+    Vote(VoteRequest),
+    AppendEntries(AppendEntriesRequest),
+}
+
+/// One member of a Raft quorum replicating the metadata log. It does no I/O
+/// besides its own log and state file and keeps no time: it is driven by
+/// `tick`, the requests of the other nodes and their responses, so a quorum
+/// can be run deterministically.
+///
+/// Offsets of the log start at 0. Every entry is stored as a record keyed by
+/// its term. A new leader appends an entry without data, which commits the
+/// entries of earlier terms along with it.
+#[derive(Debug)]
+pub struct RaftNode {
+    id: u32,
+    voters: Vec<u32>,
+    config: RaftConfig,
+    dir: PathBuf,
+    term: i32,
+    voted_for: Option<u32>,
+    role: Role,
+    leader: Option<u32>,
+    log: PartitionLog,
+    // term of each entry, by offset
+    terms: Vec<i32>,
+    commit_offset: i64,
+    applied_offset: i64,
+    // ticks since the leader was heard from, or since standing for election
+    elapsed: u32,
+    election_timeout: u32,
+    rng: u64,
+    votes: BTreeSet<u32>,
+    // on the leader: where the entries sent to each follower start, how far
+    // its log is known to match, and ticks since it last answered
+    next_offset: BTreeMap<u32, i64>,
+    match_offset: BTreeMap<u32, i64>,
+    silent_ticks: BTreeMap<u32, u32>,
+    outbox: Vec<(u32, Message)>,
+}
+
+impl RaftNode {
+    /// Opens the node's log and state in `dir`. `voters` are the ids of every
+    /// node of the quorum, `id` included.
+    pub fn open(
+        id: u32,
+        voters: impl IntoIterator<Item = u32>,
+        dir: &Path,
+        config: RaftConfig,
+    ) -> Result<Self, RaftError> {
+        let mut voters: Vec<u32> = voters.into_iter().chain([id]).collect();
+        voters.sort_unstable();
+        voters.dedup();
+
+        std::fs::create_dir_all(dir)?;
+        let (term, voted_for) = match std::fs::read(dir.join(STATE_FILE)) {
+            Ok(state) => {
+                let state: [u8; 8] = state
+                    .try_into()
+                    .map_err(|_| RaftError::Corrupted("quorum state"))?;
+                let term = i32::from_be_bytes(state[..4].try_into().unwrap());
+                let vote = u32::from_be_bytes(state[4..].try_into().unwrap());
+                (term, (vote != NO_VOTE).then_some(vote))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // entries are synced before they are acknowledged, so a torn one was
+        // never on a majority
+        let log = PartitionLog::recover(dir, METADATA_TOPIC, 0, LogConfig::default())?;
+        let mut terms = Vec::new();
+        for (_, record) in log.fetch(0, u32::MAX)? {
+            let term: [u8; 4] = record.key[..]
+                .try_into()
+                .map_err(|_| RaftError::Corrupted("metadata log"))?;
+            terms.push(i32::from_be_bytes(term));
+        }
+
+        let mut node = Self {
+            id,
+            voters,
+            config,
+            dir: dir.to_path_buf(),
+            term,
+            voted_for,
+            role: Role::Follower,
+            leader: None,
+            log,
+            terms,
+            commit_offset: 0,
+            applied_offset: 0,
+            elapsed: 0,
+            election_timeout: 0,
+            rng: u64::from(id).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            votes: BTreeSet::new(),
+            next_offset: BTreeMap::new(),
+            match_offset: BTreeMap::new(),
+            silent_ticks: BTreeMap::new(),
+            outbox: Vec::new(),
+        };
+        node.reset_election_timeout();
+        Ok(node)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn voters(&self) -> &[u32] {
+        &self.voters
+    }
+
+    pub fn term(&self) -> i32 {
+        self.term
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn leader(&self) -> Option<u32> {
+        self.leader
+    }
+
+    pub fn voted_for(&self) -> Option<u32> {
+        self.voted_for
+    }
+
+    pub fn log_end_offset(&self) -> i64 {
+        self.terms.len() as i64
+    }
+
+    /// Entries below it are on a majority of the nodes and will not change.
+    pub fn commit_offset(&self) -> i64 {
+        self.commit_offset
+    }
+
+    /// Term of the entry at `offset`, if the log has one.
+    pub fn entry_term(&self, offset: i64) -> Option<i32> {
+        usize::try_from(offset)
+            .ok()
+            .and_then(|i| self.terms.get(i))
+            .copied()
+    }
+
+    /// Whether this node leads and has committed an entry of its own term,
+    /// and with it every entry earlier leaders committed.
+    pub fn is_leader_ready(&self) -> bool {
+        self.role == Role::Leader && self.entry_term(self.commit_offset - 1) == Some(self.term)
+    }
+
+    /// On the leader, the nodes that answered within the last `ticks`, the
+    /// leader included.
+    pub fn live_voters(&self, ticks: u32) -> BTreeSet<u32> {
+        if self.role != Role::Leader {
+            return BTreeSet::new();
+        }
+        self.voters
+            .iter()
+            .copied()
+            .filter(|id| *id == self.id || self.silent_ticks.get(id).is_some_and(|&t| t <= ticks))
+            .collect()
+    }
+
+    /// Entries from `from` up to `to`.
+    pub fn read(&self, from: i64, to: i64) -> Result<Vec<RaftEntry>, RaftError> {
+        let to = to.min(self.log_end_offset());
+        if from >= to {
+            return Ok(vec![]);
+        }
+        Ok(self
+            .log
+            .fetch(from, u32::MAX)?
+            .into_iter()
+            .take_while(|(offset, _)| *offset < to)
+            .map(|(offset, record)| RaftEntry {
+                term: self.terms[offset as usize],
+                data: record.value,
+            })
+            .collect())
+    }
+
+    /// Requests to send to the other nodes.
+    pub fn take_messages(&mut self) -> Vec<(u32, Message)> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// Entries committed since the last call, with their offsets. Entries
+    /// without data are those new leaders append.
+    pub fn take_committed(&mut self) -> Result<Vec<(i64, Bytes)>, RaftError> {
+        let entries = self.read(self.applied_offset, self.commit_offset)?;
+        let first = self.applied_offset;
+        self.applied_offset += entries.len() as i64;
+        Ok((first..)
+            .zip(entries)
+            .map(|(offset, entry)| (offset, entry.data))
+            .collect())
+    }
+
+    /// Advances the node's clock by one tick: followers that have not heard
+    /// from a leader for long enough stand for election, and leaders send
+    /// their heartbeats.
+    pub fn tick(&mut self) -> Result<(), RaftError> {
+        self.elapsed += 1;
+        if self.role == Role::Leader {
+            for ticks in self.silent_ticks.values_mut() {
+                *ticks = ticks.saturating_add(1);
+            }
+            if self.elapsed >= self.config.heartbeat_ticks {
+                self.elapsed = 0;
+                self.send_appends()?;
+            }
+        } else if self.elapsed >= self.election_timeout {
+            self.start_election()?;
+        }
+        Ok(())
+    }
+
+    /// Appends `data` to the log if this node is the leader, returning its
+    /// offset. It is committed once a majority of the nodes have it, unless
+    /// leadership is lost first.
+    pub fn propose(&mut self, data: Bytes) -> Result<i64, RaftError> {
+        if self.role != Role::Leader {
+            return Err(RaftError::NotLeader(self.leader));
+        }
+        let offset = self.log_end_offset();
+        self.append(&[RaftEntry {
+            term: self.term,
+            data,
+        }])?;
+        self.send_appends()?;
+        self.advance_commit();
+        Ok(offset)
+    }
+
+    pub fn handle_vote(&mut self, req: VoteRequest) -> Result<VoteResponse, RaftError> {
+        if req.term > self.term {
+            self.step_down(req.term)?;
+        }
+        let up_to_date =
+            (req.last_log_term, req.log_end_offset) >= (self.last_log_term(), self.log_end_offset());
+        let vote_granted = req.term == self.term
+            && self.voted_for.is_none_or(|v| v == req.candidate_id)
+            && up_to_date;
+        if vote_granted {
+            self.voted_for = Some(req.candidate_id);
+            self.persist()?;
+            self.elapsed = 0;
+        }
+        Ok(VoteResponse {
+            status: status::OK,
+            term: self.term,
+            vote_granted,
+        })
+    }
+
+    pub fn handle_vote_response(&mut self, from: u32, resp: VoteResponse) -> Result<(), RaftError> {
+        if resp.term > self.term {
+            return self.step_down(resp.term);
+        }
+        if self.role != Role::Candidate || resp.term != self.term || !resp.vote_granted {
+            return Ok(());
+        }
+        self.votes.insert(from);
+        if self.is_majority(self.votes.len()) {
+            self.become_leader()?;
+        }
+        Ok(())
+    }
+
+    pub fn handle_append(
+        &mut self,
+        req: AppendEntriesRequest,
+    ) -> Result<AppendEntriesResponse, RaftError> {
+        if req.term < self.term {
+            return Ok(self.append_response(false, self.log_end_offset()));
+        }
+        if req.term > self.term || self.role != Role::Follower {
+            self.step_down(req.term)?;
+        }
+        self.leader = Some(req.leader_id);
+        self.elapsed = 0;
+
+        let end = self.log_end_offset();
+        if req.offset > end {
+            return Ok(self.append_response(false, end));
+        }
+        if let Some(prev) = req.offset.checked_sub(1).filter(|&o| o >= 0)
+            && self.entry_term(prev) != Some(req.prev_log_term)
+        {
+            // the leader can skip the rest of the entries of the term that
+            // differs, as terms only grow along the log
+            let conflict = self.terms[prev as usize];
+            let first = self.terms.partition_point(|&t| t < conflict) as i64;
+            return Ok(self.append_response(false, first));
+        }
+
+        let matched = req.offset + req.entries.len() as i64;
+        let mut new = &req.entries[..];
+        let mut offset = req.offset;
+        while let Some((entry, rest)) = new.split_first()
+            && let Some(term) = self.entry_term(offset)
+        {
+            if term != entry.term {
+                // committed entries are on every leader, so never differ
+                debug_assert!(offset >= self.commit_offset);
+                self.log.truncate(offset)?;
+                self.terms.truncate(offset as usize);
+                break;
+            }
+            new = rest;
+            offset += 1;
+        }
+        self.append(new)?;
+
+        self.commit_offset = self.commit_offset.max(req.commit_offset.min(matched));
+        Ok(self.append_response(true, matched))
+    }
+
+    pub fn handle_append_response(
+        &mut self,
+        from: u32,
+        resp: AppendEntriesResponse,
+    ) -> Result<(), RaftError> {
+        if resp.term > self.term {
+            return self.step_down(resp.term);
+        }
+        if self.role != Role::Leader || resp.term != self.term {
+            return Ok(());
+        }
+        self.silent_ticks.insert(from, 0);
+        let next = self.next_offset.entry(from).or_default();
+        if resp.success {
+            let matched = self.match_offset.entry(from).or_default();
+            *matched = (*matched).max(resp.log_end_offset);
+            *next = (*next).max(resp.log_end_offset);
+            self.advance_commit();
+            if self.next_offset[&from] >= self.log_end_offset() {
+                return Ok(());
+            }
+        } else {
+            *next = (*next).min(resp.log_end_offset).max(0);
+        }
+        // keeps going until the follower has caught up
+        self.send_append(from)
+    }
+
+    fn start_election(&mut self) -> Result<(), RaftError> {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.leader = None;
+        self.voted_for = Some(self.id);
+        self.votes = BTreeSet::from([self.id]);
+        self.persist()?;
+        self.reset_election_timeout();
+        tracing::debug!(id = self.id, term = self.term, "standing for election");
+
+        if self.is_majority(self.votes.len()) {
+            return self.become_leader();
+        }
+        for &to in self.voters.iter().filter(|&&v| v != self.id) {
+            self.outbox.push((
+                to,
+                Message::Vote(VoteRequest {
+                    term: self.term,
+                    candidate_id: self.id,
+                    last_log_term: self.last_log_term(),
+                    log_end_offset: self.log_end_offset(),
+                }),
+            ));
+        }
+        Ok(())
+    }
+
+    fn become_leader(&mut self) -> Result<(), RaftError> {
+        tracing::info!(id = self.id, term = self.term, "elected metadata log leader");
+        self.role = Role::Leader;
+        self.leader = Some(self.id);
+        self.elapsed = 0;
+        let end = self.log_end_offset();
+        let followers = self.voters.iter().copied().filter(|&v| v != self.id);
+        self.next_offset = followers.clone().map(|v| (v, end)).collect();
+        self.match_offset = followers.clone().map(|v| (v, 0)).collect();
+        self.silent_ticks = followers.map(|v| (v, 0)).collect();
+        self.propose(Bytes::new())?;
+        Ok(())
+    }
+
+    /// Follows whoever leads `term`, which is at least the current one.
+    fn step_down(&mut self, term: i32) -> Result<(), RaftError> {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.leader = None;
+            self.persist()?;
+        }
+        if self.role != Role::Follower {
+            self.role = Role::Follower;
+            self.reset_election_timeout();
+        }
+        Ok(())
+    }
+
+    fn send_appends(&mut self) -> Result<(), RaftError> {
+        let followers: Vec<u32> = self.next_offset.keys().copied().collect();
+        for to in followers {
+            self.send_append(to)?;
+        }
+        Ok(())
+    }
+
+    fn send_append(&mut self, to: u32) -> Result<(), RaftError> {
+        let offset = self.next_offset[&to];
+        let max = self.config.max_append_entries as i64;
+        let entries = self.read(offset, offset + max)?;
+        let req = AppendEntriesRequest {
+            term: self.term,
+            leader_id: self.id,
+            offset,
+            prev_log_term: self.entry_term(offset - 1).unwrap_or(0),
+            entries,
+            commit_offset: self.commit_offset,
+        };
+        self.outbox.push((to, Message::AppendEntries(req)));
+        Ok(())
+    }
+
+    /// Commits up to where a majority of the logs match the leader's, once
+    /// that includes an entry of the current term.
+    fn advance_commit(&mut self) {
+        let mut matched: Vec<i64> = self
+            .voters
+            .iter()
+            .map(|v| match self.match_offset.get(v) {
+                Some(&offset) => offset,
+                None => self.log_end_offset(),
+            })
+            .collect();
+        matched.sort_unstable();
+        let reached = matched[(matched.len() - 1) / 2];
+        if reached > self.commit_offset && self.entry_term(reached - 1) == Some(self.term) {
+            self.commit_offset = reached;
+        }
+    }
+
+    fn append(&mut self, entries: &[RaftEntry]) -> Result<(), RaftError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let records: Vec<Record> = entries
+            .iter()
+            .map(|e| Record {
+                key: Bytes::copy_from_slice(&e.term.to_be_bytes()),
+                value: e.data.clone(),
+            })
+            .collect();
+        self.log.append(&records)?;
+        self.terms.extend(entries.iter().map(|e| e.term));
+        Ok(())
+    }
+
+    fn append_response(&self, success: bool, log_end_offset: i64) -> AppendEntriesResponse {
+        AppendEntriesResponse {
+            status: status::OK,
+            term: self.term,
+            success,
+            log_end_offset,
+        }
+    }
+
+    fn last_log_term(&self) -> i32 {
+        self.terms.last().copied().unwrap_or(0)
+    }
+
+    fn is_majority(&self, count: usize) -> bool {
+        count * 2 > self.voters.len()
+    }
+
+    fn reset_election_timeout(&mut self) {
+        // xorshift, seeded by the id so that runs repeat
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let base = self.config.election_ticks.max(1);
+        self.election_timeout = base + (self.rng % u64::from(base)) as u32;
+        self.elapsed = 0;
+    }
+
+    /// Writes the term and vote next to the log, replacing the old ones in
+    /// one step.
+    fn persist(&self) -> Result<(), RaftError> {
+        let mut state = [0u8; 8];
+        state[..4].copy_from_slice(&self.term.to_be_bytes());
+        state[4..].copy_from_slice(&self.voted_for.unwrap_or(NO_VOTE).to_be_bytes());
+        let tmp = self.dir.join(format!("{STATE_FILE}.tmp"));
+        let mut f = File::create(&tmp)?;
+        f.write_all(&state)?;
+        f.sync_all()?;
+        std::fs::rename(&tmp, self.dir.join(STATE_FILE))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeSet;
+
+use controller::{MetadataRecord, PartitionRegistration, Reassignment, Registry, assign_replicas};
+
+fn apply(registry: &mut Registry, record: MetadataRecord) {
+    let offset = registry.applied_offset;
+    registry.apply(offset, &record.to_bytes().unwrap());
+}
+
+#[test]
+fn replicas_are_spread_over_brokers() {
+    assert_eq!(
+        assign_replicas(&[1, 2, 3], 4, 2),
+        [vec![1, 2], vec![2, 3], vec![3, 1], vec![1, 2]]
+    );
+}
+
+#[test]
+fn records_build_the_registry() {
+    let mut registry = Registry::default();
+    registry.apply(0, &[]);
+    assert_eq!(registry.applied_offset, 1);
+
+    apply(
+        &mut registry,
+        MetadataRecord::TopicCreated {
+            topic: "t".to_string(),
+            replicas: vec![vec![1, 2], vec![2, 1]],
+        },
+    );
+    // created twice: the first creation stands
+    apply(
+        &mut registry,
+        MetadataRecord::TopicCreated {
+            topic: "t".to_string(),
+            replicas: vec![vec![3]],
+        },
+    );
+    assert_eq!(registry.applied_offset, 3);
+    assert_eq!(
+        registry.partition("t", 1),
+        Some(&PartitionRegistration {
+            replicas: vec![2, 1],
+            leader: 2,
+            leader_epoch: 0,
+            reassignment: None,
+        })
+    );
+    assert_eq!(registry.topics["t"].len(), 2);
+
+    let moves = registry.leaders_to_move(&BTreeSet::from([1]));
+    assert_eq!(
+        moves,
+        [MetadataRecord::LeaderChanged {
+            topic: "t".to_string(),
+            partition: 1,
+            leader: 1,
+            leader_epoch: 1,
+        }]
+    );
+    for record in moves {
+        apply(&mut registry, record);
+    }
+    let p = registry.partition("t", 1).unwrap();
+    assert_eq!((p.leader, p.leader_epoch), (1, 1));
+
+    // leaders must be replicas, and epochs only go up
+    for (leader, leader_epoch) in [(3, 2), (2, 1)] {
+        apply(
+            &mut registry,
+            MetadataRecord::LeaderChanged {
+                topic: "t".to_string(),
+                partition: 1,
+                leader,
+                leader_epoch,
+            },
+        );
+    }
+    let p = registry.partition("t", 1).unwrap();
+    assert_eq!((p.leader, p.leader_epoch), (1, 1));
+
+    apply(
+        &mut registry,
+        MetadataRecord::TopicDeleted {
+            topic: "t".to_string(),
+        },
+    );
+    assert!(registry.topics.is_empty());
+}
+
+#[test]
+fn reassignments_add_replicas_before_dropping_others() {
+    let mut registry = Registry::default();
+    apply(
+        &mut registry,
+        MetadataRecord::TopicCreated {
+            topic: "t".to_string(),
+            replicas: vec![vec![1, 2]],
+        },
+    );
+    apply(
+        &mut registry,
+        MetadataRecord::ReassignmentStarted {
+            topic: "t".to_string(),
+            partition: 0,
+            replicas: vec![3, 2],
+            throttle_bytes_per_sec: 100,
+        },
+    );
+    let p = registry.partition("t", 0).unwrap();
+    assert_eq!(p.replicas, [3, 2, 1]);
+    assert_eq!(
+        p.reassignment,
+        Some(Reassignment {
+            adding: vec![3],
+            removing: vec![1],
+            throttle_bytes_per_sec: 100,
+        })
+    );
+    assert_eq!(p.target_replicas(), [3, 2]);
+
+    // one move at a time, and replicas still copying can't lead
+    apply(
+        &mut registry,
+        MetadataRecord::ReassignmentStarted {
+            topic: "t".to_string(),
+            partition: 0,
+            replicas: vec![4],
+            throttle_bytes_per_sec: 0,
+        },
+    );
+    apply(
+        &mut registry,
+        MetadataRecord::LeaderChanged {
+            topic: "t".to_string(),
+            partition: 0,
+            leader: 3,
+            leader_epoch: 1,
+        },
+    );
+    assert_eq!(registry.partition("t", 0).unwrap().replicas, [3, 2, 1]);
+    assert_eq!(registry.partition("t", 0).unwrap().leader, 1);
+    assert_eq!(
+        registry.leaders_to_move(&BTreeSet::from([2, 3])),
+        [MetadataRecord::LeaderChanged {
+            topic: "t".to_string(),
+            partition: 0,
+            leader: 2,
+            leader_epoch: 1,
+        }]
+    );
+
+    // the leader must be one of the replicas moved to
+    for leader in [1, 3] {
+        apply(
+            &mut registry,
+            MetadataRecord::ReassignmentCompleted {
+                topic: "t".to_string(),
+                partition: 0,
+                leader,
+                leader_epoch: 1,
+            },
+        );
+    }
+    assert_eq!(
+        registry.partition("t", 0),
+        Some(&PartitionRegistration {
+            replicas: vec![3, 2],
+            leader: 3,
+            leader_epoch: 1,
+            reassignment: None,
+        })
+    );
+
+    // the same replicas in another order only change the preferred leader
+    apply(
+        &mut registry,
+        MetadataRecord::ReassignmentStarted {
+            topic: "t".to_string(),
+            partition: 0,
+            replicas: vec![2, 3],
+            throttle_bytes_per_sec: 0,
+        },
+    );
+    let p = registry.partition("t", 0).unwrap();
+    assert_eq!((&p.replicas[..], p.leader), (&[2, 3][..], 3));
+    assert!(p.reassignment.is_none());
+}
+
+#[test]
+fn partitions_are_added_after_the_existing_ones() {
+    let mut registry = Registry::default();
+    apply(
+        &mut registry,
+        MetadataRecord::TopicCreated {
+            topic: "t".to_string(),
+            replicas: vec![vec![1, 2]],
+        },
+    );
+    let created = MetadataRecord::PartitionsCreated {
+        topic: "t".to_string(),
+        first_partition: 1,
+        replicas: vec![vec![2, 3], vec![3, 1]],
+    };
+    apply(&mut registry, created.clone());
+    // committed twice, or for another count: the first one stands
+    apply(&mut registry, created);
+    apply(
+        &mut registry,
+        MetadataRecord::PartitionsCreated {
+            topic: "t".to_string(),
+            first_partition: 0,
+            replicas: vec![vec![3]],
+        },
+    );
+    let partitions = &registry.topics["t"];
+    assert_eq!(partitions.len(), 3);
+    assert_eq!(
+        partitions[2],
+        PartitionRegistration {
+            replicas: vec![3, 1],
+            leader: 3,
+            leader_epoch: 0,
+            reassignment: None,
+        }
+    );
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use bytes::Bytes;
+use controller::{Message, RaftConfig, RaftNode, Role};
+use protocol::types::RaftEntry;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Nodes exchanging messages in order, in memory, so that every run of a
+/// test goes the same way. Messages to or from stopped nodes, and across cut
+/// links, are lost.
+struct Quorum {
+    dir: PathBuf,
+    ids: Vec<u32>,
+    nodes: BTreeMap<u32, RaftNode>,
+    cut: BTreeSet<(u32, u32)>,
+}
+
+impl Quorum {
+    fn new(name: &str, n: u32) -> Self {
+        let mut quorum = Self {
+            dir: temp_data_dir(name),
+            ids: (0..n).collect(),
+            nodes: BTreeMap::new(),
+            cut: BTreeSet::new(),
+        };
+        for id in 0..n {
+            quorum.start(id);
+        }
+        quorum
+    }
+
+    fn start(&mut self, id: u32) {
+        let node = RaftNode::open(
+            id,
+            self.ids.clone(),
+            &self.dir.join(id.to_string()),
+            RaftConfig::default(),
+        )
+        .unwrap();
+        self.nodes.insert(id, node);
+    }
+
+    fn stop(&mut self, id: u32) {
+        self.nodes.remove(&id);
+    }
+
+    fn node(&self, id: u32) -> &RaftNode {
+        &self.nodes[&id]
+    }
+
+    /// Cuts `id` off from every other node.
+    fn isolate(&mut self, id: u32) {
+        for &other in &self.ids {
+            if other != id {
+                self.cut.insert((id, other));
+                self.cut.insert((other, id));
+            }
+        }
+    }
+
+    fn heal(&mut self) {
+        self.cut.clear();
+    }
+
+    fn connected(&self, from: u32, to: u32) -> bool {
+        self.nodes.contains_key(&from)
+            && self.nodes.contains_key(&to)
+            && !self.cut.contains(&(from, to))
+    }
+
+    fn tick(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            for node in self.nodes.values_mut() {
+                node.tick().unwrap();
+            }
+            self.deliver();
+        }
+    }
+
+    /// Delivers requests and their responses until none are left.
+    fn deliver(&mut self) {
+        loop {
+            let mut sent = Vec::new();
+            for (&from, node) in self.nodes.iter_mut() {
+                for (to, message) in node.take_messages() {
+                    sent.push((from, to, message));
+                }
+            }
+            if sent.is_empty() {
+                return;
+            }
+            for (from, to, message) in sent {
+                if !self.connected(from, to) {
+                    continue;
+                }
+                let node = self.nodes.get_mut(&to).unwrap();
+                match message {
+                    Message::Vote(req) => {
+                        let resp = node.handle_vote(req).unwrap();
+                        if self.connected(to, from) {
+                            let node = self.nodes.get_mut(&from).unwrap();
+                            node.handle_vote_response(to, resp).unwrap();
+                        }
+                    }
+                    Message::AppendEntries(req) => {
+                        let resp = node.handle_append(req).unwrap();
+                        if self.connected(to, from) {
+                            let node = self.nodes.get_mut(&from).unwrap();
+                            node.handle_append_response(to, resp).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ticks until a node leads a majority of the quorum, which it is in
+    /// touch with, and returns it. A leader cut off from the others may still
+    /// think it leads.
+    fn elect(&mut self) -> u32 {
+        for _ in 0..200 {
+            self.tick(1);
+            let leader = self.nodes.values().find(|leader| {
+                let followers = self
+                    .nodes
+                    .values()
+                    .filter(|n| self.connected(leader.id(), n.id()) || n.id() == leader.id())
+                    .filter(|n| n.leader() == Some(leader.id()) && n.term() == leader.term())
+                    .count();
+                leader.role() == Role::Leader && followers * 2 > self.ids.len()
+            });
+            if let Some(leader) = leader {
+                return leader.id();
+            }
+        }
+        panic!("no leader elected");
+    }
+
+    fn propose(&mut self, leader: u32, data: &'static str) -> i64 {
+        let node = self.nodes.get_mut(&leader).unwrap();
+        let offset = node.propose(Bytes::from_static(data.as_bytes())).unwrap();
+        self.deliver();
+        offset
+    }
+
+    /// Data of the entries committed on `id` since the last call, without
+    /// the empty ones of new leaders.
+    fn committed(&mut self, id: u32) -> Vec<Bytes> {
+        let node = self.nodes.get_mut(&id).unwrap();
+        node.take_committed()
+            .unwrap()
+            .into_iter()
+            .map(|(_, data)| data)
+            .filter(|data| !data.is_empty())
+            .collect()
+    }
+
+    fn log(&self, id: u32) -> Vec<(i32, Bytes)> {
+        let node = self.node(id);
+        node.read(0, node.log_end_offset())
+            .unwrap()
+            .into_iter()
+            .map(|RaftEntry { term, data }| (term, data))
+            .collect()
+    }
+}
+
+#[test]
+fn one_leader_is_elected() {
+    let mut quorum = Quorum::new("raft-elect", 3);
+    let leader = quorum.elect();
+
+    let term = quorum.node(leader).term();
+    assert!(term >= 1);
+    for id in 0..3 {
+        let node = quorum.node(id);
+        assert_eq!(node.leader(), Some(leader));
+        assert_eq!(node.term(), term);
+        assert_eq!(node.role() == Role::Leader, id == leader);
+    }
+    // the entry the leader starts its term with is on every node
+    quorum.tick(5);
+    for id in 0..3 {
+        assert!(quorum.node(id).is_leader_ready() == (id == leader));
+        assert_eq!(quorum.node(id).commit_offset(), 1);
+    }
+}
+
+#[test]
+fn elections_repeat_exactly() {
+    let run = |name| {
+        let mut quorum = Quorum::new(name, 5);
+        let leader = quorum.elect();
+        (leader, quorum.node(leader).term())
+    };
+    assert_eq!(run("raft-repeat-a"), run("raft-repeat-b"));
+}
+
+#[test]
+fn entries_commit_on_a_majority() {
+    let mut quorum = Quorum::new("raft-majority", 3);
+    let leader = quorum.elect();
+    let followers: Vec<u32> = (0..3).filter(|&id| id != leader).collect();
+
+    quorum.propose(leader, "a");
+    // followers learn what is committed with the next append
+    quorum.tick(2);
+    for id in 0..3 {
+        assert_eq!(quorum.committed(id), [Bytes::from_static(b"a")]);
+    }
+
+    quorum.stop(followers[0]);
+    quorum.propose(leader, "b");
+    assert_eq!(quorum.committed(leader), [Bytes::from_static(b"b")]);
+
+    quorum.stop(followers[1]);
+    quorum.propose(leader, "c");
+    quorum.tick(3);
+    assert!(quorum.committed(leader).is_empty());
+
+    // the followers catch up once back
+    quorum.start(followers[0]);
+    quorum.start(followers[1]);
+    quorum.tick(3);
+    assert_eq!(quorum.committed(leader), [Bytes::from_static(b"c")]);
+    for &id in &followers {
+        assert_eq!(quorum.log(id), quorum.log(leader));
+    }
+}
+
+#[test]
+fn a_new_leader_takes_over_when_the_leader_fails() {
+    let mut quorum = Quorum::new("raft-failover", 3);
+    let old = quorum.elect();
+    let old_term = quorum.node(old).term();
+    quorum.propose(old, "a");
+
+    quorum.stop(old);
+    let new = quorum.elect();
+    assert_ne!(new, old);
+    assert!(quorum.node(new).term() > old_term);
+    quorum.propose(new, "b");
+
+    // the old leader follows the new one once back
+    quorum.start(old);
+    assert_eq!(quorum.node(old).term(), old_term);
+    assert_eq!(quorum.elect(), new);
+    quorum.tick(3);
+    assert_eq!(
+        quorum.committed(old),
+        [Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+    );
+}
+
+#[test]
+fn divergent_entries_are_replaced() {
+    let mut quorum = Quorum::new("raft-diverge", 3);
+    let old = quorum.elect();
+    quorum.propose(old, "a");
+    quorum.tick(3);
+
+    // the old leader keeps taking entries nobody else gets
+    quorum.isolate(old);
+    quorum.propose(old, "lost-1");
+    quorum.propose(old, "lost-2");
+    let new = quorum.elect();
+    assert_ne!(new, old);
+    quorum.propose(new, "kept");
+    assert_eq!(
+        quorum.committed(new),
+        [Bytes::from_static(b"a"), Bytes::from_static(b"kept")]
+    );
+    assert_eq!(quorum.node(old).role(), Role::Leader);
+    assert_eq!(quorum.node(old).log_end_offset(), 4);
+
+    quorum.heal();
+    assert_eq!(quorum.elect(), new);
+    quorum.tick(3);
+    assert_eq!(quorum.log(old), quorum.log(new));
+    assert_eq!(
+        quorum.committed(old),
+        [Bytes::from_static(b"a"), Bytes::from_static(b"kept")]
+    );
+}
+
+#[test]
+fn nodes_missing_committed_entries_are_not_elected() {
+    let mut quorum = Quorum::new("raft-up-to-date", 3);
+    let leader = quorum.elect();
+    let (behind, other) = match leader {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    quorum.isolate(behind);
+    quorum.propose(leader, "a");
+    assert_eq!(quorum.committed(leader), [Bytes::from_static(b"a")]);
+
+    // only `other` has every committed entry, so only it can win
+    quorum.stop(leader);
+    quorum.heal();
+    assert_eq!(quorum.elect(), other);
+    assert_eq!(quorum.log(behind), quorum.log(other));
+}
+
+#[test]
+fn term_vote_and_log_survive_restarts() {
+    let mut quorum = Quorum::new("raft-restart", 3);
+    let leader = quorum.elect();
+    quorum.propose(leader, "a");
+    quorum.tick(3);
+    let logs: Vec<_> = (0..3).map(|id| quorum.log(id)).collect();
+    let terms: Vec<_> = (0..3).map(|id| quorum.node(id).term()).collect();
+    let vote = quorum.node(leader).voted_for();
+
+    for id in 0..3 {
+        quorum.stop(id);
+        quorum.start(id);
+    }
+    for id in 0..3 {
+        assert_eq!(quorum.log(id), logs[id as usize]);
+        assert_eq!(quorum.node(id).term(), terms[id as usize]);
+        // applying starts over once the entries are known to be committed
+        assert_eq!(quorum.node(id).commit_offset(), 0);
+    }
+    assert_eq!(quorum.node(leader).voted_for(), vote);
+
+    let leader = quorum.elect();
+    quorum.tick(3);
+    for id in 0..3 {
+        assert_eq!(quorum.committed(id), [Bytes::from_static(b"a")]);
+    }
+    assert!(quorum.node(leader).term() > terms[leader as usize]);
+}
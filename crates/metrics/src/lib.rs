@@ -0,0 +1,267 @@
+//! Process-wide counters, gauges and latency histograms, rendered in the
+//! Prometheus text exposition format.
+//!
+//! Metrics are looked up by name and labels on every call and created on first
+//! use, so there is nothing to register up front.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Durations sorted into `LATENCY_BUCKETS`.
+#[derive(Debug)]
+pub struct Histogram(Mutex<HistogramState>);
+
+#[derive(Debug, Clone)]
+struct HistogramState {
+    // not cumulative; the last entry counts values past every bucket
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self(Mutex::new(HistogramState {
+            buckets: vec![0; LATENCY_BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }))
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, d: Duration) {
+        let secs = d.as_secs_f64();
+        let mut state = self.0.lock().unwrap();
+        let i = LATENCY_BUCKETS.partition_point(|&le| le < secs);
+        state.buckets[i] += 1;
+        state.sum += secs;
+        state.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.lock().unwrap().count
+    }
+}
+
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+struct Family {
+    help: &'static str,
+    kind: &'static str,
+    series: BTreeMap<Labels, Metric>,
+}
+
+static REGISTRY: LazyLock<Mutex<BTreeMap<&'static str, Family>>> = LazyLock::new(Default::default);
+
+/// Returns the metric `name` with `labels`, creating it with `new` if missing.
+/// Panics if `name` is already used by a metric of another kind.
+fn get_or_create<T>(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&'static str, &str)],
+    kind: &'static str,
+    new: impl FnOnce() -> Metric,
+    cast: impl Fn(&Metric) -> Option<Arc<T>>,
+) -> Arc<T> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let family = registry.entry(name).or_insert_with(|| Family {
+        help,
+        kind,
+        series: BTreeMap::new(),
+    });
+    if family.kind != kind {
+        let existing = family.kind;
+        // not poisoning the registry for everyone else
+        drop(registry);
+        panic!("metric {name} is a {existing}, not a {kind}");
+    }
+    let labels: Labels = labels.iter().map(|&(k, v)| (k, v.to_string())).collect();
+    let metric = family.series.entry(labels).or_insert_with(new);
+    cast(metric).expect("series of one family share its kind")
+}
+
+pub fn counter(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&'static str, &str)],
+) -> Arc<Counter> {
+    get_or_create(
+        name,
+        help,
+        labels,
+        "counter",
+        || Metric::Counter(Arc::default()),
+        |m| match m {
+            Metric::Counter(c) => Some(c.clone()),
+            _ => None,
+        },
+    )
+}
+
+pub fn gauge(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&'static str, &str)],
+) -> Arc<Gauge> {
+    get_or_create(
+        name,
+        help,
+        labels,
+        "gauge",
+        || Metric::Gauge(Arc::default()),
+        |m| match m {
+            Metric::Gauge(g) => Some(g.clone()),
+            _ => None,
+        },
+    )
+}
+
+pub fn histogram(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&'static str, &str)],
+) -> Arc<Histogram> {
+    get_or_create(
+        name,
+        help,
+        labels,
+        "histogram",
+        || Metric::Histogram(Arc::default()),
+        |m| match m {
+            Metric::Histogram(h) => Some(h.clone()),
+            _ => None,
+        },
+    )
+}
+
+/// Every metric in the Prometheus text format, version 0.0.4.
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    for (name, family) in registry.iter() {
+        let _ = writeln!(out, "# HELP {name} {}", family.help);
+        let _ = writeln!(out, "# TYPE {name} {}", family.kind);
+        for (labels, metric) in &family.series {
+            match metric {
+                Metric::Counter(c) => {
+                    let _ = writeln!(out, "{name}{} {}", format_labels(labels, None), c.get());
+                }
+                Metric::Gauge(g) => {
+                    let _ = writeln!(out, "{name}{} {}", format_labels(labels, None), g.get());
+                }
+                Metric::Histogram(h) => render_histogram(&mut out, name, labels, h),
+            }
+        }
+    }
+    out
+}
+
+fn render_histogram(out: &mut String, name: &str, labels: &Labels, h: &Histogram) {
+    let state = h.0.lock().unwrap().clone();
+    let mut cumulative = 0;
+    for (i, le) in LATENCY_BUCKETS.iter().enumerate() {
+        cumulative += state.buckets[i];
+        let le = le.to_string();
+        let _ = writeln!(
+            out,
+            "{name}_bucket{} {cumulative}",
+            format_labels(labels, Some(&le))
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{name}_bucket{} {}",
+        format_labels(labels, Some("+Inf")),
+        state.count
+    );
+    let _ = writeln!(
+        out,
+        "{name}_sum{} {}",
+        format_labels(labels, None),
+        state.sum
+    );
+    let _ = writeln!(
+        out,
+        "{name}_count{} {}",
+        format_labels(labels, None),
+        state.count
+    );
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+        .collect();
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
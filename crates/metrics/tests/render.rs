@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+#[test]
+fn counters_and_gauges_render_with_labels() {
+    metrics::counter("test_requests_total", "Requests seen", &[("api", "fetch")]).add(3);
+    metrics::counter(
+        "test_requests_total",
+        "Requests seen",
+        &[("api", "produce")],
+    )
+    .inc();
+    metrics::gauge("test_open", "Open things", &[("name", "a\"b")]).set(-2);
+
+    let out = metrics::render();
+    assert!(out.contains(
+        "# HELP test_requests_total Requests seen\n# TYPE test_requests_total counter\n"
+    ));
+    assert!(out.contains("test_requests_total{api=\"fetch\"} 3\n"));
+    assert!(out.contains("test_requests_total{api=\"produce\"} 1\n"));
+    assert!(out.contains("# TYPE test_open gauge\n"));
+    assert!(out.contains("test_open{name=\"a\\\"b\"} -2\n"));
+}
+
+#[test]
+fn histogram_buckets_are_cumulative() {
+    let h = metrics::histogram("test_latency_seconds", "Latency", &[]);
+    h.observe(Duration::from_micros(200));
+    h.observe(Duration::from_millis(3));
+    h.observe(Duration::from_secs(60));
+    assert_eq!(h.count(), 3);
+
+    let out = metrics::render();
+    assert!(out.contains("# TYPE test_latency_seconds histogram\n"));
+    assert!(out.contains("test_latency_seconds_bucket{le=\"0.0005\"} 1\n"));
+    assert!(out.contains("test_latency_seconds_bucket{le=\"0.005\"} 2\n"));
+    assert!(out.contains("test_latency_seconds_bucket{le=\"10\"} 2\n"));
+    assert!(out.contains("test_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(out.contains("test_latency_seconds_count 3\n"));
+}
+
+#[test]
+#[should_panic(expected = "is a counter")]
+fn a_name_keeps_its_kind() {
+    metrics::counter("test_kind", "Kind", &[]).inc();
+    metrics::gauge("test_kind", "Kind", &[("other", "labels")]);
+}
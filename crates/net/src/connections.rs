@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Limits applied to every connection of a listener.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Larger request frames close the connection.
+    pub max_frame_size: usize,
+    /// Connections accepted past these caps are closed right away.
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    /// Connections that start no request for this long are closed.
+    pub idle_timeout: Option<Duration>,
+    /// Time allowed to receive a request once its first byte has arrived, and to
+    /// complete a TLS handshake.
+    pub request_timeout: Option<Duration>,
+    /// Requests that take this long to decode, handle and encode are logged as
+    /// warnings with the time spent in each step.
+    pub slow_request: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 8 * 1024 * 1024, // 8 MB
+            max_connections: None,
+            max_connections_per_ip: None,
+            idle_timeout: Some(Duration::from_secs(600)),
+            request_timeout: Some(Duration::from_secs(30)),
+            slow_request: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// A snapshot of the counters of a `Connections`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub open: u64,
+    pub accepted: u64,
+    /// Connections closed on accept because of `max_connections`...
+    pub rejected_total: u64,
+    /// ...or `max_connections_per_ip`.
+    pub rejected_per_ip: u64,
+    pub idle_timeouts: u64,
+    pub request_timeouts: u64,
+}
+
+/// Limits and counters shared by the listeners serving one broker, so the caps
+/// on open connections apply across all of them.
+#[derive(Debug, Clone, Default)]
+pub struct Connections {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    limits: Limits,
+    open: Mutex<Open>,
+    accepted: AtomicU64,
+    rejected_total: AtomicU64,
+    rejected_per_ip: AtomicU64,
+    idle_timeouts: AtomicU64,
+    request_timeouts: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Open {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl Connections {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limits,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.inner.limits
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let inner = &self.inner;
+        ConnectionStats {
+            open: inner.open.lock().unwrap().total as u64,
+            accepted: inner.accepted.load(Ordering::Relaxed),
+            rejected_total: inner.rejected_total.load(Ordering::Relaxed),
+            rejected_per_ip: inner.rejected_per_ip.load(Ordering::Relaxed),
+            idle_timeouts: inner.idle_timeouts.load(Ordering::Relaxed),
+            request_timeouts: inner.request_timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Registers a connection just accepted from `peer`, or returns `None` if
+    /// that would exceed a limit and it should be closed.
+    pub(crate) fn open(&self, peer: SocketAddr) -> Option<Connection> {
+        let inner = &self.inner;
+        let mut open = inner.open.lock().unwrap();
+        if inner
+            .limits
+            .max_connections
+            .is_some_and(|max| open.total >= max)
+        {
+            inner.rejected_total.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let from_ip = open.per_ip.get(&peer.ip()).copied().unwrap_or(0);
+        if inner
+            .limits
+            .max_connections_per_ip
+            .is_some_and(|max| from_ip >= max)
+        {
+            inner.rejected_per_ip.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        *open.per_ip.entry(peer.ip()).or_default() += 1;
+        open.total += 1;
+        active_connections().inc();
+        inner.accepted.fetch_add(1, Ordering::Relaxed);
+        Some(Connection {
+            conns: self.clone(),
+            peer,
+        })
+    }
+}
+
+/// An open connection, counted against the limits until dropped.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    conns: Connections,
+    peer: SocketAddr,
+}
+
+impl Connection {
+    pub(crate) fn limits(&self) -> &Limits {
+        self.conns.limits()
+    }
+
+    pub(crate) fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub(crate) fn idle_timed_out(&self) {
+        self.conns
+            .inner
+            .idle_timeouts
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request_timed_out(&self) {
+        self.conns
+            .inner
+            .request_timeouts
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut open = self.conns.inner.open.lock().unwrap();
+        open.total -= 1;
+        active_connections().dec();
+        let ip = self.peer.ip();
+        if let Some(n) = open.per_ip.get_mut(&ip) {
+            *n -= 1;
+            if *n == 0 {
+                open.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+fn active_connections() -> Arc<metrics::Gauge> {
+    metrics::gauge(
+        "mini_kafka_connections_active",
+        "Open client connections",
+        &[],
+    )
+}
+
+/// Runs `f` for at most `limit`, returning `None` if it took longer.
+pub(crate) async fn within<F: Future>(limit: Option<Duration>, f: F) -> Option<F::Output> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, f).await.ok(),
+        None => Some(f.await),
+    }
+}
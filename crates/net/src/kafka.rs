@@ -0,0 +1,787 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use broker::{Broker, Usage};
+use bytes::{Bytes, BytesMut};
+use protocol::{
+    error::ProtoError,
+    kafka::{
+        self, KafkaRequest, KafkaResponse, RequestHeader,
+        api_versions::ApiVersionsResponse,
+        error_code,
+        fetch::{FetchPartitionResponse, FetchRequest, FetchResponse, FetchTopicResponse},
+        group::{
+            ErrorCodeResponse, FindCoordinatorResponse, JoinGroupMember, JoinGroupResponse,
+            SyncGroupResponse,
+        },
+        list_offsets::{
+            ListOffsetsPartitionResponse, ListOffsetsResponse, ListOffsetsTopicResponse,
+        },
+        metadata::{MetadataBroker, MetadataPartition, MetadataResponse, MetadataTopic},
+        offsets::{
+            OffsetCommitResponse, OffsetCommitTopicResponse, OffsetFetchPartitionResponse,
+            OffsetFetchResponse, OffsetFetchTopicResponse, PartitionError,
+        },
+        produce::{ProducePartitionResponse, ProduceResponse, ProduceTopicResponse},
+        records,
+        sasl::{
+            SaslAuthenticateRequest, SaslAuthenticateResponse, SaslHandshakeRequest,
+            SaslHandshakeResponse,
+        },
+    },
+    status,
+    types::{self, Record, Request, Response},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::Instant,
+};
+
+use tracing::Instrument;
+
+use crate::{
+    ConnContext, Connections, Credentials, Shutdown, observe_request, read_frame,
+    trace::{self, RequestTimer},
+    write_frame,
+};
+
+const NODE_ID: i32 = 0;
+const CLUSTER_ID: &str = "mini-kafka";
+
+pub async fn serve_kafka(
+    addr: &str,
+    broker: Arc<Broker>,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(%addr, "Kafka listener started");
+    serve_kafka_listener(listener, broker, sasl, conns, shutdown).await
+}
+
+/// Serves the Kafka protocol on an already bound listener, advertising its local
+/// address. With `sasl` set, clients must authenticate through SaslHandshake
+/// and SaslAuthenticate before any request other than ApiVersions.
+pub async fn serve_kafka_listener(
+    listener: TcpListener,
+    broker: Arc<Broker>,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
+    let advertised = listener.local_addr()?;
+
+    loop {
+        let (sock, peer) = tokio::select! {
+            res = listener.accept() => res?,
+            _ = shutdown.triggered() => return Ok(()),
+        };
+        let Some(conn) = conns.open(peer) else {
+            tracing::info!(%peer, "rejected Kafka connection: too many connections");
+            continue;
+        };
+        let span = trace::conn_span("kafka", peer);
+        tracing::debug!(parent: &span, "accepted connection");
+
+        let b = broker.clone();
+        let ctx = ConnContext::new(sasl.clone(), conn, shutdown.clone());
+
+        shutdown.spawn(
+            async move {
+                if let Err(e) = handle_kafka_conn(sock, b, advertised, ctx).await {
+                    tracing::warn!(error = %e, "connection closed on error");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+async fn handle_kafka_conn(
+    mut sock: TcpStream,
+    broker: Arc<Broker>,
+    advertised: SocketAddr,
+    mut ctx: ConnContext,
+) -> std::io::Result<()> {
+    loop {
+        if ctx.shutdown.is_triggered() {
+            return Ok(());
+        }
+        let read = tokio::select! {
+            res = read_frame(&mut sock, &ctx.conn) => res,
+            _ = ctx.shutdown.triggered() => return Ok(()),
+        };
+        let payload = match read {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let keep_open = handle_kafka_frame(&mut sock, &broker, advertised, &mut ctx, payload)
+            .instrument(trace::request_span())
+            .await?;
+        if !keep_open {
+            return Ok(());
+        }
+    }
+}
+
+/// Answers the request in `payload`. Returns whether the connection stays open.
+async fn handle_kafka_frame(
+    sock: &mut TcpStream,
+    broker: &Broker,
+    advertised: SocketAddr,
+    ctx: &mut ConnContext,
+    mut payload: Bytes,
+) -> std::io::Result<bool> {
+    let mut timer = RequestTimer::start();
+    let header = kafka::decode_header(&mut payload).map_err(invalid_data)?;
+    let api = kafka::api_name(header.api_key);
+    let request_size = payload.len() as u64;
+    let request = kafka::decode_request(&header, payload);
+    timer.decoded();
+    let target = request.as_ref().ok().and_then(|r| r.topic_partition());
+    trace::record_request(api, target.map(|(t, p)| (t, p.into())));
+
+    let mut resp = match request {
+        Ok(KafkaRequest::SaslHandshake(r)) => {
+            Some(KafkaResponse::SaslHandshake(sasl_handshake(ctx, r)))
+        }
+        Ok(KafkaRequest::SaslAuthenticate(r)) => {
+            Some(KafkaResponse::SaslAuthenticate(sasl_authenticate(ctx, r)))
+        }
+        // like Kafka, close the connection on anything but ApiVersions before
+        // authentication
+        Ok(req) if !ctx.may_send_requests() && !matches!(req, KafkaRequest::ApiVersions(_)) => {
+            tracing::info!("request before SASL authentication");
+            observe_request("kafka", api, timer.started(), true);
+            return Ok(false);
+        }
+        Ok(req) => handle_request(broker, ctx.principal(), req, advertised).await,
+        Err(ProtoError::UnsupportedVersion { api_key, .. }) if api_key == kafka::API_VERSIONS => {
+            let out = kafka::encode_response(
+                header.correlation_id,
+                0,
+                &kafka::unsupported_api_versions_response(),
+            )
+            .map_err(invalid_data)?;
+            write_frame(sock, &out).await?;
+            return Ok(true);
+        }
+        // like Kafka, drop the connection when a request can't be understood
+        Err(e) => {
+            observe_request("kafka", api, timer.started(), true);
+            return Err(invalid_data(e));
+        }
+    };
+    timer.stored();
+    observe_request("kafka", api, timer.started(), false);
+
+    let mut throttle = Duration::ZERO;
+    if !matches!(
+        resp,
+        Some(KafkaResponse::SaslHandshake(_) | KafkaResponse::SaslAuthenticate(_))
+    ) {
+        let usage = kafka_usage(&header, request_size, resp.as_ref());
+        let client_id = header.client_id.as_deref().unwrap_or_default();
+        throttle = broker.record_usage(ctx.principal(), client_id, usage);
+        if let Some(resp) = &mut resp {
+            let ms = throttle.as_millis().try_into().unwrap_or(i32::MAX);
+            resp.set_throttle_time_ms(ms);
+        }
+    }
+
+    // acks=0 produce requests get no response at all
+    let out = resp
+        .map(|resp| kafka::encode_response(header.correlation_id, header.api_version, &resp))
+        .transpose()
+        .map_err(invalid_data)?;
+    timer.encoded();
+    timer.finish(ctx.conn.limits().slow_request);
+    ctx.shutdown.sleep(throttle).await;
+    if let Some(out) = out {
+        write_frame(sock, &out).await?;
+    }
+    Ok(!ctx.sasl.failed())
+}
+
+fn sasl_handshake(ctx: &mut ConnContext, r: SaslHandshakeRequest) -> SaslHandshakeResponse {
+    let resp = match &ctx.credentials {
+        Some(_) => ctx.sasl.handshake(&r.mechanism),
+        None => types::SaslHandshakeResponse {
+            status: status::UNSUPPORTED_SASL_MECHANISM,
+            mechanisms: vec![],
+        },
+    };
+    SaslHandshakeResponse {
+        error_code: resp.status as i16,
+        mechanisms: resp.mechanisms,
+    }
+}
+
+fn sasl_authenticate(
+    ctx: &mut ConnContext,
+    r: SaslAuthenticateRequest,
+) -> SaslAuthenticateResponse {
+    let resp = match &ctx.credentials {
+        Some(credentials) => ctx.sasl.authenticate(credentials, &r.auth_bytes),
+        None => types::SaslAuthenticateResponse {
+            status: status::ILLEGAL_SASL_STATE,
+            error_message: "SASL is not enabled on this listener".to_string(),
+            auth_bytes: Bytes::new(),
+        },
+    };
+    SaslAuthenticateResponse {
+        error_code: resp.status as i16,
+        error_message: (resp.status != status::OK).then_some(resp.error_message),
+        auth_bytes: resp.auth_bytes,
+        // sessions don't expire
+        session_lifetime_ms: 0,
+    }
+}
+
+fn kafka_usage(header: &RequestHeader, request_size: u64, resp: Option<&KafkaResponse>) -> Usage {
+    let mut usage = Usage::default();
+    if header.api_key == kafka::PRODUCE {
+        usage.produce_bytes = request_size;
+    }
+    if let Some(KafkaResponse::Fetch(r)) = resp {
+        usage.fetch_bytes = r
+            .responses
+            .iter()
+            .flat_map(|t| &t.partitions)
+            .filter_map(|p| p.records.as_ref())
+            .map(|records| records.len() as u64)
+            .sum();
+    }
+    usage
+}
+
+fn invalid_data(e: ProtoError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+async fn handle_request(
+    broker: &Broker,
+    user: Option<&str>,
+    req: KafkaRequest,
+    advertised: SocketAddr,
+) -> Option<KafkaResponse> {
+    let resp = match req {
+        KafkaRequest::ApiVersions(_) => KafkaResponse::ApiVersions(ApiVersionsResponse {
+            error_code: error_code::NONE,
+            api_keys: kafka::SUPPORTED_APIS.to_vec(),
+            throttle_time_ms: 0,
+        }),
+        KafkaRequest::Metadata(r) => {
+            KafkaResponse::Metadata(metadata(broker, user, r, advertised).await)
+        }
+        KafkaRequest::Produce(r) => {
+            let acks = r.acks;
+            let resp = produce(broker, user, r).await;
+            if acks == 0 {
+                return None;
+            }
+            KafkaResponse::Produce(resp)
+        }
+        KafkaRequest::Fetch(r) => KafkaResponse::Fetch(fetch(broker, user, r).await),
+        KafkaRequest::ListOffsets(r) => {
+            KafkaResponse::ListOffsets(list_offsets(broker, user, r).await)
+        }
+        KafkaRequest::FindCoordinator(_) => {
+            KafkaResponse::FindCoordinator(FindCoordinatorResponse {
+                throttle_time_ms: 0,
+                error_code: error_code::NONE,
+                error_message: None,
+                node_id: NODE_ID,
+                host: advertised.ip().to_string(),
+                port: advertised.port() as i32,
+            })
+        }
+        KafkaRequest::OffsetCommit(r) => {
+            KafkaResponse::OffsetCommit(offset_commit(broker, user, r).await)
+        }
+        KafkaRequest::OffsetFetch(r) => {
+            KafkaResponse::OffsetFetch(offset_fetch(broker, user, r).await)
+        }
+        KafkaRequest::JoinGroup(r) => KafkaResponse::JoinGroup(join_group(broker, user, r).await),
+        KafkaRequest::SyncGroup(r) => {
+            let req = Request::SyncGroup(types::SyncGroupRequest {
+                group_id: r.group_id,
+                generation_id: r.generation_id,
+                member_id: r.member_id,
+                assignments: r
+                    .assignments
+                    .into_iter()
+                    .map(|a| types::MemberAssignment {
+                        member_id: a.member_id,
+                        assignment: a.assignment,
+                    })
+                    .collect(),
+            });
+            KafkaResponse::SyncGroup(match broker.handle_as(user, req).await {
+                Response::SyncGroup(r) => SyncGroupResponse {
+                    throttle_time_ms: 0,
+                    error_code: r.status as i16,
+                    assignment: r.assignment,
+                },
+                _ => SyncGroupResponse {
+                    throttle_time_ms: 0,
+                    error_code: error_code::UNKNOWN_SERVER_ERROR,
+                    assignment: Bytes::new(),
+                },
+            })
+        }
+        KafkaRequest::Heartbeat(r) => {
+            let req = Request::Heartbeat(types::HeartbeatRequest {
+                group_id: r.group_id,
+                generation_id: r.generation_id,
+                member_id: r.member_id,
+            });
+            let error_code = match broker.handle_as(user, req).await {
+                Response::Heartbeat(r) => r.status as i16,
+                _ => error_code::UNKNOWN_SERVER_ERROR,
+            };
+            KafkaResponse::Heartbeat(ErrorCodeResponse {
+                throttle_time_ms: 0,
+                error_code,
+            })
+        }
+        KafkaRequest::LeaveGroup(r) => {
+            let req = Request::LeaveGroup(types::LeaveGroupRequest {
+                group_id: r.group_id,
+                member_id: r.member_id,
+            });
+            let error_code = match broker.handle_as(user, req).await {
+                Response::LeaveGroup(r) => r.status as i16,
+                _ => error_code::UNKNOWN_SERVER_ERROR,
+            };
+            KafkaResponse::LeaveGroup(ErrorCodeResponse {
+                throttle_time_ms: 0,
+                error_code,
+            })
+        }
+        KafkaRequest::SaslHandshake(_) | KafkaRequest::SaslAuthenticate(_) => {
+            unreachable!("SASL requests are answered with the connection's state")
+        }
+    };
+
+    Some(resp)
+}
+
+async fn metadata(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::metadata::MetadataRequest,
+    advertised: SocketAddr,
+) -> MetadataResponse {
+    let req = Request::Metadata(types::MetadataRequest {
+        topics: r.topics.unwrap_or_default(),
+        allow_auto_create: r.allow_auto_topic_creation,
+    });
+
+    let topics = match broker.handle_as(user, req).await {
+        Response::Metadata(m) => m
+            .topics
+            .into_iter()
+            .map(|t| MetadataTopic {
+                error_code: t.status as i16,
+                is_internal: t.name.starts_with("__"),
+                name: t.name,
+                partitions: t
+                    .partitions
+                    .into_iter()
+                    .map(|p| MetadataPartition {
+                        error_code: error_code::NONE,
+                        partition_index: p as i32,
+                        leader_id: NODE_ID,
+                        replica_nodes: vec![NODE_ID],
+                        isr_nodes: vec![NODE_ID],
+                        offline_replicas: vec![],
+                    })
+                    .collect(),
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    MetadataResponse {
+        throttle_time_ms: 0,
+        brokers: vec![MetadataBroker {
+            node_id: NODE_ID,
+            host: advertised.ip().to_string(),
+            port: advertised.port() as i32,
+            rack: None,
+        }],
+        cluster_id: Some(CLUSTER_ID.to_string()),
+        controller_id: NODE_ID,
+        topics,
+    }
+}
+
+async fn produce(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::produce::ProduceRequest,
+) -> ProduceResponse {
+    let mut responses = Vec::with_capacity(r.topics.len());
+
+    for t in r.topics {
+        let mut partitions = Vec::with_capacity(t.partitions.len());
+        for p in t.partitions {
+            let mut resp = ProducePartitionResponse {
+                index: p.index,
+                error_code: error_code::NONE,
+                base_offset: -1,
+                log_append_time_ms: -1,
+                log_start_offset: -1,
+            };
+
+            let Ok(partition) = u16::try_from(p.index) else {
+                resp.error_code = error_code::UNKNOWN_TOPIC_OR_PARTITION;
+                partitions.push(resp);
+                continue;
+            };
+
+            if !matches!(r.acks, -1..=1) {
+                resp.error_code = error_code::INVALID_REQUIRED_ACKS;
+                partitions.push(resp);
+                continue;
+            }
+
+            let records = match p.records.map(records::decode_record_batches) {
+                Some(Ok(records)) => records,
+                Some(Err(ProtoError::UnsupportedCompression(_))) => {
+                    resp.error_code = error_code::UNSUPPORTED_COMPRESSION_TYPE;
+                    partitions.push(resp);
+                    continue;
+                }
+                Some(Err(_)) | None => {
+                    resp.error_code = error_code::CORRUPT_MESSAGE;
+                    partitions.push(resp);
+                    continue;
+                }
+            };
+            // batches encode lengths as varints, which may exceed what the log
+            // can hold
+            if !records.iter().all(Record::fits_length_prefixes) {
+                resp.error_code = error_code::RECORD_TOO_LARGE;
+                partitions.push(resp);
+                continue;
+            }
+
+            let req = Request::Produce(types::ProduceRequest {
+                topic: t.name.clone(),
+                partition,
+                acks: r.acks,
+                records,
+            });
+            match broker.handle_as(user, req).await {
+                Response::Produce(pr) => {
+                    resp.error_code = pr.status as i16;
+                    resp.base_offset = pr.base_offset;
+                }
+                _ => resp.error_code = error_code::UNKNOWN_SERVER_ERROR,
+            }
+            partitions.push(resp);
+        }
+        responses.push(ProduceTopicResponse {
+            name: t.name,
+            partitions,
+        });
+    }
+
+    ProduceResponse {
+        responses,
+        throttle_time_ms: 0,
+    }
+}
+
+/// Long-polls until `min_bytes` are available or `max_wait_ms` passes.
+async fn fetch(broker: &Broker, user: Option<&str>, r: FetchRequest) -> FetchResponse {
+    let deadline = Instant::now() + Duration::from_millis(r.max_wait_ms.max(0) as u64);
+
+    loop {
+        let appended = broker.appended().notified();
+        tokio::pin!(appended);
+        appended.as_mut().enable();
+
+        let (resp, bytes) = fetch_once(broker, user, &r).await;
+        if bytes >= r.min_bytes.max(1) as usize || Instant::now() >= deadline {
+            return resp;
+        }
+
+        let _ = tokio::time::timeout_at(deadline, appended).await;
+    }
+}
+
+async fn fetch_once(
+    broker: &Broker,
+    user: Option<&str>,
+    r: &FetchRequest,
+) -> (FetchResponse, usize) {
+    let mut total = 0usize;
+    let mut responses = Vec::with_capacity(r.topics.len());
+
+    for t in &r.topics {
+        let mut partitions = Vec::with_capacity(t.partitions.len());
+        for p in &t.partitions {
+            let mut resp = FetchPartitionResponse {
+                partition_index: p.partition,
+                error_code: error_code::NONE,
+                high_watermark: -1,
+                last_stable_offset: -1,
+                log_start_offset: -1,
+                records: None,
+            };
+
+            let Ok(partition) = u16::try_from(p.partition) else {
+                resp.error_code = error_code::UNKNOWN_TOPIC_OR_PARTITION;
+                partitions.push(resp);
+                continue;
+            };
+
+            let budget = (r.max_bytes.max(0) as usize).saturating_sub(total);
+            let max_bytes = budget.min(p.partition_max_bytes.max(0) as usize) as u32;
+            let req = Request::Fetch(types::FetchRequest {
+                topic: t.topic.clone(),
+                partition,
+                offset: p.fetch_offset,
+                max_bytes,
+            });
+
+            match broker.handle_as(user, req).await {
+                Response::Fetch(mut fr) => {
+                    // Storage always hands back the first record so a lone
+                    // oversized one can't stall a consumer; only the first
+                    // partition with data gets that allowance.
+                    let size: usize = fr.items.iter().map(|(_, rec)| rec.stored_len()).sum();
+                    if total > 0 && size > max_bytes as usize {
+                        fr.items.clear();
+                    }
+                    resp.error_code = fr.status as i16;
+                    resp.high_watermark = fr.high_watermark;
+                    resp.last_stable_offset = fr.high_watermark;
+                    resp.log_start_offset = fr.log_start_offset;
+
+                    let mut batch = BytesMut::new();
+                    match records::encode_record_batch(&mut batch, &fr.items) {
+                        Ok(()) => {
+                            total += batch.len();
+                            resp.records = Some(batch.freeze());
+                        }
+                        Err(_) => resp.error_code = error_code::CORRUPT_MESSAGE,
+                    }
+                }
+                _ => resp.error_code = error_code::UNKNOWN_SERVER_ERROR,
+            }
+            partitions.push(resp);
+        }
+        responses.push(FetchTopicResponse {
+            topic: t.topic.clone(),
+            partitions,
+        });
+    }
+
+    let resp = FetchResponse {
+        throttle_time_ms: 0,
+        error_code: error_code::NONE,
+        session_id: 0,
+        responses,
+    };
+    (resp, total)
+}
+
+async fn list_offsets(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::list_offsets::ListOffsetsRequest,
+) -> ListOffsetsResponse {
+    let mut topics = Vec::with_capacity(r.topics.len());
+
+    for t in r.topics {
+        let mut partitions = Vec::with_capacity(t.partitions.len());
+        for p in t.partitions {
+            let mut resp = ListOffsetsPartitionResponse {
+                partition_index: p.partition_index,
+                error_code: error_code::NONE,
+                timestamp: -1,
+                offset: -1,
+                leader_epoch: -1,
+            };
+
+            match u16::try_from(p.partition_index) {
+                Ok(partition) => {
+                    let req = Request::ListOffsets(types::ListOffsetsRequest {
+                        topic: t.name.clone(),
+                        partition,
+                        timestamp: p.timestamp,
+                    });
+                    match broker.handle_as(user, req).await {
+                        Response::ListOffsets(lr) => {
+                            resp.error_code = lr.status as i16;
+                            resp.offset = lr.offset;
+                        }
+                        _ => resp.error_code = error_code::UNKNOWN_SERVER_ERROR,
+                    }
+                }
+                Err(_) => resp.error_code = error_code::UNKNOWN_TOPIC_OR_PARTITION,
+            }
+            partitions.push(resp);
+        }
+        topics.push(ListOffsetsTopicResponse {
+            name: t.name,
+            partitions,
+        });
+    }
+
+    ListOffsetsResponse {
+        throttle_time_ms: 0,
+        topics,
+    }
+}
+
+async fn offset_commit(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::offsets::OffsetCommitRequest,
+) -> OffsetCommitResponse {
+    let mut topics = Vec::with_capacity(r.topics.len());
+
+    for t in r.topics {
+        let mut partitions = Vec::with_capacity(t.partitions.len());
+        for p in t.partitions {
+            let error_code = match u16::try_from(p.partition_index) {
+                Ok(partition) => {
+                    let req = Request::OffsetCommit(types::OffsetCommitRequest {
+                        group_id: r.group_id.clone(),
+                        topic: t.name.clone(),
+                        partition,
+                        offset: p.committed_offset,
+                    });
+                    match broker.handle_as(user, req).await {
+                        Response::OffsetCommit(cr) => cr.status as i16,
+                        _ => error_code::UNKNOWN_SERVER_ERROR,
+                    }
+                }
+                Err(_) => error_code::UNKNOWN_TOPIC_OR_PARTITION,
+            };
+            partitions.push(PartitionError {
+                partition_index: p.partition_index,
+                error_code,
+            });
+        }
+        topics.push(OffsetCommitTopicResponse {
+            name: t.name,
+            partitions,
+        });
+    }
+
+    OffsetCommitResponse {
+        throttle_time_ms: 0,
+        topics,
+    }
+}
+
+async fn offset_fetch(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::offsets::OffsetFetchRequest,
+) -> OffsetFetchResponse {
+    let mut topics = Vec::new();
+
+    // listing every committed partition (null topics) is not supported; answer with none
+    for t in r.topics.unwrap_or_default() {
+        let mut partitions = Vec::with_capacity(t.partition_indexes.len());
+        for index in t.partition_indexes {
+            let mut resp = OffsetFetchPartitionResponse {
+                partition_index: index,
+                committed_offset: -1,
+                metadata: None,
+                error_code: error_code::NONE,
+            };
+
+            match u16::try_from(index) {
+                Ok(partition) => {
+                    let req = Request::OffsetFetch(types::OffsetFetchRequest {
+                        group_id: r.group_id.clone(),
+                        topic: t.name.clone(),
+                        partition,
+                    });
+                    match broker.handle_as(user, req).await {
+                        Response::OffsetFetch(fr) => {
+                            resp.error_code = fr.status as i16;
+                            resp.committed_offset = fr.offset;
+                        }
+                        _ => resp.error_code = error_code::UNKNOWN_SERVER_ERROR,
+                    }
+                }
+                Err(_) => resp.error_code = error_code::UNKNOWN_TOPIC_OR_PARTITION,
+            }
+            partitions.push(resp);
+        }
+        topics.push(OffsetFetchTopicResponse {
+            name: t.name,
+            partitions,
+        });
+    }
+
+    OffsetFetchResponse {
+        throttle_time_ms: 0,
+        topics,
+        error_code: error_code::NONE,
+    }
+}
+
+async fn join_group(
+    broker: &Broker,
+    user: Option<&str>,
+    r: kafka::group::JoinGroupRequest,
+) -> JoinGroupResponse {
+    let member_id = r.member_id.clone();
+    let req = Request::JoinGroup(types::JoinGroupRequest {
+        group_id: r.group_id,
+        member_id: r.member_id,
+        session_timeout_ms: r.session_timeout_ms.max(0) as u32,
+        rebalance_timeout_ms: r.rebalance_timeout_ms.max(0) as u32,
+        protocol_type: r.protocol_type,
+        protocols: r
+            .protocols
+            .into_iter()
+            .map(|p| types::GroupProtocol {
+                name: p.name,
+                metadata: p.metadata,
+            })
+            .collect(),
+    });
+
+    match broker.handle_as(user, req).await {
+        Response::JoinGroup(jr) => JoinGroupResponse {
+            throttle_time_ms: 0,
+            error_code: jr.status as i16,
+            generation_id: jr.generation_id,
+            protocol_name: jr.protocol_name,
+            leader: jr.leader,
+            member_id: jr.member_id,
+            members: jr
+                .members
+                .into_iter()
+                .map(|m| JoinGroupMember {
+                    member_id: m.member_id,
+                    group_instance_id: None,
+                    metadata: m.metadata,
+                })
+                .collect(),
+        },
+        _ => JoinGroupResponse {
+            throttle_time_ms: 0,
+            error_code: error_code::UNKNOWN_SERVER_ERROR,
+            generation_id: -1,
+            protocol_name: String::new(),
+            leader: String::new(),
+            member_id,
+            members: vec![],
+        },
+    }
+}
@@ -1,77 +1,372 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use broker::Broker;
+use broker::{Broker, Usage};
+use bytes::Bytes;
 use bytes::{Buf, BufMut, BytesMut};
-use protocol::{decode_request, encode_response, types::Response};
+use protocol::{
+    decode_request, encode_response,
+    error::ProtoError,
+    status,
+    types::{
+        Request, Response, SaslAuthenticateRequest, SaslAuthenticateResponse, SaslHandshakeRequest,
+        SaslHandshakeResponse,
+    },
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
 };
 
-pub async fn serve(addr: &str, broker: Arc<Broker>) -> std::io::Result<()> {
+use connections::{Connection, within};
+use trace::RequestTimer;
+use tracing::Instrument;
+
+mod connections;
+mod kafka;
+mod sasl;
+#[cfg(target_os = "linux")]
+mod sendfile;
+mod shutdown;
+mod tls;
+mod trace;
+
+pub use connections::{ConnectionStats, Connections, Limits};
+pub use kafka::{serve_kafka, serve_kafka_listener};
+pub use sasl::Credentials;
+pub use shutdown::Shutdown;
+pub use tls::{TlsConfig, serve_tls, serve_tls_listener};
+pub use tokio_rustls::TlsAcceptor;
+
+pub async fn serve(
+    addr: &str,
+    broker: Arc<Broker>,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
     let listener = TcpListener::bind(addr).await?;
-    println!("Server listening on {}", addr);
+    tracing::info!(%addr, "native listener started");
+    serve_listener(listener, broker, sasl, conns, shutdown).await
+}
 
+/// Serves the native protocol on an already bound listener. With `sasl` set,
+/// clients must authenticate before any other request is accepted. Returns once
+/// `shutdown` is triggered; connections are left to close on their own.
+pub async fn serve_listener(
+    listener: TcpListener,
+    broker: Arc<Broker>,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> std::io::Result<()> {
     loop {
-        let (sock, peer) = listener.accept().await?;
-        println!("Accepted connection from {}", peer);
+        let (sock, peer) = tokio::select! {
+            res = listener.accept() => res?,
+            _ = shutdown.triggered() => return Ok(()),
+        };
+        let Some(conn) = conns.open(peer) else {
+            tracing::info!(%peer, "rejected connection: too many connections");
+            continue;
+        };
+        let span = trace::conn_span("native", peer);
+        tracing::debug!(parent: &span, "accepted connection");
 
         let b = broker.clone();
+        let ctx = ConnContext::new(sasl.clone(), conn, shutdown.clone());
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_conn(sock, b).await {
-                eprint!("conn error: {}", e);
+        shutdown.spawn(
+            async move {
+                if let Err(e) = handle_conn(sock, b, ctx).await {
+                    tracing::warn!(error = %e, "connection closed on error");
+                }
             }
-        });
+            .instrument(span),
+        );
+    }
+}
+
+/// State attached to one client connection.
+#[derive(Debug)]
+pub(crate) struct ConnContext {
+    credentials: Option<Arc<Credentials>>,
+    sasl: sasl::SaslSession,
+    conn: Connection,
+    shutdown: Shutdown,
+}
+
+impl ConnContext {
+    pub(crate) fn new(
+        credentials: Option<Arc<Credentials>>,
+        conn: Connection,
+        shutdown: Shutdown,
+    ) -> Self {
+        Self {
+            credentials,
+            sasl: sasl::SaslSession::new(),
+            conn,
+            shutdown,
+        }
+    }
+
+    /// The authenticated user, or `None` before authentication and on listeners
+    /// without SASL.
+    pub(crate) fn principal(&self) -> Option<&str> {
+        self.sasl.principal()
+    }
+
+    fn may_send_requests(&self) -> bool {
+        self.credentials.is_none() || self.principal().is_some()
+    }
+
+    fn sasl_handshake(&mut self, r: SaslHandshakeRequest) -> Response {
+        let resp = match &self.credentials {
+            Some(_) => self.sasl.handshake(&r.mechanism),
+            None => SaslHandshakeResponse {
+                status: status::UNSUPPORTED_SASL_MECHANISM,
+                mechanisms: vec![],
+            },
+        };
+        Response::SaslHandshake(resp)
+    }
+
+    fn sasl_authenticate(&mut self, r: SaslAuthenticateRequest) -> Response {
+        let resp = match &self.credentials {
+            Some(credentials) => self.sasl.authenticate(credentials, &r.auth_bytes),
+            None => SaslAuthenticateResponse {
+                status: status::ILLEGAL_SASL_STATE,
+                error_message: "SASL is not enabled on this listener".to_string(),
+                auth_bytes: Bytes::new(),
+            },
+        };
+        Response::SaslAuthenticate(resp)
     }
 }
 
-async fn handle_conn(mut sock: TcpStream, broker: Arc<Broker>) -> std::io::Result<()> {
+async fn handle_conn<S>(
+    mut sock: S,
+    broker: Arc<Broker>,
+    mut ctx: ConnContext,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     loop {
-        let payload = match read_frame(&mut sock).await {
-            Ok(p) => p,
+        // on shutdown, a connection closes between requests
+        if ctx.shutdown.is_triggered() {
+            return Ok(());
+        }
+        let read = tokio::select! {
+            res = read_frame(&mut sock, &ctx.conn) => res,
+            _ = ctx.shutdown.triggered() => return Ok(()),
+        };
+        let payload = match read {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(()),
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
             Err(e) => return Err(e),
         };
 
-        let resp = match decode_request(payload) {
-            Ok(req) => broker.handle(req).await,
-            Err(e) => Response::Error {
-                message: format!("invalid request: {}", e),
-            },
-        };
+        let keep_open = handle_frame(&mut sock, &broker, &mut ctx, payload)
+            .instrument(trace::request_span())
+            .await?;
+        if !keep_open {
+            return Ok(());
+        }
+    }
+}
 
-        let out = encode_response(resp).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("encode error: {}", e),
-            )
-        })?;
-        write_frame(&mut sock, &out).await?;
+/// Answers the request in `payload`. Returns whether the connection stays open.
+async fn handle_frame<S>(
+    sock: &mut S,
+    broker: &Broker,
+    ctx: &mut ConnContext,
+    payload: Bytes,
+) -> std::io::Result<bool>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut timer = RequestTimer::start();
+    let slow = ctx.conn.limits().slow_request;
+    let request_size = payload.len() as u64;
+    let request = decode_request(payload);
+    timer.decoded();
+    let api = request.as_ref().map_or("unknown", |r| r.api_key().name());
+    let target = request.as_ref().ok().and_then(|r| r.topic_partition());
+    trace::record_request(api, target.map(|(t, p)| (t, p.into())));
+
+    let mut resp = match request {
+        Ok(Request::SaslHandshake(r)) => ctx.sasl_handshake(r),
+        Ok(Request::SaslAuthenticate(r)) => ctx.sasl_authenticate(r),
+        Ok(_) if !ctx.may_send_requests() => {
+            tracing::info!("request before SASL authentication");
+            let resp = Response::Error {
+                message: "SASL authentication required".to_string(),
+            };
+            write_frame(sock, &encode_response(resp).map_err(encode_error)?).await?;
+            return Ok(false);
+        }
+        // on plain TCP, fetched records go straight from the log file to the
+        // socket; TLS and any broker error fall back to the buffered path
+        #[cfg(target_os = "linux")]
+        Ok(Request::Fetch(r)) => {
+            let tcp = (sock as &mut (dyn std::any::Any + Send)).downcast_mut::<TcpStream>();
+            if let Some(tcp) = tcp
+                && let Ok(fetch) = broker.fetch_region(ctx.principal(), &r).await
+            {
+                timer.stored();
+                observe_request("native", api, timer.started(), fetch.status != status::OK);
+                timer.finish(slow);
+                let usage = Usage {
+                    fetch_bytes: fetch.region.as_ref().map_or(0, |r| r.len),
+                    ..Usage::default()
+                };
+                let throttle = broker.record_usage(ctx.principal(), NATIVE_CLIENT_ID, usage);
+                ctx.shutdown.sleep(throttle).await;
+                sendfile::write_fetch_region(tcp, fetch, throttle_ms(throttle)).await?;
+                return Ok(true);
+            }
+            broker.handle_as(ctx.principal(), Request::Fetch(r)).await
+        }
+        Ok(req) => broker.handle_as(ctx.principal(), req).await,
+        Err(e) => Response::Error {
+            message: format!("invalid request: {}", e),
+        },
+    };
+    timer.stored();
+
+    observe_request("native", api, timer.started(), resp.is_error());
+    let mut throttle = Duration::ZERO;
+    if !matches!(
+        resp,
+        Response::SaslHandshake(_) | Response::SaslAuthenticate(_)
+    ) {
+        let usage = native_usage(request_size, &resp);
+        throttle = broker.record_usage(ctx.principal(), NATIVE_CLIENT_ID, usage);
+        match &mut resp {
+            Response::Produce(r) => r.throttle_time_ms = throttle_ms(throttle),
+            Response::Fetch(r) => r.throttle_time_ms = throttle_ms(throttle),
+            _ => {}
+        }
+    }
+
+    let out = encode_response(resp).map_err(encode_error)?;
+    timer.encoded();
+    timer.finish(slow);
+    ctx.shutdown.sleep(throttle).await;
+    write_frame(sock, &out).await?;
+    Ok(!ctx.sasl.failed())
+}
+
+/// Counts a request of `api` on a `listener` speaking the native or Kafka
+/// protocol, and how long it took until its response was ready, not counting
+/// any quota delay.
+pub(crate) fn observe_request(listener: &str, api: &str, started: Instant, failed: bool) {
+    let labels = [("listener", listener), ("api", api)];
+    metrics::counter("mini_kafka_requests_total", "Requests handled", &labels).inc();
+    metrics::histogram(
+        "mini_kafka_request_duration_seconds",
+        "Time from reading a request to its response being ready",
+        &labels,
+    )
+    .observe(started.elapsed());
+    if failed {
+        metrics::counter(
+            "mini_kafka_request_errors_total",
+            "Requests that failed or were answered with an error",
+            &labels,
+        )
+        .inc();
     }
 }
 
+// native requests carry no client id, so only user and default quotas apply
+const NATIVE_CLIENT_ID: &str = "";
+
+/// Produce requests count their size against byte quotas, fetch responses the
+/// size of their records as stored in the log.
+fn native_usage(request_size: u64, resp: &Response) -> Usage {
+    match resp {
+        Response::Produce(_) => Usage {
+            produce_bytes: request_size,
+            ..Usage::default()
+        },
+        Response::Fetch(r) => Usage {
+            fetch_bytes: r.items.iter().map(|(_, rec)| rec.stored_len() as u64).sum(),
+            ..Usage::default()
+        },
+        _ => Usage::default(),
+    }
+}
+
+fn throttle_ms(throttle: Duration) -> u32 {
+    throttle.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+fn encode_error(e: ProtoError) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("encode error: {}", e),
+    )
+}
+
 /// Frame format: u32(len, bit-endian) + [len bytes of payload]
-async fn read_frame(sock: &mut TcpStream) -> std::io::Result<bytes::Bytes> {
+///
+/// Returns `None` when the client closes the connection or stays idle past the
+/// idle timeout before starting a frame.
+async fn read_frame(
+    sock: &mut (impl AsyncRead + Unpin),
+    conn: &Connection,
+) -> std::io::Result<Option<bytes::Bytes>> {
+    let limits = conn.limits();
     let mut len_buf = [0u8; 4];
-    sock.read_exact(&mut len_buf).await?;
-    let mut cur = std::io::Cursor::new(len_buf);
-    let len = cur.get_u32() as usize;
-
-    const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024; // 8 MB
-    if len > MAX_FRAME_SIZE {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("frame size {} exceeds maximum {}", len, MAX_FRAME_SIZE),
-        ));
+    let Some(n) = within(limits.idle_timeout, sock.read(&mut len_buf)).await else {
+        conn.idle_timed_out();
+        tracing::debug!("closing idle connection");
+        return Ok(None);
+    };
+    let n = n?;
+    if n == 0 {
+        return Ok(None);
     }
 
-    let mut payload = vec![0u8; len];
-    sock.read_exact(&mut payload).await?;
-    Ok(payload.into())
+    let rest = async {
+        sock.read_exact(&mut len_buf[n..]).await?;
+        let mut cur = std::io::Cursor::new(len_buf);
+        let len = cur.get_u32() as usize;
+
+        if len > limits.max_frame_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "frame size {} exceeds maximum {}",
+                    len, limits.max_frame_size
+                ),
+            ));
+        }
+
+        let mut payload = vec![0u8; len];
+        sock.read_exact(&mut payload).await?;
+        Ok(Some(payload.into()))
+    };
+    match within(limits.request_timeout, rest).await {
+        Some(res) => res,
+        None => {
+            conn.request_timed_out();
+            Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("request from {} timed out", conn.peer()),
+            ))
+        }
+    }
 }
 
-async fn write_frame(sock: &mut TcpStream, payload: &bytes::Bytes) -> std::io::Result<()> {
+async fn write_frame(
+    sock: &mut (impl AsyncWrite + Unpin),
+    payload: &bytes::Bytes,
+) -> std::io::Result<()> {
     let mut frame = BytesMut::with_capacity(4 + payload.len());
     frame.put_u32(payload.len() as u32);
     frame.put_slice(payload);
@@ -0,0 +1,361 @@
+use std::{collections::HashMap, io, num::NonZeroU32, path::Path};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use protocol::{
+    status,
+    types::{SASL_PLAIN, SASL_SCRAM_SHA_256, SaslAuthenticateResponse, SaslHandshakeResponse},
+};
+use ring::{
+    digest, hmac, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+const SCRAM_ITERATIONS: NonZeroU32 = NonZeroU32::new(4096).unwrap();
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 18;
+
+/// Users allowed to authenticate, loaded from a file of `username:password`
+/// lines. Blank lines and lines starting with `#` are ignored. Only SCRAM
+/// keys are kept in memory, never the passwords themselves.
+#[derive(Debug)]
+pub struct Credentials {
+    users: HashMap<String, ScramCredential>,
+}
+
+#[derive(Debug, Clone)]
+struct ScramCredential {
+    salt: Vec<u8>,
+    iterations: NonZeroU32,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl Credentials {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let rng = SystemRandom::new();
+        let mut users = HashMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((user, password)) = line.split_once(':') else {
+                return Err(format!("line {}: expected `username:password`", i + 1));
+            };
+            if user.is_empty() {
+                return Err(format!("line {}: empty username", i + 1));
+            }
+
+            let salt = random_bytes(&rng, SALT_LEN);
+            let cred = ScramCredential::derive(password.as_bytes(), salt, SCRAM_ITERATIONS);
+            users.insert(user.to_string(), cred);
+        }
+
+        Ok(Self { users })
+    }
+
+    fn get(&self, user: &str) -> Option<&ScramCredential> {
+        self.users.get(user)
+    }
+}
+
+impl ScramCredential {
+    fn derive(password: &[u8], salt: Vec<u8>, iterations: NonZeroU32) -> Self {
+        let mut salted = [0u8; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password,
+            &mut salted,
+        );
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &salted);
+        let client_key = hmac::sign(&key, b"Client Key");
+        let server_key = hmac::sign(&key, b"Server Key");
+
+        Self {
+            salt,
+            iterations,
+            stored_key: digest::digest(&digest::SHA256, client_key.as_ref())
+                .as_ref()
+                .to_vec(),
+            server_key: server_key.as_ref().to_vec(),
+        }
+    }
+
+    /// Stand-in for unknown users, so the exchange fails at the proof like a
+    /// wrong password instead of revealing which usernames exist.
+    fn unknown(rng: &SystemRandom) -> Self {
+        Self {
+            salt: random_bytes(rng, SALT_LEN),
+            iterations: SCRAM_ITERATIONS,
+            stored_key: random_bytes(rng, digest::SHA256_OUTPUT_LEN),
+            server_key: random_bytes(rng, digest::SHA256_OUTPUT_LEN),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Handshake,
+    Plain,
+    ScramFirst,
+    ScramFinal {
+        user: String,
+        cred: ScramCredential,
+        nonce: String,
+        // base64 of the client-first-message's gs2 header, which the
+        // client-final-message repeats
+        channel_binding: String,
+        // client-first-message-bare + "," + server-first-message
+        auth_prefix: String,
+    },
+    Authenticated(String),
+    Failed,
+}
+
+/// The SASL exchange of one connection: a handshake picking the mechanism,
+/// then one or more authenticate round trips.
+#[derive(Debug)]
+pub(crate) struct SaslSession {
+    state: State,
+    rng: SystemRandom,
+}
+
+impl SaslSession {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Handshake,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    pub(crate) fn principal(&self) -> Option<&str> {
+        match &self.state {
+            State::Authenticated(user) => Some(user),
+            _ => None,
+        }
+    }
+
+    /// A failed or out-of-order exchange ends the connection.
+    pub(crate) fn failed(&self) -> bool {
+        matches!(self.state, State::Failed)
+    }
+
+    pub(crate) fn handshake(&mut self, mechanism: &str) -> SaslHandshakeResponse {
+        let status = match (&self.state, mechanism) {
+            (State::Handshake, SASL_PLAIN) => {
+                self.state = State::Plain;
+                status::OK
+            }
+            (State::Handshake, SASL_SCRAM_SHA_256) => {
+                self.state = State::ScramFirst;
+                status::OK
+            }
+            (State::Handshake, _) => status::UNSUPPORTED_SASL_MECHANISM,
+            _ => {
+                self.state = State::Failed;
+                status::ILLEGAL_SASL_STATE
+            }
+        };
+
+        SaslHandshakeResponse {
+            status,
+            mechanisms: vec![SASL_PLAIN.to_string(), SASL_SCRAM_SHA_256.to_string()],
+        }
+    }
+
+    pub(crate) fn authenticate(
+        &mut self,
+        credentials: &Credentials,
+        token: &[u8],
+    ) -> SaslAuthenticateResponse {
+        let state = std::mem::replace(&mut self.state, State::Failed);
+        let res = match state {
+            State::Plain => self.plain(credentials, token),
+            State::ScramFirst => self.scram_first(credentials, token),
+            State::ScramFinal {
+                user,
+                cred,
+                nonce,
+                channel_binding,
+                auth_prefix,
+            } => self.scram_final(user, &cred, &nonce, &channel_binding, &auth_prefix, token),
+            _ => {
+                return SaslAuthenticateResponse {
+                    status: status::ILLEGAL_SASL_STATE,
+                    error_message: "unexpected SaslAuthenticate".to_string(),
+                    auth_bytes: Bytes::new(),
+                };
+            }
+        };
+
+        match res {
+            Ok(auth_bytes) => SaslAuthenticateResponse {
+                status: status::OK,
+                error_message: String::new(),
+                auth_bytes,
+            },
+            Err(message) => {
+                self.state = State::Failed;
+                SaslAuthenticateResponse {
+                    status: status::SASL_AUTHENTICATION_FAILED,
+                    error_message: message,
+                    auth_bytes: Bytes::new(),
+                }
+            }
+        }
+    }
+
+    // [authzid] NUL authcid NUL passwd
+    fn plain(&mut self, credentials: &Credentials, token: &[u8]) -> Result<Bytes, String> {
+        let mut parts = token.split(|&b| b == 0);
+        let (Some(authzid), Some(user), Some(password), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed PLAIN token".to_string());
+        };
+        let user = std::str::from_utf8(user).map_err(|_| "malformed PLAIN token".to_string())?;
+        if !authzid.is_empty() && authzid != user.as_bytes() {
+            return Err("authorization id must match the username".to_string());
+        }
+
+        let cred = credentials
+            .get(user)
+            .cloned()
+            .unwrap_or_else(|| ScramCredential::unknown(&self.rng));
+        let attempt = ScramCredential::derive(password, cred.salt.clone(), cred.iterations);
+        if !ct_eq(&attempt.stored_key, &cred.stored_key) {
+            return Err(format!("authentication failed for {user}"));
+        }
+
+        self.state = State::Authenticated(user.to_string());
+        Ok(Bytes::new())
+    }
+
+    // n,,n=user,r=client-nonce
+    fn scram_first(&mut self, credentials: &Credentials, token: &[u8]) -> Result<Bytes, String> {
+        let msg = std::str::from_utf8(token).map_err(|_| "malformed SCRAM message")?;
+        let gs2_header = ["n,,", "y,,"]
+            .into_iter()
+            .find(|header| msg.starts_with(header))
+            .ok_or("channel binding and authzid are not supported")?;
+        let bare = &msg[gs2_header.len()..];
+
+        let attrs = scram_attributes(bare)?;
+        let user = attrs
+            .get(&'n')
+            .ok_or("missing username")?
+            .replace("=2C", ",")
+            .replace("=3D", "=");
+        let client_nonce = attrs.get(&'r').ok_or("missing nonce")?;
+
+        let cred = credentials
+            .get(&user)
+            .cloned()
+            .unwrap_or_else(|| ScramCredential::unknown(&self.rng));
+        let server_nonce = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(random_bytes(&self.rng, NONCE_LEN));
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            STANDARD.encode(&cred.salt),
+            cred.iterations
+        );
+
+        self.state = State::ScramFinal {
+            user,
+            cred,
+            nonce,
+            channel_binding: STANDARD.encode(gs2_header),
+            auth_prefix: format!("{bare},{server_first}"),
+        };
+        Ok(Bytes::from(server_first))
+    }
+
+    // c=biws,r=nonce,p=proof
+    fn scram_final(
+        &mut self,
+        user: String,
+        cred: &ScramCredential,
+        nonce: &str,
+        channel_binding: &str,
+        auth_prefix: &str,
+        token: &[u8],
+    ) -> Result<Bytes, String> {
+        let msg = std::str::from_utf8(token).map_err(|_| "malformed SCRAM message")?;
+        let (without_proof, proof) = msg.rsplit_once(",p=").ok_or("missing proof")?;
+        let attrs = scram_attributes(without_proof)?;
+        if attrs.get(&'c').map(String::as_str) != Some(channel_binding) {
+            return Err("channel binding mismatch".to_string());
+        }
+        if attrs.get(&'r').map(String::as_str) != Some(nonce) {
+            return Err("nonce mismatch".to_string());
+        }
+        let proof = STANDARD.decode(proof).map_err(|_| "malformed proof")?;
+
+        let auth_message = format!("{auth_prefix},{without_proof}");
+        let stored = hmac::Key::new(hmac::HMAC_SHA256, &cred.stored_key);
+        let client_signature = hmac::sign(&stored, auth_message.as_bytes());
+        if proof.len() != client_signature.as_ref().len() {
+            return Err(format!("authentication failed for {user}"));
+        }
+        let client_key: Vec<u8> = proof
+            .iter()
+            .zip(client_signature.as_ref())
+            .map(|(a, b)| a ^ b)
+            .collect();
+        let candidate = digest::digest(&digest::SHA256, &client_key);
+        if !ct_eq(candidate.as_ref(), &cred.stored_key) {
+            return Err(format!("authentication failed for {user}"));
+        }
+
+        let server = hmac::Key::new(hmac::HMAC_SHA256, &cred.server_key);
+        let server_signature = hmac::sign(&server, auth_message.as_bytes());
+
+        self.state = State::Authenticated(user);
+        Ok(Bytes::from(format!(
+            "v={}",
+            STANDARD.encode(server_signature.as_ref())
+        )))
+    }
+}
+
+fn scram_attributes(msg: &str) -> Result<HashMap<char, String>, String> {
+    let mut attrs = HashMap::new();
+    for part in msg.split(',') {
+        let mut chars = part.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), Some('=')) => {
+                attrs.insert(key, chars.as_str().to_string());
+            }
+            _ => return Err("malformed SCRAM message".to_string()),
+        }
+    }
+    if attrs.contains_key(&'m') {
+        return Err("unsupported SCRAM extension".to_string());
+    }
+    Ok(attrs)
+}
+
+fn random_bytes(rng: &SystemRandom, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    rng.fill(&mut out).expect("system random source failed");
+    out
+}
+
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
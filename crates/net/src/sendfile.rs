@@ -0,0 +1,79 @@
+use std::{io, os::fd::AsRawFd};
+
+use broker::{FetchRegion, FileRegion};
+use bytes::{BufMut, BytesMut};
+use protocol::{
+    encode_response,
+    types::{FetchResponse, Response},
+};
+use tokio::{io::AsyncWriteExt, io::Interest, net::TcpStream};
+
+// keeps a single sendfile call from monopolising the socket
+const MAX_CHUNK: u64 = 1 << 20;
+
+/// Writes a fetch response frame whose items are copied from the log file to the
+/// socket by the kernel.
+pub(crate) async fn write_fetch_region(
+    sock: &mut TcpStream,
+    fetch: FetchRegion,
+    throttle_time_ms: u32,
+) -> io::Result<()> {
+    let (records, len) = fetch.region.as_ref().map_or((0, 0), |r| (r.records, r.len));
+
+    // an item-less response ends with its u16 count, which is replaced by the region's
+    let head = encode_response(Response::Fetch(FetchResponse {
+        status: fetch.status,
+        high_watermark: fetch.high_watermark,
+        log_start_offset: fetch.log_start_offset,
+        throttle_time_ms,
+        items: vec![],
+    }))
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("encode error: {e}")))?;
+    let payload_len = u32::try_from(head.len() as u64 + len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "fetch response too large"))?;
+
+    let mut frame = BytesMut::with_capacity(4 + head.len());
+    frame.put_u32(payload_len);
+    frame.put_slice(&head[..head.len() - 2]);
+    frame.put_u16(records);
+    sock.write_all(&frame).await?;
+
+    match fetch.region {
+        Some(region) if region.len > 0 => send_region(sock, &region).await,
+        _ => Ok(()),
+    }
+}
+
+async fn send_region(sock: &TcpStream, region: &FileRegion) -> io::Result<()> {
+    let out_fd = sock.as_raw_fd();
+    let in_fd = region.file.as_raw_fd();
+    let mut position = region.position as libc::off_t;
+    let mut remaining = region.len;
+
+    while remaining > 0 {
+        sock.writable().await?;
+        let res = sock.try_io(Interest::WRITABLE, || {
+            let count = remaining.min(MAX_CHUNK) as usize;
+            // SAFETY: both descriptors stay open for the duration of the call and
+            // `position` is a valid, exclusively borrowed offset.
+            let n = unsafe { libc::sendfile(out_fd, in_fd, &mut position, count) };
+            if n < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(n as u64)
+            }
+        });
+
+        match res {
+            // logs never shrink a file a region was taken from, but if something
+            // else did, the frame length can no longer be honoured: drop the
+            // connection rather than send a torn frame
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => remaining -= n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
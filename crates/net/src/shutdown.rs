@@ -0,0 +1,58 @@
+use std::{future::Future, time::Duration};
+
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Shared by listeners to stop accepting connections and to let open ones close
+/// once the request they are handling has been answered.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+        self.tracker.close();
+    }
+
+    /// Triggers the shutdown and waits up to `timeout` for every connection to
+    /// close. Returns false if some were still open.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.trigger();
+        tokio::time::timeout(timeout, self.tracker.wait())
+            .await
+            .is_ok()
+    }
+
+    /// The number of connections still open.
+    pub fn connections(&self) -> usize {
+        self.tracker.len()
+    }
+
+    pub(crate) fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub(crate) async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Sleeps for `duration`, or less if the shutdown is triggered meanwhile.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        if !duration.is_zero() {
+            let _ = tokio::time::timeout(duration, self.triggered()).await;
+        }
+    }
+
+    pub(crate) fn spawn<F>(&self, conn: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(conn);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use broker::Broker;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        RootCertStore, ServerConfig,
+        crypto::{CryptoProvider, ring},
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::WebPkiClientVerifier,
+    },
+};
+
+use tracing::Instrument;
+
+use crate::{
+    ConnContext, Connections, Credentials, Shutdown, connections::within, handle_conn, trace,
+};
+
+/// Certificate and key for the TLS listener, all PEM encoded.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// When set, clients must present a certificate signed by one of these CAs.
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| invalid_input(&self.cert_path, e))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| invalid_input(&self.key_path, e))?;
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+
+        let builder = match &self.client_ca_path {
+            None => builder.with_no_client_auth(),
+            Some(path) => builder.with_client_cert_verifier(client_verifier(path, provider)?),
+        };
+
+        let config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+fn client_verifier(
+    path: &Path,
+    provider: Arc<CryptoProvider>,
+) -> io::Result<Arc<dyn tokio_rustls::rustls::server::danger::ClientCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(path).map_err(|e| invalid_input(path, e))? {
+        roots
+            .add(cert.map_err(|e| invalid_input(path, e))?)
+            .map_err(|e| invalid_input(path, e))?;
+    }
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| invalid_input(path, e))
+}
+
+fn invalid_input(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}: {}", path.display(), e),
+    )
+}
+
+pub async fn serve_tls(
+    addr: &str,
+    broker: Arc<Broker>,
+    tls: &TlsConfig,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> io::Result<()> {
+    let acceptor = tls.acceptor()?;
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(%addr, "TLS listener started");
+    serve_tls_listener(listener, broker, acceptor, sasl, conns, shutdown).await
+}
+
+/// Serves the native protocol over TLS on an already bound listener.
+pub async fn serve_tls_listener(
+    listener: TcpListener,
+    broker: Arc<Broker>,
+    acceptor: TlsAcceptor,
+    sasl: Option<Arc<Credentials>>,
+    conns: Connections,
+    shutdown: Shutdown,
+) -> io::Result<()> {
+    loop {
+        let (sock, peer) = tokio::select! {
+            res = listener.accept() => res?,
+            _ = shutdown.triggered() => return Ok(()),
+        };
+        let Some(conn) = conns.open(peer) else {
+            tracing::info!(%peer, "rejected TLS connection: too many connections");
+            continue;
+        };
+        let span = trace::conn_span("tls", peer);
+        tracing::debug!(parent: &span, "accepted connection");
+
+        let b = broker.clone();
+        let acceptor = acceptor.clone();
+        let sasl = sasl.clone();
+        let conn_shutdown = shutdown.clone();
+
+        shutdown.spawn(
+            async move {
+                let handshake = within(conn.limits().request_timeout, acceptor.accept(sock));
+                let sock = match handshake.await {
+                    Some(Ok(s)) => s,
+                    Some(Err(e)) => {
+                        tracing::warn!(error = %e, "TLS handshake failed");
+                        return;
+                    }
+                    None => {
+                        conn.request_timed_out();
+                        tracing::warn!("TLS handshake timed out");
+                        return;
+                    }
+                };
+                let ctx = ConnContext::new(sasl, conn, conn_shutdown);
+                if let Err(e) = handle_conn(sock, b, ctx).await {
+                    tracing::warn!(error = %e, "connection closed on error");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
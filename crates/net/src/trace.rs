@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use tracing::{Span, field::Empty};
+
+/// A span for one connection, entered by everything logged while serving it.
+pub(crate) fn conn_span(listener: &'static str, peer: std::net::SocketAddr) -> Span {
+    tracing::info_span!("conn", listener, peer = %peer)
+}
+
+/// A span for one request. Its fields are filled in by `record_request` once the
+/// request has been decoded.
+pub(crate) fn request_span() -> Span {
+    tracing::info_span!("request", api = Empty, topic = Empty, partition = Empty)
+}
+
+pub(crate) fn record_request(api: &str, target: Option<(&str, i64)>) {
+    let span = Span::current();
+    span.record("api", api);
+    if let Some((topic, partition)) = target {
+        span.record("topic", topic);
+        span.record("partition", partition);
+    }
+}
+
+/// Time spent on one request, split into decoding it, having the broker handle
+/// it and encoding the response. Quota delays fall outside every phase.
+#[derive(Debug)]
+pub(crate) struct RequestTimer {
+    started: Instant,
+    lap: Instant,
+    decode: Duration,
+    storage: Duration,
+    encode: Duration,
+}
+
+impl RequestTimer {
+    pub(crate) fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            lap: now,
+            decode: Duration::ZERO,
+            storage: Duration::ZERO,
+            encode: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn started(&self) -> Instant {
+        self.started
+    }
+
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.lap;
+        self.lap = now;
+        elapsed
+    }
+
+    pub(crate) fn decoded(&mut self) {
+        let lap = self.lap();
+        self.decode += lap;
+    }
+
+    pub(crate) fn stored(&mut self) {
+        let lap = self.lap();
+        self.storage += lap;
+    }
+
+    pub(crate) fn encoded(&mut self) {
+        let lap = self.lap();
+        self.encode += lap;
+    }
+
+    /// Logs the request in the current span, as a warning if its phases took
+    /// `slow` or longer in total.
+    pub(crate) fn finish(&self, slow: Option<Duration>) {
+        let total = self.decode + self.storage + self.encode;
+        if slow.is_some_and(|slow| total >= slow) {
+            tracing::warn!(
+                total_ms = millis(total),
+                decode_ms = millis(self.decode),
+                storage_ms = millis(self.storage),
+                encode_ms = millis(self.encode),
+                "slow request"
+            );
+        } else {
+            tracing::debug!(total_ms = millis(total), "request done");
+        }
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,142 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use broker::Broker;
+use bytes::{BufMut, BytesMut};
+use net::{Connections, Limits, Shutdown};
+use protocol::{
+    decode_response, encode_request,
+    types::{MetadataRequest, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start(name: &str, limits: Limits) -> (SocketAddr, Connections) {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let conns = Connections::new(limits);
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        conns.clone(),
+        Shutdown::new(),
+    ));
+    (addr, conns)
+}
+
+async fn metadata(sock: &mut TcpStream) -> Response {
+    let req = Request::Metadata(MetadataRequest {
+        topics: vec![],
+        allow_auto_create: false,
+    });
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+/// Waits for the server to close `sock`, failing after a few seconds.
+async fn closed(sock: &mut TcpStream) {
+    let mut buf = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), sock.read(&mut buf))
+        .await
+        .expect("connection still open");
+    assert_eq!(read.unwrap_or(0), 0);
+}
+
+#[tokio::test]
+async fn per_ip_limit_rejects_extra_connections() {
+    let limits = Limits {
+        max_connections_per_ip: Some(1),
+        ..Limits::default()
+    };
+    let (addr, conns) = start("net-conn-per-ip", limits).await;
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    assert!(matches!(metadata(&mut first).await, Response::Metadata(_)));
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    closed(&mut second).await;
+    let stats = conns.stats();
+    assert_eq!(stats.open, 1);
+    assert_eq!(stats.accepted, 1);
+    assert_eq!(stats.rejected_per_ip, 1);
+
+    // the slot frees up once the first connection is gone
+    drop(first);
+    while conns.stats().open > 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let mut third = TcpStream::connect(addr).await.unwrap();
+    assert!(matches!(metadata(&mut third).await, Response::Metadata(_)));
+    assert_eq!(conns.stats().accepted, 2);
+}
+
+#[tokio::test]
+async fn total_limit_rejects_extra_connections() {
+    let limits = Limits {
+        max_connections: Some(2),
+        ..Limits::default()
+    };
+    let (addr, conns) = start("net-conn-total", limits).await;
+
+    let mut open = Vec::new();
+    for _ in 0..2 {
+        let mut sock = TcpStream::connect(addr).await.unwrap();
+        assert!(matches!(metadata(&mut sock).await, Response::Metadata(_)));
+        open.push(sock);
+    }
+    let mut extra = TcpStream::connect(addr).await.unwrap();
+    closed(&mut extra).await;
+    assert_eq!(conns.stats().rejected_total, 1);
+}
+
+#[tokio::test]
+async fn stalled_request_times_out() {
+    let limits = Limits {
+        request_timeout: Some(Duration::from_millis(100)),
+        ..Limits::default()
+    };
+    let (addr, conns) = start("net-conn-request-timeout", limits).await;
+
+    // half a length prefix, then nothing
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+    sock.write_all(&[0, 0]).await.unwrap();
+    closed(&mut sock).await;
+
+    let stats = conns.stats();
+    assert_eq!(stats.request_timeouts, 1);
+    assert_eq!(stats.idle_timeouts, 0);
+}
+
+#[tokio::test]
+async fn idle_connection_times_out() {
+    let limits = Limits {
+        idle_timeout: Some(Duration::from_millis(100)),
+        ..Limits::default()
+    };
+    let (addr, conns) = start("net-conn-idle-timeout", limits).await;
+
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+    assert!(matches!(metadata(&mut sock).await, Response::Metadata(_)));
+    closed(&mut sock).await;
+
+    let stats = conns.stats();
+    assert_eq!(stats.idle_timeouts, 1);
+    assert_eq!(stats.request_timeouts, 0);
+}
@@ -0,0 +1,130 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Limits, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{FetchRequest, ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start(name: &str) -> TcpStream {
+    start_with(name, Limits::default()).await
+}
+
+async fn start_with(name: &str, limits: Limits) -> TcpStream {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        Connections::new(limits),
+        Shutdown::new(),
+    ));
+    TcpStream::connect(addr).await.unwrap()
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+fn fetch(offset: i64, max_bytes: u32) -> Request {
+    Request::Fetch(FetchRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        offset,
+        max_bytes,
+    })
+}
+
+#[tokio::test]
+async fn fetch_returns_stored_records() {
+    let mut sock = start("net-fetch").await;
+
+    let records = (0..3)
+        .map(|i| Record {
+            key: Bytes::from(format!("k{i}")),
+            value: Bytes::from(format!("value-{i}")),
+        })
+        .collect();
+    let req = Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records,
+    });
+    assert!(matches!(round_trip(&mut sock, req).await, Response::Produce(r) if r.base_offset == 0));
+
+    match round_trip(&mut sock, fetch(1, 1024)).await {
+        Response::Fetch(r) => {
+            assert_eq!(r.status, status::OK);
+            assert_eq!(r.high_watermark, 3);
+            let offsets: Vec<_> = r.items.iter().map(|(off, _)| *off).collect();
+            assert_eq!(offsets, vec![1, 2]);
+            assert_eq!(&r.items[1].1.value[..], b"value-2");
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+
+    // the connection stays usable after a response with no records
+    match round_trip(&mut sock, fetch(5, 1024)).await {
+        Response::Fetch(r) => {
+            assert_eq!(r.status, status::OFFSET_OUT_OF_RANGE);
+            assert!(r.items.is_empty());
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+    match round_trip(&mut sock, fetch(3, 1024)).await {
+        Response::Fetch(r) => assert!(r.status == status::OK && r.items.is_empty()),
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn oversized_frame_closes_connection() {
+    let mut sock = start_with(
+        "net-frame-limit",
+        Limits {
+            max_frame_size: 64,
+            ..Limits::default()
+        },
+    )
+    .await;
+
+    let req = Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from(vec![0u8; 128]),
+        }],
+    });
+    let payload = encode_request(&req).unwrap();
+    sock.write_u32(payload.len() as u32).await.unwrap();
+    sock.write_all(&payload).await.unwrap();
+
+    let mut buf = [0u8; 1];
+    assert_eq!(sock.read(&mut buf).await.unwrap_or(0), 0);
+}
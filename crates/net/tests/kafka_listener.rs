@@ -0,0 +1,249 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::Broker;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use net::{Connections, Credentials, Shutdown};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+// Produce v3 of ("k1", "v1") and (null, "hello") to test-0, then Fetch v4 from offset 0.
+
+const PRODUCE_V3_REQUEST: [u8; 131] = [
+    0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x07, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66, 0x6b,
+    0x61, 0xff, 0xff, 0x00, 0x01, 0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x74,
+    0x65, 0x73, 0x74, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x54, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x0e, 0x20, 0x5d, 0x8c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5,
+    0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x02, 0x14, 0x00, 0x00, 0x00,
+    0x04, 0x6b, 0x31, 0x04, 0x76, 0x31, 0x00, 0x16, 0x00, 0x00, 0x02, 0x01, 0x0a, 0x68, 0x65, 0x6c,
+    0x6c, 0x6f, 0x00,
+];
+
+const FETCH_V4_REQUEST: [u8; 64] = [
+    0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x09, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66, 0x6b,
+    0x61, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x00, 0x01, 0x00, 0x10, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+];
+
+const API_VERSIONS_V3_REQUEST: [u8; 33] = [
+    0x00, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66, 0x6b,
+    0x61, 0x00, 0x08, 0x72, 0x64, 0x6b, 0x61, 0x66, 0x6b, 0x61, 0x06, 0x32, 0x2e, 0x33, 0x2e, 0x30,
+    0x00,
+];
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start(name: &str) -> TcpStream {
+    start_with(name, None).await
+}
+
+async fn start_with(name: &str, sasl: Option<Credentials>) -> TcpStream {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_kafka_listener(
+        listener,
+        broker,
+        sasl.map(Arc::new),
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    TcpStream::connect(addr).await.unwrap()
+}
+
+async fn round_trip(sock: &mut TcpStream, payload: &[u8]) -> Bytes {
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    resp.into()
+}
+
+/// A request with a v1 header, as clients send after SaslHandshake v1.
+fn request(api_key: i16, version: i16, correlation_id: i32, body: &[u8]) -> Vec<u8> {
+    let mut out = BytesMut::new();
+    out.put_i16(api_key);
+    out.put_i16(version);
+    out.put_i32(correlation_id);
+    out.put_i16(-1); // null client id
+    out.put_slice(body);
+    out.to_vec()
+}
+
+fn sasl_handshake(mechanism: &str) -> Vec<u8> {
+    let mut body = BytesMut::new();
+    body.put_i16(mechanism.len() as i16);
+    body.put_slice(mechanism.as_bytes());
+    request(17, 1, 2, &body)
+}
+
+fn sasl_authenticate(token: &[u8]) -> Vec<u8> {
+    let mut body = BytesMut::new();
+    body.put_i32(token.len() as i32);
+    body.put_slice(token);
+    request(36, 1, 3, &body)
+}
+
+#[tokio::test]
+async fn produce_then_fetch() {
+    let mut sock = start("produce-fetch").await;
+
+    let mut resp = round_trip(&mut sock, &PRODUCE_V3_REQUEST).await;
+    assert_eq!(resp.get_i32(), 7); // correlation id
+    assert_eq!(resp.get_i32(), 1); // topics
+    assert_eq!(resp.get_i16(), 4);
+    resp.advance(4); // "test"
+    assert_eq!(resp.get_i32(), 1); // partitions
+    assert_eq!(resp.get_i32(), 0); // partition index
+    assert_eq!(resp.get_i16(), 0); // error code
+    assert_eq!(resp.get_i64(), 0); // base offset
+
+    let mut resp = round_trip(&mut sock, &FETCH_V4_REQUEST).await;
+    assert_eq!(resp.get_i32(), 9); // correlation id
+    assert_eq!(resp.get_i32(), 0); // throttle
+    assert_eq!(resp.get_i32(), 1); // topics
+    resp.advance(2 + 4); // "test"
+    assert_eq!(resp.get_i32(), 1); // partitions
+    assert_eq!(resp.get_i32(), 0); // partition index
+    assert_eq!(resp.get_i16(), 0); // error code
+    assert_eq!(resp.get_i64(), 2); // high watermark
+    assert_eq!(resp.get_i64(), 2); // last stable offset
+    assert_eq!(resp.get_i32(), 0); // aborted transactions
+    let len = resp.get_i32() as usize;
+    let records = protocol::kafka::records::decode_record_batches(resp.split_to(len)).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(&records[0].key[..], b"k1");
+    assert_eq!(&records[1].value[..], b"hello");
+}
+
+#[tokio::test]
+async fn unsupported_api_versions_falls_back_to_v0() {
+    let mut sock = start("api-versions").await;
+
+    let mut resp = round_trip(&mut sock, &API_VERSIONS_V3_REQUEST).await;
+    assert_eq!(resp.get_i32(), 1); // correlation id
+    assert_eq!(resp.get_i16(), 35); // UNSUPPORTED_VERSION
+    let count = resp.get_i32();
+    assert_eq!(count as usize, protocol::kafka::SUPPORTED_APIS.len());
+}
+
+#[tokio::test]
+async fn unauthenticated_produce_is_rejected() {
+    let credentials = Credentials::parse("alice:alice-secret\n").unwrap();
+    let mut sock = start_with("kafka-sasl-unauthenticated", Some(credentials)).await;
+
+    let mut frame = BytesMut::new();
+    frame.put_u32(PRODUCE_V3_REQUEST.len() as u32);
+    frame.put_slice(&PRODUCE_V3_REQUEST);
+    sock.write_all(&frame).await.unwrap();
+    // the connection is closed without a response
+    assert!(sock.read_u32().await.is_err());
+}
+
+#[tokio::test]
+async fn kafka_clients_authenticate_with_sasl_plain() {
+    let credentials = Credentials::parse("alice:alice-secret\n").unwrap();
+    let mut sock = start_with("kafka-sasl-plain", Some(credentials)).await;
+
+    let mut resp = round_trip(&mut sock, &sasl_handshake("PLAIN")).await;
+    assert_eq!(resp.get_i32(), 2); // correlation id
+    assert_eq!(resp.get_i16(), 0); // error code
+
+    let mut resp = round_trip(&mut sock, &sasl_authenticate(b"\0alice\0wrong")).await;
+    assert_eq!(resp.get_i32(), 3); // correlation id
+    assert_eq!(resp.get_i16(), 58); // SASL_AUTHENTICATION_FAILED
+    assert!(sock.read_u32().await.is_err());
+
+    let credentials = Credentials::parse("alice:alice-secret\n").unwrap();
+    let mut sock = start_with("kafka-sasl-plain-ok", Some(credentials)).await;
+    round_trip(&mut sock, &sasl_handshake("PLAIN")).await;
+    let mut resp = round_trip(&mut sock, &sasl_authenticate(b"\0alice\0alice-secret")).await;
+    assert_eq!(resp.get_i32(), 3); // correlation id
+    assert_eq!(resp.get_i16(), 0); // error code
+
+    let mut resp = round_trip(&mut sock, &PRODUCE_V3_REQUEST).await;
+    assert_eq!(resp.get_i32(), 7); // correlation id
+    assert_eq!(resp.get_i32(), 1); // topics
+    resp.advance(2 + 4); // "test"
+    assert_eq!(resp.get_i32(), 1); // partitions
+    assert_eq!(resp.get_i32(), 0); // partition index
+    assert_eq!(resp.get_i16(), 0); // error code
+}
+
+#[tokio::test]
+async fn oversized_key_is_rejected() {
+    let mut sock = start("kafka-oversized-key").await;
+
+    let record = protocol::types::Record {
+        key: Bytes::from(vec![b'k'; u16::MAX as usize + 1]),
+        value: Bytes::from_static(b"v"),
+    };
+    let mut batch = BytesMut::new();
+    protocol::kafka::records::encode_record_batch(&mut batch, &[(0, record)]).unwrap();
+    let mut body = BytesMut::new();
+    body.put_i16(-1); // null transactional id
+    body.put_i16(1); // acks
+    body.put_i32(1500); // timeout
+    body.put_i32(1); // topics
+    body.put_i16(4);
+    body.put_slice(b"test");
+    body.put_i32(1); // partitions
+    body.put_i32(0); // partition index
+    body.put_i32(batch.len() as i32);
+    body.put_slice(&batch);
+
+    let mut resp = round_trip(&mut sock, &request(0, 3, 4, &body)).await;
+    assert_eq!(resp.get_i32(), 4); // correlation id
+    assert_eq!(resp.get_i32(), 1); // topics
+    resp.advance(2 + 4); // "test"
+    assert_eq!(resp.get_i32(), 1); // partitions
+    assert_eq!(resp.get_i32(), 0); // partition index
+    assert_eq!(resp.get_i16(), 18); // RECORD_TOO_LARGE
+    assert_eq!(resp.get_i64(), -1); // base offset
+}
+
+#[tokio::test]
+async fn fetch_returns_a_record_larger_than_partition_max_bytes() {
+    let mut sock = start("kafka-fetch-large-record").await;
+    round_trip(&mut sock, &PRODUCE_V3_REQUEST).await;
+
+    let mut body = BytesMut::new();
+    body.put_i32(-1); // replica id
+    body.put_i32(500); // max wait ms
+    body.put_i32(1); // min bytes
+    body.put_i32(1); // max bytes
+    body.put_i8(0); // isolation level
+    body.put_i32(1); // topics
+    body.put_i16(4);
+    body.put_slice(b"test");
+    body.put_i32(1); // partitions
+    body.put_i32(0); // partition index
+    body.put_i64(0); // fetch offset
+    body.put_i32(1); // partition max bytes
+
+    let mut resp = round_trip(&mut sock, &request(1, 4, 5, &body)).await;
+    assert_eq!(resp.get_i32(), 5); // correlation id
+    assert_eq!(resp.get_i32(), 0); // throttle
+    assert_eq!(resp.get_i32(), 1); // topics
+    resp.advance(2 + 4); // "test"
+    assert_eq!(resp.get_i32(), 1); // partitions
+    assert_eq!(resp.get_i32(), 0); // partition index
+    assert_eq!(resp.get_i16(), 0); // error code
+    resp.advance(8 + 8 + 4); // high watermark, last stable offset, aborted transactions
+    let len = resp.get_i32() as usize;
+    let records = protocol::kafka::records::decode_record_batches(resp.split_to(len)).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(&records[0].key[..], b"k1");
+}
@@ -0,0 +1,98 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+#[tokio::test]
+async fn requests_and_partitions_are_measured() {
+    let broker = Arc::new(Broker::new(temp_data_dir("net-metrics")));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+
+    for _ in 0..2 {
+        let req = Request::Produce(ProduceRequest {
+            topic: "metered".to_string(),
+            partition: 0,
+            acks: 1,
+            records: vec![Record {
+                key: Bytes::new(),
+                value: Bytes::from_static(b"0123456789"),
+            }],
+        });
+        assert!(matches!(
+            round_trip(&mut sock, req).await,
+            Response::Produce(r) if r.status == status::OK
+        ));
+    }
+
+    let labels = [("listener", "native"), ("api", "produce")];
+    assert_eq!(
+        metrics::counter("mini_kafka_requests_total", "", &labels).get(),
+        2
+    );
+    assert_eq!(
+        metrics::histogram("mini_kafka_request_duration_seconds", "", &labels).count(),
+        2
+    );
+    // each record takes 8 + 2 + 4 bytes besides its value
+    let topic = [("topic", "metered")];
+    assert_eq!(
+        metrics::counter("mini_kafka_topic_bytes_in_total", "", &topic).get(),
+        48
+    );
+    let partition = [("topic", "metered"), ("partition", "0")];
+    assert_eq!(
+        metrics::gauge("mini_kafka_log_end_offset", "", &partition).get(),
+        2
+    );
+    assert_eq!(
+        metrics::gauge("mini_kafka_log_size_bytes", "", &partition).get(),
+        48
+    );
+    assert_eq!(
+        metrics::gauge("mini_kafka_connections_active", "", &[]).get(),
+        1
+    );
+
+    let rendered = metrics::render();
+    assert!(
+        rendered.contains("mini_kafka_requests_total{listener=\"native\",api=\"produce\"} 2\n")
+    );
+    assert!(rendered.contains("# TYPE mini_kafka_fsync_duration_seconds histogram\n"));
+}
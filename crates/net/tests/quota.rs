@@ -0,0 +1,98 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use broker::{Broker, BrokerConfig, Quota, QuotaConfig};
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{FetchRequest, ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+#[tokio::test]
+async fn responses_over_quota_are_delayed() {
+    let quotas = QuotaConfig {
+        default: Quota {
+            produce_bytes_per_sec: Some(1000),
+            fetch_bytes_per_sec: Some(1000),
+            ..Quota::default()
+        },
+        ..QuotaConfig::default()
+    };
+    let broker = Arc::new(Broker::with_config(BrokerConfig {
+        quotas,
+        ..BrokerConfig::new(temp_data_dir("net-quota"))
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+
+    // 1200 bytes at 1000 bytes/s runs about 200ms past the one second burst
+    let produce = Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from(vec![0u8; 1200]),
+        }],
+    });
+    let start = Instant::now();
+    match round_trip(&mut sock, produce).await {
+        Response::Produce(r) => {
+            assert_eq!(r.status, status::OK);
+            assert!((150..=300).contains(&r.throttle_time_ms), "{r:?}");
+            assert!(start.elapsed() >= Duration::from_millis(r.throttle_time_ms as u64));
+        }
+        other => panic!("expected Produce response, got {other:?}"),
+    }
+
+    let fetch = Request::Fetch(FetchRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        offset: 0,
+        max_bytes: 1 << 20,
+    });
+    let start = Instant::now();
+    match round_trip(&mut sock, fetch).await {
+        Response::Fetch(r) => {
+            assert_eq!(r.items.len(), 1);
+            assert!((150..=300).contains(&r.throttle_time_ms), "{r:?}");
+            assert!(start.elapsed() >= Duration::from_millis(r.throttle_time_ms as u64));
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
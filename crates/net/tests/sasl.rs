@@ -0,0 +1,277 @@
+use std::{num::NonZeroU32, path::PathBuf, sync::Arc};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Credentials, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{
+        AclBinding, AclOperation, AclPermission, CreateAclsRequest, FetchRequest, PatternType,
+        ProduceRequest, Record, Request, ResourceType, Response, SASL_PLAIN, SASL_SCRAM_SHA_256,
+        SaslAuthenticateRequest, SaslAuthenticateResponse, SaslHandshakeRequest,
+    },
+};
+use ring::{digest, hmac, pbkdf2};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn start(name: &str) -> TcpStream {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let users = "# test users\nalice:alice-secret\nbob:b:o:b\n";
+    let credentials = Credentials::parse(users).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        Some(Arc::new(credentials)),
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    TcpStream::connect(addr).await.unwrap()
+}
+
+async fn send(sock: &mut TcpStream, req: Request) {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+}
+
+async fn recv(sock: &mut TcpStream) -> Option<Response> {
+    let len = sock.read_u32().await.ok()? as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    Some(decode_response(resp.into()).unwrap())
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    send(sock, req).await;
+    recv(sock).await.expect("connection closed")
+}
+
+async fn handshake(sock: &mut TcpStream, mechanism: &str) -> u8 {
+    let req = Request::SaslHandshake(SaslHandshakeRequest {
+        mechanism: mechanism.to_string(),
+    });
+    match round_trip(sock, req).await {
+        Response::SaslHandshake(r) => r.status,
+        other => panic!("expected SaslHandshake response, got {other:?}"),
+    }
+}
+
+async fn authenticate(sock: &mut TcpStream, token: &[u8]) -> SaslAuthenticateResponse {
+    let req = Request::SaslAuthenticate(SaslAuthenticateRequest {
+        auth_bytes: Bytes::copy_from_slice(token),
+    });
+    match round_trip(sock, req).await {
+        Response::SaslAuthenticate(r) => r,
+        other => panic!("expected SaslAuthenticate response, got {other:?}"),
+    }
+}
+
+fn produce() -> Request {
+    Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    })
+}
+
+#[tokio::test]
+async fn requests_require_authentication() {
+    let mut sock = start("sasl-required").await;
+
+    assert!(matches!(
+        round_trip(&mut sock, produce()).await,
+        Response::Error { .. }
+    ));
+    assert!(recv(&mut sock).await.is_none());
+}
+
+#[tokio::test]
+async fn plain_login() {
+    let mut sock = start("sasl-plain").await;
+
+    assert_eq!(
+        handshake(&mut sock, "GSSAPI").await,
+        status::UNSUPPORTED_SASL_MECHANISM
+    );
+    assert_eq!(handshake(&mut sock, SASL_PLAIN).await, status::OK);
+    // passwords may contain ':'
+    let resp = authenticate(&mut sock, b"\0bob\0b:o:b").await;
+    assert_eq!(resp.status, status::OK);
+
+    assert!(matches!(
+        round_trip(&mut sock, produce()).await,
+        Response::Produce(r) if r.status == status::OK
+    ));
+    assert_eq!(
+        handshake(&mut sock, SASL_PLAIN).await,
+        status::ILLEGAL_SASL_STATE
+    );
+}
+
+#[tokio::test]
+async fn wrong_password_closes_connection() {
+    let mut sock = start("sasl-wrong").await;
+
+    assert_eq!(handshake(&mut sock, SASL_PLAIN).await, status::OK);
+    let resp = authenticate(&mut sock, b"\0alice\0guess").await;
+    assert_eq!(resp.status, status::SASL_AUTHENTICATION_FAILED);
+    assert!(recv(&mut sock).await.is_none());
+}
+
+/// Client side of SCRAM-SHA-256, checking the server signature on success.
+async fn scram_login(sock: &mut TcpStream, user: &str, password: &str) -> SaslAuthenticateResponse {
+    scram_login_with_binding(sock, user, password, "biws").await
+}
+
+/// Like `scram_login`, but sends `channel_binding` as the client-final `c=`.
+async fn scram_login_with_binding(
+    sock: &mut TcpStream,
+    user: &str,
+    password: &str,
+    channel_binding: &str,
+) -> SaslAuthenticateResponse {
+    assert_eq!(handshake(sock, SASL_SCRAM_SHA_256).await, status::OK);
+
+    let client_first_bare = format!("n={user},r=fyko+d2lbbFgONRv9qkxdawL");
+    let resp = authenticate(sock, format!("n,,{client_first_bare}").as_bytes()).await;
+    assert_eq!(resp.status, status::OK);
+    let server_first = String::from_utf8(resp.auth_bytes.to_vec()).unwrap();
+
+    let mut nonce = "";
+    let mut salt = vec![];
+    let mut iterations = 0;
+    for attr in server_first.split(',') {
+        match attr.split_at(2) {
+            ("r=", v) => nonce = v,
+            ("s=", v) => salt = STANDARD.decode(v).unwrap(),
+            ("i=", v) => iterations = v.parse().unwrap(),
+            _ => panic!("unexpected attribute in {server_first}"),
+        }
+    }
+    assert!(nonce.starts_with("fyko+d2lbbFgONRv9qkxdawL"));
+
+    let mut salted = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(iterations).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut salted,
+    );
+    let salted = hmac::Key::new(hmac::HMAC_SHA256, &salted);
+    let client_key = hmac::sign(&salted, b"Client Key");
+    let stored_key = digest::digest(&digest::SHA256, client_key.as_ref());
+
+    let without_proof = format!("c={channel_binding},r={nonce}");
+    let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+    let signature = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, stored_key.as_ref()),
+        auth_message.as_bytes(),
+    );
+    let proof: Vec<u8> = client_key
+        .as_ref()
+        .iter()
+        .zip(signature.as_ref())
+        .map(|(a, b)| a ^ b)
+        .collect();
+
+    let resp = authenticate(
+        sock,
+        format!("{without_proof},p={}", STANDARD.encode(proof)).as_bytes(),
+    )
+    .await;
+    if resp.status == status::OK {
+        let server_key = hmac::sign(&salted, b"Server Key");
+        let expected = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, server_key.as_ref()),
+            auth_message.as_bytes(),
+        );
+        let server_final = format!("v={}", STANDARD.encode(expected));
+        assert_eq!(&resp.auth_bytes[..], server_final.as_bytes());
+    }
+    resp
+}
+
+#[tokio::test]
+async fn scram_sha_256_login() {
+    let mut sock = start("sasl-scram").await;
+
+    let resp = scram_login(&mut sock, "alice", "alice-secret").await;
+    assert_eq!(resp.status, status::OK);
+    assert!(matches!(
+        round_trip(&mut sock, produce()).await,
+        Response::Produce(r) if r.status == status::OK
+    ));
+}
+
+#[tokio::test]
+async fn scram_rejects_unknown_user_at_proof() {
+    let mut sock = start("sasl-scram-unknown").await;
+
+    let resp = scram_login(&mut sock, "mallory", "alice-secret").await;
+    assert_eq!(resp.status, status::SASL_AUTHENTICATION_FAILED);
+    assert!(recv(&mut sock).await.is_none());
+}
+
+#[tokio::test]
+async fn scram_rejects_mismatched_channel_binding() {
+    let mut sock = start("sasl-scram-binding").await;
+
+    // "eSws" is the "y,," header, but the client-first message started "n,,"
+    let resp = scram_login_with_binding(&mut sock, "alice", "alice-secret", "eSws").await;
+    assert_eq!(resp.status, status::SASL_AUTHENTICATION_FAILED);
+    assert!(recv(&mut sock).await.is_none());
+}
+
+#[tokio::test]
+async fn acls_apply_to_the_authenticated_user() {
+    let mut sock = start("sasl-acl").await;
+
+    assert_eq!(handshake(&mut sock, SASL_PLAIN).await, status::OK);
+    let resp = authenticate(&mut sock, b"\0bob\0b:o:b").await;
+    assert_eq!(resp.status, status::OK);
+
+    let req = Request::CreateAcls(CreateAclsRequest {
+        acls: vec![AclBinding {
+            principal: "User:alice".to_string(),
+            resource_type: ResourceType::Topic,
+            resource_name: "t".to_string(),
+            pattern_type: PatternType::Literal,
+            operation: AclOperation::Read,
+            permission: AclPermission::Allow,
+        }],
+    });
+    assert!(matches!(
+        round_trip(&mut sock, req).await,
+        Response::CreateAcls(r) if r.status == status::OK
+    ));
+
+    let fetch = Request::Fetch(FetchRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        offset: 0,
+        max_bytes: 1024,
+    });
+    assert!(matches!(
+        round_trip(&mut sock, fetch).await,
+        Response::Fetch(r) if r.status == status::TOPIC_AUTHORIZATION_FAILED
+    ));
+}
@@ -0,0 +1,75 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+#[tokio::test]
+async fn drain_closes_idle_connections_and_stops_listener() {
+    let dir = temp_data_dir("net-shutdown");
+    let broker = Arc::new(Broker::new(dir.clone()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = Shutdown::new();
+    let server = tokio::spawn(net::serve_listener(
+        listener,
+        broker.clone(),
+        None,
+        Connections::default(),
+        shutdown.clone(),
+    ));
+
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+    let req = Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+    assert!(matches!(
+        round_trip(&mut sock, req).await,
+        Response::Produce(r) if r.status == status::OK
+    ));
+    assert_eq!(shutdown.connections(), 1);
+
+    assert!(shutdown.drain(Duration::from_secs(5)).await);
+    assert_eq!(shutdown.connections(), 0);
+    server.await.unwrap().unwrap();
+
+    let mut buf = [0u8; 1];
+    assert_eq!(sock.read(&mut buf).await.unwrap(), 0);
+    assert!(TcpStream::connect(addr).await.is_err());
+
+    broker.shutdown().await.unwrap();
+    assert!(dir.join(broker::CLEAN_SHUTDOWN_FILE).exists());
+}
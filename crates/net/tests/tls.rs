@@ -0,0 +1,201 @@
+use std::{path::PathBuf, sync::Arc};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Shutdown, TlsConfig};
+use protocol::{
+    decode_response, encode_request,
+    types::{FetchRequest, ProduceRequest, Record, Request, Response},
+};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        ClientConfig, RootCertStore,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    },
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A throwaway CA plus server and client certificates signed by it.
+struct Pki {
+    dir: PathBuf,
+    ca: String,
+    client_cert: String,
+    client_key: String,
+}
+
+impl Pki {
+    fn generate(dir: PathBuf) -> Self {
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &ca)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client_cert = client_params.signed_by(&client_key, &ca).unwrap();
+
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        std::fs::write(dir.join("server.pem"), server_cert.pem()).unwrap();
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+
+        Self {
+            dir,
+            ca: ca.pem(),
+            client_cert: client_cert.pem(),
+            client_key: client_key.serialize_pem(),
+        }
+    }
+
+    fn server_config(&self, mutual: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: self.dir.join("server.pem"),
+            key_path: self.dir.join("server.key"),
+            client_ca_path: mutual.then(|| self.dir.join("ca.pem")),
+        }
+    }
+
+    fn connector(&self, with_client_cert: bool) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(self.ca.as_bytes()).unwrap())
+            .unwrap();
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = if with_client_cert {
+            let cert = CertificateDer::from_pem_slice(self.client_cert.as_bytes()).unwrap();
+            let key = PrivateKeyDer::from_pem_slice(self.client_key.as_bytes()).unwrap();
+            builder.with_client_auth_cert(vec![cert], key).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+}
+
+async fn start(name: &str, pki: &Pki, mutual: bool) -> std::net::SocketAddr {
+    let broker = Arc::new(Broker::new(temp_data_dir(name)));
+    let acceptor = pki.server_config(mutual).acceptor().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_tls_listener(
+        listener,
+        broker,
+        acceptor,
+        None,
+        Connections::default(),
+        Shutdown::new(),
+    ));
+    addr
+}
+
+async fn round_trip(
+    sock: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    req: Request,
+) -> std::io::Result<Response> {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await?;
+    sock.flush().await?;
+
+    let len = sock.read_u32().await? as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await?;
+    Ok(decode_response(resp.into()).unwrap())
+}
+
+fn produce() -> Request {
+    Request::Produce(ProduceRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::from_static(b"k"),
+            value: Bytes::from_static(b"secret"),
+        }],
+    })
+}
+
+#[tokio::test]
+async fn produce_then_fetch_over_tls() {
+    let pki = Pki::generate(temp_data_dir("tls-pki"));
+    let addr = start("tls", &pki, false).await;
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let mut sock = pki
+        .connector(false)
+        .connect(server_name, tcp)
+        .await
+        .unwrap();
+
+    let resp = round_trip(&mut sock, produce()).await.unwrap();
+    assert!(matches!(resp, Response::Produce(r) if r.base_offset == 0));
+
+    let req = Request::Fetch(FetchRequest {
+        topic: "t".to_string(),
+        partition: 0,
+        offset: 0,
+        max_bytes: 1024,
+    });
+    match round_trip(&mut sock, req).await.unwrap() {
+        Response::Fetch(r) => {
+            assert_eq!(r.items.len(), 1);
+            assert_eq!(&r.items[0].1.value[..], b"secret");
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_client_certificate() {
+    let pki = Pki::generate(temp_data_dir("mtls-pki"));
+    let addr = start("mtls", &pki, true).await;
+    let server_name = ServerName::try_from("localhost").unwrap();
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let mut sock = pki
+        .connector(true)
+        .connect(server_name.clone(), tcp)
+        .await
+        .unwrap();
+    assert!(matches!(
+        round_trip(&mut sock, produce()).await.unwrap(),
+        Response::Produce(_)
+    ));
+
+    // with TLS 1.3 the client may finish its side of the handshake before the
+    // server rejects it, so the failure can surface on the first exchange
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let rejected = match pki.connector(false).connect(server_name, tcp).await {
+        Err(_) => true,
+        Ok(mut sock) => round_trip(&mut sock, produce()).await.is_err(),
+    };
+    assert!(rejected);
+}
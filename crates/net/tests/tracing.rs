@@ -0,0 +1,117 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use broker::Broker;
+use bytes::{BufMut, Bytes, BytesMut};
+use net::{Connections, Limits, Shutdown};
+use protocol::{
+    decode_response, encode_request, status,
+    types::{ProduceRequest, Record, Request, Response},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn round_trip(sock: &mut TcpStream, req: Request) -> Response {
+    let payload = encode_request(&req).unwrap();
+    let mut frame = BytesMut::with_capacity(4 + payload.len());
+    frame.put_u32(payload.len() as u32);
+    frame.put_slice(&payload);
+    sock.write_all(&frame).await.unwrap();
+
+    let len = sock.read_u32().await.unwrap() as usize;
+    let mut resp = vec![0u8; len];
+    sock.read_exact(&mut resp).await.unwrap();
+    decode_response(resp.into()).unwrap()
+}
+
+/// Collects everything logged, one JSON object per line.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn slow_requests_are_logged_with_their_span_and_timings() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_span_list(true)
+        .with_writer(move || writer.clone())
+        .finish();
+    // the runtime of the test is single threaded, so the server logs here too
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // every request counts as slow
+    let conns = Connections::new(Limits {
+        slow_request: Some(Duration::ZERO),
+        ..Limits::default()
+    });
+    let broker = Arc::new(Broker::new(temp_data_dir("net-tracing")));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(net::serve_listener(
+        listener,
+        broker,
+        None,
+        conns,
+        Shutdown::new(),
+    ));
+    let mut sock = TcpStream::connect(addr).await.unwrap();
+
+    let req = Request::Produce(ProduceRequest {
+        topic: "traced".to_string(),
+        partition: 3,
+        acks: 1,
+        records: vec![Record {
+            key: Bytes::new(),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+    assert!(matches!(
+        round_trip(&mut sock, req).await,
+        Response::Produce(r) if r.status == status::OK
+    ));
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let slow = logs
+        .lines()
+        .find(|line| line.contains("slow request"))
+        .unwrap_or_else(|| panic!("no slow request logged in:\n{logs}"));
+    assert!(slow.contains(r#""level":"WARN""#), "{slow}");
+    for field in ["total_ms", "decode_ms", "storage_ms", "encode_ms"] {
+        assert!(slow.contains(&format!(r#""{field}":"#)), "{slow}");
+    }
+    let conn = format!(r#""peer":"{}""#, sock.local_addr().unwrap());
+    assert!(
+        slow.contains(r#""listener":"native""#) && slow.contains(&conn),
+        "{slow}"
+    );
+    assert!(
+        slow.contains(r#""api":"produce""#)
+            && slow.contains(r#""topic":"traced""#)
+            && slow.contains(r#""partition":3"#),
+        "{slow}"
+    );
+}
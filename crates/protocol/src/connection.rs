@@ -0,0 +1,204 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    decode_response, encode_request,
+    error::ConnectionError,
+    status,
+    types::{Request, Response, SASL_PLAIN, SaslAuthenticateRequest, SaslHandshakeRequest},
+};
+
+type Reply = oneshot::Sender<Result<Response, ConnectionError>>;
+
+/// A connection to a broker speaking the native protocol. Requests are
+/// pipelined: more can be sent before the first is answered, and the broker
+/// answers them in order.
+#[derive(Debug)]
+pub struct Connection {
+    requests: mpsc::UnboundedSender<(Bytes, Reply)>,
+}
+
+impl Connection {
+    pub async fn connect(addr: &str) -> Result<Self, ConnectionError> {
+        let sock = TcpStream::connect(addr).await?;
+        sock.set_nodelay(true)?;
+        let (requests, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(sock, rx));
+        Ok(Self { requests })
+    }
+
+    pub async fn request(&self, req: &Request) -> Result<Response, ConnectionError> {
+        let payload = encode_request(req)?;
+        let (reply, response) = oneshot::channel();
+        self.requests.send((payload, reply)).map_err(|_| closed())?;
+        response.await.map_err(|_| closed())?
+    }
+
+    /// Whether the connection broke; it then fails every request.
+    pub fn is_closed(&self) -> bool {
+        self.requests.is_closed()
+    }
+
+    /// Logs in with SASL PLAIN, which listeners requiring SASL want before
+    /// any other request.
+    pub async fn login(&self, login: &Login) -> Result<(), ConnectionError> {
+        let req = Request::SaslHandshake(SaslHandshakeRequest {
+            mechanism: SASL_PLAIN.to_string(),
+        });
+        match self.request(&req).await? {
+            Response::SaslHandshake(r) if r.status == status::OK => {}
+            Response::SaslHandshake(r) => {
+                return Err(ConnectionError::Authentication(
+                    status::name(r.status).to_string(),
+                ));
+            }
+            other => return Err(unexpected(&other)),
+        }
+        let token = format!("\0{}\0{}", login.username, login.password);
+        let req = Request::SaslAuthenticate(SaslAuthenticateRequest {
+            auth_bytes: Bytes::from(token),
+        });
+        match self.request(&req).await? {
+            Response::SaslAuthenticate(r) if r.status == status::OK => Ok(()),
+            Response::SaslAuthenticate(r) => Err(ConnectionError::Authentication(r.error_message)),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+fn unexpected(resp: &Response) -> ConnectionError {
+    ConnectionError::Authentication(format!("unexpected response {resp:?}"))
+}
+
+/// A user to log in as on listeners that require SASL.
+#[derive(Clone)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for Login {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Login")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+fn closed() -> ConnectionError {
+    std::io::Error::from(std::io::ErrorKind::NotConnected).into()
+}
+
+/// Writes requests and reads responses until the socket fails or the
+/// `Connection` is dropped, then fails the requests still waiting.
+async fn run(sock: TcpStream, requests: mpsc::UnboundedReceiver<(Bytes, Reply)>) {
+    let (reader, writer) = sock.into_split();
+    let pending = Arc::new(Mutex::new(VecDeque::new()));
+    let err = tokio::select! {
+        res = write_requests(writer, requests, pending.clone()) => match res {
+            Some(e) => e,
+            None => return,
+        },
+        e = read_responses(reader, pending.clone()) => e,
+    };
+    let err = ConnectionError::from(err);
+    for reply in pending.lock().unwrap().drain(..) {
+        let _ = reply.send(Err(err.clone()));
+    }
+}
+
+/// Returns `None` once the `Connection` is dropped.
+async fn write_requests(
+    mut writer: OwnedWriteHalf,
+    mut requests: mpsc::UnboundedReceiver<(Bytes, Reply)>,
+    pending: Arc<Mutex<VecDeque<Reply>>>,
+) -> Option<std::io::Error> {
+    while let Some((payload, reply)) = requests.recv().await {
+        // queued first, so the response never arrives before its reply
+        pending.lock().unwrap().push_back(reply);
+        let mut frame = BytesMut::with_capacity(4 + payload.len());
+        frame.put_u32(payload.len() as u32);
+        frame.put_slice(&payload);
+        if let Err(e) = writer.write_all(&frame).await {
+            return Some(e);
+        }
+    }
+    None
+}
+
+async fn read_responses(
+    mut reader: OwnedReadHalf,
+    pending: Arc<Mutex<VecDeque<Reply>>>,
+) -> std::io::Error {
+    loop {
+        let mut len_buf = [0u8; 4];
+        if let Err(e) = reader.read_exact(&mut len_buf).await {
+            return e;
+        }
+        let len = (&len_buf[..]).get_u32() as usize;
+        let mut payload = vec![0u8; len];
+        if let Err(e) = reader.read_exact(&mut payload).await {
+            return e;
+        }
+
+        let Some(reply) = pending.lock().unwrap().pop_front() else {
+            return std::io::Error::new(std::io::ErrorKind::InvalidData, "response to no request");
+        };
+        let _ = reply.send(decode_response(payload.into()).map_err(ConnectionError::from));
+    }
+}
+
+/// A connection to one broker for the requests brokers and controllers send
+/// each other, opened on first use and again after it breaks. With `login`
+/// set, every new connection logs in first.
+#[derive(Debug)]
+pub struct Peer {
+    addr: String,
+    login: Option<Login>,
+    conn: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+impl Peer {
+    pub fn new(addr: String, login: Option<Login>) -> Self {
+        Self {
+            addr,
+            login,
+            conn: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub async fn request(&self, req: &Request) -> Result<Response, ConnectionError> {
+        self.connection().await?.request(req).await
+    }
+
+    async fn connection(&self) -> Result<Arc<Connection>, ConnectionError> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = &*conn
+            && !c.is_closed()
+        {
+            return Ok(c.clone());
+        }
+        let c = Arc::new(Connection::connect(&self.addr).await?);
+        if let Some(login) = &self.login {
+            c.login(login).await?;
+        }
+        *conn = Some(c.clone());
+        Ok(c)
+    }
+}
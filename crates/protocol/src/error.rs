@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -6,4 +8,36 @@ pub enum ProtoError {
     InvalidApiKey(u8),
     #[error("io: {0}")]
     Io(#[from] common::error::IoError),
+    #[error("unsupported version {version} for api key {api_key}")]
+    UnsupportedVersion { api_key: i16, version: i16 },
+    #[error("unexpected null")]
+    UnexpectedNull,
+    #[error("corrupt record batch: {0}")]
+    CorruptRecordBatch(&'static str),
+    #[error("unsupported compression type: {0}")]
+    UnsupportedCompression(i16),
+}
+
+/// Why a request on a `Connection` failed. It is cloned to every request
+/// waiting when the connection breaks, so the sources are shared.
+#[derive(Debug, Clone, Error)]
+pub enum ConnectionError {
+    #[error("io: {0}")]
+    Io(Arc<std::io::Error>),
+    #[error("protocol: {0}")]
+    Protocol(Arc<ProtoError>),
+    #[error("authentication failed: {0}")]
+    Authentication(String),
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(e: std::io::Error) -> Self {
+        ConnectionError::Io(Arc::new(e))
+    }
+}
+
+impl From<ProtoError> for ConnectionError {
+    fn from(e: ProtoError) -> Self {
+        ConnectionError::Protocol(Arc::new(e))
+    }
 }
@@ -0,0 +1,43 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{error::ProtoError, kafka::codec::write_array};
+
+/// ApiVersions v0-v2 has an empty request body.
+#[derive(Debug, Default)]
+pub struct ApiVersionsRequest {}
+
+impl ApiVersionsRequest {
+    pub fn decode(_buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        Ok(Self {})
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersion {
+    pub api_key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+#[derive(Debug)]
+pub struct ApiVersionsResponse {
+    pub error_code: i16,
+    pub api_keys: Vec<ApiVersion>,
+    pub throttle_time_ms: i32,
+}
+
+impl ApiVersionsResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        out.put_i16(self.error_code);
+        write_array(out, &self.api_keys, |out, k| {
+            out.put_i16(k.api_key);
+            out.put_i16(k.min_version);
+            out.put_i16(k.max_version);
+            Ok(())
+        })?;
+        if version >= 1 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{IoError, ensure_remaining};
+
+use crate::error::ProtoError;
+
+// ---------- read ----------
+pub fn read_i8(buf: &mut Bytes) -> Result<i8, IoError> {
+    ensure_remaining(buf, 1)?;
+    Ok(buf.get_i8())
+}
+
+pub fn read_i16(buf: &mut Bytes) -> Result<i16, IoError> {
+    ensure_remaining(buf, 2)?;
+    Ok(buf.get_i16())
+}
+
+pub fn read_i32(buf: &mut Bytes) -> Result<i32, IoError> {
+    ensure_remaining(buf, 4)?;
+    Ok(buf.get_i32())
+}
+
+pub fn read_i64(buf: &mut Bytes) -> Result<i64, IoError> {
+    ensure_remaining(buf, 8)?;
+    Ok(buf.get_i64())
+}
+
+pub fn read_bool(buf: &mut Bytes) -> Result<bool, IoError> {
+    Ok(read_i8(buf)? != 0)
+}
+
+pub fn read_string(buf: &mut Bytes) -> Result<String, ProtoError> {
+    read_nullable_string(buf)?.ok_or(ProtoError::UnexpectedNull)
+}
+
+pub fn read_nullable_string(buf: &mut Bytes) -> Result<Option<String>, ProtoError> {
+    let len = read_i16(buf)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let len = len as usize;
+    ensure_remaining(buf, len)?;
+    let raw = buf.split_to(len);
+    let s = std::str::from_utf8(&raw).map_err(|_| IoError::InvalidUtf8)?;
+    Ok(Some(s.to_string()))
+}
+
+pub fn read_bytes(buf: &mut Bytes) -> Result<Bytes, ProtoError> {
+    read_nullable_bytes(buf)?.ok_or(ProtoError::UnexpectedNull)
+}
+
+pub fn read_nullable_bytes(buf: &mut Bytes) -> Result<Option<Bytes>, ProtoError> {
+    let len = read_i32(buf)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let len = len as usize;
+    ensure_remaining(buf, len)?;
+    Ok(Some(buf.split_to(len)))
+}
+
+pub fn read_array<T>(
+    buf: &mut Bytes,
+    f: impl FnMut(&mut Bytes) -> Result<T, ProtoError>,
+) -> Result<Vec<T>, ProtoError> {
+    read_nullable_array(buf, f).map(Option::unwrap_or_default)
+}
+
+pub fn read_nullable_array<T>(
+    buf: &mut Bytes,
+    mut f: impl FnMut(&mut Bytes) -> Result<T, ProtoError>,
+) -> Result<Option<Vec<T>>, ProtoError> {
+    let len = read_i32(buf)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    // every element takes at least one byte, which bounds the allocation
+    let len = len as usize;
+    ensure_remaining(buf, len)?;
+    let mut items = Vec::with_capacity(len);
+    for _ in 0..len {
+        items.push(f(buf)?);
+    }
+    Ok(Some(items))
+}
+
+// ---------- write ----------
+pub fn write_bool(out: &mut BytesMut, v: bool) {
+    out.put_i8(v as i8);
+}
+
+pub fn write_string(out: &mut BytesMut, s: &str) -> Result<(), ProtoError> {
+    write_nullable_string(out, Some(s))
+}
+
+pub fn write_nullable_string(out: &mut BytesMut, s: Option<&str>) -> Result<(), ProtoError> {
+    match s {
+        None => out.put_i16(-1),
+        Some(s) => {
+            let len = i16::try_from(s.len()).map_err(|_| IoError::StringTooLong)?;
+            out.put_i16(len);
+            out.put_slice(s.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+pub fn write_bytes(out: &mut BytesMut, b: &[u8]) -> Result<(), ProtoError> {
+    write_nullable_bytes(out, Some(b))
+}
+
+pub fn write_nullable_bytes(out: &mut BytesMut, b: Option<&[u8]>) -> Result<(), ProtoError> {
+    match b {
+        None => out.put_i32(-1),
+        Some(b) => {
+            let len = i32::try_from(b.len()).map_err(|_| IoError::BytesTooLong)?;
+            out.put_i32(len);
+            out.put_slice(b);
+        }
+    }
+    Ok(())
+}
+
+pub fn write_array<T>(
+    out: &mut BytesMut,
+    items: &[T],
+    mut f: impl FnMut(&mut BytesMut, &T) -> Result<(), ProtoError>,
+) -> Result<(), ProtoError> {
+    write_array_len(out, items.len())?;
+    for item in items {
+        f(out, item)?;
+    }
+    Ok(())
+}
+
+pub fn write_i32_array(out: &mut BytesMut, items: &[i32]) -> Result<(), ProtoError> {
+    write_array_len(out, items.len())?;
+    for &item in items {
+        out.put_i32(item);
+    }
+    Ok(())
+}
+
+fn write_array_len(out: &mut BytesMut, len: usize) -> Result<(), ProtoError> {
+    let len = i32::try_from(len).map_err(|_| IoError::ArrayTooLong)?;
+    out.put_i32(len);
+    Ok(())
+}
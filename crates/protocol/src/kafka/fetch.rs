@@ -0,0 +1,137 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_array, read_i8, read_i32, read_i64, read_string, write_array, write_nullable_bytes,
+        write_string,
+    },
+};
+
+#[derive(Debug)]
+pub struct FetchPartition {
+    pub partition: i32,
+    pub current_leader_epoch: i32,
+    pub fetch_offset: i64,
+    pub log_start_offset: i64,
+    pub partition_max_bytes: i32,
+}
+
+#[derive(Debug)]
+pub struct FetchTopic {
+    pub topic: String,
+    pub partitions: Vec<FetchPartition>,
+}
+
+#[derive(Debug)]
+pub struct FetchRequest {
+    pub replica_id: i32,
+    pub max_wait_ms: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    pub isolation_level: i8,
+    pub session_id: i32,
+    pub session_epoch: i32,
+    pub topics: Vec<FetchTopic>,
+}
+
+impl FetchRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let replica_id = read_i32(buf)?;
+        let max_wait_ms = read_i32(buf)?;
+        let min_bytes = read_i32(buf)?;
+        let max_bytes = read_i32(buf)?;
+        let isolation_level = read_i8(buf)?;
+        let (session_id, session_epoch) = if version >= 7 {
+            (read_i32(buf)?, read_i32(buf)?)
+        } else {
+            (0, -1)
+        };
+        let topics = read_array(buf, |b| {
+            Ok(FetchTopic {
+                topic: read_string(b)?,
+                partitions: read_array(b, |b| {
+                    let partition = read_i32(b)?;
+                    let current_leader_epoch = if version >= 9 { read_i32(b)? } else { -1 };
+                    let fetch_offset = read_i64(b)?;
+                    let log_start_offset = if version >= 5 { read_i64(b)? } else { -1 };
+                    let partition_max_bytes = read_i32(b)?;
+                    Ok(FetchPartition {
+                        partition,
+                        current_leader_epoch,
+                        fetch_offset,
+                        log_start_offset,
+                        partition_max_bytes,
+                    })
+                })?,
+            })
+        })?;
+        // v7+ forgotten_topics_data only matters to fetch sessions, which are not supported
+        if version >= 7 {
+            read_array(buf, |b| {
+                read_string(b)?;
+                read_array(b, |b| Ok(read_i32(b)?))
+            })?;
+        }
+        Ok(Self {
+            replica_id,
+            max_wait_ms,
+            min_bytes,
+            max_bytes,
+            isolation_level,
+            session_id,
+            session_epoch,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    /// Raw RecordBatch bytes, see `kafka::records`.
+    pub records: Option<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct FetchTopicResponse {
+    pub topic: String,
+    pub partitions: Vec<FetchPartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct FetchResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub session_id: i32,
+    pub responses: Vec<FetchTopicResponse>,
+}
+
+impl FetchResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        out.put_i32(self.throttle_time_ms);
+        if version >= 7 {
+            out.put_i16(self.error_code);
+            out.put_i32(self.session_id);
+        }
+        write_array(out, &self.responses, |out, t| {
+            write_string(out, &t.topic)?;
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i32(p.partition_index);
+                out.put_i16(p.error_code);
+                out.put_i64(p.high_watermark);
+                out.put_i64(p.last_stable_offset);
+                if version >= 5 {
+                    out.put_i64(p.log_start_offset);
+                }
+                // aborted_transactions: no transactions, so always empty
+                out.put_i32(0);
+                write_nullable_bytes(out, p.records.as_deref())
+            })
+        })
+    }
+}
@@ -0,0 +1,258 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_array, read_bytes, read_i8, read_i32, read_nullable_string, read_string, write_array,
+        write_bytes, write_nullable_string, write_string,
+    },
+};
+
+#[derive(Debug)]
+pub struct FindCoordinatorRequest {
+    pub key: String,
+    pub key_type: i8,
+}
+
+impl FindCoordinatorRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let key = read_string(buf)?;
+        let key_type = if version >= 1 { read_i8(buf)? } else { 0 };
+        Ok(Self { key, key_type })
+    }
+}
+
+#[derive(Debug)]
+pub struct FindCoordinatorResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+}
+
+impl FindCoordinatorResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 1 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        out.put_i16(self.error_code);
+        if version >= 1 {
+            write_nullable_string(out, self.error_message.as_deref())?;
+        }
+        out.put_i32(self.node_id);
+        write_string(out, &self.host)?;
+        out.put_i32(self.port);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct JoinGroupProtocol {
+    pub name: String,
+    pub metadata: Bytes,
+}
+
+#[derive(Debug)]
+pub struct JoinGroupRequest {
+    pub group_id: String,
+    pub session_timeout_ms: i32,
+    pub rebalance_timeout_ms: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub protocol_type: String,
+    pub protocols: Vec<JoinGroupProtocol>,
+}
+
+impl JoinGroupRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let session_timeout_ms = read_i32(buf)?;
+        // v0 has no rebalance timeout; the session timeout doubles as one
+        let rebalance_timeout_ms = if version >= 1 {
+            read_i32(buf)?
+        } else {
+            session_timeout_ms
+        };
+        let member_id = read_string(buf)?;
+        let group_instance_id = if version >= 5 {
+            read_nullable_string(buf)?
+        } else {
+            None
+        };
+        let protocol_type = read_string(buf)?;
+        let protocols = read_array(buf, |b| {
+            Ok(JoinGroupProtocol {
+                name: read_string(b)?,
+                metadata: read_bytes(b)?,
+            })
+        })?;
+        Ok(Self {
+            group_id,
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            member_id,
+            group_instance_id,
+            protocol_type,
+            protocols,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct JoinGroupMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub metadata: Bytes,
+}
+
+#[derive(Debug)]
+pub struct JoinGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_name: String,
+    pub leader: String,
+    pub member_id: String,
+    pub members: Vec<JoinGroupMember>,
+}
+
+impl JoinGroupResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 2 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        out.put_i16(self.error_code);
+        out.put_i32(self.generation_id);
+        write_string(out, &self.protocol_name)?;
+        write_string(out, &self.leader)?;
+        write_string(out, &self.member_id)?;
+        write_array(out, &self.members, |out, m| {
+            write_string(out, &m.member_id)?;
+            if version >= 5 {
+                write_nullable_string(out, m.group_instance_id.as_deref())?;
+            }
+            write_bytes(out, &m.metadata)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncGroupAssignment {
+    pub member_id: String,
+    pub assignment: Bytes,
+}
+
+#[derive(Debug)]
+pub struct SyncGroupRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub assignments: Vec<SyncGroupAssignment>,
+}
+
+impl SyncGroupRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let generation_id = read_i32(buf)?;
+        let member_id = read_string(buf)?;
+        let group_instance_id = if version >= 3 {
+            read_nullable_string(buf)?
+        } else {
+            None
+        };
+        let assignments = read_array(buf, |b| {
+            Ok(SyncGroupAssignment {
+                member_id: read_string(b)?,
+                assignment: read_bytes(b)?,
+            })
+        })?;
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            assignments,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub assignment: Bytes,
+}
+
+impl SyncGroupResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 1 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        out.put_i16(self.error_code);
+        write_bytes(out, &self.assignment)
+    }
+}
+
+#[derive(Debug)]
+pub struct HeartbeatRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+}
+
+impl HeartbeatRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let generation_id = read_i32(buf)?;
+        let member_id = read_string(buf)?;
+        let group_instance_id = if version >= 3 {
+            read_nullable_string(buf)?
+        } else {
+            None
+        };
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct LeaveGroupRequest {
+    pub group_id: String,
+    pub member_id: String,
+}
+
+impl LeaveGroupRequest {
+    pub fn decode(buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let member_id = read_string(buf)?;
+        Ok(Self {
+            group_id,
+            member_id,
+        })
+    }
+}
+
+/// Heartbeat and LeaveGroup share the same response layout.
+#[derive(Debug)]
+pub struct ErrorCodeResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+}
+
+impl ErrorCodeResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 1 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        out.put_i16(self.error_code);
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_array, read_i8, read_i32, read_i64, read_string, write_array, write_string,
+    },
+};
+
+#[derive(Debug)]
+pub struct ListOffsetsPartition {
+    pub partition_index: i32,
+    pub current_leader_epoch: i32,
+    pub timestamp: i64,
+}
+
+#[derive(Debug)]
+pub struct ListOffsetsTopic {
+    pub name: String,
+    pub partitions: Vec<ListOffsetsPartition>,
+}
+
+#[derive(Debug)]
+pub struct ListOffsetsRequest {
+    pub replica_id: i32,
+    pub isolation_level: i8,
+    pub topics: Vec<ListOffsetsTopic>,
+}
+
+impl ListOffsetsRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let replica_id = read_i32(buf)?;
+        let isolation_level = if version >= 2 { read_i8(buf)? } else { 0 };
+        let topics = read_array(buf, |b| {
+            Ok(ListOffsetsTopic {
+                name: read_string(b)?,
+                partitions: read_array(b, |b| {
+                    let partition_index = read_i32(b)?;
+                    let current_leader_epoch = if version >= 4 { read_i32(b)? } else { -1 };
+                    let timestamp = read_i64(b)?;
+                    Ok(ListOffsetsPartition {
+                        partition_index,
+                        current_leader_epoch,
+                        timestamp,
+                    })
+                })?,
+            })
+        })?;
+        Ok(Self {
+            replica_id,
+            isolation_level,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ListOffsetsPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub timestamp: i64,
+    pub offset: i64,
+    pub leader_epoch: i32,
+}
+
+#[derive(Debug)]
+pub struct ListOffsetsTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ListOffsetsPartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct ListOffsetsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<ListOffsetsTopicResponse>,
+}
+
+impl ListOffsetsResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 2 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        write_array(out, &self.topics, |out, t| {
+            write_string(out, &t.name)?;
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i32(p.partition_index);
+                out.put_i16(p.error_code);
+                out.put_i64(p.timestamp);
+                out.put_i64(p.offset);
+                if version >= 4 {
+                    out.put_i32(p.leader_epoch);
+                }
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,107 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_bool, read_nullable_array, read_string, write_array, write_bool, write_i32_array,
+        write_nullable_string, write_string,
+    },
+};
+
+/// `topics == None` asks for every topic.
+#[derive(Debug)]
+pub struct MetadataRequest {
+    pub topics: Option<Vec<String>>,
+    pub allow_auto_topic_creation: bool,
+}
+
+impl MetadataRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let mut topics = read_nullable_array(buf, read_string)?;
+        // v0 has no null array; an empty one means "all topics"
+        if version == 0 && topics.as_ref().is_some_and(|t| t.is_empty()) {
+            topics = None;
+        }
+        let allow_auto_topic_creation = if version >= 4 { read_bool(buf)? } else { true };
+        Ok(Self {
+            topics,
+            allow_auto_topic_creation,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MetadataBroker {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MetadataPartition {
+    pub error_code: i16,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub offline_replicas: Vec<i32>,
+}
+
+#[derive(Debug)]
+pub struct MetadataTopic {
+    pub error_code: i16,
+    pub name: String,
+    pub is_internal: bool,
+    pub partitions: Vec<MetadataPartition>,
+}
+
+#[derive(Debug)]
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: Vec<MetadataBroker>,
+    pub cluster_id: Option<String>,
+    pub controller_id: i32,
+    pub topics: Vec<MetadataTopic>,
+}
+
+impl MetadataResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 3 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        write_array(out, &self.brokers, |out, b| {
+            out.put_i32(b.node_id);
+            write_string(out, &b.host)?;
+            out.put_i32(b.port);
+            if version >= 1 {
+                write_nullable_string(out, b.rack.as_deref())?;
+            }
+            Ok(())
+        })?;
+        if version >= 2 {
+            write_nullable_string(out, self.cluster_id.as_deref())?;
+        }
+        if version >= 1 {
+            out.put_i32(self.controller_id);
+        }
+        write_array(out, &self.topics, |out, t| {
+            out.put_i16(t.error_code);
+            write_string(out, &t.name)?;
+            if version >= 1 {
+                write_bool(out, t.is_internal);
+            }
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i16(p.error_code);
+                out.put_i32(p.partition_index);
+                out.put_i32(p.leader_id);
+                write_i32_array(out, &p.replica_nodes)?;
+                write_i32_array(out, &p.isr_nodes)?;
+                if version >= 5 {
+                    write_i32_array(out, &p.offline_replicas)?;
+                }
+                Ok(())
+            })
+        })
+    }
+}
@@ -0,0 +1,292 @@
+//! Subset of the Apache Kafka wire protocol, limited to the non-flexible
+//! (pre-tagged-fields) versions of each API.
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+pub mod api_versions;
+pub mod codec;
+pub mod fetch;
+pub mod group;
+pub mod list_offsets;
+pub mod metadata;
+pub mod offsets;
+pub mod produce;
+pub mod records;
+pub mod sasl;
+
+use api_versions::{ApiVersion, ApiVersionsRequest, ApiVersionsResponse};
+use fetch::{FetchRequest, FetchResponse};
+use group::{
+    ErrorCodeResponse, FindCoordinatorRequest, FindCoordinatorResponse, HeartbeatRequest,
+    JoinGroupRequest, JoinGroupResponse, LeaveGroupRequest, SyncGroupRequest, SyncGroupResponse,
+};
+use list_offsets::{ListOffsetsRequest, ListOffsetsResponse};
+use metadata::{MetadataRequest, MetadataResponse};
+use offsets::{OffsetCommitRequest, OffsetCommitResponse, OffsetFetchRequest, OffsetFetchResponse};
+use produce::{ProduceRequest, ProduceResponse};
+use sasl::{
+    SaslAuthenticateRequest, SaslAuthenticateResponse, SaslHandshakeRequest, SaslHandshakeResponse,
+};
+
+use crate::error::ProtoError;
+use codec::{read_i16, read_i32, read_nullable_string};
+
+// ---------- api keys ----------
+pub const PRODUCE: i16 = 0;
+pub const FETCH: i16 = 1;
+pub const LIST_OFFSETS: i16 = 2;
+pub const METADATA: i16 = 3;
+pub const OFFSET_COMMIT: i16 = 8;
+pub const OFFSET_FETCH: i16 = 9;
+pub const FIND_COORDINATOR: i16 = 10;
+pub const JOIN_GROUP: i16 = 11;
+pub const HEARTBEAT: i16 = 12;
+pub const LEAVE_GROUP: i16 = 13;
+pub const SYNC_GROUP: i16 = 14;
+pub const SASL_HANDSHAKE: i16 = 17;
+pub const API_VERSIONS: i16 = 18;
+pub const SASL_AUTHENTICATE: i16 = 36;
+
+/// Versions advertised through ApiVersions.
+pub const SUPPORTED_APIS: &[ApiVersion] = &[
+    api(PRODUCE, 3, 7),
+    api(FETCH, 4, 10),
+    api(LIST_OFFSETS, 1, 5),
+    api(METADATA, 0, 5),
+    api(OFFSET_COMMIT, 2, 7),
+    api(OFFSET_FETCH, 1, 5),
+    api(FIND_COORDINATOR, 0, 2),
+    api(JOIN_GROUP, 0, 5),
+    api(HEARTBEAT, 0, 3),
+    api(LEAVE_GROUP, 0, 2),
+    api(SYNC_GROUP, 0, 3),
+    api(SASL_HANDSHAKE, 1, 1),
+    api(API_VERSIONS, 0, 2),
+    api(SASL_AUTHENTICATE, 0, 1),
+];
+
+const fn api(api_key: i16, min_version: i16, max_version: i16) -> ApiVersion {
+    ApiVersion {
+        api_key,
+        min_version,
+        max_version,
+    }
+}
+
+pub fn api_name(api_key: i16) -> &'static str {
+    match api_key {
+        PRODUCE => "produce",
+        FETCH => "fetch",
+        LIST_OFFSETS => "list_offsets",
+        METADATA => "metadata",
+        OFFSET_COMMIT => "offset_commit",
+        OFFSET_FETCH => "offset_fetch",
+        FIND_COORDINATOR => "find_coordinator",
+        JOIN_GROUP => "join_group",
+        HEARTBEAT => "heartbeat",
+        LEAVE_GROUP => "leave_group",
+        SYNC_GROUP => "sync_group",
+        SASL_HANDSHAKE => "sasl_handshake",
+        API_VERSIONS => "api_versions",
+        SASL_AUTHENTICATE => "sasl_authenticate",
+        _ => "unknown",
+    }
+}
+
+pub fn is_supported(api_key: i16, version: i16) -> bool {
+    SUPPORTED_APIS
+        .iter()
+        .any(|a| a.api_key == api_key && (a.min_version..=a.max_version).contains(&version))
+}
+
+// ---------- error codes ----------
+pub mod error_code {
+    pub const NONE: i16 = 0;
+    pub const UNKNOWN_SERVER_ERROR: i16 = -1;
+    pub const CORRUPT_MESSAGE: i16 = 2;
+    pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+    pub const RECORD_TOO_LARGE: i16 = 18;
+    pub const INVALID_REQUIRED_ACKS: i16 = 21;
+    pub const UNSUPPORTED_VERSION: i16 = 35;
+    pub const UNSUPPORTED_COMPRESSION_TYPE: i16 = 76;
+}
+
+// ---------- header ----------
+#[derive(Debug, Clone)]
+pub struct RequestHeader {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+}
+
+/// Request header v1. Only non-flexible versions are supported, so header v2
+/// (with tagged fields) never needs to be parsed past `client_id`.
+pub fn decode_header(buf: &mut Bytes) -> Result<RequestHeader, ProtoError> {
+    Ok(RequestHeader {
+        api_key: read_i16(buf)?,
+        api_version: read_i16(buf)?,
+        correlation_id: read_i32(buf)?,
+        client_id: read_nullable_string(buf)?,
+    })
+}
+
+// ---------- requests / responses ----------
+#[derive(Debug)]
+pub enum KafkaRequest {
+    ApiVersions(ApiVersionsRequest),
+    Metadata(MetadataRequest),
+    Produce(ProduceRequest),
+    Fetch(FetchRequest),
+    ListOffsets(ListOffsetsRequest),
+    FindCoordinator(FindCoordinatorRequest),
+    OffsetCommit(OffsetCommitRequest),
+    OffsetFetch(OffsetFetchRequest),
+    JoinGroup(JoinGroupRequest),
+    SyncGroup(SyncGroupRequest),
+    Heartbeat(HeartbeatRequest),
+    LeaveGroup(LeaveGroupRequest),
+    SaslHandshake(SaslHandshakeRequest),
+    SaslAuthenticate(SaslAuthenticateRequest),
+}
+
+#[derive(Debug)]
+pub enum KafkaResponse {
+    ApiVersions(ApiVersionsResponse),
+    Metadata(MetadataResponse),
+    Produce(ProduceResponse),
+    Fetch(FetchResponse),
+    ListOffsets(ListOffsetsResponse),
+    FindCoordinator(FindCoordinatorResponse),
+    OffsetCommit(OffsetCommitResponse),
+    OffsetFetch(OffsetFetchResponse),
+    JoinGroup(JoinGroupResponse),
+    SyncGroup(SyncGroupResponse),
+    Heartbeat(ErrorCodeResponse),
+    LeaveGroup(ErrorCodeResponse),
+    SaslHandshake(SaslHandshakeResponse),
+    SaslAuthenticate(SaslAuthenticateResponse),
+}
+
+impl KafkaRequest {
+    /// The partition the request is about, when it names exactly one.
+    pub fn topic_partition(&self) -> Option<(&str, i32)> {
+        fn single<T>(items: &[T]) -> Option<&T> {
+            match items {
+                [item] => Some(item),
+                _ => None,
+            }
+        }
+        match self {
+            KafkaRequest::Produce(r) => {
+                let t = single(&r.topics)?;
+                Some((&t.name, single(&t.partitions)?.index))
+            }
+            KafkaRequest::Fetch(r) => {
+                let t = single(&r.topics)?;
+                Some((&t.topic, single(&t.partitions)?.partition))
+            }
+            KafkaRequest::ListOffsets(r) => {
+                let t = single(&r.topics)?;
+                Some((&t.name, single(&t.partitions)?.partition_index))
+            }
+            KafkaRequest::OffsetCommit(r) => {
+                let t = single(&r.topics)?;
+                Some((&t.name, single(&t.partitions)?.partition_index))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl KafkaResponse {
+    pub fn set_throttle_time_ms(&mut self, ms: i32) {
+        match self {
+            KafkaResponse::ApiVersions(r) => r.throttle_time_ms = ms,
+            KafkaResponse::Metadata(r) => r.throttle_time_ms = ms,
+            KafkaResponse::Produce(r) => r.throttle_time_ms = ms,
+            KafkaResponse::Fetch(r) => r.throttle_time_ms = ms,
+            KafkaResponse::ListOffsets(r) => r.throttle_time_ms = ms,
+            KafkaResponse::FindCoordinator(r) => r.throttle_time_ms = ms,
+            KafkaResponse::OffsetCommit(r) => r.throttle_time_ms = ms,
+            KafkaResponse::OffsetFetch(r) => r.throttle_time_ms = ms,
+            KafkaResponse::JoinGroup(r) => r.throttle_time_ms = ms,
+            KafkaResponse::SyncGroup(r) => r.throttle_time_ms = ms,
+            KafkaResponse::Heartbeat(r) | KafkaResponse::LeaveGroup(r) => r.throttle_time_ms = ms,
+            KafkaResponse::SaslHandshake(_) | KafkaResponse::SaslAuthenticate(_) => {}
+        }
+    }
+}
+
+pub fn decode_request(header: &RequestHeader, body: Bytes) -> Result<KafkaRequest, ProtoError> {
+    let v = header.api_version;
+    if !is_supported(header.api_key, v) {
+        return Err(ProtoError::UnsupportedVersion {
+            api_key: header.api_key,
+            version: v,
+        });
+    }
+
+    let mut b = body;
+    let req = match header.api_key {
+        API_VERSIONS => KafkaRequest::ApiVersions(ApiVersionsRequest::decode(&mut b, v)?),
+        METADATA => KafkaRequest::Metadata(MetadataRequest::decode(&mut b, v)?),
+        PRODUCE => KafkaRequest::Produce(ProduceRequest::decode(&mut b, v)?),
+        FETCH => KafkaRequest::Fetch(FetchRequest::decode(&mut b, v)?),
+        LIST_OFFSETS => KafkaRequest::ListOffsets(ListOffsetsRequest::decode(&mut b, v)?),
+        FIND_COORDINATOR => {
+            KafkaRequest::FindCoordinator(FindCoordinatorRequest::decode(&mut b, v)?)
+        }
+        OFFSET_COMMIT => KafkaRequest::OffsetCommit(OffsetCommitRequest::decode(&mut b, v)?),
+        OFFSET_FETCH => KafkaRequest::OffsetFetch(OffsetFetchRequest::decode(&mut b, v)?),
+        JOIN_GROUP => KafkaRequest::JoinGroup(JoinGroupRequest::decode(&mut b, v)?),
+        SYNC_GROUP => KafkaRequest::SyncGroup(SyncGroupRequest::decode(&mut b, v)?),
+        HEARTBEAT => KafkaRequest::Heartbeat(HeartbeatRequest::decode(&mut b, v)?),
+        LEAVE_GROUP => KafkaRequest::LeaveGroup(LeaveGroupRequest::decode(&mut b, v)?),
+        SASL_HANDSHAKE => KafkaRequest::SaslHandshake(SaslHandshakeRequest::decode(&mut b, v)?),
+        SASL_AUTHENTICATE => {
+            KafkaRequest::SaslAuthenticate(SaslAuthenticateRequest::decode(&mut b, v)?)
+        }
+        _ => unreachable!("is_supported only accepts known api keys"),
+    };
+    Ok(req)
+}
+
+/// Encodes `resp` behind a v0 response header (correlation id only).
+pub fn encode_response(
+    correlation_id: i32,
+    version: i16,
+    resp: &KafkaResponse,
+) -> Result<Bytes, ProtoError> {
+    let mut out = BytesMut::with_capacity(256);
+    out.put_i32(correlation_id);
+
+    match resp {
+        KafkaResponse::ApiVersions(r) => r.encode(&mut out, version)?,
+        KafkaResponse::Metadata(r) => r.encode(&mut out, version)?,
+        KafkaResponse::Produce(r) => r.encode(&mut out, version)?,
+        KafkaResponse::Fetch(r) => r.encode(&mut out, version)?,
+        KafkaResponse::ListOffsets(r) => r.encode(&mut out, version)?,
+        KafkaResponse::FindCoordinator(r) => r.encode(&mut out, version)?,
+        KafkaResponse::OffsetCommit(r) => r.encode(&mut out, version)?,
+        KafkaResponse::OffsetFetch(r) => r.encode(&mut out, version)?,
+        KafkaResponse::JoinGroup(r) => r.encode(&mut out, version)?,
+        KafkaResponse::SyncGroup(r) => r.encode(&mut out, version)?,
+        KafkaResponse::Heartbeat(r) => r.encode(&mut out, version)?,
+        KafkaResponse::LeaveGroup(r) => r.encode(&mut out, version)?,
+        KafkaResponse::SaslHandshake(r) => r.encode(&mut out, version)?,
+        KafkaResponse::SaslAuthenticate(r) => r.encode(&mut out, version)?,
+    }
+
+    Ok(out.freeze())
+}
+
+/// The response to an ApiVersions request whose version we don't support: a v0
+/// body carrying UNSUPPORTED_VERSION, so the client can retry with a version we list.
+pub fn unsupported_api_versions_response() -> KafkaResponse {
+    KafkaResponse::ApiVersions(ApiVersionsResponse {
+        error_code: error_code::UNSUPPORTED_VERSION,
+        api_keys: SUPPORTED_APIS.to_vec(),
+        throttle_time_ms: 0,
+    })
+}
@@ -0,0 +1,178 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_array, read_i32, read_i64, read_nullable_array, read_nullable_string, read_string,
+        write_array, write_nullable_string, write_string,
+    },
+};
+
+#[derive(Debug)]
+pub struct OffsetCommitPartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_metadata: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct OffsetCommitTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitPartition>,
+}
+
+#[derive(Debug)]
+pub struct OffsetCommitRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub topics: Vec<OffsetCommitTopic>,
+}
+
+impl OffsetCommitRequest {
+    pub fn decode(buf: &mut Bytes, version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let generation_id = read_i32(buf)?;
+        let member_id = read_string(buf)?;
+        let group_instance_id = if version >= 7 {
+            read_nullable_string(buf)?
+        } else {
+            None
+        };
+        if (2..=4).contains(&version) {
+            let _retention_time_ms = read_i64(buf)?;
+        }
+        let topics = read_array(buf, |b| {
+            Ok(OffsetCommitTopic {
+                name: read_string(b)?,
+                partitions: read_array(b, |b| {
+                    let partition_index = read_i32(b)?;
+                    let committed_offset = read_i64(b)?;
+                    if version >= 6 {
+                        let _committed_leader_epoch = read_i32(b)?;
+                    }
+                    let committed_metadata = read_nullable_string(b)?;
+                    Ok(OffsetCommitPartition {
+                        partition_index,
+                        committed_offset,
+                        committed_metadata,
+                    })
+                })?,
+            })
+        })?;
+        Ok(Self {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PartitionError {
+    pub partition_index: i32,
+    pub error_code: i16,
+}
+
+#[derive(Debug)]
+pub struct OffsetCommitTopicResponse {
+    pub name: String,
+    pub partitions: Vec<PartitionError>,
+}
+
+#[derive(Debug)]
+pub struct OffsetCommitResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetCommitTopicResponse>,
+}
+
+impl OffsetCommitResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 3 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        write_array(out, &self.topics, |out, t| {
+            write_string(out, &t.name)?;
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i32(p.partition_index);
+                out.put_i16(p.error_code);
+                Ok(())
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchTopic {
+    pub name: String,
+    pub partition_indexes: Vec<i32>,
+}
+
+/// `topics == None` asks for every partition the group has committed.
+#[derive(Debug)]
+pub struct OffsetFetchRequest {
+    pub group_id: String,
+    pub topics: Option<Vec<OffsetFetchTopic>>,
+}
+
+impl OffsetFetchRequest {
+    pub fn decode(buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        let group_id = read_string(buf)?;
+        let topics = read_nullable_array(buf, |b| {
+            Ok(OffsetFetchTopic {
+                name: read_string(b)?,
+                partition_indexes: read_array(b, |b| Ok(read_i32(b)?))?,
+            })
+        })?;
+        Ok(Self { group_id, topics })
+    }
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchPartitionResponse {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub metadata: Option<String>,
+    pub error_code: i16,
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchTopicResponse {
+    pub name: String,
+    pub partitions: Vec<OffsetFetchPartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetFetchTopicResponse>,
+    pub error_code: i16,
+}
+
+impl OffsetFetchResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        if version >= 3 {
+            out.put_i32(self.throttle_time_ms);
+        }
+        write_array(out, &self.topics, |out, t| {
+            write_string(out, &t.name)?;
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i32(p.partition_index);
+                out.put_i64(p.committed_offset);
+                if version >= 5 {
+                    out.put_i32(-1); // committed_leader_epoch
+                }
+                write_nullable_string(out, p.metadata.as_deref())?;
+                out.put_i16(p.error_code);
+                Ok(())
+            })
+        })?;
+        if version >= 2 {
+            out.put_i16(self.error_code);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_array, read_i16, read_i32, read_nullable_bytes, read_nullable_string, read_string,
+        write_array, write_string,
+    },
+};
+
+#[derive(Debug)]
+pub struct ProducePartitionData {
+    pub index: i32,
+    /// Raw RecordBatch bytes, see `kafka::records`.
+    pub records: Option<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct ProduceTopicData {
+    pub name: String,
+    pub partitions: Vec<ProducePartitionData>,
+}
+
+#[derive(Debug)]
+pub struct ProduceRequest {
+    pub transactional_id: Option<String>,
+    pub acks: i16,
+    pub timeout_ms: i32,
+    pub topics: Vec<ProduceTopicData>,
+}
+
+impl ProduceRequest {
+    pub fn decode(buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        let transactional_id = read_nullable_string(buf)?;
+        let acks = read_i16(buf)?;
+        let timeout_ms = read_i32(buf)?;
+        let topics = read_array(buf, |b| {
+            Ok(ProduceTopicData {
+                name: read_string(b)?,
+                partitions: read_array(b, |b| {
+                    Ok(ProducePartitionData {
+                        index: read_i32(b)?,
+                        records: read_nullable_bytes(b)?,
+                    })
+                })?,
+            })
+        })?;
+        Ok(Self {
+            transactional_id,
+            acks,
+            timeout_ms,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ProducePartitionResponse {
+    pub index: i32,
+    pub error_code: i16,
+    pub base_offset: i64,
+    pub log_append_time_ms: i64,
+    pub log_start_offset: i64,
+}
+
+#[derive(Debug)]
+pub struct ProduceTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ProducePartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct ProduceResponse {
+    pub responses: Vec<ProduceTopicResponse>,
+    pub throttle_time_ms: i32,
+}
+
+impl ProduceResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        write_array(out, &self.responses, |out, t| {
+            write_string(out, &t.name)?;
+            write_array(out, &t.partitions, |out, p| {
+                out.put_i32(p.index);
+                out.put_i16(p.error_code);
+                out.put_i64(p.base_offset);
+                out.put_i64(p.log_append_time_ms);
+                if version >= 5 {
+                    out.put_i64(p.log_start_offset);
+                }
+                Ok(())
+            })
+        })?;
+        out.put_i32(self.throttle_time_ms);
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{ensure_remaining, read_varint, read_varlong, write_varint, write_varlong};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{read_i8, read_i16, read_i32, read_i64},
+    types::Record,
+};
+
+// RecordBatch v2 (magic = 2):
+// [base_offset:i64][batch_length:i32][partition_leader_epoch:i32][magic:i8][crc:u32]
+// [attributes:i16][last_offset_delta:i32][base_timestamp:i64][max_timestamp:i64]
+// [producer_id:i64][producer_epoch:i16][base_sequence:i32][record_count:i32][records...]
+const MAGIC: i8 = 2;
+// everything after batch_length up to and including record_count
+const BATCH_HEADER_LEN: usize = 4 + 1 + 4 + 2 + 4 + 8 + 8 + 8 + 2 + 4 + 4;
+const COMPRESSION_MASK: i16 = 0x07;
+const NO_TIMESTAMP: i64 = -1;
+
+/// Decodes every batch in a produce request's `records` field.
+pub fn decode_record_batches(mut data: Bytes) -> Result<Vec<Record>, ProtoError> {
+    let mut records = Vec::new();
+
+    while data.has_remaining() {
+        let _base_offset = read_i64(&mut data)?;
+        let batch_length = read_i32(&mut data)?;
+        if batch_length < BATCH_HEADER_LEN as i32 {
+            return Err(ProtoError::CorruptRecordBatch("batch length too small"));
+        }
+        ensure_remaining(&data, batch_length as usize)?;
+        let mut batch = data.split_to(batch_length as usize);
+
+        let _leader_epoch = read_i32(&mut batch)?;
+        if read_i8(&mut batch)? != MAGIC {
+            return Err(ProtoError::CorruptRecordBatch("unsupported magic"));
+        }
+        let crc = read_i32(&mut batch)? as u32;
+        if crc32c(&batch) != crc {
+            return Err(ProtoError::CorruptRecordBatch("crc mismatch"));
+        }
+
+        let attributes = read_i16(&mut batch)?;
+        if attributes & COMPRESSION_MASK != 0 {
+            return Err(ProtoError::UnsupportedCompression(
+                attributes & COMPRESSION_MASK,
+            ));
+        }
+
+        // last_offset_delta, timestamps, producer id/epoch and base sequence
+        batch.advance(4 + 8 + 8 + 8 + 2 + 4);
+        let count = read_i32(&mut batch)?;
+        for _ in 0..count {
+            records.push(decode_record(&mut batch)?);
+        }
+    }
+
+    Ok(records)
+}
+
+fn decode_record(batch: &mut Bytes) -> Result<Record, ProtoError> {
+    let len = read_varint(batch)?;
+    if len < 0 {
+        return Err(ProtoError::CorruptRecordBatch("negative record length"));
+    }
+    ensure_remaining(batch, len as usize)?;
+    let mut rec = batch.split_to(len as usize);
+
+    let _attributes = read_i8(&mut rec)?;
+    let _timestamp_delta = read_varlong(&mut rec)?;
+    let _offset_delta = read_varint(&mut rec)?;
+    let key = read_varint_bytes(&mut rec)?.unwrap_or_default();
+    let value = read_varint_bytes(&mut rec)?.unwrap_or_default();
+
+    // headers are accepted but not stored
+    let headers = read_varint(&mut rec)?;
+    for _ in 0..headers {
+        read_varint_bytes(&mut rec)?;
+        read_varint_bytes(&mut rec)?;
+    }
+
+    Ok(Record { key, value })
+}
+
+fn read_varint_bytes(buf: &mut Bytes) -> Result<Option<Bytes>, ProtoError> {
+    let len = read_varint(buf)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    ensure_remaining(buf, len as usize)?;
+    Ok(Some(buf.split_to(len as usize)))
+}
+
+/// Encodes fetched items as a single uncompressed batch based at the first item's offset.
+pub fn encode_record_batch(out: &mut BytesMut, items: &[(i64, Record)]) -> Result<(), ProtoError> {
+    let Some(&(base_offset, _)) = items.first() else {
+        return Ok(());
+    };
+    let last_offset = items.last().map(|(off, _)| *off).unwrap_or(base_offset);
+
+    let mut body = BytesMut::with_capacity(BATCH_HEADER_LEN + 64 * items.len());
+    body.put_i16(0); // attributes: no compression, CreateTime
+    body.put_i32(checked_i32(last_offset - base_offset)?);
+    body.put_i64(NO_TIMESTAMP);
+    body.put_i64(NO_TIMESTAMP);
+    body.put_i64(-1); // producer_id
+    body.put_i16(-1); // producer_epoch
+    body.put_i32(-1); // base_sequence
+    body.put_i32(checked_i32(items.len())?);
+
+    let mut rec = BytesMut::new();
+    for (offset, record) in items {
+        rec.clear();
+        rec.put_i8(0);
+        write_varlong(&mut rec, 0);
+        write_varint(&mut rec, checked_i32(offset - base_offset)?);
+        // storage cannot tell a null key from an empty one; empty keys go out as null
+        if record.key.is_empty() {
+            write_varint(&mut rec, -1);
+        } else {
+            write_varint(&mut rec, checked_i32(record.key.len())?);
+            rec.put_slice(&record.key);
+        }
+        write_varint(&mut rec, checked_i32(record.value.len())?);
+        rec.put_slice(&record.value);
+        write_varint(&mut rec, 0); // headers
+
+        write_varint(&mut body, checked_i32(rec.len())?);
+        body.put_slice(&rec);
+    }
+
+    out.put_i64(base_offset);
+    out.put_i32(checked_i32(4 + 1 + 4 + body.len())?);
+    out.put_i32(0); // partition_leader_epoch
+    out.put_i8(MAGIC);
+    out.put_u32(crc32c(&body));
+    out.put_slice(&body);
+    Ok(())
+}
+
+fn checked_i32<T: TryInto<i32>>(n: T) -> Result<i32, ProtoError> {
+    n.try_into()
+        .map_err(|_| ProtoError::CorruptRecordBatch("field does not fit in i32"))
+}
+
+// ---------- crc32c ----------
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
@@ -0,0 +1,69 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtoError,
+    kafka::codec::{
+        read_bytes, read_string, write_array, write_bytes, write_nullable_string, write_string,
+    },
+};
+
+/// Only v1 is supported: the tokens then travel in SaslAuthenticate requests
+/// instead of as raw frames.
+#[derive(Debug)]
+pub struct SaslHandshakeRequest {
+    pub mechanism: String,
+}
+
+impl SaslHandshakeRequest {
+    pub fn decode(buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        Ok(Self {
+            mechanism: read_string(buf)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SaslHandshakeResponse {
+    pub error_code: i16,
+    pub mechanisms: Vec<String>,
+}
+
+impl SaslHandshakeResponse {
+    pub fn encode(&self, out: &mut BytesMut, _version: i16) -> Result<(), ProtoError> {
+        out.put_i16(self.error_code);
+        write_array(out, &self.mechanisms, |out, m| write_string(out, m))
+    }
+}
+
+#[derive(Debug)]
+pub struct SaslAuthenticateRequest {
+    pub auth_bytes: Bytes,
+}
+
+impl SaslAuthenticateRequest {
+    pub fn decode(buf: &mut Bytes, _version: i16) -> Result<Self, ProtoError> {
+        Ok(Self {
+            auth_bytes: read_bytes(buf)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SaslAuthenticateResponse {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub auth_bytes: Bytes,
+    pub session_lifetime_ms: i64,
+}
+
+impl SaslAuthenticateResponse {
+    pub fn encode(&self, out: &mut BytesMut, version: i16) -> Result<(), ProtoError> {
+        out.put_i16(self.error_code);
+        write_nullable_string(out, self.error_message.as_deref())?;
+        write_bytes(out, &self.auth_bytes)?;
+        if version >= 1 {
+            out.put_i64(self.session_lifetime_ms);
+        }
+        Ok(())
+    }
+}
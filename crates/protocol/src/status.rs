@@ -0,0 +1,69 @@
+// ---------- status codes ----------
+// Numbered like Kafka's error codes so the Kafka listener can pass them through.
+pub const OK: u8 = 0;
+pub const OFFSET_OUT_OF_RANGE: u8 = 1;
+pub const CORRUPT_MESSAGE: u8 = 2;
+pub const UNKNOWN_TOPIC_OR_PARTITION: u8 = 3;
+pub const NOT_LEADER_OR_FOLLOWER: u8 = 6;
+pub const REQUEST_TIMED_OUT: u8 = 7;
+pub const INVALID_TOPIC: u8 = 17;
+pub const RECORD_TOO_LARGE: u8 = 18;
+pub const NOT_ENOUGH_REPLICAS: u8 = 19;
+pub const NOT_ENOUGH_REPLICAS_AFTER_APPEND: u8 = 20;
+pub const INVALID_REQUIRED_ACKS: u8 = 21;
+pub const ILLEGAL_GENERATION: u8 = 22;
+pub const INCONSISTENT_GROUP_PROTOCOL: u8 = 23;
+pub const INVALID_GROUP_ID: u8 = 24;
+pub const UNKNOWN_MEMBER_ID: u8 = 25;
+pub const REBALANCE_IN_PROGRESS: u8 = 27;
+pub const TOPIC_AUTHORIZATION_FAILED: u8 = 29;
+pub const GROUP_AUTHORIZATION_FAILED: u8 = 30;
+pub const CLUSTER_AUTHORIZATION_FAILED: u8 = 31;
+pub const UNSUPPORTED_SASL_MECHANISM: u8 = 33;
+pub const ILLEGAL_SASL_STATE: u8 = 34;
+pub const TOPIC_ALREADY_EXISTS: u8 = 36;
+pub const INVALID_PARTITIONS: u8 = 37;
+pub const INVALID_REPLICATION_FACTOR: u8 = 38;
+pub const INVALID_REPLICA_ASSIGNMENT: u8 = 39;
+pub const NOT_CONTROLLER: u8 = 41;
+pub const SASL_AUTHENTICATION_FAILED: u8 = 58;
+pub const REASSIGNMENT_IN_PROGRESS: u8 = 60;
+pub const FENCED_LEADER_EPOCH: u8 = 74;
+pub const UNKNOWN_LEADER_EPOCH: u8 = 76;
+
+/// Kafka's name for `status`, for error messages.
+pub fn name(status: u8) -> &'static str {
+    match status {
+        OK => "NONE",
+        OFFSET_OUT_OF_RANGE => "OFFSET_OUT_OF_RANGE",
+        CORRUPT_MESSAGE => "CORRUPT_MESSAGE",
+        UNKNOWN_TOPIC_OR_PARTITION => "UNKNOWN_TOPIC_OR_PARTITION",
+        NOT_LEADER_OR_FOLLOWER => "NOT_LEADER_OR_FOLLOWER",
+        REQUEST_TIMED_OUT => "REQUEST_TIMED_OUT",
+        INVALID_TOPIC => "INVALID_TOPIC_EXCEPTION",
+        RECORD_TOO_LARGE => "RECORD_TOO_LARGE",
+        NOT_ENOUGH_REPLICAS => "NOT_ENOUGH_REPLICAS",
+        NOT_ENOUGH_REPLICAS_AFTER_APPEND => "NOT_ENOUGH_REPLICAS_AFTER_APPEND",
+        INVALID_REQUIRED_ACKS => "INVALID_REQUIRED_ACKS",
+        ILLEGAL_GENERATION => "ILLEGAL_GENERATION",
+        INCONSISTENT_GROUP_PROTOCOL => "INCONSISTENT_GROUP_PROTOCOL",
+        INVALID_GROUP_ID => "INVALID_GROUP_ID",
+        UNKNOWN_MEMBER_ID => "UNKNOWN_MEMBER_ID",
+        REBALANCE_IN_PROGRESS => "REBALANCE_IN_PROGRESS",
+        TOPIC_AUTHORIZATION_FAILED => "TOPIC_AUTHORIZATION_FAILED",
+        GROUP_AUTHORIZATION_FAILED => "GROUP_AUTHORIZATION_FAILED",
+        CLUSTER_AUTHORIZATION_FAILED => "CLUSTER_AUTHORIZATION_FAILED",
+        UNSUPPORTED_SASL_MECHANISM => "UNSUPPORTED_SASL_MECHANISM",
+        ILLEGAL_SASL_STATE => "ILLEGAL_SASL_STATE",
+        TOPIC_ALREADY_EXISTS => "TOPIC_ALREADY_EXISTS",
+        INVALID_PARTITIONS => "INVALID_PARTITIONS",
+        INVALID_REPLICATION_FACTOR => "INVALID_REPLICATION_FACTOR",
+        INVALID_REPLICA_ASSIGNMENT => "INVALID_REPLICA_ASSIGNMENT",
+        NOT_CONTROLLER => "NOT_CONTROLLER",
+        SASL_AUTHENTICATION_FAILED => "SASL_AUTHENTICATION_FAILED",
+        REASSIGNMENT_IN_PROGRESS => "REASSIGNMENT_IN_PROGRESS",
+        FENCED_LEADER_EPOCH => "FENCED_LEADER_EPOCH",
+        UNKNOWN_LEADER_EPOCH => "UNKNOWN_LEADER_EPOCH",
+        _ => "UNKNOWN",
+    }
+}
@@ -1,10 +1,70 @@
-use bytes::Bytes;
+use bytes::{Buf, Bytes, BytesMut};
+use common::{Decode, Encode, IoError};
 
 // ---------- domain types ----------
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ApiKey {
     Produce = 1,
     Fetch = 2,
+    ListOffsets = 3,
+    Metadata = 4,
+    OffsetCommit = 5,
+    OffsetFetch = 6,
+    JoinGroup = 7,
+    SyncGroup = 8,
+    Heartbeat = 9,
+    LeaveGroup = 10,
+    SaslHandshake = 11,
+    SaslAuthenticate = 12,
+    CreateAcls = 13,
+    DeleteAcls = 14,
+    DescribeAcls = 15,
+    CreateTopic = 16,
+    DeleteTopic = 17,
+    ListGroups = 18,
+    DescribeGroup = 19,
+    ReplicaFetch = 20,
+    Vote = 21,
+    AppendEntries = 22,
+    OffsetForLeaderEpoch = 23,
+    DescribePartition = 24,
+    AlterPartitionReassignments = 25,
+    ListPartitionReassignments = 26,
+    CreatePartitions = 27,
+}
+
+impl ApiKey {
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiKey::Produce => "produce",
+            ApiKey::Fetch => "fetch",
+            ApiKey::ListOffsets => "list_offsets",
+            ApiKey::Metadata => "metadata",
+            ApiKey::OffsetCommit => "offset_commit",
+            ApiKey::OffsetFetch => "offset_fetch",
+            ApiKey::JoinGroup => "join_group",
+            ApiKey::SyncGroup => "sync_group",
+            ApiKey::Heartbeat => "heartbeat",
+            ApiKey::LeaveGroup => "leave_group",
+            ApiKey::SaslHandshake => "sasl_handshake",
+            ApiKey::SaslAuthenticate => "sasl_authenticate",
+            ApiKey::CreateAcls => "create_acls",
+            ApiKey::DeleteAcls => "delete_acls",
+            ApiKey::DescribeAcls => "describe_acls",
+            ApiKey::CreateTopic => "create_topic",
+            ApiKey::DeleteTopic => "delete_topic",
+            ApiKey::ListGroups => "list_groups",
+            ApiKey::DescribeGroup => "describe_group",
+            ApiKey::ReplicaFetch => "replica_fetch",
+            ApiKey::Vote => "vote",
+            ApiKey::AppendEntries => "append_entries",
+            ApiKey::OffsetForLeaderEpoch => "offset_for_leader_epoch",
+            ApiKey::DescribePartition => "describe_partition",
+            ApiKey::AlterPartitionReassignments => "alter_partition_reassignments",
+            ApiKey::ListPartitionReassignments => "list_partition_reassignments",
+            ApiKey::CreatePartitions => "create_partitions",
+        }
+    }
 }
 
 impl TryFrom<u8> for ApiKey {
@@ -14,6 +74,31 @@ impl TryFrom<u8> for ApiKey {
         match value {
             1 => Ok(ApiKey::Produce),
             2 => Ok(ApiKey::Fetch),
+            3 => Ok(ApiKey::ListOffsets),
+            4 => Ok(ApiKey::Metadata),
+            5 => Ok(ApiKey::OffsetCommit),
+            6 => Ok(ApiKey::OffsetFetch),
+            7 => Ok(ApiKey::JoinGroup),
+            8 => Ok(ApiKey::SyncGroup),
+            9 => Ok(ApiKey::Heartbeat),
+            10 => Ok(ApiKey::LeaveGroup),
+            11 => Ok(ApiKey::SaslHandshake),
+            12 => Ok(ApiKey::SaslAuthenticate),
+            13 => Ok(ApiKey::CreateAcls),
+            14 => Ok(ApiKey::DeleteAcls),
+            15 => Ok(ApiKey::DescribeAcls),
+            16 => Ok(ApiKey::CreateTopic),
+            17 => Ok(ApiKey::DeleteTopic),
+            18 => Ok(ApiKey::ListGroups),
+            19 => Ok(ApiKey::DescribeGroup),
+            20 => Ok(ApiKey::ReplicaFetch),
+            21 => Ok(ApiKey::Vote),
+            22 => Ok(ApiKey::AppendEntries),
+            23 => Ok(ApiKey::OffsetForLeaderEpoch),
+            24 => Ok(ApiKey::DescribePartition),
+            25 => Ok(ApiKey::AlterPartitionReassignments),
+            26 => Ok(ApiKey::ListPartitionReassignments),
+            27 => Ok(ApiKey::CreatePartitions),
             x => Err(x),
         }
     }
@@ -25,20 +110,158 @@ pub struct Record {
     pub value: Bytes,
 }
 
-#[derive(Debug)]
+impl Record {
+    /// Size of the record in a partition log or a `FetchResponse` item, with its
+    /// offset and length prefixes.
+    pub fn stored_len(&self) -> usize {
+        8 + 2 + self.key.len() + 4 + self.value.len()
+    }
+
+    /// Whether the key and value lengths fit their u16 and u32 prefixes.
+    pub fn fits_length_prefixes(&self) -> bool {
+        self.key.len() <= u16::MAX as usize && self.value.len() <= u32::MAX as usize
+    }
+}
+
+// keys keep their u16 length prefix, unlike plain `Bytes`
+impl Encode for Record {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), IoError> {
+        common::write_record_bytes(buf, &self.key, &self.value)
+    }
+}
+
+impl Decode for Record {
+    fn decode(buf: &mut dyn Buf) -> Result<Self, IoError> {
+        let (key, value) = common::read_record(buf)?;
+        Ok(Record { key, value })
+    }
+}
+
+#[derive(Debug, Encode, Decode)]
 pub enum Request {
+    #[wire(tag = ApiKey::Produce as u8)]
     Produce(ProduceRequest),
+    #[wire(tag = ApiKey::Fetch as u8)]
     Fetch(FetchRequest),
+    #[wire(tag = ApiKey::ListOffsets as u8)]
+    ListOffsets(ListOffsetsRequest),
+    #[wire(tag = ApiKey::Metadata as u8)]
+    Metadata(MetadataRequest),
+    #[wire(tag = ApiKey::OffsetCommit as u8)]
+    OffsetCommit(OffsetCommitRequest),
+    #[wire(tag = ApiKey::OffsetFetch as u8)]
+    OffsetFetch(OffsetFetchRequest),
+    #[wire(tag = ApiKey::JoinGroup as u8)]
+    JoinGroup(JoinGroupRequest),
+    #[wire(tag = ApiKey::SyncGroup as u8)]
+    SyncGroup(SyncGroupRequest),
+    #[wire(tag = ApiKey::Heartbeat as u8)]
+    Heartbeat(HeartbeatRequest),
+    #[wire(tag = ApiKey::LeaveGroup as u8)]
+    LeaveGroup(LeaveGroupRequest),
+    #[wire(tag = ApiKey::SaslHandshake as u8)]
+    SaslHandshake(SaslHandshakeRequest),
+    #[wire(tag = ApiKey::SaslAuthenticate as u8)]
+    SaslAuthenticate(SaslAuthenticateRequest),
+    #[wire(tag = ApiKey::CreateAcls as u8)]
+    CreateAcls(CreateAclsRequest),
+    #[wire(tag = ApiKey::DeleteAcls as u8)]
+    DeleteAcls(DeleteAclsRequest),
+    #[wire(tag = ApiKey::DescribeAcls as u8)]
+    DescribeAcls(DescribeAclsRequest),
+    #[wire(tag = ApiKey::CreateTopic as u8)]
+    CreateTopic(CreateTopicRequest),
+    #[wire(tag = ApiKey::DeleteTopic as u8)]
+    DeleteTopic(DeleteTopicRequest),
+    #[wire(tag = ApiKey::ListGroups as u8)]
+    ListGroups(ListGroupsRequest),
+    #[wire(tag = ApiKey::DescribeGroup as u8)]
+    DescribeGroup(DescribeGroupRequest),
+    #[wire(tag = ApiKey::ReplicaFetch as u8)]
+    ReplicaFetch(ReplicaFetchRequest),
+    #[wire(tag = ApiKey::Vote as u8)]
+    Vote(VoteRequest),
+    #[wire(tag = ApiKey::AppendEntries as u8)]
+    AppendEntries(AppendEntriesRequest),
+    #[wire(tag = ApiKey::OffsetForLeaderEpoch as u8)]
+    OffsetForLeaderEpoch(OffsetForLeaderEpochRequest),
+    #[wire(tag = ApiKey::DescribePartition as u8)]
+    DescribePartition(DescribePartitionRequest),
+    #[wire(tag = ApiKey::AlterPartitionReassignments as u8)]
+    AlterPartitionReassignments(AlterPartitionReassignmentsRequest),
+    #[wire(tag = ApiKey::ListPartitionReassignments as u8)]
+    ListPartitionReassignments(ListPartitionReassignmentsRequest),
+    #[wire(tag = ApiKey::CreatePartitions as u8)]
+    CreatePartitions(CreatePartitionsRequest),
 }
 
-#[derive(Debug)]
+impl Request {
+    pub fn api_key(&self) -> ApiKey {
+        match self {
+            Request::Produce(_) => ApiKey::Produce,
+            Request::Fetch(_) => ApiKey::Fetch,
+            Request::ListOffsets(_) => ApiKey::ListOffsets,
+            Request::Metadata(_) => ApiKey::Metadata,
+            Request::OffsetCommit(_) => ApiKey::OffsetCommit,
+            Request::OffsetFetch(_) => ApiKey::OffsetFetch,
+            Request::JoinGroup(_) => ApiKey::JoinGroup,
+            Request::SyncGroup(_) => ApiKey::SyncGroup,
+            Request::Heartbeat(_) => ApiKey::Heartbeat,
+            Request::LeaveGroup(_) => ApiKey::LeaveGroup,
+            Request::SaslHandshake(_) => ApiKey::SaslHandshake,
+            Request::SaslAuthenticate(_) => ApiKey::SaslAuthenticate,
+            Request::CreateAcls(_) => ApiKey::CreateAcls,
+            Request::DeleteAcls(_) => ApiKey::DeleteAcls,
+            Request::DescribeAcls(_) => ApiKey::DescribeAcls,
+            Request::CreateTopic(_) => ApiKey::CreateTopic,
+            Request::DeleteTopic(_) => ApiKey::DeleteTopic,
+            Request::ListGroups(_) => ApiKey::ListGroups,
+            Request::DescribeGroup(_) => ApiKey::DescribeGroup,
+            Request::ReplicaFetch(_) => ApiKey::ReplicaFetch,
+            Request::Vote(_) => ApiKey::Vote,
+            Request::AppendEntries(_) => ApiKey::AppendEntries,
+            Request::OffsetForLeaderEpoch(_) => ApiKey::OffsetForLeaderEpoch,
+            Request::DescribePartition(_) => ApiKey::DescribePartition,
+            Request::AlterPartitionReassignments(_) => ApiKey::AlterPartitionReassignments,
+            Request::ListPartitionReassignments(_) => ApiKey::ListPartitionReassignments,
+            Request::CreatePartitions(_) => ApiKey::CreatePartitions,
+        }
+    }
+
+    /// The partition the request is about, for requests about a single one.
+    pub fn topic_partition(&self) -> Option<(&str, u16)> {
+        match self {
+            Request::Produce(r) => Some((&r.topic, r.partition)),
+            Request::Fetch(r) => Some((&r.topic, r.partition)),
+            Request::ReplicaFetch(r) => Some((&r.topic, r.partition)),
+            Request::OffsetForLeaderEpoch(r) => Some((&r.topic, r.partition)),
+            Request::DescribePartition(r) => Some((&r.topic, r.partition)),
+            Request::ListOffsets(r) => Some((&r.topic, r.partition)),
+            Request::OffsetCommit(r) => Some((&r.topic, r.partition)),
+            Request::OffsetFetch(r) => Some((&r.topic, r.partition)),
+            _ => None,
+        }
+    }
+}
+
+/// `acks` values of `ProduceRequest`: the response is sent once the leader
+/// has appended the records, or once every in-sync replica has them. The
+/// native protocol answers every request, so 0 is taken as 1.
+pub const ACKS_LEADER: i16 = 1;
+pub const ACKS_ALL: i16 = -1;
+
+/// `acks` comes last so that requests from clients predating it still
+/// decode, as `ACKS_LEADER`.
+#[derive(Debug, Encode, Decode)]
 pub struct ProduceRequest {
     pub topic: String,
     pub partition: u16,
     pub records: Vec<Record>,
+    #[wire(default = ACKS_LEADER)]
+    pub acks: i16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Encode, Decode)]
 pub struct FetchRequest {
     pub topic: String,
     pub partition: u16,
@@ -46,21 +269,628 @@ pub struct FetchRequest {
     pub max_bytes: u32,
 }
 
-#[derive(Debug)]
+/// A fetch by the follower `replica_id` of the partition, which also tells the
+/// leader that the follower's log ends at `offset`. Records above the high
+/// watermark are returned too, and when there are none the leader waits up to
+/// `max_wait_ms` for some to be appended. `leader_epoch` is the epoch the
+/// follower knows the leader in; fetches for another one are refused.
+#[derive(Debug, Encode, Decode)]
+pub struct ReplicaFetchRequest {
+    pub replica_id: u32,
+    pub topic: String,
+    pub partition: u16,
+    pub leader_epoch: i32,
+    pub offset: i64,
+    pub max_bytes: u32,
+    pub max_wait_ms: u32,
+}
+
+/// A follower asking the leader where `leader_epoch`, the latest epoch of
+/// the follower's log, ends in the leader's log, to remove what it has past
+/// that point before fetching. `current_leader_epoch` is the epoch the
+/// follower knows the leader in, as in `ReplicaFetchRequest`.
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetForLeaderEpochRequest {
+    pub replica_id: u32,
+    pub topic: String,
+    pub partition: u16,
+    pub current_leader_epoch: i32,
+    pub leader_epoch: i32,
+}
+
+/// The controller asking a partition's leader how far its replicas have
+/// come.
+#[derive(Debug, Encode, Decode)]
+pub struct DescribePartitionRequest {
+    pub topic: String,
+    pub partition: u16,
+}
+
+/// A controller asking the others for their vote to lead the metadata log in
+/// `term`. Votes only go to candidates whose log is at least as up to date
+/// as the voter's: ending in a later term, or as long in the same one.
+#[derive(Debug, Encode, Decode)]
+pub struct VoteRequest {
+    pub term: i32,
+    pub candidate_id: u32,
+    pub last_log_term: i32,
+    pub log_end_offset: i64,
+}
+
+/// An entry of the metadata log and the term it was written in.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct RaftEntry {
+    pub term: i32,
+    pub data: Bytes,
+}
+
+/// The metadata log leader of `term` sending `entries` from `offset` on to a
+/// follower, whose log must hold the leader's entry before `offset`, from
+/// `prev_log_term`. Entries below `commit_offset` are on a majority of the
+/// controllers. Sent without entries as a heartbeat.
+#[derive(Debug, Encode, Decode)]
+pub struct AppendEntriesRequest {
+    pub term: i32,
+    pub leader_id: u32,
+    pub offset: i64,
+    pub prev_log_term: i32,
+    pub entries: Vec<RaftEntry>,
+    pub commit_offset: i64,
+}
+
+/// `timestamp` values understood by ListOffsets besides real timestamps.
+pub const LATEST_TIMESTAMP: i64 = -1;
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListOffsetsRequest {
+    pub topic: String,
+    pub partition: u16,
+    pub timestamp: i64,
+}
+
+/// An empty `topics` list asks for every topic.
+#[derive(Debug, Encode, Decode)]
+pub struct MetadataRequest {
+    pub topics: Vec<String>,
+    pub allow_auto_create: bool,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetCommitRequest {
+    pub group_id: String,
+    pub topic: String,
+    pub partition: u16,
+    pub offset: i64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetFetchRequest {
+    pub group_id: String,
+    pub topic: String,
+    pub partition: u16,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct GroupProtocol {
+    pub name: String,
+    pub metadata: Bytes,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct JoinGroupRequest {
+    pub group_id: String,
+    pub member_id: String,
+    pub session_timeout_ms: u32,
+    pub rebalance_timeout_ms: u32,
+    pub protocol_type: String,
+    pub protocols: Vec<GroupProtocol>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct MemberAssignment {
+    pub member_id: String,
+    pub assignment: Bytes,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SyncGroupRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub assignments: Vec<MemberAssignment>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct HeartbeatRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct LeaveGroupRequest {
+    pub group_id: String,
+    pub member_id: String,
+}
+
+/// SASL mechanism names accepted by `SaslHandshakeRequest`.
+pub const SASL_PLAIN: &str = "PLAIN";
+pub const SASL_SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+#[derive(Debug, Encode, Decode)]
+pub struct SaslHandshakeRequest {
+    pub mechanism: String,
+}
+
+/// One step of the mechanism chosen by the handshake; `auth_bytes` are the raw
+/// SASL tokens.
+#[derive(Debug, Encode, Decode)]
+pub struct SaslAuthenticateRequest {
+    pub auth_bytes: Bytes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum AclOperation {
+    #[wire(tag = 1)]
+    Read,
+    #[wire(tag = 2)]
+    Write,
+    #[wire(tag = 3)]
+    Create,
+    #[wire(tag = 4)]
+    Delete,
+    #[wire(tag = 5)]
+    Describe,
+    /// Changing cluster settings, including the ACLs themselves.
+    #[wire(tag = 6)]
+    Alter,
+    /// Requests brokers send each other, such as replica fetches.
+    #[wire(tag = 7)]
+    ClusterAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum ResourceType {
+    #[wire(tag = 1)]
+    Topic,
+    #[wire(tag = 2)]
+    Group,
+    #[wire(tag = 3)]
+    Cluster,
+}
+
+/// `Literal` names match exactly, with `*` matching every name; `Prefixed`
+/// names match every resource starting with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum PatternType {
+    #[wire(tag = 1)]
+    Literal,
+    #[wire(tag = 2)]
+    Prefixed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum AclPermission {
+    #[wire(tag = 1)]
+    Allow,
+    #[wire(tag = 2)]
+    Deny,
+}
+
+/// Principals are written `User:<name>`; `User:*` matches everyone and
+/// unauthenticated clients are `User:ANONYMOUS`.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AclBinding {
+    pub principal: String,
+    pub resource_type: ResourceType,
+    pub resource_name: String,
+    pub pattern_type: PatternType,
+    pub operation: AclOperation,
+    pub permission: AclPermission,
+}
+
+/// Name of the single cluster resource.
+pub const CLUSTER_RESOURCE: &str = "mini-kafka";
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateAclsRequest {
+    pub acls: Vec<AclBinding>,
+}
+
+/// Removes bindings equal to the given ones.
+#[derive(Debug, Encode, Decode)]
+pub struct DeleteAclsRequest {
+    pub acls: Vec<AclBinding>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DescribeAclsRequest {}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateTopicRequest {
+    pub topic: String,
+    pub partitions: u16,
+}
+
+/// Raises the topic's partition count to `partitions`. Topics never lose
+/// partitions, so the count must be above the current one.
+#[derive(Debug, Encode, Decode)]
+pub struct CreatePartitionsRequest {
+    pub topic: String,
+    pub partitions: u16,
+}
+
+/// Removes every partition of the topic. Committed offsets of the topic are
+/// kept.
+#[derive(Debug, Encode, Decode)]
+pub struct DeleteTopicRequest {
+    pub topic: String,
+}
+
+/// Moves the partition to `replicas`, the first of which is preferred as its
+/// leader. New replicas copy the partition from its leader, at most
+/// `throttle_bytes_per_sec` each when above 0, and the replicas left out keep
+/// it until every new one is in sync.
+#[derive(Debug, Encode, Decode)]
+pub struct AlterPartitionReassignmentsRequest {
+    pub topic: String,
+    pub partition: u16,
+    pub replicas: Vec<u32>,
+    pub throttle_bytes_per_sec: i64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListPartitionReassignmentsRequest {}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListGroupsRequest {}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DescribeGroupRequest {
+    pub group_id: String,
+}
+
+/// Tag of `Response::Error`, outside the range of api keys.
+pub const ERROR_TAG: u8 = 255;
+
+#[derive(Debug, Encode, Decode)]
 pub enum Response {
+    #[wire(tag = ApiKey::Produce as u8)]
     Produce(ProduceResponse),
+    #[wire(tag = ApiKey::Fetch as u8)]
     Fetch(FetchResponse),
+    #[wire(tag = ApiKey::ListOffsets as u8)]
+    ListOffsets(ListOffsetsResponse),
+    #[wire(tag = ApiKey::Metadata as u8)]
+    Metadata(MetadataResponse),
+    #[wire(tag = ApiKey::OffsetCommit as u8)]
+    OffsetCommit(OffsetCommitResponse),
+    #[wire(tag = ApiKey::OffsetFetch as u8)]
+    OffsetFetch(OffsetFetchResponse),
+    #[wire(tag = ApiKey::JoinGroup as u8)]
+    JoinGroup(JoinGroupResponse),
+    #[wire(tag = ApiKey::SyncGroup as u8)]
+    SyncGroup(SyncGroupResponse),
+    #[wire(tag = ApiKey::Heartbeat as u8)]
+    Heartbeat(HeartbeatResponse),
+    #[wire(tag = ApiKey::LeaveGroup as u8)]
+    LeaveGroup(LeaveGroupResponse),
+    #[wire(tag = ApiKey::SaslHandshake as u8)]
+    SaslHandshake(SaslHandshakeResponse),
+    #[wire(tag = ApiKey::SaslAuthenticate as u8)]
+    SaslAuthenticate(SaslAuthenticateResponse),
+    #[wire(tag = ApiKey::CreateAcls as u8)]
+    CreateAcls(CreateAclsResponse),
+    #[wire(tag = ApiKey::DeleteAcls as u8)]
+    DeleteAcls(DeleteAclsResponse),
+    #[wire(tag = ApiKey::DescribeAcls as u8)]
+    DescribeAcls(DescribeAclsResponse),
+    #[wire(tag = ApiKey::CreateTopic as u8)]
+    CreateTopic(CreateTopicResponse),
+    #[wire(tag = ApiKey::DeleteTopic as u8)]
+    DeleteTopic(DeleteTopicResponse),
+    #[wire(tag = ApiKey::ListGroups as u8)]
+    ListGroups(ListGroupsResponse),
+    #[wire(tag = ApiKey::DescribeGroup as u8)]
+    DescribeGroup(DescribeGroupResponse),
+    #[wire(tag = ApiKey::ReplicaFetch as u8)]
+    ReplicaFetch(ReplicaFetchResponse),
+    #[wire(tag = ApiKey::Vote as u8)]
+    Vote(VoteResponse),
+    #[wire(tag = ApiKey::AppendEntries as u8)]
+    AppendEntries(AppendEntriesResponse),
+    #[wire(tag = ApiKey::OffsetForLeaderEpoch as u8)]
+    OffsetForLeaderEpoch(OffsetForLeaderEpochResponse),
+    #[wire(tag = ApiKey::DescribePartition as u8)]
+    DescribePartition(DescribePartitionResponse),
+    #[wire(tag = ApiKey::AlterPartitionReassignments as u8)]
+    AlterPartitionReassignments(AlterPartitionReassignmentsResponse),
+    #[wire(tag = ApiKey::ListPartitionReassignments as u8)]
+    ListPartitionReassignments(ListPartitionReassignmentsResponse),
+    #[wire(tag = ApiKey::CreatePartitions as u8)]
+    CreatePartitions(CreatePartitionsResponse),
+    #[wire(tag = ERROR_TAG)]
     Error { message: String },
 }
 
-#[derive(Debug)]
+impl Response {
+    /// Whether the response reports a failure, either as `Error` or through a
+    /// status other than OK. Per-topic metadata statuses are not considered.
+    pub fn is_error(&self) -> bool {
+        let status = match self {
+            Response::Produce(r) => r.status,
+            Response::Fetch(r) => r.status,
+            Response::ListOffsets(r) => r.status,
+            Response::OffsetCommit(r) => r.status,
+            Response::OffsetFetch(r) => r.status,
+            Response::JoinGroup(r) => r.status,
+            Response::SyncGroup(r) => r.status,
+            Response::Heartbeat(r) => r.status,
+            Response::LeaveGroup(r) => r.status,
+            Response::SaslHandshake(r) => r.status,
+            Response::SaslAuthenticate(r) => r.status,
+            Response::CreateAcls(r) => r.status,
+            Response::DeleteAcls(r) => r.status,
+            Response::DescribeAcls(r) => r.status,
+            Response::CreateTopic(r) => r.status,
+            Response::DeleteTopic(r) => r.status,
+            Response::ListGroups(r) => r.status,
+            Response::DescribeGroup(r) => r.status,
+            Response::ReplicaFetch(r) => r.status,
+            Response::Vote(r) => r.status,
+            Response::AppendEntries(r) => r.status,
+            Response::OffsetForLeaderEpoch(r) => r.status,
+            Response::DescribePartition(r) => r.status,
+            Response::AlterPartitionReassignments(r) => r.status,
+            Response::ListPartitionReassignments(r) => r.status,
+            Response::CreatePartitions(r) => r.status,
+            Response::Metadata(_) => crate::status::OK,
+            Response::Error { .. } => return true,
+        };
+        status != crate::status::OK
+    }
+}
+
+/// `throttle_time_ms` is how long the response was held back for exceeding a
+/// quota; clients should wait as long again before their next request.
+#[derive(Debug, Encode, Decode)]
 pub struct ProduceResponse {
     pub status: u8,
     pub base_offset: i64,
+    pub throttle_time_ms: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Encode, Decode)]
 pub struct FetchResponse {
     pub status: u8,
+    pub high_watermark: i64,
+    pub log_start_offset: i64,
+    pub throttle_time_ms: u32,
+    pub items: Vec<(i64, Record)>,
+}
+
+/// A `FetchResponse` for a follower, with the leader epochs of the records
+/// as `(epoch, start offset)`: the epoch of the first record, then those
+/// starting at later records.
+#[derive(Debug, Encode, Decode)]
+pub struct ReplicaFetchResponse {
+    pub status: u8,
+    pub high_watermark: i64,
+    pub log_start_offset: i64,
+    pub epochs: Vec<(i32, i64)>,
     pub items: Vec<(i64, Record)>,
 }
+
+/// The latest epoch of the leader's log up to the one asked for, and the
+/// offset after its last record; both -1 if the leader's log has none.
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetForLeaderEpochResponse {
+    pub status: u8,
+    pub leader_epoch: i32,
+    pub end_offset: i64,
+}
+
+/// The leader's view of the partition: its epoch, where its log ends, and
+/// where the log of each follower ended at its last fetch.
+#[derive(Debug, Encode, Decode)]
+pub struct DescribePartitionResponse {
+    pub status: u8,
+    pub leader_epoch: i32,
+    pub log_end_offset: i64,
+    pub high_watermark: i64,
+    pub isr: Vec<u32>,
+    pub replica_offsets: Vec<(u32, i64)>,
+}
+
+/// `term` is the voter's, after seeing the request's.
+#[derive(Debug, Encode, Decode)]
+pub struct VoteResponse {
+    pub status: u8,
+    pub term: i32,
+    pub vote_granted: bool,
+}
+
+/// When `success`, the follower's log matches the leader's up to
+/// `log_end_offset`. Otherwise the leader should try again from there: it is
+/// where the follower's log ends, or where it starts to differ.
+#[derive(Debug, Encode, Decode)]
+pub struct AppendEntriesResponse {
+    pub status: u8,
+    pub term: i32,
+    pub success: bool,
+    pub log_end_offset: i64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListOffsetsResponse {
+    pub status: u8,
+    pub offset: i64,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct MetadataResponse {
+    pub topics: Vec<TopicMetadata>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct TopicMetadata {
+    pub status: u8,
+    pub name: String,
+    pub partitions: Vec<u16>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetCommitResponse {
+    pub status: u8,
+}
+
+/// `offset` is -1 when the group has not committed anything for the partition.
+#[derive(Debug, Encode, Decode)]
+pub struct OffsetFetchResponse {
+    pub status: u8,
+    pub offset: i64,
+}
+
+/// `members` is only filled in for the group leader.
+#[derive(Debug, Encode, Decode)]
+pub struct JoinGroupResponse {
+    pub status: u8,
+    pub generation_id: i32,
+    pub protocol_name: String,
+    pub leader: String,
+    pub member_id: String,
+    pub members: Vec<GroupProtocolMember>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct GroupProtocolMember {
+    pub member_id: String,
+    pub metadata: Bytes,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SyncGroupResponse {
+    pub status: u8,
+    pub assignment: Bytes,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct HeartbeatResponse {
+    pub status: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct LeaveGroupResponse {
+    pub status: u8,
+}
+
+/// `mechanisms` lists what the server supports, whether or not the requested one was.
+#[derive(Debug, Encode, Decode)]
+pub struct SaslHandshakeResponse {
+    pub status: u8,
+    pub mechanisms: Vec<String>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct SaslAuthenticateResponse {
+    pub status: u8,
+    pub error_message: String,
+    pub auth_bytes: Bytes,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateAclsResponse {
+    pub status: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DeleteAclsResponse {
+    pub status: u8,
+    pub deleted: u32,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DescribeAclsResponse {
+    pub status: u8,
+    pub acls: Vec<AclBinding>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreateTopicResponse {
+    pub status: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct CreatePartitionsResponse {
+    pub status: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct DeleteTopicResponse {
+    pub status: u8,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct AlterPartitionReassignmentsResponse {
+    pub status: u8,
+}
+
+/// A partition moving to the replicas in `replicas` that are not in
+/// `removing`. `log_end_offset` is where the leader's log ended and
+/// `replica_offsets` where those of the `adding` replicas did when the
+/// controller last asked, -1 before it has.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct PartitionReassignment {
+    pub topic: String,
+    pub partition: u16,
+    pub replicas: Vec<u32>,
+    pub adding: Vec<u32>,
+    pub removing: Vec<u32>,
+    pub throttle_bytes_per_sec: i64,
+    pub log_end_offset: i64,
+    pub replica_offsets: Vec<(u32, i64)>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListPartitionReassignmentsResponse {
+    pub status: u8,
+    pub reassignments: Vec<PartitionReassignment>,
+}
+
+/// Groups with members or committed offsets. `state` is one of the states
+/// `DescribeGroupResponse` reports.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct GroupListing {
+    pub group_id: String,
+    pub protocol_type: String,
+    pub state: String,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct ListGroupsResponse {
+    pub status: u8,
+    pub groups: Vec<GroupListing>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct CommittedOffset {
+    pub topic: String,
+    pub partition: u16,
+    pub offset: i64,
+}
+
+/// `state` is `Empty`, `PreparingRebalance`, `AwaitingSync` or `Stable`, or
+/// `Dead` for a group that has neither members nor committed offsets.
+/// `members` carry the assignments of the current generation.
+#[derive(Debug, Encode, Decode)]
+pub struct DescribeGroupResponse {
+    pub status: u8,
+    pub state: String,
+    pub protocol_type: String,
+    pub protocol_name: String,
+    pub generation_id: i32,
+    pub leader: String,
+    pub members: Vec<MemberAssignment>,
+    pub offsets: Vec<CommittedOffset>,
+}
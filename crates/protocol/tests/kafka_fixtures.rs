@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use protocol::error::ProtoError;
+    use protocol::kafka::{
+        self, KafkaRequest, KafkaResponse,
+        metadata::{MetadataBroker, MetadataPartition, MetadataResponse, MetadataTopic},
+        produce::{ProducePartitionResponse, ProduceResponse, ProduceTopicResponse},
+        records,
+    };
+    use protocol::types::Record;
+
+    // Frames below are payloads without the u32 length prefix, laid out as a
+    // librdkafka client sends them.
+
+    const PRODUCE_V3_REQUEST: [u8; 131] = [
+        0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x07, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66,
+        0x6b, 0x61, 0xff, 0xff, 0x00, 0x01, 0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x04, 0x74, 0x65, 0x73, 0x74, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x54, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x48, 0x00,
+        0x00, 0x00, 0x00, 0x02, 0x0e, 0x20, 0x5d, 0x8c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x00,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+        0x00, 0x00, 0x02, 0x14, 0x00, 0x00, 0x00, 0x04, 0x6b, 0x31, 0x04, 0x76, 0x31, 0x00, 0x16,
+        0x00, 0x00, 0x02, 0x01, 0x0a, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00,
+    ];
+
+    const FETCH_V4_REQUEST: [u8; 64] = [
+        0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x09, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66,
+        0x6b, 0x61, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x10, 0x00, 0x00,
+    ];
+
+    const METADATA_V1_REQUEST: [u8; 21] = [
+        0x00, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66,
+        0x6b, 0x61, 0xff, 0xff, 0xff, 0xff,
+    ];
+
+    const API_VERSIONS_V0_REQUEST: [u8; 17] = [
+        0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66,
+        0x6b, 0x61,
+    ];
+
+    const API_VERSIONS_V3_REQUEST: [u8; 33] = [
+        0x00, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x07, 0x72, 0x64, 0x6b, 0x61, 0x66,
+        0x6b, 0x61, 0x00, 0x08, 0x72, 0x64, 0x6b, 0x61, 0x66, 0x6b, 0x61, 0x06, 0x32, 0x2e, 0x33,
+        0x2e, 0x30, 0x00,
+    ];
+
+    const METADATA_V1_RESPONSE: [u8; 76] = [
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x31,
+        0x32, 0x37, 0x2e, 0x30, 0x2e, 0x30, 0x2e, 0x31, 0x00, 0x00, 0x23, 0x85, 0xff, 0xff, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74,
+        0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    const PRODUCE_V3_RESPONSE: [u8; 44] = [
+        0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    const FETCH_BATCH: [u8; 84] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00, 0x00,
+        0x00, 0x02, 0x73, 0x20, 0x53, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x14, 0x00, 0x00, 0x00, 0x04, 0x6b, 0x31, 0x04, 0x76, 0x31, 0x00, 0x16, 0x00, 0x00,
+        0x02, 0x01, 0x0a, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00,
+    ];
+
+    fn decode(frame: &[u8]) -> (kafka::RequestHeader, Result<KafkaRequest, ProtoError>) {
+        let mut buf = Bytes::copy_from_slice(frame);
+        let header = kafka::decode_header(&mut buf).unwrap();
+        let req = kafka::decode_request(&header, buf);
+        (header, req)
+    }
+
+    #[test]
+    fn decode_produce_v3_request() {
+        let (header, req) = decode(&PRODUCE_V3_REQUEST);
+        assert_eq!(header.api_key, kafka::PRODUCE);
+        assert_eq!(header.api_version, 3);
+        assert_eq!(header.correlation_id, 7);
+        assert_eq!(header.client_id.as_deref(), Some("rdkafka"));
+
+        match req.unwrap() {
+            KafkaRequest::Produce(r) => {
+                assert_eq!(r.transactional_id, None);
+                assert_eq!(r.acks, 1);
+                assert_eq!(r.timeout_ms, 1500);
+                assert_eq!(r.topics.len(), 1);
+                assert_eq!(r.topics[0].name, "test");
+                assert_eq!(r.topics[0].partitions[0].index, 0);
+
+                let batch = r.topics[0].partitions[0].records.clone().unwrap();
+                let recs = records::decode_record_batches(batch).unwrap();
+                assert_eq!(recs.len(), 2);
+                assert_eq!(&recs[0].key[..], b"k1");
+                assert_eq!(&recs[0].value[..], b"v1");
+                assert_eq!(&recs[1].key[..], b"");
+                assert_eq!(&recs[1].value[..], b"hello");
+            }
+            other => panic!("expected Produce request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_fetch_v4_request() {
+        let (header, req) = decode(&FETCH_V4_REQUEST);
+        assert_eq!(header.correlation_id, 9);
+
+        match req.unwrap() {
+            KafkaRequest::Fetch(r) => {
+                assert_eq!(r.replica_id, -1);
+                assert_eq!(r.max_wait_ms, 500);
+                assert_eq!(r.min_bytes, 1);
+                assert_eq!(r.max_bytes, 1024 * 1024);
+                assert_eq!(r.topics[0].topic, "test");
+                let p = &r.topics[0].partitions[0];
+                assert_eq!(p.partition, 0);
+                assert_eq!(p.fetch_offset, 0);
+                assert_eq!(p.partition_max_bytes, 1024 * 1024);
+            }
+            other => panic!("expected Fetch request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_metadata_v1_all_topics() {
+        let (_, req) = decode(&METADATA_V1_REQUEST);
+        match req.unwrap() {
+            KafkaRequest::Metadata(r) => {
+                assert!(r.topics.is_none());
+                assert!(r.allow_auto_topic_creation);
+            }
+            other => panic!("expected Metadata request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn api_versions_v0_is_supported_and_v3_is_not() {
+        let (_, req) = decode(&API_VERSIONS_V0_REQUEST);
+        assert!(matches!(req.unwrap(), KafkaRequest::ApiVersions(_)));
+
+        let (header, req) = decode(&API_VERSIONS_V3_REQUEST);
+        assert_eq!(header.correlation_id, 1);
+        match req.unwrap_err() {
+            ProtoError::UnsupportedVersion { api_key, version } => {
+                assert_eq!(api_key, kafka::API_VERSIONS);
+                assert_eq!(version, 3);
+            }
+            other => panic!("expected UnsupportedVersion, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn encode_metadata_v1_response() {
+        let resp = KafkaResponse::Metadata(MetadataResponse {
+            throttle_time_ms: 0,
+            brokers: vec![MetadataBroker {
+                node_id: 0,
+                host: "127.0.0.1".to_string(),
+                port: 9093,
+                rack: None,
+            }],
+            cluster_id: None,
+            controller_id: 0,
+            topics: vec![MetadataTopic {
+                error_code: 0,
+                name: "test".to_string(),
+                is_internal: false,
+                partitions: vec![MetadataPartition {
+                    error_code: 0,
+                    partition_index: 0,
+                    leader_id: 0,
+                    replica_nodes: vec![0],
+                    isr_nodes: vec![0],
+                    offline_replicas: vec![],
+                }],
+            }],
+        });
+
+        let out = kafka::encode_response(2, 1, &resp).unwrap();
+        assert_eq!(&out[..], &METADATA_V1_RESPONSE[..]);
+    }
+
+    #[test]
+    fn encode_produce_v3_response() {
+        let resp = KafkaResponse::Produce(ProduceResponse {
+            responses: vec![ProduceTopicResponse {
+                name: "test".to_string(),
+                partitions: vec![ProducePartitionResponse {
+                    index: 0,
+                    error_code: 0,
+                    base_offset: 0,
+                    log_append_time_ms: -1,
+                    log_start_offset: 0,
+                }],
+            }],
+            throttle_time_ms: 0,
+        });
+
+        let out = kafka::encode_response(7, 3, &resp).unwrap();
+        assert_eq!(&out[..], &PRODUCE_V3_RESPONSE[..]);
+    }
+
+    #[test]
+    fn encode_fetched_records_as_batch() {
+        let items = vec![
+            (
+                5,
+                Record {
+                    key: Bytes::from_static(b"k1"),
+                    value: Bytes::from_static(b"v1"),
+                },
+            ),
+            (
+                6,
+                Record {
+                    key: Bytes::new(),
+                    value: Bytes::from_static(b"hello"),
+                },
+            ),
+        ];
+
+        let mut out = BytesMut::new();
+        records::encode_record_batch(&mut out, &items).unwrap();
+        assert_eq!(&out[..], &FETCH_BATCH[..]);
+
+        let decoded = records::decode_record_batches(out.freeze()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(&decoded[1].value[..], b"hello");
+    }
+
+    #[test]
+    fn reject_batch_with_bad_crc() {
+        let mut batch = FETCH_BATCH;
+        let last = batch.len() - 2;
+        batch[last] ^= 0xff;
+
+        let err = records::decode_record_batches(Bytes::copy_from_slice(&batch)).unwrap_err();
+        assert!(matches!(err, ProtoError::CorruptRecordBatch(_)));
+    }
+}
@@ -0,0 +1,189 @@
+use bytes::Bytes;
+use protocol::error::ProtoError;
+use protocol::types::{
+    AppendEntriesRequest, FetchResponse, GroupProtocol, JoinGroupRequest,
+    ListPartitionReassignmentsResponse, MetadataRequest, PartitionReassignment, ProduceRequest,
+    RaftEntry, Record, ReplicaFetchResponse, Request, Response,
+};
+use protocol::{decode_request, decode_response, encode_request, encode_response};
+
+#[test]
+fn produce_request_round_trip() {
+    let req = Request::Produce(ProduceRequest {
+        topic: "test".to_string(),
+        partition: 2,
+        acks: -1,
+        records: vec![Record {
+            key: Bytes::from_static(b"k"),
+            value: Bytes::from_static(b"v"),
+        }],
+    });
+
+    match decode_request(encode_request(&req).unwrap()).unwrap() {
+        Request::Produce(r) => {
+            assert_eq!(r.topic, "test");
+            assert_eq!(r.partition, 2);
+            assert_eq!(r.acks, -1);
+            assert_eq!(&r.records[0].key[..], b"k");
+            assert_eq!(&r.records[0].value[..], b"v");
+        }
+        other => panic!("expected Produce request, got {other:?}"),
+    }
+}
+
+#[test]
+fn group_and_metadata_requests_round_trip() {
+    let req = Request::JoinGroup(JoinGroupRequest {
+        group_id: "g".to_string(),
+        member_id: String::new(),
+        session_timeout_ms: 10_000,
+        rebalance_timeout_ms: 30_000,
+        protocol_type: "consumer".to_string(),
+        protocols: vec![GroupProtocol {
+            name: "range".to_string(),
+            metadata: Bytes::from_static(b"m"),
+        }],
+    });
+    match decode_request(encode_request(&req).unwrap()).unwrap() {
+        Request::JoinGroup(r) => {
+            assert_eq!(r.group_id, "g");
+            assert_eq!(r.rebalance_timeout_ms, 30_000);
+            assert_eq!(r.protocols[0].name, "range");
+        }
+        other => panic!("expected JoinGroup request, got {other:?}"),
+    }
+
+    let req = Request::Metadata(MetadataRequest {
+        topics: vec!["a".to_string(), "b".to_string()],
+        allow_auto_create: false,
+    });
+    match decode_request(encode_request(&req).unwrap()).unwrap() {
+        Request::Metadata(r) => {
+            assert_eq!(r.topics, vec!["a", "b"]);
+            assert!(!r.allow_auto_create);
+        }
+        other => panic!("expected Metadata request, got {other:?}"),
+    }
+}
+
+#[test]
+fn fetch_and_error_responses_round_trip() {
+    let resp = Response::Fetch(FetchResponse {
+        status: 0,
+        high_watermark: 8,
+        log_start_offset: 0,
+        throttle_time_ms: 250,
+        items: vec![(
+            7,
+            Record {
+                key: Bytes::new(),
+                value: Bytes::from_static(b"hello"),
+            },
+        )],
+    });
+    match decode_response(encode_response(resp).unwrap()).unwrap() {
+        Response::Fetch(r) => {
+            assert_eq!(r.high_watermark, 8);
+            assert_eq!(r.throttle_time_ms, 250);
+            assert_eq!(r.items[0].0, 7);
+            assert_eq!(&r.items[0].1.value[..], b"hello");
+        }
+        other => panic!("expected Fetch response, got {other:?}"),
+    }
+
+    let resp = Response::Error {
+        message: "boom".to_string(),
+    };
+    let bytes = encode_response(resp).unwrap();
+    assert_eq!(bytes[0], 255);
+    match decode_response(bytes).unwrap() {
+        Response::Error { message } => assert_eq!(message, "boom"),
+        other => panic!("expected Error response, got {other:?}"),
+    }
+}
+
+#[test]
+fn append_entries_request_round_trip() {
+    let req = Request::AppendEntries(AppendEntriesRequest {
+        term: 3,
+        leader_id: 1,
+        offset: 5,
+        prev_log_term: 2,
+        entries: vec![RaftEntry {
+            term: 3,
+            data: Bytes::from_static(b"d"),
+        }],
+        commit_offset: 4,
+    });
+    match decode_request(encode_request(&req).unwrap()).unwrap() {
+        Request::AppendEntries(r) => {
+            assert_eq!((r.term, r.leader_id, r.offset), (3, 1, 5));
+            assert_eq!((r.prev_log_term, r.commit_offset), (2, 4));
+            assert_eq!(r.entries[0].term, 3);
+            assert_eq!(&r.entries[0].data[..], b"d");
+        }
+        other => panic!("expected AppendEntries request, got {other:?}"),
+    }
+}
+
+#[test]
+fn replica_fetch_response_round_trip() {
+    let resp = Response::ReplicaFetch(ReplicaFetchResponse {
+        status: 0,
+        high_watermark: 4,
+        log_start_offset: 0,
+        epochs: vec![(2, 1), (5, 3)],
+        items: vec![(
+            3,
+            Record {
+                key: Bytes::new(),
+                value: Bytes::from_static(b"v"),
+            },
+        )],
+    });
+    match decode_response(encode_response(resp).unwrap()).unwrap() {
+        Response::ReplicaFetch(r) => {
+            assert_eq!(r.epochs, [(2, 1), (5, 3)]);
+            assert_eq!(r.items[0].0, 3);
+        }
+        other => panic!("expected ReplicaFetch response, got {other:?}"),
+    }
+}
+
+#[test]
+fn partition_reassignments_round_trip() {
+    let resp = Response::ListPartitionReassignments(ListPartitionReassignmentsResponse {
+        status: 0,
+        reassignments: vec![PartitionReassignment {
+            topic: "t".to_string(),
+            partition: 2,
+            replicas: vec![3, 1, 2],
+            adding: vec![3],
+            removing: vec![2],
+            throttle_bytes_per_sec: 1024,
+            log_end_offset: 9,
+            replica_offsets: vec![(3, 4)],
+        }],
+    });
+    match decode_response(encode_response(resp).unwrap()).unwrap() {
+        Response::ListPartitionReassignments(r) => {
+            let [r] = &r.reassignments[..] else {
+                panic!("expected one reassignment, got {:?}", r.reassignments);
+            };
+            assert_eq!((r.topic.as_str(), r.partition), ("t", 2));
+            assert_eq!(r.replicas, [3, 1, 2]);
+            assert_eq!((&r.adding[..], &r.removing[..]), (&[3][..], &[2][..]));
+            assert_eq!(r.throttle_bytes_per_sec, 1024);
+            assert_eq!(r.replica_offsets, [(3, 4)]);
+        }
+        other => panic!("expected ListPartitionReassignments response, got {other:?}"),
+    }
+}
+
+#[test]
+fn unknown_api_key_is_reported() {
+    match decode_request(Bytes::from_static(&[42])).unwrap_err() {
+        ProtoError::InvalidApiKey(42) => {}
+        e => panic!("expected InvalidApiKey, got {e:?}"),
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::StorageError;
+
+const VERSION: &str = "0";
+
+/// The leader epochs a partition's records were appended in, each with the
+/// offset of its first record, kept in a `{topic}-{partition}.epochs` file
+/// next to the log:
+///
+/// ```text
+/// 0         version
+/// 2         entries
+/// 0 0       epoch, start offset
+/// 3 120
+/// ```
+///
+/// Epochs and start offsets both only go up. An epoch ends where the next one
+/// starts, the last one at the end of the log.
+#[derive(Debug)]
+pub struct LeaderEpochCache {
+    path: PathBuf,
+    entries: Vec<(i32, i64)>,
+}
+
+impl LeaderEpochCache {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(text) => parse(&text).ok_or(StorageError::Corrupted)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[(i32, i64)] {
+        &self.entries
+    }
+
+    pub fn latest_epoch(&self) -> Option<i32> {
+        self.entries.last().map(|&(epoch, _)| epoch)
+    }
+
+    /// Notes that records from `start_offset` on are appended in `epoch`.
+    /// Entries for later epochs or offsets are dropped, and an older epoch
+    /// than the latest one is ignored.
+    pub fn assign(&mut self, epoch: i32, start_offset: i64) -> Result<(), StorageError> {
+        if self.latest_epoch().is_some_and(|latest| latest >= epoch) {
+            return Ok(());
+        }
+        self.entries.retain(|&(_, start)| start < start_offset);
+        self.entries.push((epoch, start_offset));
+        self.save()
+    }
+
+    /// The latest epoch up to `epoch` and the offset it ends at, with
+    /// `log_end_offset` as the end of the last one. Both are -1 when every
+    /// known epoch is later than `epoch`.
+    pub fn end_offset_for(&self, epoch: i32, log_end_offset: i64) -> (i32, i64) {
+        let i = self.entries.partition_point(|&(e, _)| e <= epoch);
+        if i == 0 {
+            return (-1, -1);
+        }
+        let end = self
+            .entries
+            .get(i)
+            .map_or(log_end_offset, |&(_, start)| start);
+        (self.entries[i - 1].0, end)
+    }
+
+    /// Drops the epochs starting at `offset` or after, where the log now
+    /// ends.
+    pub fn truncate_from(&mut self, offset: i64) -> Result<(), StorageError> {
+        let len = self.entries.len();
+        self.entries.retain(|&(_, start)| start < offset);
+        if self.entries.len() == len {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Drops the epochs that ended before `offset`, where the log now starts.
+    pub fn truncate_before(&mut self, offset: i64) -> Result<(), StorageError> {
+        // the epoch `offset` falls in stays, starting there
+        let covering = self.entries.partition_point(|&(_, start)| start <= offset);
+        if covering == 0 {
+            return Ok(());
+        }
+        self.entries.drain(..covering - 1);
+        if self.entries[0].1 == offset && covering == 1 {
+            return Ok(());
+        }
+        self.entries[0].1 = offset;
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> Result<(), StorageError> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        self.entries.clear();
+        self.save()
+    }
+
+    /// Writes the entries next to the file, then swaps them in.
+    fn save(&self) -> Result<(), StorageError> {
+        let mut text = format!("{VERSION}\n{}\n", self.entries.len());
+        for (epoch, start) in &self.entries {
+            text.push_str(&format!("{epoch} {start}\n"));
+        }
+        let tmp = self.path.with_extension("epochs.tmp");
+        let mut out = File::create(&tmp)?;
+        out.write_all(text.as_bytes())?;
+        out.sync_data()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn parse(text: &str) -> Option<Vec<(i32, i64)>> {
+    let mut lines = text.lines();
+    if lines.next()? != VERSION {
+        return None;
+    }
+    let count: usize = lines.next()?.parse().ok()?;
+    let entries = lines
+        .map(|line| {
+            let (epoch, start) = line.split_once(' ')?;
+            Some((epoch.parse().ok()?, start.parse().ok()?))
+        })
+        .collect::<Option<Vec<(i32, i64)>>>()?;
+    let ordered = entries
+        .windows(2)
+        .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1);
+    (entries.len() == count && ordered).then_some(entries)
+}
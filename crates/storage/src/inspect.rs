@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use bytes::{Buf, Bytes};
+
+use crate::StorageError;
+
+/// A record as laid out in a log file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub offset: i64,
+    /// Byte position of the record in the file.
+    pub position: u64,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+impl LogEntry {
+    /// Bytes the record takes on disk.
+    pub fn size(&self) -> u64 {
+        (8 + 2 + self.key.len() + 4 + self.value.len()) as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The file ends inside a record, as after an interrupted append.
+    Torn,
+    /// A record's offset doesn't follow the one before it.
+    OffsetOutOfOrder { expected: i64, found: i64 },
+}
+
+/// The first record of a log file that can't be read, and everything after
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Corruption {
+    /// Where the bad record starts, which is also how much of the file is
+    /// good.
+    pub position: u64,
+    pub kind: CorruptionKind,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            CorruptionKind::Torn => write!(f, "torn record at byte {}", self.position),
+            CorruptionKind::OffsetOutOfOrder { expected, found } => write!(
+                f,
+                "offset {found} where {expected} was expected at byte {}",
+                self.position
+            ),
+        }
+    }
+}
+
+/// A log file read record by record, as far as it is readable.
+#[derive(Debug)]
+pub struct LogFile {
+    pub entries: Vec<LogEntry>,
+    /// Length of the file.
+    pub len: u64,
+    pub corruption: Option<Corruption>,
+}
+
+impl LogFile {
+    /// Reads the records of the log at `path` without opening it as a
+    /// `PartitionLog`, so that a log which fails with `Corrupted` can be
+    /// looked at. Records carry no timestamps or checksums, so a record is
+    /// only known to be bad when it is cut short or out of sequence.
+    pub fn read(path: &Path) -> Result<Self, StorageError> {
+        let buf = Bytes::from(std::fs::read(path)?);
+        let len = buf.len() as u64;
+        let mut rest = buf.clone();
+        let mut entries: Vec<LogEntry> = Vec::new();
+        let mut corruption = None;
+
+        while rest.has_remaining() {
+            let position = len - rest.remaining() as u64;
+            let Some(entry) = Self::next_entry(&mut rest, position) else {
+                corruption = Some(Corruption {
+                    position,
+                    kind: CorruptionKind::Torn,
+                });
+                break;
+            };
+            // retention may have removed the first records, but after that
+            // offsets go up by one
+            if let Some(last) = entries.last()
+                && entry.offset != last.offset + 1
+            {
+                corruption = Some(Corruption {
+                    position,
+                    kind: CorruptionKind::OffsetOutOfOrder {
+                        expected: last.offset + 1,
+                        found: entry.offset,
+                    },
+                });
+                break;
+            }
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            entries,
+            len,
+            corruption,
+        })
+    }
+
+    fn next_entry(rest: &mut Bytes, position: u64) -> Option<LogEntry> {
+        // [offset:i64][klen:u16][key bytes][vlen:u32][value bytes]
+        if rest.remaining() < 8 + 2 {
+            return None;
+        }
+        let offset = rest.get_i64();
+        let klen = rest.get_u16() as usize;
+        if rest.remaining() < klen + 4 {
+            return None;
+        }
+        let key = rest.split_to(klen);
+        let vlen = rest.get_u32() as usize;
+        if rest.remaining() < vlen {
+            return None;
+        }
+        let value = rest.split_to(vlen);
+        Some(LogEntry {
+            offset,
+            position,
+            key,
+            value,
+        })
+    }
+
+    /// How many bytes from the start of the file are good records.
+    pub fn valid_len(&self) -> u64 {
+        self.corruption.map_or(self.len, |c| c.position)
+    }
+
+    /// The offset index of the good records, mapping each offset to the byte
+    /// position of its record.
+    pub fn index(&self) -> BTreeMap<i64, u64> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.offset, entry.position))
+            .collect()
+    }
+}
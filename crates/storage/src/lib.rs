@@ -3,31 +3,93 @@ use std::{
     fs::{File, OpenOptions},
     io::{Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use bytes::{Buf, BufMut, BytesMut};
 use protocol::types::Record;
 
+mod epoch;
+mod inspect;
+
+pub use epoch::LeaderEpochCache;
+pub use inspect::{Corruption, CorruptionKind, LogEntry, LogFile};
+
 #[derive(thiserror::Error, Debug)]
 pub enum StorageError {
     #[error("io: {0}")]
     Io(#[from] std::io::Error),
     #[error("corrupted log")]
     Corrupted,
+    #[error("record too large: {key_len} byte key, {value_len} byte value")]
+    RecordTooLarge { key_len: usize, value_len: usize },
+}
+
+/// A byte range of a partition log holding `records` whole records. The on-disk
+/// record layout is the same as `FetchResponse::items` on the wire, so a region
+/// can be copied to a socket unchanged. The log never shrinks the file a region
+/// was taken from, so its bytes stay put however the log changes meanwhile.
+#[derive(Debug)]
+pub struct FileRegion {
+    pub file: File,
+    pub position: u64,
+    pub len: u64,
+    pub records: u16,
+}
+
+/// Retention and flush settings of a partition log.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    /// Oldest records are removed once the log grows an eighth past this size,
+    /// bringing it back under it, so the log isn't rewritten on every append.
+    /// The newest record is always kept. Records carry no timestamps, so there
+    /// is no time based retention.
+    pub retention_bytes: Option<u64>,
+    /// Appends are synced to disk after this many records...
+    pub flush_interval_messages: u64,
+    /// ...or when an append comes this long after the last sync.
+    pub flush_interval: Option<Duration>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            retention_bytes: None,
+            flush_interval_messages: 1,
+            flush_interval: None,
+        }
+    }
 }
 
+/// Logs may grow past their retention limit by this fraction of it before
+/// they are trimmed.
+const RETENTION_SLACK: u64 = 8;
+
 // [offset:i64][klen:u16][key bytes][vlen:u32][value bytes]
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct PartitionLog {
     path: PathBuf,
     file: File,
     next_offset: i64,
     index: BTreeMap<i64, u64>,
+    size: u64,
+    config: LogConfig,
+    unflushed: u64,
+    last_flush: Instant,
+    epochs: LeaderEpochCache,
 }
 
 impl PartitionLog {
     pub fn open(dir: &Path, topic: &str, partition: u16) -> Result<Self, StorageError> {
+        Self::open_with(dir, topic, partition, LogConfig::default())
+    }
+
+    pub fn open_with(
+        dir: &Path,
+        topic: &str,
+        partition: u16,
+        config: LogConfig,
+    ) -> Result<Self, StorageError> {
         std::fs::create_dir_all(dir)?;
         let filename = format!("{topic}-{partition}.log");
         let path = dir.join(filename);
@@ -39,19 +101,62 @@ impl PartitionLog {
             .open(&path)?;
 
         let mut scan = OpenOptions::new().read(true).open(&path)?;
-        let (index, next_offset) = Self::scan_build_index(&mut scan)?;
+        let (index, next_offset, valid_len) = Self::scan_build_index(&mut scan)?;
+
+        let size = file.seek(SeekFrom::End(0))?;
+        if valid_len != size {
+            return Err(StorageError::Corrupted);
+        }
 
-        let _ = file.seek(SeekFrom::End(0));
+        let mut epochs = LeaderEpochCache::open(&dir.join(format!("{topic}-{partition}.epochs")))?;
+        // epochs of records cut off outside the broker; the latest one may
+        // start at the end of the log, before its first record
+        epochs.truncate_from(next_offset + 1)?;
 
         Ok(Self {
             path,
             file,
             next_offset,
             index,
+            size,
+            config,
+            unflushed: 0,
+            last_flush: Instant::now(),
+            epochs,
         })
     }
 
-    fn scan_build_index(f: &mut File) -> Result<(BTreeMap<i64, u64>, i64), StorageError> {
+    /// Opens the log after an unclean shutdown, cutting off a record torn by an
+    /// interrupted append instead of failing with `Corrupted`.
+    pub fn recover(
+        dir: &Path,
+        topic: &str,
+        partition: u16,
+        config: LogConfig,
+    ) -> Result<Self, StorageError> {
+        let path = dir.join(format!("{topic}-{partition}.log"));
+        if let Ok(mut f) = OpenOptions::new().read(true).write(true).open(&path) {
+            let (_, _, valid_len) = Self::scan_build_index(&mut f)?;
+            if valid_len < f.metadata()?.len() {
+                f.set_len(valid_len)?;
+                f.sync_all()?;
+            }
+        }
+        Self::open_with(dir, topic, partition, config)
+    }
+
+    /// The offset index `open` builds for the log file at `path`. It covers
+    /// every complete record, whether or not its offset follows the one
+    /// before it.
+    pub fn scan_index(path: &Path) -> Result<BTreeMap<i64, u64>, StorageError> {
+        let mut f = OpenOptions::new().read(true).open(path)?;
+        Ok(Self::scan_build_index(&mut f)?.0)
+    }
+
+    /// Indexes the complete records of `f`, returning the index, the next offset
+    /// and the length of the complete records, which is short of the file length
+    /// if the last record is torn.
+    fn scan_build_index(f: &mut File) -> Result<(BTreeMap<i64, u64>, i64, u64), StorageError> {
         let mut index = BTreeMap::new();
         let _ = f.seek(SeekFrom::Start(0));
 
@@ -60,36 +165,37 @@ impl PartitionLog {
 
         let mut cur = std::io::Cursor::new(&buf);
         let mut next_offset = 0i64;
+        let mut valid_len = 0u64;
 
         while (cur.position() as usize) < buf.len() {
-            let pos = cur.position() as u64;
+            let pos = cur.position();
 
             if buf.len() - (cur.position() as usize) < 8 {
-                return Err(StorageError::Corrupted);
+                break;
             }
 
             // read offset value
             let offset = cur.get_i64();
             if buf.len() - (cur.position() as usize) < 2 {
-                return Err(StorageError::Corrupted);
+                break;
             }
 
             // read key length
             let klen = cur.get_u16() as usize;
             if buf.len() - (cur.position() as usize) < klen {
-                return Err(StorageError::Corrupted);
+                break;
             }
 
             // skip key bytes
             cur.set_position(cur.position() + klen as u64);
             if buf.len() - (cur.position() as usize) < 4 {
-                return Err(StorageError::Corrupted);
+                break;
             }
 
             // read value length
             let vlen = cur.get_u32() as usize;
             if buf.len() - (cur.position() as usize) < vlen {
-                return Err(StorageError::Corrupted);
+                break;
             }
 
             // skip value bytes
@@ -97,12 +203,38 @@ impl PartitionLog {
 
             index.insert(offset, pos);
             next_offset = offset + 1;
+            valid_len = cur.position();
         }
 
-        Ok((index, next_offset))
+        Ok((index, next_offset, valid_len))
+    }
+
+    pub fn next_offset(&self) -> i64 {
+        self.next_offset
+    }
+
+    /// Size of the log file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn start_offset(&self) -> i64 {
+        self.index
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.next_offset)
     }
 
+    /// Appends `records`, returning the offset of the first. Nothing is
+    /// written if any key is longer than a u16 or value than a u32.
     pub fn append(&mut self, records: &[Record]) -> Result<i64, StorageError> {
+        if let Some(rec) = records.iter().find(|rec| !rec.fits_length_prefixes()) {
+            return Err(StorageError::RecordTooLarge {
+                key_len: rec.key.len(),
+                value_len: rec.value.len(),
+            });
+        }
         let base = self.next_offset;
 
         for rec in records {
@@ -118,6 +250,7 @@ impl PartitionLog {
 
             self.file.write_all(&out)?;
             self.index.insert(offset, pos);
+            self.size = pos + out.len() as u64;
 
             self.next_offset += 1;
         }
@@ -125,13 +258,194 @@ impl PartitionLog {
         // flush to OS buffer
         self.file.flush()?;
 
-        // commit to disk
-        self.file.sync_data()?;
+        self.unflushed += records.len() as u64;
+        let interval_elapsed = self
+            .config
+            .flush_interval
+            .is_some_and(|interval| self.last_flush.elapsed() >= interval);
+        if self.unflushed >= self.config.flush_interval_messages || interval_elapsed {
+            self.sync()?;
+        }
+
+        if let Some(limit) = self.config.retention_bytes {
+            self.enforce_retention(limit)?;
+        }
 
         Ok(base)
     }
 
-    //// Fetch records starting from offset, up to max_bytes
+    /// Removes every record, so that the next one appended gets `next_offset`,
+    /// as when a follower falls behind the start of its leader's log. Until a
+    /// record is appended, the log starts over at 0 when it is reopened.
+    pub fn reset(&mut self, next_offset: i64) -> Result<(), StorageError> {
+        self.cut(0)?;
+        self.index.clear();
+        self.next_offset = next_offset;
+        self.epochs.clear()
+    }
+
+    /// Removes the records at `offset` and after, as when a replica's log
+    /// diverged from its leader's, so that the next record appended gets
+    /// `offset`. Removing every record works like `reset`.
+    pub fn truncate(&mut self, offset: i64) -> Result<(), StorageError> {
+        if offset >= self.next_offset {
+            return Ok(());
+        }
+        if offset <= self.start_offset() {
+            return self.reset(offset);
+        }
+        self.cut(self.index[&offset])?;
+        self.index.split_off(&offset);
+        self.next_offset = offset;
+        self.epochs.truncate_from(offset)
+    }
+
+    /// Notes that the records appended from now on are appended in leader
+    /// epoch `epoch`. Epochs older than the latest one are ignored.
+    pub fn assign_epoch(&mut self, epoch: i32) -> Result<(), StorageError> {
+        self.epochs.assign(epoch, self.next_offset)
+    }
+
+    /// The leader epochs of the records, each with the offset of its first
+    /// record.
+    pub fn epochs(&self) -> &[(i32, i64)] {
+        self.epochs.entries()
+    }
+
+    pub fn latest_epoch(&self) -> Option<i32> {
+        self.epochs.latest_epoch()
+    }
+
+    /// The latest leader epoch up to `epoch` and the offset after its last
+    /// record, or -1 for both if the log has no records of such an epoch.
+    pub fn end_offset_for_epoch(&self, epoch: i32) -> (i32, i64) {
+        self.epochs.end_offset_for(epoch, self.next_offset)
+    }
+
+    /// Cuts the file off at `len` bytes.
+    fn cut(&mut self, len: u64) -> Result<(), StorageError> {
+        self.swap_in(0..len)
+    }
+
+    /// Replaces the file with the `range` of its bytes. They are written next to
+    /// the log and then renamed over it, so readers holding the old file keep
+    /// their view of it.
+    fn swap_in(&mut self, range: std::ops::Range<u64>) -> Result<(), StorageError> {
+        let tmp = self.path.with_extension("log.tmp");
+        let mut out = File::create(&tmp)?;
+        let mut f = OpenOptions::new().read(true).open(&self.path)?;
+        f.seek(SeekFrom::Start(range.start))?;
+        std::io::copy(&mut f.take(range.end - range.start), &mut out)?;
+        out.sync_data()?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        // appends find their position from the file's
+        self.size = self.file.seek(SeekFrom::End(0))?;
+        self.unflushed = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Commits all appended records to disk.
+    pub fn sync(&mut self) -> Result<(), StorageError> {
+        let start = Instant::now();
+        if let Err(e) = self.file.sync_data() {
+            metrics::counter("mini_kafka_fsync_errors_total", "Failed log syncs", &[]).inc();
+            return Err(e.into());
+        }
+        metrics::histogram(
+            "mini_kafka_fsync_duration_seconds",
+            "Time taken to sync a log to disk",
+            &[],
+        )
+        .observe(start.elapsed());
+        self.unflushed = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Rewrites the log without the oldest records until it fits in `limit` bytes,
+    /// once it has grown past the limit by `RETENTION_SLACK`.
+    fn enforce_retention(&mut self, limit: u64) -> Result<(), StorageError> {
+        if self.size <= limit + limit / RETENTION_SLACK {
+            return Ok(());
+        }
+        let Some((&start, &cut)) = self
+            .index
+            .iter()
+            .find(|&(_, &pos)| self.size - pos <= limit)
+            .or_else(|| self.index.iter().next_back())
+        else {
+            return Ok(());
+        };
+        if cut == 0 {
+            return Ok(());
+        }
+
+        self.swap_in(cut..self.size)?;
+        self.index = self
+            .index
+            .split_off(&start)
+            .into_iter()
+            .map(|(offset, pos)| (offset, pos - cut))
+            .collect();
+        self.epochs.truncate_before(start)
+    }
+
+    /// Like `fetch`, but returns the file range of the matching records instead of
+    /// reading them. At most `u16::MAX` records fit in one region.
+    pub fn fetch_region(&self, offset: i64, max_bytes: u32) -> Result<FileRegion, StorageError> {
+        self.fetch_region_until(offset, self.next_offset, max_bytes)
+    }
+
+    /// Like `fetch_region`, but stops before the record at `until`.
+    pub fn fetch_region_until(
+        &self,
+        offset: i64,
+        until: i64,
+        max_bytes: u32,
+    ) -> Result<FileRegion, StorageError> {
+        let file = OpenOptions::new().read(true).open(&self.path)?;
+        let file_len = file.metadata()?.len();
+        let until = until.max(offset);
+        let region_end = match self.index.range(until..).next() {
+            Some((_, &pos)) => pos,
+            None => file_len,
+        };
+
+        let mut entries = self
+            .index
+            .range(offset..until)
+            .map(|(_, &pos)| pos)
+            .peekable();
+        let position = entries.peek().copied().unwrap_or(region_end);
+        let mut end = position;
+        let mut records = 0u16;
+
+        while let Some(pos) = entries.next() {
+            let next = entries.peek().copied().unwrap_or(region_end);
+            if (records > 0 && next - position > max_bytes as u64) || records == u16::MAX {
+                break;
+            }
+            debug_assert_eq!(pos, end);
+            end = next;
+            records += 1;
+        }
+
+        Ok(FileRegion {
+            file,
+            position,
+            len: end - position,
+            records,
+        })
+    }
+
+    //// Fetch records starting from offset, up to max_bytes. The first record is
+    //// returned even when it is larger, so readers can't get stuck behind it.
     pub fn fetch(&self, offset: i64, max_bytes: u32) -> Result<Vec<(i64, Record)>, StorageError> {
         // Get current offset + position
         let start = match self.index.range(offset..).next() {
@@ -147,7 +461,7 @@ impl PartitionLog {
         let mut items = Vec::new();
 
         loop {
-            if remaining < 8 + 2 + 4 {
+            if !items.is_empty() && remaining < 8 + 2 + 4 {
                 break;
             }
 
@@ -172,7 +486,7 @@ impl PartitionLog {
             let klen = cur.get_u16() as usize;
             remaining = remaining.saturating_sub(2);
 
-            if remaining < klen {
+            if !items.is_empty() && remaining < klen {
                 break;
             }
 
@@ -188,7 +502,7 @@ impl PartitionLog {
             let vlen = cur.get_u32() as usize;
             remaining = remaining.saturating_sub(4);
 
-            if remaining < vlen {
+            if !items.is_empty() && remaining < vlen {
                 break;
             }
 
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use protocol::types::Record;
+use storage::{LogConfig, PartitionLog};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// 8 + 2 + 0 + 4 + 10 bytes on disk
+fn record(i: usize) -> Record {
+    Record {
+        key: Bytes::new(),
+        value: Bytes::from(format!("value-{i:04}")),
+    }
+}
+
+const RECORD_SIZE: u64 = 24;
+
+fn append(log: &mut PartitionLog, n: usize) {
+    let records: Vec<_> = (0..n).map(record).collect();
+    log.append(&records).unwrap();
+}
+
+#[test]
+fn epochs_end_where_the_next_one_starts() {
+    let dir = temp_data_dir("epochs-end");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    assert_eq!(log.latest_epoch(), None);
+    assert_eq!(log.end_offset_for_epoch(0), (-1, -1));
+
+    log.assign_epoch(1).unwrap();
+    append(&mut log, 3);
+    log.assign_epoch(3).unwrap();
+    append(&mut log, 2);
+    // an older epoch than the latest changes nothing
+    log.assign_epoch(2).unwrap();
+    assert_eq!(log.epochs(), [(1, 0), (3, 3)]);
+
+    assert_eq!(log.end_offset_for_epoch(0), (-1, -1));
+    assert_eq!(log.end_offset_for_epoch(1), (1, 3));
+    // epoch 2 had no records here, so it ends where epoch 1 does
+    assert_eq!(log.end_offset_for_epoch(2), (1, 3));
+    assert_eq!(log.end_offset_for_epoch(3), (3, 5));
+    assert_eq!(log.end_offset_for_epoch(7), (3, 5));
+
+    // an epoch without records yet is replaced by the next one
+    log.assign_epoch(4).unwrap();
+    log.assign_epoch(5).unwrap();
+    assert_eq!(log.epochs(), [(1, 0), (3, 3), (5, 5)]);
+}
+
+#[test]
+fn epochs_follow_truncation_and_retention() {
+    let dir = temp_data_dir("epochs-truncate");
+    let config = LogConfig {
+        retention_bytes: Some(4 * RECORD_SIZE),
+        ..LogConfig::default()
+    };
+    let mut log = PartitionLog::open_with(&dir, "t", 0, config).unwrap();
+    log.assign_epoch(0).unwrap();
+    append(&mut log, 2);
+    log.assign_epoch(1).unwrap();
+    append(&mut log, 2);
+    log.assign_epoch(2).unwrap();
+    append(&mut log, 1);
+
+    // the oldest record went, and epoch 0 now starts with the log
+    assert_eq!(log.start_offset(), 1);
+    assert_eq!(log.epochs(), [(0, 1), (1, 2), (2, 4)]);
+
+    log.truncate(4).unwrap();
+    assert_eq!(log.epochs(), [(0, 1), (1, 2)]);
+    assert_eq!(log.end_offset_for_epoch(2), (1, 4));
+
+    log.reset(9).unwrap();
+    assert!(log.epochs().is_empty());
+}
+
+#[test]
+fn epochs_survive_restarts() {
+    let dir = temp_data_dir("epochs-restart");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.assign_epoch(0).unwrap();
+    append(&mut log, 2);
+    log.assign_epoch(4).unwrap();
+    drop(log);
+
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    assert_eq!(log.epochs(), [(0, 0), (4, 2)]);
+    append(&mut log, 1);
+    drop(log);
+
+    // records cut off without the log knowing take their epochs with them
+    let path = dir.join("t-0.log");
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(RECORD_SIZE).unwrap();
+    drop(file);
+    let log = PartitionLog::open(&dir, "t", 0).unwrap();
+    assert_eq!(log.epochs(), [(0, 0)]);
+
+    std::fs::write(dir.join("t-0.epochs"), "0\n2\n0 0\n").unwrap();
+    assert!(PartitionLog::open(&dir, "t", 0).is_err());
+}
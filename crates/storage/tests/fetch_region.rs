@@ -0,0 +1,110 @@
+use std::{io::Read, io::Seek, io::SeekFrom, path::PathBuf};
+
+use bytes::{Bytes, BytesMut};
+use common::Encode;
+use protocol::types::Record;
+use storage::PartitionLog;
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn record(key: &'static [u8], value: &'static [u8]) -> Record {
+    Record {
+        key: Bytes::from_static(key),
+        value: Bytes::from_static(value),
+    }
+}
+
+fn read_region(region: &mut storage::FileRegion) -> Vec<u8> {
+    let mut out = vec![0u8; region.len as usize];
+    region.file.seek(SeekFrom::Start(region.position)).unwrap();
+    region.file.read_exact(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn region_bytes_match_encoded_items() {
+    let dir = temp_data_dir("fetch-region");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(b"a", b"1"), record(b"", b"22"), record(b"c", b"333")])
+        .unwrap();
+
+    for (offset, max_bytes) in [(0, u32::MAX), (1, u32::MAX), (0, 40), (3, u32::MAX)] {
+        let items = log.fetch(offset, max_bytes).unwrap();
+        let mut region = log.fetch_region(offset, max_bytes).unwrap();
+
+        let mut expected = BytesMut::new();
+        for item in &items {
+            item.encode(&mut expected).unwrap();
+        }
+        assert_eq!(region.records as usize, items.len());
+        assert_eq!(read_region(&mut region), &expected[..]);
+    }
+}
+
+#[test]
+fn region_never_splits_a_record() {
+    let dir = temp_data_dir("fetch-region-split");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(b"k", b"value"), record(b"k", b"value")])
+        .unwrap();
+
+    // one record takes 8 + 2 + 1 + 4 + 5 = 20 bytes
+    let region = log.fetch_region(0, 39).unwrap();
+    assert_eq!((region.records, region.len), (1, 20));
+    let region = log.fetch_region(0, 40).unwrap();
+    assert_eq!((region.records, region.len), (2, 40));
+}
+
+#[test]
+fn first_record_is_returned_past_max_bytes() {
+    let dir = temp_data_dir("fetch-first-record");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(b"k", b"value"), record(b"k", b"value")])
+        .unwrap();
+
+    let items = log.fetch(0, 1).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(&items[0].1.value[..], b"value");
+    let region = log.fetch_region(0, 1).unwrap();
+    assert_eq!((region.records, region.len), (1, 20));
+}
+
+#[test]
+fn region_survives_truncation_during_a_fetch() {
+    let dir = temp_data_dir("fetch-region-truncate");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(b"a", b"1"), record(b"b", b"2"), record(b"c", b"3")])
+        .unwrap();
+    let mut region = log.fetch_region(1, u32::MAX).unwrap();
+    let expected = read_region(&mut region);
+
+    // the follower diverged: its tail is replaced while the region is being sent
+    log.truncate(1).unwrap();
+    log.append(&[record(b"x", b"other value")]).unwrap();
+    assert_eq!(
+        &log.fetch(1, u32::MAX).unwrap()[0].1.value[..],
+        b"other value"
+    );
+    assert_eq!(read_region(&mut region), expected);
+
+    log.reset(5).unwrap();
+    assert_eq!(read_region(&mut region), expected);
+}
+
+#[test]
+fn region_stops_before_until() {
+    let dir = temp_data_dir("fetch-region-until");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(b"", b"1"), record(b"", b"2"), record(b"", b"3")])
+        .unwrap();
+
+    // each record takes 8 + 2 + 4 + 1 = 15 bytes
+    let region = log.fetch_region_until(0, 2, u32::MAX).unwrap();
+    assert_eq!((region.position, region.records, region.len), (0, 2, 30));
+    let region = log.fetch_region_until(2, 2, u32::MAX).unwrap();
+    assert_eq!((region.position, region.records, region.len), (30, 0, 0));
+}
@@ -0,0 +1,104 @@
+use std::{io::Write, path::PathBuf};
+
+use bytes::Bytes;
+use protocol::types::Record;
+use storage::{CorruptionKind, LogFile, PartitionLog, StorageError};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn record(key: &str, value: &str) -> Record {
+    Record {
+        key: Bytes::copy_from_slice(key.as_bytes()),
+        value: Bytes::copy_from_slice(value.as_bytes()),
+    }
+}
+
+fn append_raw(path: &PathBuf, bytes: &[u8]) {
+    let mut f = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+    f.write_all(bytes).unwrap();
+}
+
+#[test]
+fn intact_log_is_listed() {
+    let dir = temp_data_dir("inspect-intact");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record("k", "first"), record("", "second")])
+        .unwrap();
+
+    let file = LogFile::read(&dir.join("t-0.log")).unwrap();
+    assert_eq!(file.corruption, None);
+    assert_eq!(file.valid_len(), file.len);
+    let entries: Vec<_> = file
+        .entries
+        .iter()
+        .map(|e| (e.offset, e.position, e.key.len(), e.value.len()))
+        .collect();
+    assert_eq!(entries, [(0, 0, 1, 5), (1, 20, 0, 6)]);
+    assert_eq!(file.entries[1].size(), 20);
+}
+
+#[test]
+fn torn_record_is_found() {
+    let dir = temp_data_dir("inspect-torn");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record("", "kept")]).unwrap();
+    drop(log);
+    let path = dir.join("t-0.log");
+    // an offset and a key length promising more than is there
+    append_raw(&path, &[0, 0, 0, 0, 0, 0, 0, 1, 0, 9, b'k']);
+
+    assert!(matches!(
+        PartitionLog::open(&dir, "t", 0),
+        Err(StorageError::Corrupted)
+    ));
+    let file = LogFile::read(&path).unwrap();
+    assert_eq!(file.entries.len(), 1);
+    let corruption = file.corruption.unwrap();
+    assert_eq!(corruption.kind, CorruptionKind::Torn);
+    assert_eq!(corruption.position, 18);
+    assert_eq!(file.valid_len(), 18);
+    assert_eq!(file.len, 29);
+}
+
+#[test]
+fn offset_gap_is_found() {
+    let dir = temp_data_dir("inspect-gap");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record("", "a")]).unwrap();
+    drop(log);
+    let path = dir.join("t-0.log");
+    // a whole record, but with offset 5 after offset 0
+    append_raw(&path, &[0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 1, b'b']);
+
+    let file = LogFile::read(&path).unwrap();
+    assert_eq!(file.entries.len(), 1);
+    let corruption = file.corruption.unwrap();
+    assert_eq!(
+        corruption.kind,
+        CorruptionKind::OffsetOutOfOrder {
+            expected: 1,
+            found: 5
+        }
+    );
+    assert_eq!(corruption.position, 15);
+}
+
+#[test]
+fn rebuilt_index_differs_where_offsets_are_out_of_order() {
+    let dir = temp_data_dir("inspect-index");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record("", "a"), record("", "b")]).unwrap();
+    drop(log);
+    let path = dir.join("t-0.log");
+    append_raw(&path, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, b'c']);
+
+    // the broker's index points offset 0 at the duplicate
+    let scanned = PartitionLog::scan_index(&path).unwrap();
+    assert_eq!(scanned.into_iter().collect::<Vec<_>>(), [(0, 30), (1, 15)]);
+    let rebuilt = LogFile::read(&path).unwrap().index();
+    assert_eq!(rebuilt.into_iter().collect::<Vec<_>>(), [(0, 0), (1, 15)]);
+}
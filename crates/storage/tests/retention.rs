@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use protocol::types::Record;
+use storage::{LogConfig, PartitionLog};
+
+fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-kafka-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// 8 + 2 + 0 + 4 + 10 bytes on disk
+fn record(i: usize) -> Record {
+    Record {
+        key: Bytes::new(),
+        value: Bytes::from(format!("value-{i:04}")),
+    }
+}
+
+const RECORD_SIZE: u64 = 24;
+
+#[test]
+fn retention_drops_oldest_records() {
+    let dir = temp_data_dir("retention");
+    let config = LogConfig {
+        retention_bytes: Some(3 * RECORD_SIZE),
+        ..LogConfig::default()
+    };
+
+    let mut log = PartitionLog::open_with(&dir, "t", 0, config.clone()).unwrap();
+    for i in 0..5 {
+        log.append(&[record(i)]).unwrap();
+    }
+    assert_eq!(log.start_offset(), 2);
+    assert_eq!(log.next_offset(), 5);
+
+    let items = log.fetch(0, u32::MAX).unwrap();
+    let offsets: Vec<_> = items.iter().map(|(off, _)| *off).collect();
+    assert_eq!(offsets, vec![2, 3, 4]);
+    assert_eq!(&items[0].1.value[..], b"value-0002");
+
+    let len = std::fs::metadata(dir.join("t-0.log")).unwrap().len();
+    assert_eq!(len, 3 * RECORD_SIZE);
+
+    // appends keep working after the rewrite, and a reopen sees the same log
+    log.append(&[record(5)]).unwrap();
+    drop(log);
+    let log = PartitionLog::open_with(&dir, "t", 0, config).unwrap();
+    assert_eq!(log.start_offset(), 3);
+    assert_eq!(log.next_offset(), 6);
+}
+
+#[test]
+fn retention_waits_for_slack_before_trimming() {
+    let dir = temp_data_dir("retention-slack");
+    let config = LogConfig {
+        retention_bytes: Some(8 * RECORD_SIZE),
+        ..LogConfig::default()
+    };
+
+    let mut log = PartitionLog::open_with(&dir, "t", 0, config).unwrap();
+    for i in 0..9 {
+        log.append(&[record(i)]).unwrap();
+    }
+    // one record past the limit is within the slack of an eighth
+    assert_eq!((log.start_offset(), log.size()), (0, 9 * RECORD_SIZE));
+
+    log.append(&[record(9)]).unwrap();
+    assert_eq!((log.start_offset(), log.size()), (2, 8 * RECORD_SIZE));
+}
+
+#[test]
+fn retention_keeps_the_newest_record() {
+    let dir = temp_data_dir("retention-newest");
+    let config = LogConfig {
+        retention_bytes: Some(1),
+        ..LogConfig::default()
+    };
+
+    let mut log = PartitionLog::open_with(&dir, "t", 0, config).unwrap();
+    log.append(&[record(0), record(1)]).unwrap();
+    assert_eq!(log.start_offset(), 1);
+    assert_eq!(log.fetch(0, u32::MAX).unwrap().len(), 1);
+}
+
+#[test]
+fn deferred_flush_still_reads_back() {
+    let dir = temp_data_dir("flush-interval");
+    let config = LogConfig {
+        flush_interval_messages: 100,
+        ..LogConfig::default()
+    };
+
+    let mut log = PartitionLog::open_with(&dir, "t", 0, config).unwrap();
+    log.append(&[record(0), record(1)]).unwrap();
+    assert_eq!(log.fetch(0, u32::MAX).unwrap().len(), 2);
+    log.sync().unwrap();
+}
+
+#[test]
+fn recover_cuts_torn_tail() {
+    let dir = temp_data_dir("recover");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(0), record(1)]).unwrap();
+    drop(log);
+
+    // an append interrupted half way through its record
+    let path = dir.join("t-0.log");
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 2, 0]);
+    std::fs::write(&path, &bytes).unwrap();
+
+    assert!(matches!(
+        PartitionLog::open(&dir, "t", 0),
+        Err(storage::StorageError::Corrupted)
+    ));
+
+    let mut log = PartitionLog::recover(&dir, "t", 0, LogConfig::default()).unwrap();
+    assert_eq!(log.next_offset(), 2);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 2 * RECORD_SIZE);
+    assert_eq!(log.append(&[record(2)]).unwrap(), 2);
+}
+
+#[test]
+fn reset_starts_over_at_offset() {
+    let dir = temp_data_dir("reset");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(0), record(1)]).unwrap();
+
+    log.reset(7).unwrap();
+    assert_eq!(
+        (log.start_offset(), log.next_offset(), log.size()),
+        (7, 7, 0)
+    );
+    assert_eq!(log.append(&[record(7)]).unwrap(), 7);
+    let fetched = log.fetch(7, u32::MAX).unwrap();
+    assert_eq!(fetched.len(), 1);
+    assert_eq!(fetched[0].1.value, record(7).value);
+    drop(log);
+
+    let log = PartitionLog::open(&dir, "t", 0).unwrap();
+    assert_eq!((log.start_offset(), log.next_offset()), (7, 8));
+}
+
+#[test]
+fn truncate_removes_later_records() {
+    let dir = temp_data_dir("truncate");
+    let mut log = PartitionLog::open(&dir, "t", 0).unwrap();
+    log.append(&[record(0), record(1), record(2)]).unwrap();
+
+    log.truncate(5).unwrap();
+    assert_eq!(log.next_offset(), 3);
+    log.truncate(1).unwrap();
+    assert_eq!(
+        (log.start_offset(), log.next_offset(), log.size()),
+        (0, 1, RECORD_SIZE)
+    );
+    assert_eq!(log.append(&[record(9)]).unwrap(), 1);
+    let values: Vec<_> = log
+        .fetch(0, u32::MAX)
+        .unwrap()
+        .into_iter()
+        .map(|(_, r)| r.value)
+        .collect();
+    assert_eq!(values, [record(0).value, record(9).value]);
+    drop(log);
+
+    let log = PartitionLog::open(&dir, "t", 0).unwrap();
+    assert_eq!((log.start_offset(), log.next_offset()), (0, 2));
+}